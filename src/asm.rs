@@ -0,0 +1,411 @@
+//! Assembling programs written with the mnemonics the disassembler prints (those of
+//! Cowgod's technical reference), and listing ROMs in a form that assembles back into the
+//! same bytes.
+//!
+//! Source has one instruction per line, assembled from `0x200` on (or from where the memory
+//! layout starts programs, with [`assemble_at`]):
+//!
+//! ```text
+//! ; Draws a sprite, then waits forever.
+//! start:
+//!     LD I, sprite
+//!     DRW V0, V1, 5
+//! loop: JP loop
+//! sprite:
+//!     DB 0xF0, 0x90, 0xF0, 0x90, 0xF0
+//! ```
+//!
+//! Labels end in `:`, and `;` starts a comment. Numbers are decimal, hex with a `0x` or `$`
+//! prefix, or binary with `0b`. Besides instructions, `DB` emits bytes and `DW` big-endian
+//! words. Mnemonics and register names are case-insensitive; labels aren't.
+
+use std::fmt::Write as _;
+
+use anyhow::{bail, Context};
+
+use crate::{chip8::PROG_CTR_START_ADDR, instruction::Instruction, symbols::Symbols};
+
+const MEMORY_END: usize = 0x1000;
+
+const MNEMONICS: [&str; 20] = [
+    "SYS", "CLS", "RET", "JP", "CALL", "SE", "SNE", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR",
+    "SUBN", "SHL", "RND", "DRW", "SKP", "SKNP",
+];
+
+/// An assembled program, and the addresses of its labels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Program {
+    pub bytes: Vec<u8>,
+    pub symbols: Symbols,
+}
+
+pub fn assemble(source: &str) -> anyhow::Result<Program> {
+    assemble_at(source, PROG_CTR_START_ADDR)
+}
+
+/// Assembles `source` into a program that's loaded at `start`.
+pub fn assemble_at(source: &str, start: u16) -> anyhow::Result<Program> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(line).with_context(|| format!("line {}", i + 1)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    // Labels can be used before they're defined, so they're all placed first.
+    let mut symbols = Symbols::default();
+    let mut addr = start as usize;
+    for (i, line) in lines.iter().enumerate() {
+        for label in &line.labels {
+            if symbols.address(label).is_some() {
+                bail!("line {}: label {label:?} is already defined", i + 1);
+            }
+            symbols.insert(addr as u16, label);
+        }
+        addr += line.size();
+    }
+    if addr > MEMORY_END {
+        bail!(
+            "program is {} bytes, more than fits into memory",
+            addr - start as usize
+        );
+    }
+
+    let mut bytes = Vec::with_capacity(addr - start as usize);
+    for (i, line) in lines.iter().enumerate() {
+        line.emit(&symbols, &mut bytes)
+            .with_context(|| format!("line {}", i + 1))?;
+    }
+
+    Ok(Program { bytes, symbols })
+}
+
+/// Lists `rom` as source for [`assemble`]: one instruction per line, with its address and
+/// opcode in a comment, and the labels in `symbols` that fall on instructions. Words that
+/// aren't instructions become `DW`s, and a trailing odd byte a `DB`.
+pub fn listing(rom: &[u8], symbols: &Symbols) -> String {
+    listing_at(rom, PROG_CTR_START_ADDR, symbols)
+}
+
+/// Lists `rom` as loaded at `start`, as source for [`assemble_at`].
+pub fn listing_at(rom: &[u8], start: u16, symbols: &Symbols) -> String {
+    let end = start as usize + rom.len();
+
+    // Operands can only name labels that the listing defines.
+    let mut labels = Symbols::default();
+    for (addr, label) in symbols.iter() {
+        if (start as usize..end).contains(&(addr as usize))
+            && (addr - start).is_multiple_of(2)
+            && is_label(label)
+        {
+            labels.insert(addr, label);
+        }
+    }
+
+    let mut listing = String::new();
+    for (i, chunk) in rom.chunks(2).enumerate() {
+        let addr = start + 2 * i as u16;
+        if let Some(label) = labels.label(addr) {
+            let _ = writeln!(listing, "{label}:");
+        }
+
+        let (text, raw) = match *chunk {
+            [hi, lo] => {
+                let word = u16::from_be_bytes([hi, lo]);
+                let text = Instruction::decode(word).map_or_else(
+                    || format!("DW {word:#06x}"),
+                    |instruction| labels.disassemble(&instruction),
+                );
+                (text, format!("{word:04x}"))
+            }
+            [byte] => (format!("DB {byte:#04x}"), format!("{byte:02x}")),
+            _ => unreachable!(),
+        };
+        let _ = writeln!(listing, "    {text:<20} ; {addr:03x}: {raw}");
+    }
+
+    listing
+}
+
+#[derive(Debug)]
+struct Line<'a> {
+    labels: Vec<&'a str>,
+    mnemonic: Option<String>,
+    operands: Vec<&'a str>,
+}
+
+fn parse_line(line: &str) -> anyhow::Result<Line<'_>> {
+    let mut rest = line.split(';').next().unwrap_or_default().trim();
+
+    let mut labels = Vec::new();
+    while let Some(colon) = rest.find(':') {
+        let label = &rest[..colon];
+        if !is_label(label) {
+            bail!("invalid label {label:?}");
+        }
+        labels.push(label);
+        rest = rest[colon + 1..].trim_start();
+    }
+
+    if rest.is_empty() {
+        return Ok(Line {
+            labels,
+            mnemonic: None,
+            operands: Vec::new(),
+        });
+    }
+
+    let (mnemonic, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let operands = match operands.trim() {
+        "" => Vec::new(),
+        operands => operands.split(',').map(str::trim).collect(),
+    };
+
+    Ok(Line {
+        labels,
+        mnemonic: Some(mnemonic.to_ascii_uppercase()),
+        operands,
+    })
+}
+
+fn is_label(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+}
+
+impl Line<'_> {
+    fn size(&self) -> usize {
+        match self.mnemonic.as_deref() {
+            None => 0,
+            Some("DB") => self.operands.len(),
+            Some("DW") => 2 * self.operands.len(),
+            Some(_) => 2,
+        }
+    }
+
+    fn emit(&self, symbols: &Symbols, bytes: &mut Vec<u8>) -> anyhow::Result<()> {
+        let Some(mnemonic) = self.mnemonic.as_deref() else {
+            return Ok(());
+        };
+
+        match mnemonic {
+            "DB" => {
+                for operand in &self.operands {
+                    bytes.push(fits(value(operand, symbols)?, 0xFF, "byte")? as u8);
+                }
+            }
+            "DW" => {
+                for operand in &self.operands {
+                    bytes.extend(value(operand, symbols)?.to_be_bytes());
+                }
+            }
+            _ => {
+                let instruction = self
+                    .operands
+                    .iter()
+                    .map(|operand| Operand::parse(operand, symbols))
+                    .collect::<anyhow::Result<Vec<_>>>()
+                    .and_then(|operands| instruction(mnemonic, &operands))
+                    .with_context(|| {
+                        format!("{mnemonic} {}", self.operands.join(", "))
+                            .trim_end()
+                            .to_string()
+                    })?;
+                bytes.extend(instruction.encode().to_be_bytes());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    V(u8),
+    I,
+    /// `[I]`, the memory I points at.
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    B,
+    Value(u16),
+}
+
+impl Operand {
+    fn parse(s: &str, symbols: &Symbols) -> anyhow::Result<Self> {
+        let upper = s.to_ascii_uppercase();
+        let operand = match upper.as_str() {
+            "I" => Self::I,
+            "[I]" => Self::IndirectI,
+            "DT" => Self::Dt,
+            "ST" => Self::St,
+            "K" => Self::K,
+            "F" => Self::F,
+            "B" => Self::B,
+            _ => match upper.strip_prefix('V').filter(|x| x.len() == 1) {
+                Some(x) => Self::V(u8::from_str_radix(x, 16).context("invalid register")?),
+                None => Self::Value(value(s, symbols)?),
+            },
+        };
+
+        Ok(operand)
+    }
+}
+
+/// A number or a label's address.
+fn value(s: &str, symbols: &Symbols) -> anyhow::Result<u16> {
+    let (digits, radix) = if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix('$')) {
+        (hex, 16)
+    } else if let Some(binary) = s.strip_prefix("0b") {
+        (binary, 2)
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        (s, 10)
+    } else {
+        return symbols
+            .address(s)
+            .with_context(|| format!("unknown label {s:?}"));
+    };
+
+    u16::from_str_radix(digits, radix).with_context(|| format!("invalid number {s:?}"))
+}
+
+fn fits(value: u16, max: u16, what: &str) -> anyhow::Result<u16> {
+    if value > max {
+        bail!("{value:#x} doesn't fit into a {what}");
+    }
+    Ok(value)
+}
+
+fn instruction(mnemonic: &str, operands: &[Operand]) -> anyhow::Result<Instruction> {
+    use Instruction as In;
+    use Operand::{Value, B, F, I, K, V};
+
+    let addr = |a: u16| fits(a, 0xFFF, "12-bit address");
+    let byte = |kk: u16| Ok::<_, anyhow::Error>(fits(kk, 0xFF, "byte")? as u8);
+
+    let instruction = match (mnemonic, operands) {
+        ("CLS", []) => In::Cls,
+        ("RET", []) => In::Ret,
+        ("SYS", [Value(a)]) => In::Sys(addr(*a)?),
+        ("JP", [Value(a)]) => In::Jp(addr(*a)?),
+        ("JP", [V(0), Value(a)]) => In::JpV0(addr(*a)?),
+        ("CALL", [Value(a)]) => In::Call(addr(*a)?),
+        ("SE", [V(x), V(y)]) => In::SeReg(*x, *y),
+        ("SE", [V(x), Value(kk)]) => In::SeByte(*x, byte(*kk)?),
+        ("SNE", [V(x), V(y)]) => In::SneReg(*x, *y),
+        ("SNE", [V(x), Value(kk)]) => In::SneByte(*x, byte(*kk)?),
+        ("LD", [V(x), V(y)]) => In::LdReg(*x, *y),
+        ("LD", [V(x), Value(kk)]) => In::LdByte(*x, byte(*kk)?),
+        ("LD", [I, Value(a)]) => In::LdI(addr(*a)?),
+        ("LD", [V(x), Operand::Dt]) => In::LdVxDt(*x),
+        ("LD", [V(x), K]) => In::LdVxK(*x),
+        ("LD", [Operand::Dt, V(x)]) => In::LdDtVx(*x),
+        ("LD", [Operand::St, V(x)]) => In::LdStVx(*x),
+        ("LD", [F, V(x)]) => In::LdFVx(*x),
+        ("LD", [B, V(x)]) => In::LdBVx(*x),
+        ("LD", [Operand::IndirectI, V(x)]) => In::LdMemVx(*x),
+        ("LD", [V(x), Operand::IndirectI]) => In::LdVxMem(*x),
+        ("ADD", [V(x), V(y)]) => In::AddReg(*x, *y),
+        ("ADD", [V(x), Value(kk)]) => In::AddByte(*x, byte(*kk)?),
+        ("ADD", [I, V(x)]) => In::AddIVx(*x),
+        ("OR", [V(x), V(y)]) => In::Or(*x, *y),
+        ("AND", [V(x), V(y)]) => In::And(*x, *y),
+        ("XOR", [V(x), V(y)]) => In::Xor(*x, *y),
+        ("SUB", [V(x), V(y)]) => In::Sub(*x, *y),
+        ("SUBN", [V(x), V(y)]) => In::Subn(*x, *y),
+        // Shifting Vx into itself does the same with and without the shift quirk.
+        ("SHR", [V(x)]) => In::Shr(*x, *x),
+        ("SHR", [V(x), V(y)]) => In::Shr(*x, *y),
+        ("SHL", [V(x)]) => In::Shl(*x, *x),
+        ("SHL", [V(x), V(y)]) => In::Shl(*x, *y),
+        ("RND", [V(x), Value(kk)]) => In::Rnd(*x, byte(*kk)?),
+        ("DRW", [V(x), V(y), Value(n)]) => In::Drw(*x, *y, fits(*n, 0xF, "nibble")? as u8),
+        ("SKP", [V(x)]) => In::Skp(*x),
+        ("SKNP", [V(x)]) => In::Sknp(*x),
+        _ if MNEMONICS.contains(&mnemonic) => bail!("invalid operands"),
+        _ => bail!("unknown instruction"),
+    };
+
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn assemble_program() {
+        let source = "
+            ; Draws a sprite, then waits forever.
+            start:
+                ld i, sprite    ; forward reference
+                DRW V0, V1, 5
+                SHR VA
+            loop: JP loop
+            sprite:
+                DB 0xF0, $90, 0b11110000, 144
+                DW 0xF0F0
+        ";
+
+        let program = assemble(source).unwrap();
+        assert_eq!(
+            vec![
+                0xA2, 0x08, 0xD0, 0x15, 0x8A, 0xA6, 0x12, 0x06, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0
+            ],
+            program.bytes
+        );
+        assert_eq!(Some(0x206), program.symbols.address("loop"));
+        assert_eq!(Some(0x208), program.symbols.address("sprite"));
+    }
+
+    #[test]
+    fn assemble_errors() {
+        let error = |source| format!("{:#}", assemble(source).unwrap_err());
+
+        assert_eq!(
+            "line 2: JP nowhere: unknown label \"nowhere\"",
+            error("CLS\nJP nowhere")
+        );
+        assert_eq!("line 1: FOO V1: unknown instruction", error("FOO V1"));
+        assert_eq!(
+            "line 1: LD V1, 0x100: 0x100 doesn't fit into a byte",
+            error("LD V1, 0x100")
+        );
+        assert_eq!("line 1: SKP I: invalid operands", error("SKP I"));
+        assert_eq!(
+            "line 2: label \"a\" is already defined",
+            error("a: CLS\na: RET")
+        );
+    }
+
+    #[test]
+    fn listing_round_trip() {
+        let rom = [
+            0x22, 0x06, 0x12, 0x02, 0x51, 0x21, 0x00, 0xEE, 0x13, 0x00, 0xAB,
+        ];
+        let symbols = Symbols::parse("206 draw\n203 middle\n300 outside").unwrap();
+
+        let listing = listing(&rom, &symbols);
+        assert!(listing.contains("    CALL draw            ; 200: 2206\n"));
+        assert!(listing.contains("draw:\n    RET"));
+        assert!(listing.contains("DW 0x5121"));
+        assert!(listing.contains("JP 0x300"));
+
+        let program = assemble(&listing).unwrap();
+        assert_eq!(rom.to_vec(), program.bytes);
+    }
+
+    #[test]
+    fn other_start_address() {
+        let program = assemble_at("loop: JP loop", 0x600).unwrap();
+        assert_eq!(vec![0x16, 0x00], program.bytes);
+        assert_eq!(Some(0x600), program.symbols.address("loop"));
+
+        let listing = listing_at(&program.bytes, 0x600, &program.symbols);
+        assert_eq!("loop:\n    JP loop              ; 600: 1600\n", listing);
+    }
+}
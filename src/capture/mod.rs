@@ -0,0 +1,396 @@
+//! Saving the display as images: PNG screenshots, and ranges of frames as an animated GIF
+//! or a sequence of PNGs. Independent of any frontend, so headless runs can capture too.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::warn;
+
+use crate::{
+    chip8::Screen,
+    palette::{Palette, Rgb},
+    FRAME_HZ,
+};
+
+pub const DEFAULT_SCALE: u32 = 8;
+
+/// How the display is turned into an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Size of a CHIP-8 pixel, in image pixels.
+    pub scale: u32,
+    pub palette: Palette,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            scale: DEFAULT_SCALE,
+            palette: Palette::default(),
+        }
+    }
+}
+
+impl RenderOptions {
+    pub fn image_size(&self, screen: &Screen) -> (u32, u32) {
+        let scale = self.scale.max(1);
+        (screen.width as u32 * scale, screen.height as u32 * scale)
+    }
+
+    /// The screen scaled up, with one byte per image pixel: its index into
+    /// [`Screen::color_table`].
+    fn indices(&self, screen: &Screen) -> Vec<u8> {
+        let scale = self.scale.max(1) as usize;
+        let mut indices = Vec::with_capacity(screen.pixels.len() * scale * scale);
+
+        for row in 0..screen.height {
+            let line: Vec<u8> = (row * screen.width..(row + 1) * screen.width)
+                .flat_map(|i| std::iter::repeat_n(screen.color_index(i), scale))
+                .collect();
+            for _ in 0..scale {
+                indices.extend(&line);
+            }
+        }
+
+        indices
+    }
+
+    /// The screen scaled up, as RGB bytes.
+    pub fn render(&self, screen: &Screen) -> Vec<u8> {
+        let colors = screen.color_table(&self.palette);
+        self.indices(screen)
+            .into_iter()
+            .flat_map(|index| colors[index as usize])
+            .collect()
+    }
+
+    pub fn write_png(&self, screen: &Screen, writer: impl Write) -> anyhow::Result<()> {
+        let (width, height) = self.image_size(screen);
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.render(screen))?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn save_png(&self, screen: &Screen, path: &Path) -> anyhow::Result<()> {
+        File::create(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|file| self.write_png(screen, BufWriter::new(file)))
+            .with_context(|| format!("failed to save screenshot {}", path.display()))
+    }
+}
+
+impl RenderOptions {
+    /// Whether the PNG at `path` shows `screen` the way these options draw it, like a
+    /// screenshot saved by an earlier run.
+    pub fn matches_png(&self, screen: &Screen, path: &Path) -> anyhow::Result<bool> {
+        let file =
+            File::open(path).with_context(|| format!("failed to open image {}", path.display()))?;
+        let mut decoder = png::Decoder::new(BufReader::new(file));
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = decoder
+            .read_info()
+            .with_context(|| format!("invalid PNG {}", path.display()))?;
+        let mut image = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut image)?;
+
+        Ok(info.color_type == png::ColorType::Rgb
+            && (info.width, info.height) == self.image_size(screen)
+            && image[..info.buffer_size()] == self.render(screen))
+    }
+}
+
+/// An animated GIF being written, and what its header says.
+struct GifWriter {
+    encoder: gif::Encoder<BufWriter<File>>,
+    size: (u16, u16),
+    palette: Vec<Rgb>,
+}
+
+impl GifWriter {
+    /// Starts a looping animation at `path`, with `palette` as the global colour table.
+    fn create(path: &Path, size: (u16, u16), palette: &[Rgb]) -> anyhow::Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut encoder =
+            gif::Encoder::new(BufWriter::new(file), size.0, size.1, palette.as_flattened())?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        Ok(Self {
+            encoder,
+            size,
+            palette: palette.to_vec(),
+        })
+    }
+
+    /// Adds a frame of indices into `palette`, shown for `delay` hundredths of a second.
+    /// The frame gets its own colour table if `palette` isn't the GIF's.
+    fn write_frame(&mut self, indices: &[u8], palette: &[Rgb], delay: u16) -> anyhow::Result<()> {
+        let mut frame = gif::Frame::from_indexed_pixels(self.size.0, self.size.1, indices, None);
+        if palette != self.palette {
+            frame.palette = Some(palette.as_flattened().to_vec());
+        }
+        frame.delay = delay;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+
+    fn finish(self) -> std::io::Result<()> {
+        self.encoder.into_inner()?.flush()
+    }
+}
+
+enum Sink {
+    Gif {
+        encoder: Option<GifWriter>,
+        // The last distinct frame, its colours, and the frame it first appeared on. It's
+        // written once it changes, so that the GIF holds it for as long as the emulator did.
+        pending: Option<(Vec<u8>, Vec<Rgb>, u64)>,
+    },
+    Png,
+}
+
+/// Saves the frames within a range as they're drawn, to an animated GIF if the path ends
+/// in `.gif`, and otherwise to numbered PNGs in the directory at the path.
+pub struct FrameCapture {
+    path: PathBuf,
+    options: RenderOptions,
+    frames: RangeInclusive<u64>,
+    frame: u64,
+    sink: Sink,
+}
+
+impl std::fmt::Debug for FrameCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameCapture")
+            .field("path", &self.path)
+            .field("frames", &self.frames)
+            .field("frame", &self.frame)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FrameCapture {
+    /// Captures the frames numbered `frames`, counting from the next one.
+    pub fn new(
+        path: &Path,
+        options: RenderOptions,
+        frames: RangeInclusive<u64>,
+    ) -> anyhow::Result<Self> {
+        let sink = if path.extension().is_some_and(|ext| ext == "gif") {
+            Sink::Gif {
+                encoder: None,
+                pending: None,
+            }
+        } else {
+            std::fs::create_dir_all(path)
+                .with_context(|| format!("failed to create directory {}", path.display()))?;
+            Sink::Png
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            options,
+            frames,
+            frame: 0,
+            sink,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Frames seen so far, captured or not.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Whether all frames in the range have been captured.
+    pub fn is_done(&self) -> bool {
+        self.frame > *self.frames.end()
+    }
+
+    /// Called at the end of every frame with what's on the screen.
+    pub fn add_frame(&mut self, screen: &Screen) -> anyhow::Result<()> {
+        let frame = self.frame;
+        self.frame += 1;
+        if !self.frames.contains(&frame) {
+            return Ok(());
+        }
+
+        match &mut self.sink {
+            Sink::Gif { encoder, pending } => {
+                let (width, height) = self.options.image_size(screen);
+                let size = (width as u16, height as u16);
+                if encoder.as_ref().is_some_and(|encoder| encoder.size != size) {
+                    // The display changed size, as MEGA-CHIP's does.
+                    warn!("skipping frame {frame}, which isn't the size of the GIF");
+                    return Ok(());
+                }
+
+                let indices = self.options.indices(screen);
+                let palette = screen.color_table(&self.options.palette);
+                if pending
+                    .as_ref()
+                    .is_some_and(|(last, colors, _)| (last, colors) == (&indices, &palette))
+                {
+                    return Ok(());
+                }
+
+                if encoder.is_none() {
+                    *encoder = Some(GifWriter::create(&self.path, size, &palette)?);
+                }
+
+                if let (Some(encoder), Some((last, colors, start))) = (encoder, pending.take()) {
+                    encoder.write_frame(&last, &colors, gif_delay(start, frame))?;
+                }
+                *pending = Some((indices, palette, frame));
+            }
+            Sink::Png => {
+                let path = self.path.join(format!("{frame:06}.png"));
+                self.options.save_png(screen, &path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes out anything still buffered.
+    pub fn finish(self) -> anyhow::Result<()> {
+        if let Sink::Gif {
+            encoder: Some(mut encoder),
+            pending,
+        } = self.sink
+        {
+            if let Some((last, colors, start)) = pending {
+                let end = self.frame.min(self.frames.end().saturating_add(1));
+                encoder.write_frame(&last, &colors, gif_delay(start, end.max(start + 1)))?;
+            }
+            encoder
+                .finish()
+                .with_context(|| format!("failed to write {}", self.path.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How long to show a GIF frame spanning emulator frames `start..end`, in hundredths of a
+/// second. Rounding both ends keeps the animation from drifting out of time.
+fn gif_delay(start: u64, end: u64) -> u16 {
+    let centis = |frame: u64| (frame * 100 + FRAME_HZ as u64 / 2) / FRAME_HZ as u64;
+    (centis(end) - centis(start)).clamp(1, u16::MAX as u64) as u16
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::ScreenColors;
+
+    #[test]
+    fn png_screenshot() {
+        let pixels: Vec<u8> = (0..64 * 32)
+            .map(|i| if i == 65 { 0xFF } else { 0 })
+            .collect();
+        let screen = Screen {
+            pixels: &pixels,
+            width: 64,
+            height: 32,
+            colors: ScreenColors::Palette,
+        };
+        let options = RenderOptions {
+            scale: 2,
+            palette: "#000000,#ff8000".parse().unwrap(),
+        };
+
+        let mut bytes = Vec::new();
+        options.write_png(&screen, &mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut rgb = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut rgb).unwrap();
+        assert_eq!((128, 64), (info.width, info.height));
+
+        // Pixel (1, 1) covers image pixels (2..4, 2..4).
+        let at = |x: usize, y: usize| &rgb[(y * 128 + x) * 3..][..3];
+        assert_eq!([0xFF, 0x80, 0x00], at(2, 3));
+        assert_eq!([0xFF, 0x80, 0x00], at(3, 2));
+        assert_eq!([0, 0, 0], at(1, 2));
+        assert_eq!([0, 0, 0], at(4, 4));
+
+        let path = std::env::temp_dir().join(format!("patata-{}.png", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        let matches = options.matches_png(&screen, &path).unwrap();
+        let blank = options.matches_png(
+            &Screen {
+                pixels: &[0; 64 * 32],
+                ..screen
+            },
+            &path,
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches);
+        assert!(!blank.unwrap());
+    }
+
+    #[test]
+    fn gif_capture() {
+        let mut pixels = vec![0; 64 * 32];
+        let options = RenderOptions {
+            scale: 1,
+            palette: "#000000,#ff8000".parse().unwrap(),
+        };
+        let path = std::env::temp_dir().join(format!("patata-{}.gif", std::process::id()));
+
+        let mut capture = FrameCapture::new(&path, options, 1..=90).unwrap();
+        for frame in 0..120 {
+            if frame == 30 {
+                pixels[65] = 0xFF;
+            }
+            capture
+                .add_frame(&Screen {
+                    pixels: &pixels,
+                    width: 64,
+                    height: 32,
+                    colors: ScreenColors::Palette,
+                })
+                .unwrap();
+        }
+        capture.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let file = File::open(&path).unwrap();
+        let mut decoder = options.read_info(BufReader::new(file)).unwrap();
+        assert_eq!((64, 32), (decoder.width(), decoder.height()));
+        assert_eq!(
+            Some(&[0, 0, 0, 0xFF, 0x80, 0x00][..]),
+            decoder.global_palette()
+        );
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[65], frame.palette.is_some()));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        // Frames 1 to 29 are blank, and 30 to 90 show the pixel.
+        assert_eq!(vec![(48, 0, false), (102, 1, false)], frames);
+    }
+
+    #[test]
+    fn gif_delays_add_up() {
+        let total: u64 = (0..60).map(|f| gif_delay(f, f + 1) as u64).sum();
+        assert_eq!(100, total);
+        assert_eq!(50, gif_delay(0, 30));
+    }
+}
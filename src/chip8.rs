@@ -1,28 +1,141 @@
 #![allow(clippy::cast_lossless)]
 
-use log::{info, trace};
+use std::{fmt, ops::Range};
+
+use anyhow::Context;
+use log::{info, trace, warn};
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 
 use crate::{
-    fonts::FONT_SET,
+    cosmac::{
+        cdp1861::{DISPLAY_LINES, LINE_BYTES},
+        InterpreterState, Vip, VipImages,
+    },
+    coverage::Coverage,
+    fonts::{BIG_FONT_SET, FONT_SET},
+    instruction::Instruction,
+    layout::MemoryLayout,
     opcode::OpCode,
+    palette::{Palette, Rgb, VP590_BACKGROUND, VP590_FOREGROUND},
+    quirks::{Platform, Quirks},
     subsystem::{
         keypad::Keypad,
         reg::IndexRegister,
         timer::Timer,
-        video::{DrawCoords, Video},
+        video::{Blend, Video, ZONE_WIDTH},
     },
+    timing::{Timing, VIP_FRAME_CYCLES},
+};
+
+pub use crate::subsystem::{
+    sample::Sample,
+    video::{ColorLayer, IndexedPalette},
 };
 
-const MEMORY_SIZE_BYTES: usize = 4096;
-const PROG_CTR_START_ADDR: u16 = 0x200;
-const MAX_ROM_SIZE_BYTES: usize = MEMORY_SIZE_BYTES - 0x200;
-const FONTSET_START_ADDR: usize = 0x50;
+/// MEGA-CHIP's display, which `0011` switches to.
+const MEGA_WIDTH: usize = 256;
+const MEGA_HEIGHT: usize = 192;
+/// SUPER-CHIP's high resolution display, which `00FF` switches to.
+const HIRES_WIDTH: usize = 128;
+const HIRES_HEIGHT: usize = 64;
+
+/// Where programs start in the default memory layout, and in assembled programs.
+pub const PROG_CTR_START_ADDR: u16 = 0x200;
+pub const FONT_GLYPH_BYTES: usize = 5;
+pub const BIG_FONT_GLYPH_BYTES: usize = 10;
+
+/// Why the instruction at `address` can't be executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Not an instruction, or a `SYS` call into machine code.
+    InvalidOpcode { address: u16, opcode: u16 },
+    /// A `CALL` with all 16 stack entries in use.
+    StackOverflow { address: u16 },
+    /// A `RET` with nothing on the stack.
+    StackUnderflow { address: u16 },
+    /// An access through I that runs past the end of memory, or a program counter there.
+    OutOfBounds { address: u16 },
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::InvalidOpcode { address, opcode } => {
+                write!(f, "invalid opcode {opcode:04x} at {address:#05x}")
+            }
+            Self::StackOverflow { address } => write!(f, "stack overflow at {address:#05x}"),
+            Self::StackUnderflow { address } => write!(f, "stack underflow at {address:#05x}"),
+            Self::OutOfBounds { address } => {
+                write!(f, "memory access out of bounds at {address:#05x}")
+            }
+        }
+    }
+}
+
+/// What's on the display: one byte per pixel, row by row, non-zero when lit or, on an
+/// indexed display, the pixel's colour.
+#[derive(Debug, Clone, Copy)]
+pub struct Screen<'a> {
+    pub pixels: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    pub colors: ScreenColors<'a>,
+}
+
+/// Where the colours of a [`Screen`]'s pixels come from.
+#[derive(Debug, Clone, Copy)]
+pub enum ScreenColors<'a> {
+    /// The palette's background and foreground.
+    Palette,
+    /// CHIP-8X's colours, which replace the palette's.
+    Zones(&'a ColorLayer),
+    /// MEGA-CHIP's colours, which pixels are indices into.
+    Indexed(&'a IndexedPalette),
+}
+
+impl Screen<'_> {
+    /// The colours the screen is drawn with, indexed by [`Screen::color_index`].
+    pub fn color_table(&self, palette: &Palette) -> Vec<Rgb> {
+        match self.colors {
+            ScreenColors::Palette => vec![palette.background, palette.foreground],
+            ScreenColors::Zones(_) => VP590_BACKGROUND
+                .iter()
+                .chain(&VP590_FOREGROUND)
+                .copied()
+                .collect(),
+            ScreenColors::Indexed(palette) => {
+                let fade = |c: u8| (u16::from(c) * u16::from(palette.alpha) / 0xFF) as u8;
+                palette.colors().iter().map(|rgb| rgb.map(fade)).collect()
+            }
+        }
+    }
+
+    /// The index into [`Screen::color_table`] of the colour of unlit pixels.
+    pub fn background_index(&self) -> u8 {
+        match self.colors {
+            ScreenColors::Zones(colors) => colors.background(),
+            ScreenColors::Palette | ScreenColors::Indexed(_) => 0,
+        }
+    }
+
+    /// The index into [`Screen::color_table`] of the colour of the `i`th pixel.
+    pub fn color_index(&self, i: usize) -> u8 {
+        let pixel = self.pixels[i];
+        match self.colors {
+            ScreenColors::Palette => u8::from(pixel != 0),
+            ScreenColors::Zones(colors) if pixel != 0 => {
+                VP590_BACKGROUND.len() as u8 + colors.foreground(i % self.width, i / self.width)
+            }
+            ScreenColors::Zones(colors) => colors.background(),
+            ScreenColors::Indexed(_) => pixel,
+        }
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Chip8 {
     pub registers: [u8; 16],
-    pub memory: [u8; MEMORY_SIZE_BYTES],
+    pub memory: Vec<u8>,
     pub index: IndexRegister,
     // `program_counter` needs to hold the maximum possible address in `memory`
     pub program_counter: u16,
@@ -30,42 +143,117 @@ pub struct Chip8 {
     pub stack_pointer: u8,
     pub delay_timer: Timer,
     pub sound_timer: Timer,
+    pub quirks: Quirks,
+    /// The interpreter whose extra instructions to run.
+    pub platform: Platform,
+    /// How long instructions take. Under [`Timing::Vip`], the timers count down once per
+    /// frame, in [`Chip8::tick_timers`], rather than after every instruction.
+    pub timing: Timing,
+    /// The last byte CHIP-8X's `FxF8` sent to the I/O port.
+    pub io_output: u8,
+    /// What CHIP-8X's `FxFB` reads from the I/O port.
+    pub io_input: u8,
+    layout: MemoryLayout,
     keypad: Keypad,
+    /// CHIP-8X's second keypad.
+    second_keypad: Keypad,
     display: Video,
+    /// Whether SUPER-CHIP's 128x64 display is on, outside MEGA-CHIP's own.
+    hires: bool,
+    /// The HP 48's RPL user flags, which `Fx75` and `Fx85` save and restore registers in.
+    rpl_flags: [u8; 16],
+    /// MEGA-CHIP's sprite width and height, where 0 means 256.
+    sprite_size: (u8, u8),
+    blend: Blend,
+    collision_color: u8,
+    sample: Option<Sample>,
+    samples_started: u32,
+    /// The COSMAC VIP that runs the program instead, if there is one.
+    vip: Option<Box<Vip>>,
     rng: SmallRng,
+    coverage: Option<Box<Coverage>>,
 }
 
 impl Default for Chip8 {
     fn default() -> Self {
-        let mut memory = [0; 4096];
+        Self::new(MemoryLayout::default())
+    }
+}
 
-        memory[FONTSET_START_ADDR..(FONTSET_START_ADDR + FONT_SET.len())]
-            .copy_from_slice(&FONT_SET);
+impl Chip8 {
+    /// A machine with memory and a display laid out as in `layout`, which must be valid
+    /// (see [`MemoryLayout::validate`]).
+    pub fn new(layout: MemoryLayout) -> Self {
+        let mut memory = vec![0; layout.memory_size];
+
+        let font_start = layout.font_start as usize;
+        memory[font_start..(font_start + FONT_SET.len())].copy_from_slice(&FONT_SET);
 
         Self {
             registers: [0; 16],
             memory,
             index: IndexRegister::default(),
-            program_counter: PROG_CTR_START_ADDR,
+            program_counter: layout.program_start,
             stack: [0; 16],
             stack_pointer: 0,
             delay_timer: Timer::default(),
             sound_timer: Timer::default(),
+            quirks: Quirks::default(),
+            platform: Platform::default(),
+            timing: Timing::default(),
+            io_output: 0,
+            io_input: 0,
+            layout,
             keypad: Keypad::default(),
-            display: Video::default(),
+            second_keypad: Keypad::default(),
+            display: Video::new(layout.width, layout.height),
+            hires: false,
+            rpl_flags: [0; 16],
+            sprite_size: (0, 0),
+            blend: Blend::default(),
+            collision_color: 0,
+            sample: None,
+            samples_started: 0,
+            vip: None,
             rng: SmallRng::from_entropy(),
+            coverage: None,
         }
     }
-}
 
-impl Chip8 {
     // Reference: https://austinmorlan.com/posts/chip8_emulator/
     pub fn tick(&mut self) {
+        if self.vip.is_some() {
+            self.tick_vip();
+            return;
+        }
+
         let opcode = self.next_opcode();
+        let schip = matches!(self.platform, Platform::SuperChip | Platform::MegaChip);
+        let mega = self.platform == Platform::MegaChip;
 
         match opcode.nibbles() {
             (0x00, 0x00, 0x0E, 0x00) => self.op_00E0(),
+            (0x00, 0x02, 0x03, 0x00) if self.platform == Platform::Chip8HiRes => self.op_00E0(),
+            (0x00, 0x00, 0x0F, 0x00) if self.platform == Platform::Chip8X => self.op_00F0(),
+            (0x00, 0x02, 0x0A, 0x00) if self.platform == Platform::Chip8X => self.op_02A0(),
             (0x00, 0x00, 0x0E, 0x0E) => self.op_00EE(),
+            (0x00, 0x00, 0x0C, n) if schip => self.display.scroll_down(n as usize),
+            (0x00, 0x00, 0x0F, 0x0B) if schip => self.display.scroll_right(4),
+            (0x00, 0x00, 0x0F, 0x0C) if schip => self.display.scroll_left(4),
+            (0x00, 0x00, 0x0F, 0x0D) if schip => self.op_00FD(),
+            (0x00, 0x00, 0x0F, 0x0E) if schip => self.op_00FE(),
+            (0x00, 0x00, 0x0F, 0x0F) if schip => self.op_00FF(),
+            (0x00, 0x00, 0x01, 0x00) if mega => self.op_0010(),
+            (0x00, 0x00, 0x01, 0x01) if mega => self.op_0011(),
+            (0x00, 0x01, _, _) if mega => self.op_01nn(opcode),
+            (0x00, 0x02, _, _) if mega => self.op_02nn(opcode),
+            (0x00, 0x03, _, _) if mega => self.sprite_size.0 = opcode.kk(),
+            (0x00, 0x04, _, _) if mega => self.sprite_size.1 = opcode.kk(),
+            (0x00, 0x05, _, _) if mega => self.op_05nn(opcode),
+            (0x00, 0x06, 0x00, _) if mega => self.op_060n(opcode),
+            (0x00, 0x07, 0x00, 0x00) if mega => self.sample = None,
+            (0x00, 0x08, 0x00, _) if mega => self.op_080n(opcode),
+            (0x00, 0x09, _, _) if mega => self.collision_color = opcode.kk(),
             (0x01, _, _, _) => self.op_1nnn(opcode),
             (0x02, _, _, _) => self.op_2nnn(opcode),
             (0x03, _, _, _) => self.op_3xkk(opcode),
@@ -84,44 +272,153 @@ impl Chip8 {
             (0x08, _, _, 0x0E) => self.op_8xyE(opcode),
             (0x09, _, _, 0x00) => self.op_9xy0(opcode),
             (0x0A, _, _, _) => self.op_Annn(opcode),
+            (0x0B, _, _, 0x00) if self.platform == Platform::Chip8X => self.op_Bxy0(opcode),
+            (0x0B, _, _, _) if self.platform == Platform::Chip8X => self.op_BxyN(opcode),
             (0x0B, _, _, _) => self.op_Bnnn(opcode),
             (0x0C, _, _, _) => {
                 let byte = self.rng.gen();
                 self.op_Cxkk(opcode, byte);
             }
+            (0x0D, _, _, _) if self.display.palette().is_some() => self.op_Dxyn_indexed(opcode),
+            (0x0D, _, _, 0x00) if schip => self.op_Dxy0(opcode),
             (0x0D, _, _, _) => self.op_Dxyn(opcode),
             (0x0E, _, 0x09, 0x0E) => self.op_Ex9E(opcode),
             (0x0E, _, 0x0A, 0x01) => self.op_ExA1(opcode),
+            (0x0E, _, 0x0F, 0x02) if self.platform == Platform::Chip8X => self.op_ExF2(opcode),
+            (0x0E, _, 0x0F, 0x05) if self.platform == Platform::Chip8X => self.op_ExF5(opcode),
             (0x0F, _, 0x00, 0x07) => self.op_Fx07(opcode),
             (0x0F, _, 0x00, 0x0A) => self.op_Fx0A(opcode),
             (0x0F, _, 0x01, 0x05) => self.op_Fx15(opcode),
             (0x0F, _, 0x01, 0x08) => self.op_Fx18(opcode),
             (0x0F, _, 0x01, 0x0E) => self.op_Fx1E(opcode),
             (0x0F, _, 0x02, 0x09) => self.op_Fx29(opcode),
+            (0x0F, _, 0x03, 0x00) if schip => self.op_Fx30(opcode),
             (0x0F, _, 0x03, 0x03) => self.op_Fx33(opcode),
-            (0x0F, _, 0x01, 0x55) => self.op_Fx55(opcode),
-            (0x0F, _, 0x01, 0x65) => self.op_Fx65(opcode),
+            (0x0F, _, 0x05, 0x05) => self.op_Fx55(opcode),
+            (0x0F, _, 0x06, 0x05) => self.op_Fx65(opcode),
+            (0x0F, _, 0x07, 0x05) if schip => self.op_Fx75(opcode),
+            (0x0F, _, 0x08, 0x05) if schip => self.op_Fx85(opcode),
+            (0x0F, _, 0x0F, 0x08) if self.platform == Platform::Chip8X => self.op_FxF8(opcode),
+            (0x0F, _, 0x0F, 0x0B) if self.platform == Platform::Chip8X => self.op_FxFB(opcode),
             _ => unreachable!("{:?}", opcode),
         }
 
+        if self.timing == Timing::Fixed {
+            self.tick_timers();
+        }
+    }
+
+    /// Runs the next instruction on the VIP's interpreter, or a frame of it if it takes
+    /// longer, and copies what it did into the machine.
+    fn tick_vip(&mut self) {
+        let Some(vip) = self.vip.as_deref_mut() else {
+            return;
+        };
+
+        // Take any changes made since, like by a debugger.
+        if vip.at_instruction() {
+            if let Some(coverage) = &mut self.coverage {
+                coverage.record_execution(self.program_counter as usize);
+            }
+            let state = InterpreterState {
+                registers: self.registers,
+                index: self.index.get() as u16,
+                program_counter: self.program_counter,
+                delay_timer: self.delay_timer.cur_count(),
+                sound_timer: self.sound_timer.cur_count(),
+                ..InterpreterState::default()
+            };
+            vip.set_interpreter_state(&mut self.memory, &state);
+        }
+
+        let step = vip.run_instruction(&mut self.memory, self.keypad.keys(), VIP_FRAME_CYCLES);
+
+        if step.finished {
+            let state = vip.interpreter_state(&self.memory);
+            self.registers = state.registers;
+            self.index.load_long(u32::from(state.index));
+            self.program_counter = state.program_counter;
+            self.delay_timer.set(state.delay_timer);
+            self.sound_timer.set(state.sound_timer);
+            self.stack = state.stack;
+            self.stack_pointer = state.stack_pointer;
+        }
+
+        if step.frames > 0 {
+            // The interpreter repeats each row of display memory over several lines.
+            let height = self.display.height();
+            let frame = vip.video.frame();
+            let rows: Vec<u8> = (0..height)
+                .flat_map(|row| {
+                    let line = row * DISPLAY_LINES / height;
+                    frame[line * LINE_BYTES..][..LINE_BYTES].iter().copied()
+                })
+                .collect();
+            self.display.load_bits(&rows);
+        }
+    }
+
+    /// Runs programs on a COSMAC VIP with `images` from now on, with VIP timing. Needs a
+    /// display 64 pixels wide and the interpreter to fit below the program.
+    pub fn attach_vip(&mut self, images: VipImages) -> anyhow::Result<()> {
+        let layout = self.layout;
+        if layout.width != LINE_BYTES * 8 || !DISPLAY_LINES.is_multiple_of(layout.height) {
+            anyhow::bail!(
+                "the VIP can't show a {}x{} display",
+                layout.width,
+                layout.height
+            );
+        }
+        if images.interpreter.len() > layout.program_start as usize {
+            anyhow::bail!(
+                "the VIP interpreter doesn't fit below programs at {:#05x}",
+                layout.program_start
+            );
+        }
+
+        self.vip = Some(Box::new(Vip::new(images, &mut self.memory)?));
+        self.timing = Timing::Vip;
+        Ok(())
+    }
+
+    /// The COSMAC VIP running the program, if one is.
+    pub fn vip(&self) -> Option<&Vip> {
+        self.vip.as_deref()
+    }
+
+    /// Counts the delay and sound timers down by one.
+    pub fn tick_timers(&mut self) {
         self.delay_timer.tick();
         self.sound_timer.tick();
     }
 
     pub fn load_rom(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
         let nbytes = bytes.len();
+        let layout = self.layout;
+        layout
+            .validate()
+            .with_context(|| format!("invalid memory layout {layout}"))?;
+        let max_rom_size = layout.max_rom_size();
 
-        if nbytes == 0 || nbytes > MAX_ROM_SIZE_BYTES {
+        if nbytes == 0 || nbytes > max_rom_size {
             anyhow::bail!(
                 "rom length is invalid. Received {} bytes, expected between {} and {} bytes.",
                 nbytes,
                 0,
-                MAX_ROM_SIZE_BYTES
+                max_rom_size
             );
         }
 
-        let src_copy_range =
-            (PROG_CTR_START_ADDR as usize)..(PROG_CTR_START_ADDR as usize + nbytes);
+        let start = layout.program_start as usize;
+        let src_copy_range = start..(start + nbytes);
+
+        let font_start = layout.font_start as usize;
+        if font_start < src_copy_range.end && src_copy_range.start < font_start + FONT_SET.len() {
+            anyhow::bail!(
+                "the rom at {start:#05x}-{:#05x} overlaps the font at {font_start:#05x}",
+                src_copy_range.end - 1
+            );
+        }
 
         self.memory[src_copy_range].copy_from_slice(bytes);
 
@@ -130,12 +427,250 @@ impl Chip8 {
         Ok(())
     }
 
+    /// Resets the CPU, timers and screen, but not memory, so that the program in memory
+    /// starts over, including any changes it made to itself.
+    pub fn reset(&mut self) {
+        self.registers = [0; 16];
+        self.index = IndexRegister::default();
+        self.program_counter = self.layout.program_start;
+        self.stack = [0; 16];
+        self.stack_pointer = 0;
+        self.delay_timer = Timer::default();
+        self.sound_timer = Timer::default();
+        self.display = Video::new(self.layout.width, self.layout.height);
+        self.hires = false;
+        self.sprite_size = (0, 0);
+        self.blend = Blend::default();
+        self.collision_color = 0;
+        self.sample = None;
+        if let Some(vip) = &mut self.vip {
+            vip.reset(&mut self.memory);
+        }
+    }
+
+    pub fn layout(&self) -> MemoryLayout {
+        self.layout
+    }
+
+    /// Runs `platform`'s instructions from now on. SUPER-CHIP's and MEGA-CHIP's interpreters
+    /// also have the large digits in memory, right after the small ones, as long as they fit
+    /// before the program.
+    pub fn set_platform(&mut self, platform: Platform) {
+        self.platform = platform;
+        if !matches!(platform, Platform::SuperChip | Platform::MegaChip) {
+            return;
+        }
+
+        let start = self.big_font_start() as usize;
+        let font = start..start + BIG_FONT_SET.len();
+        if font.end <= self.layout.program_start as usize {
+            self.memory[font].copy_from_slice(&BIG_FONT_SET);
+        } else {
+            warn!("no room for the large font at {start:#05x}, before the program");
+        }
+    }
+
+    fn big_font_start(&self) -> u16 {
+        self.layout.font_start + FONT_SET.len() as u16
+    }
+
+    /// Makes `RND` deterministic, producing the same numbers for the same seed.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    /// The state of the keypad, one bit per key.
+    pub fn keys(&self) -> u16 {
+        self.keypad.keys()
+    }
+
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keypad.set_keys(keys);
+    }
+
+    /// Sets the state of CHIP-8X's second keypad, one bit per key.
+    pub fn set_second_keys(&mut self, keys: u16) {
+        self.second_keypad.set_keys(keys);
+    }
+
+    pub fn screen(&self) -> Screen<'_> {
+        Screen {
+            pixels: self.display.buffer(),
+            width: self.display.width(),
+            height: self.display.height(),
+            colors: match self.display.palette() {
+                Some(palette) => ScreenColors::Indexed(palette),
+                None if self.platform == Platform::Chip8X => {
+                    ScreenColors::Zones(self.display.colors())
+                }
+                None => ScreenColors::Palette,
+            },
+        }
+    }
+
+    /// The digitised sound MEGA-CHIP is playing.
+    pub fn sample(&self) -> Option<&Sample> {
+        self.sample.as_ref()
+    }
+
+    /// Hashes the machine state, to check that two runs are in sync. This is FNV-1a, which
+    /// unlike `std`'s hasher is stable across Rust versions and platforms.
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET: u64 = 0xCBF2_9CE4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01B3;
+
+        let words = [
+            self.index.get() as u16,
+            self.program_counter,
+            u16::from(self.stack_pointer),
+            u16::from(self.delay_timer.cur_count()),
+            u16::from(self.sound_timer.cur_count()),
+            self.keypad.keys(),
+        ];
+
+        let bytes = self
+            .registers
+            .iter()
+            .chain(&self.memory)
+            .copied()
+            .chain(
+                self.stack
+                    .iter()
+                    .chain(&words)
+                    .flat_map(|w| w.to_le_bytes()),
+            )
+            .chain(self.display.buffer().iter().copied());
+
+        bytes.fold(FNV_OFFSET, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+        })
+    }
+
+    /// Starts counting executions and memory accesses per address, if not already counting.
+    pub fn enable_coverage(&mut self) {
+        if self.coverage.is_none() {
+            self.coverage = Some(Box::new(Coverage::new(self.memory.len())));
+        }
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_deref()
+    }
+
+    pub fn coverage_mut(&mut self) -> Option<&mut Coverage> {
+        self.coverage.as_deref_mut()
+    }
+
+    /// Returns the raw opcode at the program counter, without advancing it.
+    pub fn peek_opcode(&self) -> u16 {
+        let pc = self.program_counter as usize;
+
+        match self.memory.get(pc..pc + 2) {
+            Some(&[hi, lo]) => u16::from(OpCode::from((hi, lo))),
+            _ => 0,
+        }
+    }
+
+    /// Checks whether the next instruction can be executed, without executing it.
+    pub fn fault(&self) -> Option<Fault> {
+        // The VIP runs whatever the interpreter makes of the program.
+        if self.vip.is_some() {
+            return None;
+        }
+
+        let address = self.program_counter;
+        if address as usize + 1 >= self.memory.len() {
+            return Some(Fault::OutOfBounds { address });
+        }
+
+        let opcode = self.peek_opcode();
+        if let Some(accessed) = self.platform_access(opcode) {
+            return (accessed.end > self.memory.len()).then_some(Fault::OutOfBounds { address });
+        }
+
+        let accessed = match Instruction::decode(opcode) {
+            // The hi-res interpreter's screen clearing machine code.
+            Some(Instruction::Sys(0x230)) if self.platform == Platform::Chip8HiRes => 0,
+            None | Some(Instruction::Sys(_)) => {
+                return Some(Fault::InvalidOpcode { address, opcode })
+            }
+            Some(Instruction::Call(_)) if self.stack_pointer as usize >= self.stack.len() => {
+                return Some(Fault::StackOverflow { address })
+            }
+            Some(Instruction::Ret) if self.stack_pointer == 0 => {
+                return Some(Fault::StackUnderflow { address })
+            }
+            Some(Instruction::Drw(_, _, n)) => n as usize,
+            Some(Instruction::LdBVx(_)) => 3,
+            Some(Instruction::LdMemVx(x) | Instruction::LdVxMem(x)) => x as usize + 1,
+            Some(_) => 0,
+        };
+
+        (self.index.get() + accessed > self.memory.len()).then_some(Fault::OutOfBounds { address })
+    }
+
+    /// The memory that `opcode` reads or writes, if it's one of the platform's own
+    /// instructions rather than CHIP-8's.
+    fn platform_access(&self, opcode: u16) -> Option<Range<usize>> {
+        let index = self.index.get();
+        let opcode = OpCode::from(opcode);
+
+        match (self.platform, opcode.nibbles()) {
+            (
+                Platform::Chip8X,
+                (0x00, 0x00, 0x0F, 0x00)
+                | (0x00, 0x02, 0x0A, 0x00)
+                | (0x0B, _, _, _)
+                | (0x0E, _, 0x0F, 0x02 | 0x05)
+                | (0x0F, _, 0x0F, 0x08 | 0x0B),
+            ) => Some(0..0),
+            (Platform::SuperChip | Platform::MegaChip, (0x0D, _, _, 0x00))
+                if self.display.palette().is_none() =>
+            {
+                Some(index..index + 32)
+            }
+            (Platform::MegaChip, (0x00, 0x01, _, _)) => {
+                // The low 16 bits of the address follow the instruction.
+                let pc = self.program_counter as usize;
+                Some(pc + 2..pc + 4)
+            }
+            (Platform::MegaChip, (0x00, 0x02, _, _)) => {
+                Some(index..index + opcode.kk() as usize * 4)
+            }
+            (Platform::MegaChip, (0x00, 0x06, 0x00, _)) => {
+                let size = Sample::size(self.memory.get(index..).unwrap_or_default());
+                Some(index..index + size)
+            }
+            (Platform::MegaChip, (0x00, 0x08, 0x00, n)) if Blend::from_mode(n).is_some() => {
+                Some(0..0)
+            }
+            (
+                Platform::SuperChip | Platform::MegaChip,
+                (0x00, 0x00, 0x0C, _)
+                | (0x00, 0x00, 0x0F, 0x0B..=0x0F)
+                | (0x0F, _, 0x03, 0x00)
+                | (0x0F, _, 0x07 | 0x08, 0x05),
+            ) => Some(0..0),
+            (
+                Platform::MegaChip,
+                (0x00, 0x00, 0x01, 0x00 | 0x01)
+                | (0x00, 0x03..=0x05 | 0x09, _, _)
+                | (0x00, 0x07, 0x00, 0x00),
+            ) => Some(0..0),
+            (_, (0x0D, _, _, _)) if self.display.palette().is_some() => {
+                let (width, height) = self.sprite_dimensions();
+                Some(index..index + width * height)
+            }
+            _ => None,
+        }
+    }
+
     fn next_opcode(&mut self) -> OpCode {
         // Opcodes are 2 bytes long.
         // `program_counter` must always point to at least 1 less than the last memory index,
         // to allow taking 2 bytes.
         assert!(
-            (self.program_counter as usize + 1) < MEMORY_SIZE_BYTES,
+            (self.program_counter as usize + 1) < self.memory.len(),
             "program counter too large ({})",
             self.program_counter
         );
@@ -145,6 +680,10 @@ impl Chip8 {
             self.memory[self.program_counter as usize + 1],
         ));
 
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_execution(self.program_counter as usize);
+        }
+
         self.program_counter += 2;
 
         opcode
@@ -157,6 +696,120 @@ impl Chip8 {
         self.display.clear();
     }
 
+    /// CHIP-8X: resets the background to blue.
+    #[allow(non_snake_case)]
+    fn op_00F0(&mut self) {
+        trace!("COLBG reset");
+        self.display.colors_mut().reset_background();
+    }
+
+    /// CHIP-8X: steps the background to the next colour.
+    #[allow(non_snake_case)]
+    fn op_02A0(&mut self) {
+        trace!("COLBG next");
+        self.display.colors_mut().cycle_background();
+    }
+
+    /// SUPER-CHIP: exits the interpreter. The program stops here.
+    #[allow(non_snake_case)]
+    fn op_00FD(&mut self) {
+        trace!("EXIT");
+        self.program_counter -= 2;
+    }
+
+    /// SUPER-CHIP: switches to the low resolution display.
+    #[allow(non_snake_case)]
+    fn op_00FE(&mut self) {
+        trace!("LOW");
+        self.hires = false;
+        self.display = self.schip_display();
+    }
+
+    /// SUPER-CHIP: switches to the 128x64 display.
+    #[allow(non_snake_case)]
+    fn op_00FF(&mut self) {
+        trace!("HIGH");
+        self.hires = true;
+        self.display = self.schip_display();
+    }
+
+    /// The SUPER-CHIP display at the resolution set by `00FE` and `00FF`.
+    fn schip_display(&self) -> Video {
+        if self.hires {
+            Video::new(HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            Video::new(self.layout.width, self.layout.height)
+        }
+    }
+
+    /// MEGA-CHIP: switches back to the SUPER-CHIP display.
+    #[allow(non_snake_case)]
+    fn op_0010(&mut self) {
+        trace!("MEGAOFF");
+        self.display = self.schip_display();
+    }
+
+    /// MEGA-CHIP: switches to the 256x192 display with 256 colours.
+    #[allow(non_snake_case)]
+    fn op_0011(&mut self) {
+        trace!("MEGAON");
+        self.display = Video::indexed(MEGA_WIDTH, MEGA_HEIGHT);
+    }
+
+    /// MEGA-CHIP: points I at the 24-bit address whose low 16 bits are the next word.
+    #[allow(non_snake_case)]
+    fn op_01nn(&mut self, opcode: OpCode) {
+        let pc = self.program_counter as usize;
+        let low = u16::from_be_bytes([self.memory[pc], self.memory[pc + 1]]);
+        let address = u32::from(opcode.kk()) << 16 | u32::from(low);
+        trace!("LDHI I, {address:#08x}");
+
+        self.index.load_long(address);
+        self.program_counter += 2;
+    }
+
+    /// MEGA-CHIP: loads nn colours, as ARGB bytes at I, into the palette from colour 1 on.
+    #[allow(non_snake_case)]
+    fn op_02nn(&mut self, opcode: OpCode) {
+        trace!("LDPAL {:?}", opcode);
+        let colors = self.index.get()..self.index.get() + opcode.kk() as usize * 4;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(colors.clone());
+        }
+        if let Some(palette) = self.display.palette_mut() {
+            palette.load(&self.memory[colors]);
+        }
+    }
+
+    /// MEGA-CHIP: sets how opaque the screen is.
+    #[allow(non_snake_case)]
+    fn op_05nn(&mut self, opcode: OpCode) {
+        trace!("ALPHA {:?}", opcode);
+        if let Some(palette) = self.display.palette_mut() {
+            palette.alpha = opcode.kk();
+        }
+    }
+
+    /// MEGA-CHIP: plays the digitised sound at I, over and over if n is 0.
+    #[allow(non_snake_case)]
+    fn op_060n(&mut self, opcode: OpCode) {
+        trace!("DIGISND {:?}", opcode);
+        let memory = &self.memory[self.index.get()..];
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(self.index.get()..self.index.get() + Sample::size(memory));
+        }
+
+        self.sample = Sample::read(memory, opcode.n() == 0, self.samples_started);
+        self.samples_started = self.samples_started.wrapping_add(1);
+    }
+
+    /// MEGA-CHIP: sets how sprites are blended with the screen.
+    #[allow(non_snake_case)]
+    fn op_080n(&mut self, opcode: OpCode) {
+        trace!("BMODE {:?}", opcode);
+        self.blend = Blend::from_mode(opcode.n()).unwrap_or_default();
+    }
+
     /// RET
     #[allow(non_snake_case)]
     fn op_00EE(&mut self) {
@@ -226,18 +879,36 @@ impl Chip8 {
     fn op_8xy1(&mut self, opcode: OpCode) {
         trace!("OR Vx, Vy {:?}", opcode);
         self.registers[opcode.x() as usize] |= self.registers[opcode.y() as usize];
+        self.reset_vf_quirk();
     }
 
     /// AND Vx, Vy
     fn op_8xy2(&mut self, opcode: OpCode) {
         trace!("AND Vx, Vy {:?}", opcode);
         self.registers[opcode.x() as usize] &= self.registers[opcode.y() as usize];
+        self.reset_vf_quirk();
     }
 
     /// XOR Vx, Vy
     fn op_8xy3(&mut self, opcode: OpCode) {
         trace!("XOR Vx, Vy {:?}", opcode);
         self.registers[opcode.x() as usize] ^= self.registers[opcode.y() as usize];
+        self.reset_vf_quirk();
+    }
+
+    fn reset_vf_quirk(&mut self) {
+        if self.quirks.vf_reset {
+            self.registers[0x0F] = 0;
+        }
+    }
+
+    /// The register that `SHR` and `SHL` shift, which depends on the `shift_vy` quirk.
+    fn shift_source(&self, opcode: OpCode) -> u8 {
+        if self.quirks.shift_vy {
+            self.registers[opcode.y() as usize]
+        } else {
+            self.registers[opcode.x() as usize]
+        }
     }
 
     #[allow(clippy::cast_possible_truncation)]
@@ -249,8 +920,8 @@ impl Chip8 {
 
         let (res, has_overflow) = vx.overflowing_add(vy);
 
-        self.registers[0x0F] = has_overflow as u8;
         self.registers[opcode.x() as usize] = res;
+        self.registers[0x0F] = has_overflow as u8;
     }
 
     /// SUB Vx, Vy
@@ -261,16 +932,16 @@ impl Chip8 {
 
         let (res, has_overflow) = vx.overflowing_sub(vy);
 
-        self.registers[0x0F] = !has_overflow as u8;
         self.registers[opcode.x() as usize] = res;
+        self.registers[0x0F] = !has_overflow as u8;
     }
 
     /// SHR Vx
     fn op_8xy6(&mut self, opcode: OpCode) {
         trace!("SHR Vx {:?}", opcode);
-        let vx = self.registers[opcode.x() as usize];
-        self.registers[0x0F] = vx & 0b0000_0001;
+        let vx = self.shift_source(opcode);
         self.registers[opcode.x() as usize] = vx >> 1;
+        self.registers[0x0F] = vx & 0b0000_0001;
     }
 
     /// SUBN Vx, Vy
@@ -281,17 +952,17 @@ impl Chip8 {
 
         let (res, has_overflow) = vy.overflowing_sub(vx);
 
-        self.registers[0x0F] = !has_overflow as u8;
         self.registers[opcode.x() as usize] = res;
+        self.registers[0x0F] = !has_overflow as u8;
     }
 
     /// SHL Vx
     #[allow(non_snake_case)]
     fn op_8xyE(&mut self, opcode: OpCode) {
         trace!("SHL Vx {:?}", opcode);
-        let vx = self.registers[opcode.x() as usize];
-        self.registers[0x0F] = (vx >> 7) & 0b0000_0001;
+        let vx = self.shift_source(opcode);
         self.registers[opcode.x() as usize] = vx << 1;
+        self.registers[0x0F] = (vx >> 7) & 0b0000_0001;
     }
 
     /// SNE Vx,Vy
@@ -315,7 +986,45 @@ impl Chip8 {
     #[allow(clippy::cast_possible_truncation)]
     fn op_Bnnn(&mut self, opcode: OpCode) {
         trace!("JP V0, addr {:?}", opcode);
-        self.program_counter = (self.registers[0] + (opcode.nnn() as u8)) as u16;
+        if self.quirks.jump_vx {
+            // `xnn + Vx` can run past the end of memory, so it wraps around.
+            let address = opcode.nnn() as usize + self.registers[opcode.x() as usize] as usize;
+            self.program_counter = (address % self.memory.len()) as u16;
+        } else {
+            self.program_counter = (self.registers[0] + (opcode.nnn() as u8)) as u16;
+        }
+    }
+
+    /// CHIP-8X: colours zones of 8x4 pixels with Vy. The low nibbles of Vx and V(x+1) are
+    /// the first column and row of zones, and their high nibbles how many more to colour.
+    #[allow(non_snake_case)]
+    fn op_Bxy0(&mut self, opcode: OpCode) {
+        trace!("COL Vx, Vy {:?}", opcode);
+        let x = opcode.x() as usize;
+        let (horizontal, vertical) = (self.registers[x], self.registers[(x + 1) % 16]);
+        let color = self.registers[opcode.y() as usize];
+
+        let span =
+            |byte: u8| (byte & 0xF) as usize..(byte & 0xF) as usize + (byte >> 4) as usize + 1;
+        let rows = span(vertical);
+        self.display
+            .colors_mut()
+            .fill(span(horizontal), rows.start * 4..rows.end * 4, color);
+    }
+
+    /// CHIP-8X: colours the zone of 8 pixels around (Vx, V(x+1)) with Vy, and those of the
+    /// n - 1 rows below.
+    #[allow(non_snake_case)]
+    fn op_BxyN(&mut self, opcode: OpCode) {
+        trace!("COL Vx, Vy, n {:?}", opcode);
+        let x = opcode.x() as usize;
+        let column = self.registers[x] as usize % self.display.width() / ZONE_WIDTH;
+        let row = self.registers[(x + 1) % 16] as usize % self.display.height();
+        let color = self.registers[opcode.y() as usize];
+
+        self.display
+            .colors_mut()
+            .fill(column..column + 1, row..row + opcode.n() as usize, color);
     }
 
     /// RND Vx, byte, rand
@@ -331,15 +1040,65 @@ impl Chip8 {
     #[allow(non_snake_case)]
     fn op_Dxyn(&mut self, opcode: OpCode) {
         trace!("DRW Vx, Vy, nibble {:?}", opcode);
-        let pos_x = opcode.x();
-        let pos_y = opcode.y();
-        let coords = DrawCoords::new(pos_x, pos_y);
+        let pos_x = self.registers[opcode.x() as usize];
+        let pos_y = self.registers[opcode.y() as usize];
+        let coords = self.display.coords(pos_x, pos_y);
 
         let height = opcode.n() as usize;
-        self.display.draw(
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(self.index.get()..self.index.get() + height);
+        }
+        let collision = self.display.draw(
             &self.memory[self.index.get()..(self.index.get() + height)],
             &coords,
         );
+        self.registers[0x0F] = collision as u8;
+    }
+
+    /// SUPER-CHIP: draws a 16x16 sprite, two bytes per row.
+    #[allow(non_snake_case)]
+    fn op_Dxy0(&mut self, opcode: OpCode) {
+        trace!("DRW Vx, Vy, 0 {:?}", opcode);
+        let pos_x = self.registers[opcode.x() as usize];
+        let pos_y = self.registers[opcode.y() as usize];
+        let coords = self.display.coords(pos_x, pos_y);
+
+        let sprite = self.index.get()..self.index.get() + 32;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(sprite.clone());
+        }
+        let collision = self.display.draw_large(&self.memory[sprite], &coords);
+        self.registers[0x0F] = collision as u8;
+    }
+
+    /// MEGA-CHIP: draws a sprite of the size set by `03nn` and `04nn` from the colour
+    /// indices at I. VF is set if it's drawn over the colour set by `09nn`.
+    #[allow(non_snake_case)]
+    fn op_Dxyn_indexed(&mut self, opcode: OpCode) {
+        trace!("DRW Vx, Vy {:?}", opcode);
+        let pos_x = self.registers[opcode.x() as usize];
+        let pos_y = self.registers[opcode.y() as usize];
+        let coords = self.display.coords(pos_x, pos_y);
+
+        let (width, height) = self.sprite_dimensions();
+        let sprite = self.index.get()..self.index.get() + width * height;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(sprite.clone());
+        }
+        let collision = self.display.draw_indexed(
+            &self.memory[sprite],
+            width,
+            &coords,
+            self.blend,
+            self.collision_color,
+        );
+        self.registers[0x0F] = collision as u8;
+    }
+
+    /// The width and height of MEGA-CHIP sprites.
+    fn sprite_dimensions(&self) -> (usize, usize) {
+        let pixels = |size: u8| if size == 0 { 256 } else { size as usize };
+        (pixels(self.sprite_size.0), pixels(self.sprite_size.1))
     }
 
     /// SKP Vx
@@ -366,6 +1125,28 @@ impl Chip8 {
         }
     }
 
+    /// CHIP-8X: skips the next instruction if key Vx of the second keypad is pressed.
+    #[allow(non_snake_case)]
+    fn op_ExF2(&mut self, opcode: OpCode) {
+        trace!("SKP2 Vx {:?}", opcode);
+        let key = self.registers[opcode.x() as usize] & 0xF;
+
+        if self.second_keypad.is_key_pressed(key) {
+            self.program_counter += 2;
+        }
+    }
+
+    /// CHIP-8X: skips the next instruction if key Vx of the second keypad isn't pressed.
+    #[allow(non_snake_case)]
+    fn op_ExF5(&mut self, opcode: OpCode) {
+        trace!("SKNP2 Vx {:?}", opcode);
+        let key = self.registers[opcode.x() as usize] & 0xF;
+
+        if !self.second_keypad.is_key_pressed(key) {
+            self.program_counter += 2;
+        }
+    }
+
     /// LD Vx, DT
     #[allow(non_snake_case)]
     fn op_Fx07(&mut self, opcode: OpCode) {
@@ -418,7 +1199,17 @@ impl Chip8 {
         let digit = self.registers[opcode.x() as usize];
 
         self.index
-            .load((FONTSET_START_ADDR + (5 * digit as usize)) as u16);
+            .load(self.layout.font_start + (FONT_GLYPH_BYTES * digit as usize) as u16);
+    }
+
+    /// SUPER-CHIP: points I at the large digit in Vx.
+    #[allow(non_snake_case)]
+    fn op_Fx30(&mut self, opcode: OpCode) {
+        trace!("LD HF, Vx {:?}", opcode);
+        let digit = self.registers[opcode.x() as usize] % 10;
+
+        self.index
+            .load(self.big_font_start() + (BIG_FONT_GLYPH_BYTES * digit as usize) as u16);
     }
 
     /// LD B, Vx
@@ -426,6 +1217,9 @@ impl Chip8 {
     fn op_Fx33(&mut self, opcode: OpCode) {
         trace!("LD B, Vx {:?}", opcode);
         let mut val = self.registers[opcode.x() as usize];
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_write(self.index.get()..self.index.get() + 3);
+        }
 
         for i in (0..3).rev() {
             self.memory[self.index.get() + i] = val % 10;
@@ -438,10 +1232,17 @@ impl Chip8 {
     fn op_Fx55(&mut self, opcode: OpCode) {
         trace!("LD [I], Vx {:?}", opcode);
         let x = opcode.x() as usize;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_write(self.index.get()..self.index.get() + x + 1);
+        }
 
         for i in 0..=x {
             self.memory[self.index.get() + i] = self.registers[i];
         }
+
+        if self.quirks.load_store_i {
+            self.index += (x + 1) as u8;
+        }
     }
 
     /// LD Vx, I
@@ -449,10 +1250,47 @@ impl Chip8 {
     fn op_Fx65(&mut self, opcode: OpCode) {
         trace!("LD Vx, I {:?}", opcode);
         let x = opcode.x() as usize;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record_read(self.index.get()..self.index.get() + x + 1);
+        }
 
         for i in 0..=x {
             self.registers[i] = self.memory[self.index.get() + i];
         }
+
+        if self.quirks.load_store_i {
+            self.index += (x + 1) as u8;
+        }
+    }
+
+    /// SUPER-CHIP: saves V0 to Vx in the RPL user flags.
+    #[allow(non_snake_case)]
+    fn op_Fx75(&mut self, opcode: OpCode) {
+        trace!("LD R, Vx {:?}", opcode);
+        let x = opcode.x() as usize;
+        self.rpl_flags[..=x].copy_from_slice(&self.registers[..=x]);
+    }
+
+    /// SUPER-CHIP: restores V0 to Vx from the RPL user flags.
+    #[allow(non_snake_case)]
+    fn op_Fx85(&mut self, opcode: OpCode) {
+        trace!("LD Vx, R {:?}", opcode);
+        let x = opcode.x() as usize;
+        self.registers[..=x].copy_from_slice(&self.rpl_flags[..=x]);
+    }
+
+    /// CHIP-8X: sends Vx to the I/O port.
+    #[allow(non_snake_case)]
+    fn op_FxF8(&mut self, opcode: OpCode) {
+        trace!("OUT Vx {:?}", opcode);
+        self.io_output = self.registers[opcode.x() as usize];
+    }
+
+    /// CHIP-8X: reads the I/O port into Vx.
+    #[allow(non_snake_case)]
+    fn op_FxFB(&mut self, opcode: OpCode) {
+        trace!("IN Vx {:?}", opcode);
+        self.registers[opcode.x() as usize] = self.io_input;
     }
 }
 
@@ -460,6 +1298,10 @@ impl Chip8 {
 mod test {
     use super::*;
 
+    const DEFAULT_LAYOUT: MemoryLayout = MemoryLayout::PRESETS[0].1;
+    const MAX_ROM_SIZE_BYTES: usize = DEFAULT_LAYOUT.max_rom_size();
+    const FONTSET_START_ADDR: usize = DEFAULT_LAYOUT.font_start as usize;
+
     #[test]
     fn pc_starts_at_correct_address() {
         let c = Chip8::default();
@@ -504,6 +1346,42 @@ mod test {
         );
     }
 
+    #[test]
+    fn other_layouts() {
+        let eti660: MemoryLayout = "eti660".parse().unwrap();
+        let mut c = Chip8::new(eti660);
+        assert!(c.load_rom(&vec![0; 0x1000 - 0x600 + 1]).is_err());
+        c.load_rom(&[0xD0, 0x05]).unwrap();
+        assert_eq!(0x600, c.program_counter);
+        assert_eq!(0xD0, c.memory[0x600]);
+        assert_eq!((64, 48), (c.screen().width, c.screen().height));
+
+        // Draws at (0, 40), which a 64x32 display would wrap to (0, 8).
+        c.registers[1] = 40;
+        c.index.load(eti660.font_start);
+        c.op_Dxyn(OpCode::from((0xD0, 0x15)));
+        assert_ne!(0, c.screen().pixels[40 * 64]);
+        assert_eq!(0, c.registers[0x0F]);
+        c.op_Dxyn(OpCode::from((0xD0, 0x15)));
+        assert_eq!(1, c.registers[0x0F]);
+
+        c.program_counter = 0x700;
+        c.reset();
+        assert_eq!(0x600, c.program_counter);
+
+        let big: MemoryLayout = "memory=0x10000,font=0x0".parse().unwrap();
+        let mut c = Chip8::new(big);
+        c.load_rom(&vec![1; 0x8000]).unwrap();
+        assert_eq!(0x10000, c.memory.len());
+        assert_eq!(FONT_SET, c.memory[..FONT_SET.len()]);
+        c.registers[0] = 2;
+        c.op_Fx29(OpCode::from((0xF0, 0x29)));
+        assert_eq!(10, c.index.get());
+
+        let mut c = Chip8::new("start=0x200,font=0x200".parse().unwrap());
+        assert!(c.load_rom(&[0x00, 0xE0]).is_err());
+    }
+
     #[test]
     fn load_font_set() {
         let c = Chip8::default();
@@ -537,6 +1415,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn call_addr() {
         let mut c = Chip8::default();
         c.program_counter = 0xFED;
@@ -929,6 +1808,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::identity_op)]
     fn load_registers() {
         let mut c = Chip8::default();
         let opcode = OpCode::from((0xF3, 0x65));
@@ -943,4 +1823,511 @@ mod test {
 
         assert_eq!([0xDE, 0xAD, 0xBE, 0xEF], c.registers[0..=3]);
     }
+
+    #[test]
+    fn coverage_counts() {
+        let mut c = Chip8::default();
+        // 200: LD I, 0x300; 202: DRW V0, V0, 2; 204: JP 0x200
+        c.load_rom(&[0xA3, 0x00, 0xD0, 0x02, 0x12, 0x00]).unwrap();
+        c.enable_coverage();
+
+        for _ in 0..4 {
+            c.tick();
+        }
+
+        let coverage = c.coverage().unwrap();
+        assert_eq!(2, coverage.executions()[0x200]);
+        assert_eq!(1, coverage.executions()[0x202]);
+        assert_eq!([1, 1, 0], coverage.reads()[0x300..0x303]);
+        assert!(coverage.writes().iter().all(|&w| w == 0));
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let mut c = Chip8::default();
+        // LD I, 0x300; LD V0, 7; LD V1, 9; LD [I], V1; LD V0, 0; LD V1, 0; LD V1, [I]
+        c.load_rom(&[
+            0xA3, 0x00, 0x60, 0x07, 0x61, 0x09, 0xF1, 0x55, 0x60, 0x00, 0x61, 0x00, 0xF1, 0x65,
+        ])
+        .unwrap();
+
+        for _ in 0..4 {
+            c.tick();
+        }
+        assert_eq!([7, 9, 0], c.memory[0x300..0x303]);
+
+        for _ in 0..3 {
+            c.tick();
+        }
+        assert_eq!([7, 9], c.registers[..2]);
+        assert_eq!(0x300, c.index.get());
+    }
+
+    #[test]
+    fn quirk_shift_vy() {
+        let mut c = Chip8::default();
+        c.quirks.shift_vy = true;
+
+        c.registers[0x00] = 0xFF;
+        c.registers[0x01] = 0b1000_0010;
+
+        c.op_8xy6(OpCode::from((0x80, 0x16)));
+        assert_eq!(0b0100_0001, c.registers[0x00]);
+        assert_eq!(0, c.registers[0x0F]);
+
+        c.op_8xyE(OpCode::from((0x80, 0x1E)));
+        assert_eq!(0b0000_0100, c.registers[0x00]);
+        assert_eq!(1, c.registers[0x0F]);
+        assert_eq!(0b1000_0010, c.registers[0x01]);
+    }
+
+    #[test]
+    fn quirk_load_store_i() {
+        let mut c = Chip8::default();
+        c.index.load(0x300);
+
+        c.op_Fx55(OpCode::from((0xF2, 0x55)));
+        assert_eq!(0x300, c.index.get());
+
+        c.quirks.load_store_i = true;
+        c.op_Fx55(OpCode::from((0xF2, 0x55)));
+        assert_eq!(0x303, c.index.get());
+        c.op_Fx65(OpCode::from((0xF1, 0x65)));
+        assert_eq!(0x305, c.index.get());
+    }
+
+    #[test]
+    fn quirk_jump_vx() {
+        let mut c = Chip8::default();
+        c.quirks.jump_vx = true;
+
+        c.registers[0] = 0x10;
+        c.registers[2] = 0x04;
+        c.op_Bnnn(OpCode::from((0xB2, 0x34)));
+        assert_eq!(0x238, c.program_counter);
+
+        // Wraps around the end of memory.
+        c.registers[0xF] = 0xFF;
+        c.op_Bnnn(OpCode::from((0xBF, 0xFF)));
+        assert_eq!(0x0FE, c.program_counter);
+    }
+
+    #[test]
+    fn quirk_vf_reset() {
+        let mut c = Chip8::default();
+
+        c.registers[0x0F] = 5;
+        c.op_8xy1(OpCode::from((0x80, 0x11)));
+        assert_eq!(5, c.registers[0x0F]);
+
+        c.quirks.vf_reset = true;
+        let ops: [fn(&mut Chip8, OpCode); 3] = [Chip8::op_8xy1, Chip8::op_8xy2, Chip8::op_8xy3];
+        for op in ops {
+            c.registers[0x0F] = 5;
+            op(&mut c, OpCode::from((0x80, 0x11)));
+            assert_eq!(0, c.registers[0x0F]);
+        }
+    }
+
+    #[test]
+    fn faults() {
+        let mut c = Chip8::default();
+        c.load_rom(&[0x00, 0xEE, 0x51, 0x21, 0xF2, 0x55]).unwrap();
+        assert_eq!(Some(Fault::StackUnderflow { address: 0x200 }), c.fault());
+
+        c.program_counter = 0x202;
+        assert_eq!(
+            Some(Fault::InvalidOpcode {
+                address: 0x202,
+                opcode: 0x5121
+            }),
+            c.fault()
+        );
+
+        c.program_counter = 0x204;
+        c.index.load(0xFFD);
+        assert_eq!(None, c.fault());
+        c.index.load(0xFFE);
+        assert_eq!(Some(Fault::OutOfBounds { address: 0x204 }), c.fault());
+    }
+
+    #[test]
+    fn flag_written_after_result() {
+        let mut c = Chip8::default();
+        // LD VF, 3; LD V1, 5; SUB VF, V1; LD V2, 0x81; SHR VF, V2 with shift_vy.
+        c.load_rom(&[0x6F, 0x03, 0x61, 0x05, 0x8F, 0x15, 0x62, 0x81, 0x8F, 0x26])
+            .unwrap();
+        c.quirks.shift_vy = true;
+        for _ in 0..3 {
+            c.tick();
+        }
+        assert_eq!(0, c.registers[0xF]);
+        c.tick();
+        c.tick();
+        assert_eq!(1, c.registers[0xF]);
+    }
+
+    #[test]
+    fn drw_reads_coordinates_from_registers() {
+        let mut c = Chip8::default();
+        // LD V3, 10; LD V4, 5; LD I, 0x300; DRW V3, V4, 1
+        c.load_rom(&[0x63, 0x0A, 0x64, 0x05, 0xA3, 0x00, 0xD3, 0x41])
+            .unwrap();
+        c.memory[0x300] = 0b1000_0001;
+        for _ in 0..4 {
+            c.tick();
+        }
+
+        let lit: Vec<_> = (0..c.screen().pixels.len())
+            .filter(|&i| c.screen().pixels[i] != 0)
+            .collect();
+        assert_eq!(vec![5 * 64 + 10, 5 * 64 + 17], lit);
+    }
+
+    #[test]
+    fn drw_sets_vf_on_collision() {
+        let mut c = Chip8::default();
+        // LD VF, 7; LD I, 0x300; DRW V0, V1, 1; DRW V0, V1, 1; DRW V0, V1, 1
+        c.load_rom(&[0x6F, 0x07, 0xA3, 0x00, 0xD0, 0x11, 0xD0, 0x11, 0xD0, 0x11])
+            .unwrap();
+        c.memory[0x300] = 0xFF;
+
+        for _ in 0..3 {
+            c.tick();
+        }
+        assert_eq!(0, c.registers[0xF]);
+        c.tick();
+        assert_eq!(1, c.registers[0xF]);
+        c.tick();
+        assert_eq!(0, c.registers[0xF]);
+    }
+
+    #[test]
+    fn hires() {
+        let platform = Platform::Chip8HiRes;
+        let mut c = Chip8::new(platform.layout());
+        c.platform = platform;
+        let mut rom = vec![0; 0x66];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        rom[0x60..].copy_from_slice(&[0xD0, 0x15, 0x02, 0x30, 0x12, 0x64]);
+        c.load_rom(&rom).unwrap();
+        c.registers[1] = 60;
+        c.index.load(0x50);
+
+        c.tick();
+        c.tick();
+        assert_eq!((64, 64), (c.screen().width, c.screen().height));
+        assert_ne!(0, c.screen().pixels[60 * 64]);
+        assert_eq!(None, c.fault());
+        c.tick();
+        assert!(c.screen().pixels.iter().all(|&p| p == 0));
+
+        c.platform = Platform::Chip8;
+        c.program_counter = 0x262;
+        assert_eq!(
+            Some(Fault::InvalidOpcode {
+                address: 0x262,
+                opcode: 0x0230
+            }),
+            c.fault()
+        );
+    }
+
+    #[test]
+    fn chip8x() {
+        let platform = Platform::Chip8X;
+        let mut c = Chip8::new(platform.layout());
+        c.platform = platform;
+        #[rustfmt::skip]
+        c.load_rom(&[
+            0x02, 0xA0, // next background
+            0xB1, 0x30, // colour zones
+            0xB1, 0x33, // colour rows
+            0xE4, 0xF2, // skip if key V4 of the second keypad is pressed
+            0x00, 0x00,
+            0xF3, 0xF8, // output V3
+            0xF5, 0xFB, // input into V5
+            0x00, 0xF0, // reset the background
+        ])
+        .unwrap();
+        c.registers[1..=4].copy_from_slice(&[0x10, 0x01, 5, 3]);
+        c.set_second_keys(1 << 3);
+        c.io_input = 0x42;
+
+        c.tick();
+        assert_eq!(1, c.screen().background_index());
+        c.tick();
+        let colors = c.display.colors();
+        assert_eq!(5, colors.foreground(15, 4));
+        assert_eq!(5, colors.foreground(0, 7));
+        assert_eq!(1, colors.foreground(16, 4));
+        assert_eq!(1, colors.foreground(0, 8));
+        assert_eq!(None, c.fault());
+        c.tick();
+        let colors = c.display.colors();
+        assert_eq!(5, colors.foreground(16, 1));
+        assert_eq!(5, colors.foreground(23, 3));
+        assert_eq!(1, colors.foreground(16, 0));
+        assert_eq!(1, colors.foreground(24, 1));
+        c.tick();
+        assert_eq!(0x30A, c.program_counter);
+        c.tick();
+        c.tick();
+        assert_eq!((5, 0x42), (c.io_output, c.registers[5]));
+        c.tick();
+        assert_eq!(0, c.screen().background_index());
+
+        c.platform = Platform::Chip8;
+        assert!(matches!(c.screen().colors, ScreenColors::Palette));
+        c.program_counter = 0x306;
+        assert!(c.fault().is_some());
+    }
+
+    #[test]
+    fn megachip() {
+        let platform = Platform::MegaChip;
+        let mut c = Chip8::new(platform.layout());
+        c.set_platform(platform);
+        #[rustfmt::skip]
+        c.load_rom(&[
+            0x00, 0x11, // switch to 256x192
+            0x01, 0x01, 0x00, 0x00, // I = 0x10000
+            0x02, 0x02, // load 2 colours
+            0x03, 0x02, // 2 pixels wide
+            0x04, 0x02, // and high
+            0x01, 0x01, 0x00, 0x08,
+            0xD0, 0x10,
+            0x09, 0x01, // collide with colour 1
+            0xD0, 0x10,
+            0x01, 0x01, 0x00, 0x0C,
+            0x06, 0x01, // play once
+            0x07, 0x00, // stop
+            0x00, 0x10, // switch back
+        ])
+        .unwrap();
+        #[rustfmt::skip]
+        c.memory[0x10000..0x10014].copy_from_slice(&[
+            0xFF, 0xFF, 0x00, 0x00, 0xFF, 0x00, 0xFF, 0x00, // red, green
+            0x01, 0x02, 0x00, 0x01, // sprite
+            0x1F, 0x40, 0x00, 0x00, 0x02, 0x00, 0x80, 0xFF, // sample
+        ]);
+
+        c.tick();
+        assert_eq!((256, 192), (c.screen().width, c.screen().height));
+        assert_eq!(None, c.fault());
+        c.tick();
+        assert_eq!(0x10000, c.index.get());
+        c.tick();
+        let screen = c.screen();
+        let colors = screen.color_table(&Palette::default());
+        assert_eq!([[0, 0, 0], [0xFF, 0, 0], [0, 0xFF, 0]], colors[..3]);
+
+        for _ in 0..4 {
+            c.tick();
+        }
+        assert_eq!(0, c.registers[0xF]);
+        assert_eq!([1, 2], c.screen().pixels[..2]);
+        assert_eq!([0, 1], c.screen().pixels[256..258]);
+        c.tick();
+        c.tick();
+        assert_eq!(1, c.registers[0xF]);
+
+        c.tick();
+        assert_eq!(None, c.fault());
+        c.tick();
+        let sample = c.sample().unwrap();
+        assert_eq!((8000, false), (sample.rate, sample.looping));
+        assert_eq!(vec![0x80, 0xFF], sample.data);
+        c.tick();
+        assert_eq!(None, c.sample());
+        c.tick();
+        assert!(matches!(c.screen().colors, ScreenColors::Palette));
+        assert_eq!(64, c.screen().width);
+
+        c.index.load_long(c.memory.len() as u32 - 2);
+        c.program_counter = 0x206;
+        assert_eq!(Some(Fault::OutOfBounds { address: 0x206 }), c.fault());
+    }
+
+    #[test]
+    fn megachip_schip_instructions() {
+        let platform = Platform::MegaChip;
+        let mut c = Chip8::new(platform.layout());
+        c.set_platform(platform);
+        #[rustfmt::skip]
+        c.load_rom(&[
+            0x00, 0xFF, // switch to 128x64
+            0x60, 0x07, // V0 = 7
+            0xF0, 0x30, // I = the large 7
+            0xD1, 0x10, // draw a 16x16 sprite
+            0x00, 0xC2, // scroll down 2
+            0x00, 0xFB, // scroll right 4
+            0x00, 0xFC, // scroll left 4
+            0xF0, 0x75, // save V0
+            0x60, 0x00,
+            0xF0, 0x85, // restore V0
+            0x00, 0x11, // switch to 256x192
+            0x00, 0x10, // and back to 128x64
+            0x00, 0xFE, // switch to 64x32
+            0x00, 0xFD, // exit
+        ])
+        .unwrap();
+        let big_font = c.layout().font_start as usize + FONT_SET.len();
+        assert_eq!(
+            BIG_FONT_SET,
+            c.memory[big_font..big_font + BIG_FONT_SET.len()]
+        );
+
+        c.tick();
+        assert_eq!((128, 64), (c.screen().width, c.screen().height));
+        assert_eq!(None, c.fault());
+        c.tick();
+        c.tick();
+        assert_eq!(big_font + 7 * BIG_FONT_GLYPH_BYTES, c.index.get());
+        assert_eq!(None, c.fault());
+        c.tick();
+        assert_eq!(0, c.registers[0xF]);
+        // The large 7's first two bytes, 0xFF and 0xFF, make up the sprite's first row.
+        let lit = |c: &Chip8, row: usize| {
+            let pixels = &c.screen().pixels[row * 128..(row + 1) * 128];
+            pixels.iter().filter(|&&pixel| pixel != 0).count()
+        };
+        assert_eq!(16, lit(&c, 0));
+
+        c.tick();
+        assert_eq!((0, 16), (lit(&c, 0), lit(&c, 2)));
+        c.tick();
+        assert_eq!(0, c.screen().pixels[2 * 128 + 3]);
+        assert_ne!(0, c.screen().pixels[2 * 128 + 4]);
+        c.tick();
+        assert_ne!(0, c.screen().pixels[2 * 128]);
+
+        for _ in 0..3 {
+            c.tick();
+        }
+        assert_eq!(7, c.registers[0]);
+
+        c.tick();
+        c.tick();
+        assert_eq!((128, 64), (c.screen().width, c.screen().height));
+        c.tick();
+        assert_eq!((64, 32), (c.screen().width, c.screen().height));
+
+        assert_eq!(None, c.fault());
+        let pc = c.program_counter;
+        c.tick();
+        c.tick();
+        assert_eq!(pc, c.program_counter);
+
+        c.reset();
+        c.program_counter = pc;
+        c.platform = Platform::Chip8;
+        assert!(c.fault().is_some());
+    }
+
+    #[test]
+    fn superchip() {
+        let platform = Platform::SuperChip;
+        let mut c = Chip8::new(platform.layout());
+        c.set_platform(platform);
+        #[rustfmt::skip]
+        c.load_rom(&[
+            0x00, 0xFF, // switch to 128x64
+            0x60, 0x07, // V0 = 7
+            0xF0, 0x30, // I = the large 7
+            0xD1, 0x10, // draw a 16x16 sprite
+            0xF0, 0x75, // save V0
+            0x00, 0x11, // MEGA-CHIP's 256x192, which SUPER-CHIP doesn't have
+        ])
+        .unwrap();
+        let big_font = c.layout().font_start as usize + FONT_SET.len();
+        assert_eq!(
+            BIG_FONT_SET,
+            c.memory[big_font..big_font + BIG_FONT_SET.len()]
+        );
+
+        for _ in 0..4 {
+            assert_eq!(None, c.fault());
+            c.tick();
+        }
+        assert_eq!((128, 64), (c.screen().width, c.screen().height));
+        assert_eq!(big_font + 7 * BIG_FONT_GLYPH_BYTES, c.index.get());
+        assert_eq!(
+            16,
+            c.screen().pixels[..128].iter().filter(|&&p| p != 0).count()
+        );
+
+        assert_eq!(None, c.fault());
+        c.tick();
+        assert_eq!(7, c.rpl_flags[0]);
+        assert!(c.fault().is_some());
+    }
+
+    #[test]
+    fn reset_keeps_memory() {
+        let mut c = Chip8::default();
+        // LD V0, 0x12; LD [I], V0 with I pointing into the second instruction.
+        c.load_rom(&[0x60, 0x12, 0xF0, 0x55]).unwrap();
+        c.index.load(0x203);
+        c.tick();
+        c.tick();
+        assert_eq!(0x12, c.memory[0x203]);
+
+        c.reset();
+        assert_eq!(PROG_CTR_START_ADDR, c.program_counter);
+        assert_eq!([0; 16], c.registers);
+        assert_eq!(0, c.index.get());
+        assert_eq!(0x12, c.memory[0x203]);
+    }
+
+    #[test]
+    fn vip() {
+        #[rustfmt::skip]
+        let monitor = vec![
+            // Leave 0x0000 for RAM, and run the interpreter.
+            0xC0, 0x80, 0x03, 0xC0, 0x00, 0x00, 0, 0, 0, 0, 0, 0, 0, 0,
+            // 00E: return from the interrupt.
+            0x72, 0x70,
+            // 010: save T and D, point R0 at the display, and wait for the first line.
+            0x22, 0x78, 0x22, 0x52, 0x9B, 0xB0, 0xF8, 0x00, 0xA0, 0xC4, 0xC4, 0xC4, 0xE2,
+            // 01D: show each row on 4 lines, until R0 wraps to the next page.
+            0xA0, 0xE2, 0xE2, 0xA0, 0xE2, 0xE2, 0xA0, 0xE2, 0xE2, 0xE2, 0x80, 0x3A, 0x1D,
+            0x30, 0x0E,
+        ];
+        #[rustfmt::skip]
+        let interpreter = vec![
+            // R1 = 0x8010, RB.1 = 0x0F, R2 = 0x0ECF, R4 = 0x0020
+            0xF8, 0x80, 0xB1, 0xF8, 0x10, 0xA1, 0xF8, 0x0F, 0xBB,
+            0xF8, 0x0E, 0xB2, 0xF8, 0xCF, 0xA2, 0xF8, 0x20, 0xA4,
+            // Turn the display on, and start the fetch loop, which never fetches anything.
+            0xE2, 0x69, 0xD4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+            0x30, 0x20,
+        ];
+
+        let mut c = Chip8::default();
+        c.load_rom(&[0x12, 0x00]).unwrap();
+        c.attach_vip(VipImages {
+            monitor,
+            interpreter,
+        })
+        .unwrap();
+        assert_eq!(None, c.fault());
+        assert_eq!(Timing::Vip, c.timing);
+
+        c.tick();
+        assert!(c.vip().unwrap().at_instruction());
+        c.memory[0xF00] = 0x80;
+        c.memory[0xFFF] = 0x01;
+        for _ in 0..3 {
+            c.tick();
+        }
+
+        let vip = c.vip().unwrap();
+        assert!(vip.video.frames() >= 2);
+        assert_eq!((0x8010, true), (vip.cpu.r[1], vip.cpu.ie));
+        let pixels = c.screen().pixels;
+        assert_eq!(0xFF, pixels[0]);
+        assert_eq!(0, pixels[1]);
+        assert_eq!(0xFF, pixels[64 * 32 - 1]);
+    }
 }
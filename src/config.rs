@@ -0,0 +1,398 @@
+//! Where configuration files live, the config file: defaults for command-line options and
+//! overrides for single ROMs, and the state file: what the program remembers between runs.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, TableLike};
+
+use crate::{
+    cosmac::Backend,
+    gamepad::GamepadMapping,
+    keymap::Keymap,
+    layout::MemoryLayout,
+    palette::Palette,
+    quirks::{Platform, Quirks},
+    timing::Timing,
+    ui::DebugInterfaceSettings,
+};
+
+/// How many ROMs [`State::recent_roms`] keeps.
+pub const RECENT_ROMS: usize = 10;
+
+/// `$XDG_CONFIG_HOME/patata`, falling back to `~/.config/patata`, or `%APPDATA%\patata` on
+/// Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    let non_empty = |var| std::env::var_os(var).filter(|v| !v.is_empty());
+
+    non_empty("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| non_empty("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .or_else(|| non_empty("APPDATA").map(PathBuf::from))
+        .map(|dir| dir.join("patata"))
+}
+
+/// Defaults for command-line options, which win over them, and overrides of those defaults
+/// for ROMs with the given SHA-1:
+///
+/// ```toml
+/// platform = "schip"
+/// ipf = 30
+/// palette = "amber"
+/// keymap = "1234qwerasdfzxcv"
+/// scale = 6
+/// rom-db = "/usr/share/chip-8-database/programs.json"
+///
+/// [roms.0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b]
+/// name = "pong.ch8"
+/// platform = "chip8"
+/// ipf = 11
+///
+/// [roms.0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b.gamepad]
+/// dpup = "1"
+/// dpdown = "4"
+///
+/// [roms.5b0cd5a5a4e1a1b1d1b7c6a0f6f0b5e9b3c3c4d2]
+/// name = "etipong.ch8"
+/// layout = "eti660"
+/// ```
+///
+/// The program only writes to the file when settings are saved from the debugger, and then
+/// only changes the settings saved, keeping comments and the rest of the file as it was.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub layout: Option<MemoryLayout>,
+    /// Instructions per frame.
+    pub ipf: Option<u32>,
+    pub timing: Option<Timing>,
+    pub backend: Option<Backend>,
+    /// The COSMAC VIP's monitor ROM and CHIP-8 interpreter, for the `vip` backend.
+    pub vip_rom: Option<PathBuf>,
+    pub vip_interpreter: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub scale: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+    pub rom_db: Option<PathBuf>,
+    /// The gamepad mapping of ROMs without their own.
+    pub gamepad: Option<GamepadMapping>,
+    /// Overrides by ROM SHA-1.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub roms: BTreeMap<String, RomConfig>,
+}
+
+/// Settings for a single ROM, which win over the defaults in the rest of the config file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct RomConfig {
+    /// The ROM's file name, for people reading the file.
+    pub name: Option<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub layout: Option<MemoryLayout>,
+    /// Instructions per frame.
+    pub ipf: Option<u32>,
+    pub timing: Option<Timing>,
+    pub backend: Option<Backend>,
+    pub palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+    pub gamepad: Option<GamepadMapping>,
+}
+
+impl Config {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        toml::from_str(&s).with_context(|| format!("invalid config file {}", path.display()))
+    }
+
+    /// Saves the config over `text`, the file it was loaded from, changing only the
+    /// settings that differ so that comments and formatting are kept.
+    pub fn save_over(&self, text: &str) -> anyhow::Result<String> {
+        let mut document: DocumentMut = text.parse()?;
+        let config: DocumentMut = toml::to_string(self)?.parse()?;
+        merge(document.as_table_mut(), config.as_table());
+        Ok(document.to_string())
+    }
+
+    /// The config with the overrides for the ROM with SHA-1 `rom_sha1` applied.
+    pub fn for_rom(&self, rom_sha1: &str) -> Self {
+        let mut config = self.clone();
+        let Some(rom) = self.roms.get(rom_sha1) else {
+            return config;
+        };
+
+        // The ROM's platform brings its own quirks, instead of the default ones.
+        if rom.platform.is_some() {
+            config.platform = rom.platform;
+            config.quirks = None;
+        }
+        config.quirks = rom.quirks.or(config.quirks);
+        config.layout = rom.layout.or(config.layout);
+        config.ipf = rom.ipf.or(config.ipf);
+        config.timing = rom.timing.or(config.timing);
+        config.backend = rom.backend.or(config.backend);
+        config.palette = rom.palette.or(config.palette);
+        config.keymap = rom.keymap.or(config.keymap);
+        config
+    }
+}
+
+/// Makes `table` hold what `new` does, leaving alone the entries that are already the same.
+fn merge(table: &mut dyn TableLike, new: &dyn TableLike) {
+    let removed: Vec<String> = table
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect();
+    for key in removed {
+        table.remove(&key);
+    }
+
+    for (key, item) in new.iter() {
+        let Some(old) = table.get_mut(key) else {
+            table.insert(key, item.clone());
+            continue;
+        };
+
+        match (old.as_table_like_mut(), item.as_table_like()) {
+            (Some(old), Some(item)) => merge(old, item),
+            _ => match (old.as_value_mut(), item.as_value()) {
+                (Some(old), Some(value)) if bare(old) == bare(value) => {}
+                (Some(old), Some(value)) => {
+                    let decor = old.decor().clone();
+                    *old = value.clone();
+                    *old.decor_mut() = decor;
+                }
+                _ => *old = item.clone(),
+            },
+        }
+    }
+}
+
+/// `value` without the whitespace and comments around it.
+fn bare(value: &toml_edit::Value) -> String {
+    let mut value = value.clone();
+    value.decor_mut().clear();
+    value.to_string()
+}
+
+/// What the program remembers between runs. It's kept apart from the config file, in a
+/// file of its own that the program rewrites whenever it likes.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct State {
+    /// ROMs opened in a window, most recent first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub recent_roms: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "is_default")]
+    pub debugger: DebugInterfaceSettings,
+}
+
+impl State {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("state.toml"))
+    }
+
+    /// Loads the state at `path`, or a fresh one if there's no file there yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(s) => {
+                toml::from_str(&s).with_context(|| format!("invalid state file {}", path.display()))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => {
+                Err(err).with_context(|| format!("failed to read state file {}", path.display()))
+            }
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, toml::to_string(self)?)
+            .with_context(|| format!("failed to write state file {}", path.display()))
+    }
+
+    /// Puts `rom` first in the recent ROMs.
+    pub fn remember_rom(&mut self, rom: &Path) {
+        let rom = std::path::absolute(rom).unwrap_or_else(|_| rom.to_path_buf());
+        self.recent_roms.retain(|path| *path != rom);
+        self.recent_roms.insert(0, rom);
+        self.recent_roms.truncate(RECENT_ROMS);
+    }
+}
+
+/// The config and state files, and the ROM they're being used for.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    /// Where the config is saved. This is `None` for a config file given with `--config`,
+    /// which is never written to.
+    pub path: Option<PathBuf>,
+    pub config: Config,
+    /// Where the state is saved.
+    pub state_path: Option<PathBuf>,
+    pub state: State,
+    pub rom_sha1: String,
+    pub rom_name: String,
+}
+
+impl Settings {
+    pub fn rom(&self) -> Option<&RomConfig> {
+        self.config.roms.get(&self.rom_sha1)
+    }
+
+    /// Changes the config with `change` and saves it. Changes made to the file since it was
+    /// loaded are kept.
+    pub fn update(&mut self, change: impl FnOnce(&mut Config)) -> anyhow::Result<()> {
+        let path = self
+            .path
+            .as_deref()
+            .context("settings are only saved to the default config file")?;
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read config file {}", path.display()))
+            }
+        };
+        self.config = toml::from_str(&text)
+            .with_context(|| format!("invalid config file {}", path.display()))?;
+        change(&mut self.config);
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, self.config.save_over(&text)?)
+            .with_context(|| format!("failed to write config file {}", path.display()))
+    }
+
+    /// Changes the state with `change` and saves it, if there's somewhere to save it.
+    pub fn update_state(&mut self, change: impl FnOnce(&mut State)) -> anyhow::Result<()> {
+        let Some(path) = self.state_path.as_deref() else {
+            change(&mut self.state);
+            return Ok(());
+        };
+        self.state = State::load(path)?;
+        change(&mut self.state);
+        self.state.save(path)
+    }
+
+    /// Changes the overrides for the ROM with `change` and saves them.
+    pub fn update_rom(&mut self, change: impl FnOnce(&mut RomConfig)) -> anyhow::Result<()> {
+        let (rom_sha1, rom_name) = (self.rom_sha1.clone(), self.rom_name.clone());
+        self.update(|config| {
+            let rom = config.roms.entry(rom_sha1).or_default();
+            rom.name.get_or_insert(rom_name);
+            change(rom);
+        })
+    }
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let config: Config = toml::from_str(
+            "platform = \"schip\"\nquirks = \"shift-vy\"\nipf = 30\npalette = \"amber\"",
+        )
+        .unwrap();
+
+        assert_eq!(Some(Platform::SuperChip), config.platform);
+        assert_eq!(Some("shift-vy".parse().unwrap()), config.quirks);
+        assert_eq!(Some(30), config.ipf);
+        assert_eq!(Some("amber".parse().unwrap()), config.palette);
+        assert_eq!(None, config.keymap);
+
+        assert!(toml::from_str::<Config>("speed = 3").is_err());
+        assert!(toml::from_str::<Config>("palette = \"plaid\"").is_err());
+    }
+
+    #[test]
+    fn rom_overrides() {
+        let config: Config = toml::from_str(
+            "quirks = \"shift-vy\"\nipf = 30\n[roms.abc]\nplatform = \"schip\"\nlayout = \"eti660\"\nkeymap = \"1234qwerasdfzxcv\"",
+        )
+        .unwrap();
+
+        let pong = config.for_rom("abc");
+        assert_eq!(Some(Platform::SuperChip), pong.platform);
+        assert_eq!(None, pong.quirks);
+        assert_eq!(Some(MemoryLayout::PRESETS[1].1), pong.layout);
+        assert_eq!(Some(30), pong.ipf);
+        assert_eq!(Some("1234qwerasdfzxcv".parse().unwrap()), pong.keymap);
+        assert_eq!(config, config.for_rom("def"));
+
+        let text = toml::to_string(&config).unwrap();
+        assert!(text.contains("[roms.abc]\n"));
+        assert_eq!(config, toml::from_str(&text).unwrap());
+    }
+
+    #[test]
+    fn save_keeps_comments() {
+        let text = "# Defaults\nipf = 30 # fast\nscale = 4\n\n# Pong\n[roms.abc]\nipf = 9\n";
+        let mut config: Config = toml::from_str(text).unwrap();
+        config.ipf = Some(20);
+        config.scale = None;
+        config.roms.get_mut("abc").unwrap().gamepad = Some(GamepadMapping::default());
+        config.roms.insert(
+            "def".to_string(),
+            RomConfig {
+                platform: Some(Platform::SuperChip),
+                ..RomConfig::default()
+            },
+        );
+
+        let saved = config.save_over(text).unwrap();
+        assert!(saved.starts_with("# Defaults\nipf = 20 # fast\n\n# Pong\n[roms.abc]\nipf = 9\n"));
+        assert!(saved.contains("[roms.abc.gamepad]\n"));
+        assert!(saved.contains("[roms.def]\nplatform = \"schip\"\n"));
+        assert_eq!(config, toml::from_str(&saved).unwrap());
+        assert_eq!(saved, config.save_over(&saved).unwrap());
+    }
+
+    #[test]
+    fn state() {
+        let mut state = State::default();
+        state.remember_rom(Path::new("/roms/a.ch8"));
+        state.remember_rom(Path::new("/roms/b.ch8"));
+        state.remember_rom(Path::new("/roms/a.ch8"));
+        assert_eq!(
+            vec![PathBuf::from("/roms/a.ch8"), PathBuf::from("/roms/b.ch8")],
+            state.recent_roms
+        );
+
+        let text = toml::to_string(&state).unwrap();
+        assert!(!text.contains("[debugger]"));
+        assert_eq!(state, toml::from_str(&text).unwrap());
+
+        state = toml::from_str(
+            "[debugger]\nmem-heatmap = \"reads\"\nwindow-size = [800.0, 600.0]\n[debugger.panels]\nprofile = false",
+        )
+        .unwrap();
+        assert_eq!(
+            state,
+            toml::from_str(&toml::to_string(&state).unwrap()).unwrap()
+        );
+        assert!(toml::from_str::<Config>("recent-roms = []").is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! The RCA CDP1861 video display controller, as the COSMAC VIP clocks it.
+//!
+//! A frame is 262 lines of 14 machine cycles. Two lines before the 128 display lines, the
+//! chip interrupts the CPU, whose interrupt routine points R0 at display memory. Each display
+//! line then takes 8 bytes from memory by DMA, stealing 8 of its 14 cycles. EF1 is asserted
+//! for the 4 lines before the display starts and the last 4 of it, for routines that want
+//! to know where the beam is.
+
+pub const LINE_CYCLES: u64 = 14;
+pub const FRAME_LINES: u64 = 262;
+pub const FRAME_CYCLES: u64 = LINE_CYCLES * FRAME_LINES;
+/// Lines taken from memory per frame.
+pub const DISPLAY_LINES: usize = 128;
+/// Bytes per display line, a bit per pixel.
+pub const LINE_BYTES: usize = 8;
+
+const INTERRUPT_LINE: u64 = 78;
+const DISPLAY_START: u64 = 80;
+const FLAG_LINES: u64 = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cdp1861 {
+    /// Turned on by `INP 1` and off by `OUT 1`.
+    pub enabled: bool,
+    /// Machine cycles into the frame.
+    cycle: u64,
+    /// The next display line to take from memory.
+    next_line: usize,
+    interrupted: bool,
+    lines: Vec<u8>,
+    /// The last complete frame.
+    frame: Vec<u8>,
+    frames: u64,
+}
+
+impl Default for Cdp1861 {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cycle: 0,
+            next_line: 0,
+            interrupted: false,
+            lines: vec![0; DISPLAY_LINES * LINE_BYTES],
+            frame: vec![0; DISPLAY_LINES * LINE_BYTES],
+            frames: 0,
+        }
+    }
+}
+
+impl Cdp1861 {
+    fn line(&self) -> u64 {
+        self.cycle / LINE_CYCLES
+    }
+
+    /// Whether the interrupt for this frame is due and hasn't been taken yet.
+    pub fn interrupt_requested(&self) -> bool {
+        self.enabled && !self.interrupted && (INTERRUPT_LINE..DISPLAY_START).contains(&self.line())
+    }
+
+    pub fn acknowledge_interrupt(&mut self) {
+        self.interrupted = true;
+    }
+
+    pub fn ef1(&self) -> bool {
+        let end = DISPLAY_START + DISPLAY_LINES as u64;
+        let line = self.line();
+        self.enabled
+            && ((DISPLAY_START - FLAG_LINES..DISPLAY_START).contains(&line)
+                || (end - FLAG_LINES..end).contains(&line))
+    }
+
+    /// Whether the current line's bytes are due to be taken from memory.
+    pub fn dma_due(&self) -> bool {
+        self.enabled
+            && self.next_line < DISPLAY_LINES
+            && self.line() >= DISPLAY_START + self.next_line as u64
+    }
+
+    /// Shows `bytes` on the current line, which took the DMA cycles that fetched them.
+    /// Returns whether the frame ended.
+    pub fn dma(&mut self, bytes: [u8; LINE_BYTES]) -> bool {
+        let start = self.next_line * LINE_BYTES;
+        self.lines[start..start + LINE_BYTES].copy_from_slice(&bytes);
+        self.next_line += 1;
+        self.advance(LINE_BYTES as u64)
+    }
+
+    /// Lets `cycles` machine cycles pass. Returns whether the frame ended.
+    pub fn advance(&mut self, cycles: u64) -> bool {
+        self.cycle += cycles;
+        if self.cycle < FRAME_CYCLES {
+            return false;
+        }
+
+        self.cycle -= FRAME_CYCLES;
+        self.frames += 1;
+        if self.enabled {
+            std::mem::swap(&mut self.frame, &mut self.lines);
+        } else {
+            self.frame.fill(0);
+        }
+        self.next_line = 0;
+        self.interrupted = false;
+        true
+    }
+
+    /// The last complete frame: [`DISPLAY_LINES`] lines of [`LINE_BYTES`] bytes.
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+
+    /// Frames completed.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frame() {
+        let mut video = Cdp1861 {
+            enabled: true,
+            ..Cdp1861::default()
+        };
+
+        assert!(!video.advance(INTERRUPT_LINE * LINE_CYCLES - 1));
+        assert!(video.ef1());
+        assert!(!video.interrupt_requested());
+        video.advance(1);
+        assert!(video.interrupt_requested());
+        video.acknowledge_interrupt();
+        assert!(!video.interrupt_requested());
+
+        video.advance(2 * LINE_CYCLES - 1);
+        assert!(!video.dma_due());
+        video.advance(1);
+        assert!(!video.ef1());
+        for line in 0..DISPLAY_LINES {
+            assert!(video.dma_due());
+            video.dma([line as u8; LINE_BYTES]);
+            assert!(!video.dma_due());
+            video.advance(LINE_CYCLES - LINE_BYTES as u64);
+        }
+
+        let rest = FRAME_CYCLES - (DISPLAY_START + DISPLAY_LINES as u64) * LINE_CYCLES;
+        assert!(video.advance(rest));
+        assert_eq!(1, video.frames());
+        assert_eq!(
+            [5; LINE_BYTES],
+            video.frame()[5 * LINE_BYTES..][..LINE_BYTES]
+        );
+    }
+}
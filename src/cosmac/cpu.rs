@@ -0,0 +1,385 @@
+//! The RCA CDP1802 microprocessor.
+
+/// What the CPU is connected to: memory, the I/O ports of `OUT`/`INP`, and the external
+/// flags that `B1`-`B4` test.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// `OUT port`, for ports 1 to 7.
+    fn output(&mut self, port: u8, value: u8);
+    /// `INP port`, for ports 1 to 7.
+    fn input(&mut self, port: u8) -> u8;
+    /// Whether external flag `EFn` is asserted, for flags 1 to 4.
+    fn flag(&mut self, n: u8) -> bool;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cdp1802 {
+    /// The 16 scratchpad registers, any of which can be the program counter (`P`), the
+    /// data pointer (`X`) or the DMA pointer (R0).
+    pub r: [u16; 16],
+    pub p: u8,
+    pub x: u8,
+    pub d: u8,
+    pub df: bool,
+    /// X and P as saved by an interrupt or `MARK`.
+    pub t: u8,
+    /// Interrupts enabled.
+    pub ie: bool,
+    pub q: bool,
+    /// Stopped by `IDL` until the next interrupt or DMA.
+    pub idle: bool,
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Self {
+        Self {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+}
+
+impl Cdp1802 {
+    /// The state after the reset line is released: P, X, Q and R0 cleared and interrupts
+    /// enabled. The other registers keep whatever they held.
+    pub fn reset(&mut self) {
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+        self.r[0] = 0;
+    }
+
+    /// Takes an interrupt if they're enabled, saving X and P in T and running from R1 with
+    /// X = 2. Returns the machine cycles that took.
+    pub fn interrupt(&mut self) -> u8 {
+        if !self.ie {
+            return 0;
+        }
+        self.t = self.x << 4 | self.p;
+        self.x = 2;
+        self.p = 1;
+        self.ie = false;
+        self.idle = false;
+        1
+    }
+
+    /// A DMA out cycle: the byte at R0, which is then incremented.
+    pub fn dma_out(&mut self, bus: &mut impl Bus) -> u8 {
+        let byte = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        byte
+    }
+
+    /// Executes one instruction, and returns the machine cycles it took: 3 for long
+    /// branches and skips, and 2 for everything else. Idling takes 1.
+    pub fn step(&mut self, bus: &mut impl Bus) -> u8 {
+        if self.idle {
+            return 1;
+        }
+
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0xF) as usize;
+        let x = self.x as usize;
+
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            // LDN
+            0x0 => self.d = bus.read(self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(bus, n as u8);
+                self.short_branch(bus, taken);
+            }
+            // LDA
+            0x4 => {
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            // STR
+            0x5 => bus.write(self.r[n], self.d),
+            // IRX
+            0x6 if n == 0 => self.r[x] = self.r[x].wrapping_add(1),
+            // OUT
+            0x6 if n < 8 => {
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                bus.output(n as u8, value);
+            }
+            // Unused.
+            0x6 if n == 8 => {}
+            // INP
+            0x6 => {
+                self.d = bus.input(n as u8 - 8);
+                bus.write(self.r[x], self.d);
+            }
+            0x7 => self.op_7n(bus, n as u8),
+            // GLO, GHI, PLO, PHI
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = self.r[n] & 0xFF00 | u16::from(self.d),
+            0xB => self.r[n] = self.r[n] & 0x00FF | u16::from(self.d) << 8,
+            0xC => {
+                self.op_Cn(bus, n as u8);
+                return 3;
+            }
+            // SEP, SEX
+            0xD => self.p = n as u8,
+            0xE => self.x = n as u8,
+            _ => self.op_Fn(bus, n as u8),
+        }
+
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut impl Bus) -> u8 {
+        let p = self.p as usize;
+        let byte = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    /// Whether short branch `3n` is taken.
+    fn condition(&mut self, bus: &mut impl Bus, n: u8) -> bool {
+        let condition = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 3),
+        };
+        condition != (n & 0x8 != 0)
+    }
+
+    fn short_branch(&mut self, bus: &mut impl Bus, taken: bool) {
+        let p = self.p as usize;
+        if taken {
+            let target = bus.read(self.r[p]);
+            self.r[p] = self.r[p] & 0xFF00 | u16::from(target);
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    /// Adds `a`, `b` and a carry, setting DF to the carry out.
+    fn add(&mut self, a: u8, b: u8, carry: bool) -> u8 {
+        let sum = u16::from(a) + u16::from(b) + u16::from(carry);
+        self.df = sum > 0xFF;
+        sum as u8
+    }
+
+    /// `a - b`, minus a borrow, setting DF when nothing was borrowed.
+    fn sub(&mut self, a: u8, b: u8, borrow: bool) -> u8 {
+        self.add(a, !b, !borrow)
+    }
+
+    /// The arithmetic and logic of `Fn` and `7n`: `n` selects the operation the way the
+    /// low 3 bits of `F1`-`F7` do.
+    fn alu(&mut self, n: u8, operand: u8, with_carry: bool) {
+        let carry = with_carry && self.df;
+        let borrow = with_carry && !self.df;
+        self.d = match n & 0x7 {
+            0 => operand,
+            1 => self.d | operand,
+            2 => self.d & operand,
+            3 => self.d ^ operand,
+            4 => self.add(self.d, operand, carry),
+            5 => self.sub(operand, self.d, borrow),
+            7 => self.sub(self.d, operand, borrow),
+            _ => unreachable!("shifts take no operand"),
+        };
+    }
+
+    /// RET, DIS, LDXA, STXD, the arithmetic with carry, SAV, MARK, REQ, SEQ and the
+    /// shifts through DF.
+    #[allow(non_snake_case)]
+    fn op_7n(&mut self, bus: &mut impl Bus, n: u8) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                let t = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = t >> 4;
+                self.p = t & 0xF;
+                self.ie = n == 0x0;
+            }
+            0x2 => {
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x4 | 0x5 | 0x7 => {
+                let operand = bus.read(self.r[x]);
+                self.alu(n, operand, true);
+            }
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = self.d >> 1 | u8::from(carry) << 7;
+            }
+            0x8 => bus.write(self.r[x], self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC | 0xD | 0xF => {
+                let operand = self.fetch(bus);
+                self.alu(n, operand, true);
+            }
+            _ => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | u8::from(carry);
+            }
+        }
+    }
+
+    /// Long branches and skips, and NOP.
+    #[allow(non_snake_case)]
+    fn op_Cn(&mut self, bus: &mut impl Bus, n: u8) {
+        let p = self.p as usize;
+        let condition = match n & 0x3 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            _ => self.df,
+        };
+        // Bit 3 inverts branches, but LSNQ, LSNZ and LSNF are the skips without it.
+        let inverted = n & 0x8 != 0;
+
+        let (skip, branch) = match n {
+            // NOP
+            0x4 => (false, false),
+            // LSIE
+            0xC => (self.ie, false),
+            // LSKP
+            0x8 => (true, false),
+            _ if n & 0x4 != 0 => (condition == inverted, false),
+            _ => (false, condition != inverted),
+        };
+
+        if branch {
+            let hi = bus.read(self.r[p]);
+            let lo = bus.read(self.r[p].wrapping_add(1));
+            self.r[p] = u16::from_be_bytes([hi, lo]);
+        } else if skip || n & 0x4 == 0 {
+            // Long branches not taken skip their address too.
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    /// LDX, the logic and arithmetic on M(R(X)) or an immediate byte, and the shifts.
+    #[allow(non_snake_case)]
+    fn op_Fn(&mut self, bus: &mut impl Bus, n: u8) {
+        match n {
+            0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0xE => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            0x0..=0x7 => {
+                let operand = bus.read(self.r[self.x as usize]);
+                self.alu(n, operand, false);
+            }
+            _ => {
+                let operand = self.fetch(bus);
+                self.alu(n, operand, false);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Ram(Vec<u8>);
+
+    impl Bus for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            self.0[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0[address as usize] = value;
+        }
+
+        fn output(&mut self, _: u8, _: u8) {}
+
+        fn input(&mut self, port: u8) -> u8 {
+            port
+        }
+
+        fn flag(&mut self, n: u8) -> bool {
+            n == 3
+        }
+    }
+
+    fn run(program: &[u8], steps: usize) -> (Cdp1802, Ram) {
+        let mut ram = Ram(vec![0; 0x100]);
+        ram.0[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::default();
+        for _ in 0..steps {
+            cpu.step(&mut ram);
+        }
+        (cpu, ram)
+    }
+
+    #[test]
+    fn arithmetic() {
+        // LDI 0xF0; ADI 0x20; PLO 3; LDI 0x10; SMI 0x20
+        let (cpu, _) = run(&[0xF8, 0xF0, 0xFC, 0x20, 0xA3, 0xF8, 0x10, 0xFF, 0x20], 3);
+        assert_eq!(0x10, cpu.r[3]);
+        assert!(cpu.df);
+
+        let (cpu, _) = run(&[0xF8, 0xF0, 0xFC, 0x20, 0xA3, 0xF8, 0x10, 0xFF, 0x20], 5);
+        assert_eq!(0xF0, cpu.d);
+        // Borrowed.
+        assert!(!cpu.df);
+    }
+
+    #[test]
+    fn branches() {
+        // 00: LDI 0; BZ 06; IDL; 06: B3 0A; IDL; 0A: LBR 0x0020
+        let program = [
+            0xF8, 0x00, 0x32, 0x06, 0x00, 0x00, 0x36, 0x0A, 0x00, 0x00, 0xC0, 0x00,
+        ];
+        let (cpu, _) = run(&[&program[..], &[0x20]].concat(), 4);
+        assert_eq!(0x20, cpu.r[0]);
+        assert!(!cpu.idle);
+    }
+
+    #[test]
+    fn memory_and_interrupts() {
+        // LDI 0x40; PLO 2; SEX 2; LDI 0x5A; STXD; INP 4; SEQ
+        let (mut cpu, ram) = run(&[0xF8, 0x40, 0xA2, 0xE2, 0xF8, 0x5A, 0x73, 0x6C, 0x7B], 7);
+        assert_eq!(0x5A, ram.0[0x40]);
+        assert_eq!(4, ram.0[0x3F]);
+        assert!(cpu.q);
+
+        assert_eq!(1, cpu.interrupt());
+        assert_eq!((1, 2, 0x20), (cpu.p, cpu.x, cpu.t));
+        assert_eq!(0, cpu.interrupt());
+    }
+}
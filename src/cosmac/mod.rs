@@ -0,0 +1,374 @@
+//! The COSMAC VIP, to run CHIP-8 programs on the original interpreter rather than on the
+//! built-in one: a CDP1802 CPU, a CDP1861 display, the hex keypad, RAM, and the monitor
+//! ROM at 0x8000.
+//!
+//! Neither the monitor ROM nor the interpreter come with this program. Both are 512-byte
+//! images: the monitor is what the VIP's ROM chip holds, and the interpreter is what the
+//! monitor loads from tape into 0x000-0x1FF. The interpreter uses the monitor's interrupt
+//! routine and hex digits.
+//!
+//! The machine boots through the monitor, as when the VIP is reset without holding C. From
+//! then on, [`Vip::run_instruction`] runs until the interpreter finishes a CHIP-8
+//! instruction, which it does by switching to its fetch loop with `SEP R4`. This is also how
+//! `0nnn` machine code subroutines return.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+
+pub use cdp1861::Cdp1861;
+pub use cpu::{Bus, Cdp1802};
+
+pub mod cdp1861;
+pub mod cpu;
+
+pub const MONITOR_SIZE: usize = 0x200;
+
+/// The monitor is at 0x8000, and repeats up to 0xFFFF.
+const ROM_BIT: u16 = 0x8000;
+/// `SEP R4`, which ends every CHIP-8 instruction.
+const END_OF_INSTRUCTION: u8 = 0xD4;
+
+/// What runs CHIP-8 programs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// This program's own interpreter.
+    #[default]
+    Native,
+    /// The original interpreter, on an emulated COSMAC VIP.
+    Vip,
+}
+
+impl Backend {
+    const NAMES: [(&'static str, Self); 2] = [("native", Self::Native), ("vip", Self::Vip)];
+}
+
+/// The ROM and the interpreter a [`Vip`] runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VipImages {
+    pub monitor: Vec<u8>,
+    pub interpreter: Vec<u8>,
+}
+
+impl VipImages {
+    pub fn load(monitor: &Path, interpreter: &Path) -> anyhow::Result<Self> {
+        let read = |path: &Path, what| {
+            let bytes = std::fs::read(path)
+                .with_context(|| format!("failed to read VIP {what} {}", path.display()))?;
+            if bytes.is_empty() || bytes.len() > MONITOR_SIZE {
+                bail!(
+                    "VIP {what} {} is {} bytes, expected up to {MONITOR_SIZE}",
+                    path.display(),
+                    bytes.len()
+                );
+            }
+            Ok(bytes)
+        };
+
+        Ok(Self {
+            monitor: read(monitor, "ROM")?,
+            interpreter: read(interpreter, "interpreter")?,
+        })
+    }
+}
+
+/// The CHIP-8 state that the original interpreter keeps in the VIP's registers and memory.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterpreterState {
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub program_counter: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    /// Return addresses, oldest first.
+    pub stack: [u16; 16],
+    pub stack_pointer: u8,
+}
+
+/// What a call to [`Vip::run_instruction`] did.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VipStep {
+    /// Machine cycles, including those taken by the display.
+    pub cycles: u64,
+    /// Frames the display finished.
+    pub frames: u64,
+    /// Whether the instruction finished, rather than running out of cycles.
+    pub finished: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Vip {
+    pub cpu: Cdp1802,
+    pub video: Cdp1861,
+    images: VipImages,
+    /// After a reset, the monitor also appears at 0x0000, until it jumps to 0x8000.
+    rom_at_zero: bool,
+    /// The key that `OUT 2` selected, which EF3 says is down or not.
+    key_latch: u8,
+    /// Where the interpreter's stack started, once it has.
+    stack_top: Option<u16>,
+    last_step: VipStep,
+}
+
+struct VipBus<'a> {
+    memory: &'a mut [u8],
+    monitor: &'a [u8],
+    rom_at_zero: &'a mut bool,
+    key_latch: &'a mut u8,
+    display: &'a mut bool,
+    ef1: bool,
+    keys: u16,
+}
+
+impl Bus for VipBus<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        if address & ROM_BIT != 0 {
+            *self.rom_at_zero = false;
+        } else if !*self.rom_at_zero {
+            // RAM repeats, as not every address line is decoded.
+            return self.memory[address as usize % self.memory.len()];
+        }
+        self.monitor
+            .get(address as usize % MONITOR_SIZE)
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & ROM_BIT == 0 {
+            let len = self.memory.len();
+            self.memory[address as usize % len] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => *self.display = false,
+            2 => *self.key_latch = value & 0xF,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            *self.display = true;
+        }
+        0
+    }
+
+    fn flag(&mut self, n: u8) -> bool {
+        match n {
+            1 => self.ef1,
+            3 => self.keys & 1 << *self.key_latch != 0,
+            _ => false,
+        }
+    }
+}
+
+impl Vip {
+    /// A VIP about to boot, with memory that must be a power of two between 4K and 32K.
+    pub fn new(images: VipImages, memory: &mut [u8]) -> anyhow::Result<Self> {
+        let size = memory.len();
+        if !size.is_power_of_two() || !(0x1000..=0x8000).contains(&size) {
+            bail!("the VIP has 4K to 32K of memory in a power of two, not {size:#x} bytes");
+        }
+
+        let mut vip = Self {
+            cpu: Cdp1802::default(),
+            video: Cdp1861::default(),
+            images,
+            rom_at_zero: true,
+            key_latch: 0,
+            stack_top: None,
+            last_step: VipStep::default(),
+        };
+        vip.reset(memory);
+        Ok(vip)
+    }
+
+    /// Presses reset, reloading the interpreter into `memory`.
+    pub fn reset(&mut self, memory: &mut [u8]) {
+        let interpreter = &self.images.interpreter;
+        memory[..interpreter.len()].copy_from_slice(interpreter);
+        self.cpu.reset();
+        self.video = Cdp1861::default();
+        self.rom_at_zero = true;
+        self.stack_top = None;
+        self.last_step = VipStep::default();
+    }
+
+    /// Whether the interpreter is between two CHIP-8 instructions, rather than booting or
+    /// in the middle of one.
+    pub fn at_instruction(&self) -> bool {
+        self.last_step.finished
+    }
+
+    pub fn last_step(&self) -> VipStep {
+        self.last_step
+    }
+
+    /// Runs until the interpreter finishes a CHIP-8 instruction, or for at least `budget`
+    /// machine cycles, like while it waits for a key.
+    pub fn run_instruction(&mut self, memory: &mut [u8], keys: u16, budget: u64) -> VipStep {
+        let mut step = VipStep::default();
+
+        while step.cycles < budget {
+            let dma_due = self.video.dma_due();
+            let interrupt = self.video.interrupt_requested() && self.cpu.ie;
+            let ef1 = self.video.ef1();
+            let mut bus = VipBus {
+                memory: &mut *memory,
+                monitor: &self.images.monitor,
+                rom_at_zero: &mut self.rom_at_zero,
+                key_latch: &mut self.key_latch,
+                display: &mut self.video.enabled,
+                ef1,
+                keys,
+            };
+
+            if dma_due {
+                let bytes = std::array::from_fn(|_| self.cpu.dma_out(&mut bus));
+                step.cycles += cdp1861::LINE_BYTES as u64;
+                step.frames += u64::from(self.video.dma(bytes));
+                continue;
+            }
+
+            let (cycles, opcode) = if interrupt {
+                self.video.acknowledge_interrupt();
+                (self.cpu.interrupt(), None)
+            } else if self.cpu.idle {
+                (self.cpu.step(&mut bus), None)
+            } else {
+                let opcode = bus.read(self.cpu.r[self.cpu.p as usize]);
+                (self.cpu.step(&mut bus), Some(opcode))
+            };
+            step.cycles += u64::from(cycles);
+            step.frames += u64::from(self.video.advance(u64::from(cycles)));
+
+            if opcode == Some(END_OF_INSTRUCTION) {
+                self.stack_top.get_or_insert(self.cpu.r[2]);
+                step.finished = true;
+                break;
+            }
+        }
+
+        self.last_step = step;
+        step
+    }
+
+    /// The CHIP-8 state, which is only meaningful between instructions.
+    ///
+    /// The interpreter keeps the program counter in R5, I in RA and the delay and sound
+    /// timers in the high and low bytes of R8, which the monitor's interrupt routine counts
+    /// down. V0-VF are the last 16 bytes of the page R6 points into. CALL pushes return
+    /// addresses on the 1802's stack, high byte first.
+    pub fn interpreter_state(&self, memory: &[u8]) -> InterpreterState {
+        let r = &self.cpu.r;
+        let read = |address: u16| memory[address as usize % memory.len()];
+
+        let registers_at = r[6] & 0xFF00 | 0xF0;
+        let mut state = InterpreterState {
+            registers: std::array::from_fn(|i| read(registers_at + i as u16)),
+            index: r[0xA],
+            program_counter: r[5],
+            delay_timer: (r[8] >> 8) as u8,
+            sound_timer: r[8] as u8,
+            ..InterpreterState::default()
+        };
+
+        if let Some(top) = self.stack_top.filter(|&top| top >= r[2]) {
+            let depth = ((top - r[2]) / 2).min(16);
+            for i in 0..depth {
+                let at = top - 2 * (i + 1);
+                state.stack[i as usize] = u16::from_be_bytes([read(at), read(at + 1)]);
+            }
+            state.stack_pointer = depth as u8;
+        }
+
+        state
+    }
+
+    /// Changes the registers, I, the program counter and the timers, as between
+    /// instructions. The stack is left as it is.
+    pub fn set_interpreter_state(&mut self, memory: &mut [u8], state: &InterpreterState) {
+        let r = &mut self.cpu.r;
+        let registers_at = (r[6] & 0xFF00 | 0xF0) as usize;
+        for (i, &value) in state.registers.iter().enumerate() {
+            let len = memory.len();
+            memory[(registers_at + i) % len] = value;
+        }
+        r[0xA] = state.index;
+        r[5] = state.program_counter;
+        r[8] = u16::from_be_bytes([state.delay_timer, state.sound_timer]);
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Self::NAMES.iter().find(|(_, b)| b == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Backend {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, backend)| *backend)
+            .ok_or_else(|| anyhow::anyhow!("unknown backend {s:?}, expected native or vip"))
+    }
+}
+
+serde_string!(Backend);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_instruction() {
+        // Jumps to 0x8003, so that the monitor leaves 0x0000, then to the interpreter.
+        let monitor = vec![0xC0, 0x80, 0x03, 0xC0, 0x00, 0x00];
+        #[rustfmt::skip]
+        let interpreter = vec![
+            // R5 = 0x200, R4 = 0x00A; SEP R4
+            0xF8, 0x02, 0xB5, 0xF8, 0x0A, 0xA4, 0xD4, 0x00, 0x00, 0x00,
+            // 00A: the fetch loop. INC R5 twice, and run the instruction at 0x014.
+            0x15, 0x15, 0xF8, 0x14, 0xA3, 0xD3, 0x30, 0x0A, 0x00, 0x00,
+            // 014: LD DT, 7
+            0xF8, 0x07, 0xB8, 0xD4,
+        ];
+        let mut memory = vec![0; 0x1000];
+        let images = VipImages {
+            monitor,
+            interpreter,
+        };
+        let mut vip = Vip::new(images, &mut memory).unwrap();
+        assert!(!vip.at_instruction());
+
+        let boot = vip.run_instruction(&mut memory, 0, 100);
+        assert_eq!(3 + 3 + 5 * 2, boot.cycles);
+        assert!(vip.at_instruction());
+        assert_eq!(0x200, vip.interpreter_state(&memory).program_counter);
+
+        let mut state = vip.interpreter_state(&memory);
+        state.registers[3] = 9;
+        vip.set_interpreter_state(&mut memory, &state);
+        assert_eq!(9, memory[0xF3]);
+
+        let step = vip.run_instruction(&mut memory, 0, 100);
+        assert_eq!(8 * 2, step.cycles);
+        let state = vip.interpreter_state(&memory);
+        assert_eq!(
+            (0x202, 7, 9),
+            (state.program_counter, state.delay_timer, state.registers[3])
+        );
+
+        // Stops in the middle of an instruction that takes too long.
+        assert!(!vip.run_instruction(&mut memory, 0, 4).finished);
+        assert!(!vip.at_instruction());
+    }
+}
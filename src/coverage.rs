@@ -0,0 +1,280 @@
+//! Execution and memory access counters, and coverage reports that compare the code that
+//! ran with the code found by static analysis of the ROM.
+//!
+//! Counting is off by default, since it costs a little on every instruction; enable it
+//! with [`Chip8::enable_coverage`](crate::chip8::Chip8::enable_coverage).
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    ops::{Range, RangeInclusive},
+};
+
+use crate::{instruction::Instruction, symbols::Symbols};
+
+/// Per-address counters of executed instructions, and per-byte counters of the reads and
+/// writes made by instructions (`DRW`, `LD B`, `LD [I]` and `LD Vx, [I]`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Coverage {
+    executions: Vec<u32>,
+    reads: Vec<u32>,
+    writes: Vec<u32>,
+}
+
+impl Coverage {
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            executions: vec![0; memory_size],
+            reads: vec![0; memory_size],
+            writes: vec![0; memory_size],
+        }
+    }
+
+    pub fn record_execution(&mut self, addr: usize) {
+        if let Some(count) = self.executions.get_mut(addr) {
+            *count = count.saturating_add(1);
+        }
+    }
+
+    pub fn record_read(&mut self, range: Range<usize>) {
+        count_range(&mut self.reads, range);
+    }
+
+    pub fn record_write(&mut self, range: Range<usize>) {
+        count_range(&mut self.writes, range);
+    }
+
+    /// How many times the instruction at each address was executed.
+    pub fn executions(&self) -> &[u32] {
+        &self.executions
+    }
+
+    pub fn reads(&self) -> &[u32] {
+        &self.reads
+    }
+
+    pub fn writes(&self) -> &[u32] {
+        &self.writes
+    }
+
+    pub fn clear(&mut self) {
+        for counts in [&mut self.executions, &mut self.reads, &mut self.writes] {
+            counts.fill(0);
+        }
+    }
+}
+
+fn count_range(counts: &mut [u32], range: Range<usize>) {
+    let end = range.end.min(counts.len());
+    for count in &mut counts[range.start.min(end)..end] {
+        *count = count.saturating_add(1);
+    }
+}
+
+/// Finds the addresses of the instructions reachable from `entry`, by following jumps,
+/// calls and skips.
+///
+/// The search stops at opcode `0000` and at bytes that don't decode as an instruction, and
+/// can't follow `JP V0, nnn`, so code only reached through jump tables is not found.
+pub fn find_code(memory: &[u8], entry: usize) -> BTreeSet<usize> {
+    let mut code = BTreeSet::new();
+    let mut pending = vec![entry];
+
+    while let Some(addr) = pending.pop() {
+        if addr + 1 >= memory.len() || code.contains(&addr) {
+            continue;
+        }
+
+        let raw = u16::from_be_bytes([memory[addr], memory[addr + 1]]);
+        let Some(instruction) = Instruction::decode(raw).filter(|_| raw != 0) else {
+            continue;
+        };
+        code.insert(addr);
+
+        match instruction {
+            Instruction::Jp(nnn) => pending.push(nnn as usize),
+            Instruction::Call(nnn) => pending.extend([nnn as usize, addr + 2]),
+            Instruction::Ret | Instruction::JpV0(_) => {}
+            Instruction::SeByte(..)
+            | Instruction::SneByte(..)
+            | Instruction::SeReg(..)
+            | Instruction::SneReg(..)
+            | Instruction::Skp(_)
+            | Instruction::Sknp(_) => pending.extend([addr + 2, addr + 4]),
+            _ => pending.push(addr + 2),
+        }
+    }
+
+    code
+}
+
+/// Compares the instructions that were executed with those found by [`find_code`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Instruction addresses found by static analysis.
+    pub code: BTreeSet<usize>,
+    /// Addresses of executed instructions, with their execution counts.
+    pub executed: Vec<(usize, u32)>,
+}
+
+impl CoverageReport {
+    pub fn new(coverage: &Coverage, memory: &[u8], entry: usize) -> Self {
+        let executed = coverage
+            .executions()
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(addr, &count)| (addr, count))
+            .collect();
+
+        Self {
+            code: find_code(memory, entry),
+            executed,
+        }
+    }
+
+    pub fn code_bytes(&self) -> usize {
+        2 * self.code.len()
+    }
+
+    pub fn executed_code_bytes(&self) -> usize {
+        2 * self
+            .executed
+            .iter()
+            .filter(|(addr, _)| self.code.contains(addr))
+            .count()
+    }
+
+    /// Percentage of the statically found code that was executed.
+    pub fn percent(&self) -> f64 {
+        match self.code_bytes() {
+            0 => 0.,
+            total => 100. * self.executed_code_bytes() as f64 / total as f64,
+        }
+    }
+
+    /// Runs of statically found instructions that never executed: candidates for dead code.
+    pub fn unexecuted(&self) -> Vec<RangeInclusive<usize>> {
+        let executed: BTreeSet<_> = self.executed.iter().map(|&(addr, _)| addr).collect();
+        let mut runs: Vec<RangeInclusive<usize>> = Vec::new();
+
+        for &addr in self.code.difference(&executed) {
+            match runs.last_mut() {
+                Some(run) if *run.end() + 1 == addr => *run = *run.start()..=addr + 1,
+                _ => runs.push(addr..=addr + 1),
+            }
+        }
+
+        runs
+    }
+
+    /// Executed instructions that static analysis did not find: either the targets of
+    /// computed jumps, or data being executed by mistake.
+    pub fn outside_code(&self) -> Vec<(usize, u32)> {
+        self.executed
+            .iter()
+            .filter(|(addr, _)| !self.code.contains(addr))
+            .copied()
+            .collect()
+    }
+
+    pub fn to_text(&self, symbols: &Symbols) -> String {
+        let location = |addr: usize| match symbols.locate(addr as u16) {
+            Some(place) => format!("{addr:#05x} ({place})"),
+            None => format!("{addr:#05x}"),
+        };
+
+        let mut report = format!(
+            "Executed {} of {} code bytes found by static analysis ({:.1}%)\n",
+            self.executed_code_bytes(),
+            self.code_bytes(),
+            self.percent()
+        );
+
+        let unexecuted = self.unexecuted();
+        if !unexecuted.is_empty() {
+            report.push_str("\nNever executed (possible dead code):\n");
+            for run in unexecuted {
+                let len = run.end() - run.start() + 1;
+                let _ = writeln!(report, "  {}, {len} bytes", location(*run.start()));
+            }
+        }
+
+        let outside = self.outside_code();
+        if !outside.is_empty() {
+            report.push_str("\nExecuted outside the code found (computed jumps or data):\n");
+            for (addr, count) in outside {
+                let _ = writeln!(report, "  {}, {count} times", location(addr));
+            }
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn memory(rom: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0; 4096];
+        memory[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        memory
+    }
+
+    #[test]
+    fn find_code_follows_control_flow() {
+        let memory = memory(&[
+            0x22, 0x0A, // 200: CALL 20a
+            0x30, 0x01, // 202: SE V0, 0x01
+            0x12, 0x08, // 204: JP 208
+            0x12, 0x0C, // 206: JP 20c
+            0x12, 0x08, // 208: JP 208
+            0x00, 0xEE, // 20a: RET
+            0x60, 0x01, // 20c: LD V0, 0x01
+            0xFF, 0xFF, // 20e: not an instruction
+        ]);
+
+        let code: Vec<_> = find_code(&memory, 0x200).into_iter().collect();
+        assert_eq!(vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20A, 0x20C], code);
+    }
+
+    #[test]
+    fn counts_accesses() {
+        let mut coverage = Coverage::new(16);
+        coverage.record_execution(2);
+        coverage.record_execution(2);
+        coverage.record_read(4..6);
+        coverage.record_write(14..20);
+
+        assert_eq!(2, coverage.executions()[2]);
+        assert_eq!([0, 1, 1, 0], coverage.reads()[3..7]);
+        assert_eq!([1, 1], coverage.writes()[14..]);
+    }
+
+    #[test]
+    fn report() {
+        let memory = memory(&[
+            0x30, 0x01, // 200: SE V0, 0x01
+            0x12, 0x00, // 202: JP 200
+            0x60, 0x01, // 204: LD V0, 0x01
+            0x12, 0x04, // 206: JP 204
+        ]);
+        let mut coverage = Coverage::new(memory.len());
+        coverage.record_execution(0x200);
+        coverage.record_execution(0x202);
+        coverage.record_execution(0x300);
+
+        let report = CoverageReport::new(&coverage, &memory, 0x200);
+        assert_eq!(8, report.code_bytes());
+        assert_eq!(4, report.executed_code_bytes());
+        assert_eq!(50., report.percent());
+        assert_eq!(vec![0x204..=0x207], report.unexecuted());
+        assert_eq!(vec![(0x300, 1)], report.outside_code());
+
+        let symbols = Symbols::parse("204 unused").unwrap();
+        let text = report.to_text(&symbols);
+        assert!(text.starts_with("Executed 4 of 8 code bytes"));
+        assert!(text.contains("0x204 (unused), 4 bytes"));
+    }
+}
@@ -0,0 +1,302 @@
+//! Which gamepad controls press which keypad keys.
+//!
+//! Every game puts up, down and fire on different keys, so besides a default mapping, ROMs
+//! can have their own. Mappings are kept in the config file, the ROMs' with their other
+//! overrides:
+//!
+//! ```toml
+//! [gamepad]
+//! dpup = "5"
+//! a = "6"
+//!
+//! [roms.0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b]
+//! name = "pong.ch8"
+//!
+//! [roms.0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b.gamepad]
+//! dpup = "1"
+//! dpdown = "4"
+//! ```
+//!
+//! Controls are named like SDL's game controller buttons, plus `leftx-`, `lefty+` and so
+//! on for the directions of the analog sticks.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{Config, Settings},
+    romdb::RomKeys,
+};
+
+pub const CONTROLS: [&str; 23] = [
+    "dpup",
+    "dpdown",
+    "dpleft",
+    "dpright",
+    "a",
+    "b",
+    "x",
+    "y",
+    "leftshoulder",
+    "rightshoulder",
+    "leftstick",
+    "rightstick",
+    "back",
+    "start",
+    "guide",
+    "leftx-",
+    "leftx+",
+    "lefty-",
+    "lefty+",
+    "rightx-",
+    "rightx+",
+    "righty-",
+    "righty+",
+];
+
+/// Keypad keys by control.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<String, String>",
+    into = "BTreeMap<String, String>"
+)]
+pub struct GamepadMapping {
+    controls: BTreeMap<String, u8>,
+}
+
+impl Default for GamepadMapping {
+    /// The directions on the keys most games move with (5, 7, 8 and 9, or W, A, S and D
+    /// on a keyboard), and the face buttons on the keys around them.
+    fn default() -> Self {
+        let controls = [
+            ("dpup", 0x5),
+            ("dpdown", 0x8),
+            ("dpleft", 0x7),
+            ("dpright", 0x9),
+            ("leftx-", 0x7),
+            ("leftx+", 0x9),
+            ("lefty-", 0x5),
+            ("lefty+", 0x8),
+            ("a", 0x6),
+            ("b", 0x4),
+            ("x", 0xE),
+            ("y", 0xD),
+            ("start", 0xF),
+            ("back", 0x0),
+        ];
+
+        Self {
+            controls: controls
+                .into_iter()
+                .map(|(control, key)| (control.to_string(), key))
+                .collect(),
+        }
+    }
+}
+
+impl GamepadMapping {
+    pub fn key(&self, control: &str) -> Option<u8> {
+        self.controls.get(control).copied()
+    }
+
+    /// Maps `control` to `key`, or unmaps it.
+    pub fn set(&mut self, control: &str, key: Option<u8>) {
+        match key {
+            Some(key) => self.controls.insert(control.to_string(), key & 0xF),
+            None => self.controls.remove(control),
+        };
+    }
+
+    /// This mapping with the directions and face buttons on the keys a ROM uses, where the
+    /// ROM database says which they are.
+    pub fn with_rom_keys(mut self, keys: &RomKeys) -> Self {
+        let controls: [(Option<u8>, &[&str]); 6] = [
+            (keys.up, &["dpup", "lefty-"]),
+            (keys.down, &["dpdown", "lefty+"]),
+            (keys.left, &["dpleft", "leftx-"]),
+            (keys.right, &["dpright", "leftx+"]),
+            (keys.a, &["a"]),
+            (keys.b, &["b"]),
+        ];
+
+        for (key, controls) in controls {
+            if let Some(key) = key {
+                for control in controls {
+                    self.set(control, Some(key));
+                }
+            }
+        }
+        self
+    }
+
+    /// The keys pressed by `controls`, one bit per key.
+    pub fn keys<'a>(&self, controls: impl IntoIterator<Item = &'a str>) -> u16 {
+        controls
+            .into_iter()
+            .filter_map(|control| self.key(control))
+            .fold(0, |keys, key| keys | 1 << key)
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for GamepadMapping {
+    type Error = anyhow::Error;
+
+    fn try_from(map: BTreeMap<String, String>) -> Result<Self, Self::Error> {
+        let mut controls = BTreeMap::new();
+
+        for (control, key) in map {
+            if !CONTROLS.contains(&control.as_str()) {
+                bail!("unknown gamepad control {control:?}");
+            }
+            let key = u8::from_str_radix(&key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .with_context(|| format!("invalid keypad key {key:?} for {control}"))?;
+            controls.insert(control, key);
+        }
+
+        Ok(Self { controls })
+    }
+}
+
+impl From<GamepadMapping> for BTreeMap<String, String> {
+    fn from(mapping: GamepadMapping) -> Self {
+        mapping
+            .controls
+            .into_iter()
+            .map(|(control, key)| (control, format!("{key:X}")))
+            .collect()
+    }
+}
+
+/// The gamepad mappings in the config file, and the ROM they're being used for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GamepadSettings {
+    /// The mapping of ROMs without their own.
+    pub default: GamepadMapping,
+    /// The ROM's own mapping, if it has one.
+    pub rom: Option<GamepadMapping>,
+}
+
+impl GamepadSettings {
+    /// The mappings `config` has for the ROM with SHA-1 `rom_sha1`.
+    pub fn new(config: &Config, rom_sha1: &str) -> Self {
+        Self {
+            default: config.gamepad.clone().unwrap_or_default(),
+            rom: config
+                .roms
+                .get(rom_sha1)
+                .and_then(|rom| rom.gamepad.clone()),
+        }
+    }
+
+    /// The ROM's own mapping if it has one, and otherwise the default.
+    pub fn mapping(&self) -> &GamepadMapping {
+        self.rom.as_ref().unwrap_or(&self.default)
+    }
+
+    pub fn mapping_mut(&mut self) -> &mut GamepadMapping {
+        self.rom.as_mut().unwrap_or(&mut self.default)
+    }
+
+    pub fn has_rom_profile(&self) -> bool {
+        self.rom.is_some()
+    }
+
+    /// Gives the ROM its own mapping, starting from the default, or goes back to the
+    /// default.
+    pub fn set_rom_profile(&mut self, enabled: bool) {
+        if !enabled {
+            self.rom = None;
+        } else if self.rom.is_none() {
+            self.rom = Some(self.default.clone());
+        }
+    }
+
+    /// Uses the default mapping adapted to the ROM's `keys`, unless the ROM already has a
+    /// mapping. The mapping is only kept if it's saved.
+    pub fn apply_rom_keys(&mut self, keys: &RomKeys) {
+        if *keys == RomKeys::default() || self.has_rom_profile() {
+            return;
+        }
+
+        self.rom = Some(self.default.clone().with_rom_keys(keys));
+    }
+
+    /// Saves the mappings to the config file of `settings`, the ROM's as its overrides.
+    pub fn save(&self, settings: &mut Settings) -> anyhow::Result<()> {
+        let (default, rom) = (self.default.clone(), self.rom.clone());
+        let (rom_sha1, rom_name) = (settings.rom_sha1.clone(), settings.rom_name.clone());
+
+        settings.update(|config| {
+            config.gamepad = Some(default);
+            match rom {
+                Some(mapping) => {
+                    let rom = config.roms.entry(rom_sha1).or_default();
+                    rom.name.get_or_insert(rom_name);
+                    rom.gamepad = Some(mapping);
+                }
+                None => {
+                    if let Some(rom) = config.roms.get_mut(&rom_sha1) {
+                        rom.gamepad = None;
+                    }
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let mut config: Config =
+            toml::from_str("[gamepad]\ndpup = \"2\"\n[roms.abc.gamepad]\ndpup = \"1\"\nb = \"C\"")
+                .unwrap();
+
+        let pong = GamepadSettings::new(&config, "abc");
+        assert_eq!(Some(0x1), pong.mapping().key("dpup"));
+        assert_eq!(Some(0xC), pong.mapping().key("b"));
+        assert_eq!(None, pong.mapping().key("a"));
+
+        let mut other = GamepadSettings::new(&config, "def");
+        assert_eq!(Some(0x2), other.mapping().key("dpup"));
+        other.apply_rom_keys(&RomKeys {
+            a: Some(0xA),
+            ..RomKeys::default()
+        });
+        assert!(other.has_rom_profile());
+        assert_eq!(Some(0xA), other.mapping().key("a"));
+
+        config.gamepad = None;
+        assert_eq!(
+            Some(0x5),
+            GamepadSettings::new(&config, "def").mapping().key("dpup")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_mappings() {
+        assert!(toml::from_str::<Config>("[gamepad]\nturbo = \"1\"").is_err());
+        assert!(toml::from_str::<Config>("[roms.abc.gamepad]\na = \"10\"").is_err());
+    }
+
+    #[test]
+    fn pressed_controls_to_keys() {
+        let mapping = GamepadMapping::default();
+        assert_eq!(1 << 0x5 | 1 << 0x6, mapping.keys(["dpup", "a", "guide"]));
+
+        let keys = RomKeys {
+            up: Some(0x1),
+            a: Some(0xA),
+            ..RomKeys::default()
+        };
+        let pong = mapping.with_rom_keys(&keys);
+        assert_eq!(1 << 0x1 | 1 << 0xA, pong.keys(["lefty-", "a"]));
+        assert_eq!(Some(0x8), pong.key("dpdown"));
+    }
+}
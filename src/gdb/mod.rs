@@ -0,0 +1,628 @@
+//! A GDB remote serial protocol stub, so that ROMs can be debugged from gdb or lldb.
+//!
+//! Registers are numbered V0-VF (0-15, 8 bits each), I (16, 16 bits), PC (17, 16 bits),
+//! then SP, DT and ST (18-20, 8 bits each), as described to the client by the target
+//! description XML. Memory accesses address the 4K memory of the [`Chip8`](crate::chip8::Chip8).
+//!
+//! `monitor` commands give access to the ROM's [symbols](crate::symbols::Symbols), e.g.
+//! `monitor break draw_player` or `monitor info symbol 2a4`.
+
+use std::{
+    fmt::Write as _,
+    io::{self, Read},
+    net::{Ipv4Addr, TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use packet::{Incoming, PacketReader};
+
+use crate::Chip8Runner;
+
+mod packet;
+
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+const NUM_REGISTERS: usize = 21;
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+
+// How many instructions to run between checks for a client interrupt while continuing.
+const INTERRUPT_POLL_STEPS: u32 = 64;
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.patata.chip8.core">
+    <reg name="v0" bitsize="8" regnum="0" type="uint8"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Resume {
+    Step,
+    Continue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Reply(String),
+    Resume(Resume),
+    Detach,
+    Kill,
+}
+
+impl Action {
+    fn ok() -> Self {
+        Self::Reply("OK".to_string())
+    }
+
+    fn error() -> Self {
+        Self::Reply("E01".to_string())
+    }
+
+    fn unsupported() -> Self {
+        Self::Reply(String::new())
+    }
+}
+
+#[derive(Debug)]
+pub struct GdbStub {
+    runner: Chip8Runner,
+    no_ack: bool,
+}
+
+impl GdbStub {
+    pub fn new(runner: Chip8Runner) -> Self {
+        Self {
+            runner,
+            no_ack: false,
+        }
+    }
+
+    pub fn runner(&self) -> &Chip8Runner {
+        &self.runner
+    }
+
+    pub fn runner_mut(&mut self) -> &mut Chip8Runner {
+        &mut self.runner
+    }
+
+    /// Listens on localhost and serves debugger clients, one at a time, until a client
+    /// kills the target.
+    pub fn listen(&mut self, port: u16) -> anyhow::Result<()> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        info!("waiting for a GDB connection on {}", listener.local_addr()?);
+
+        for stream in listener.incoming() {
+            let stream = stream?;
+            info!("GDB client connected from {}", stream.peer_addr()?);
+            self.no_ack = false;
+
+            match self.serve(stream) {
+                Ok(true) => continue,
+                Ok(false) => break,
+                Err(err) => warn!("GDB connection closed: {err}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Serves a single client. Returns whether to keep listening for other clients.
+    fn serve(&mut self, stream: TcpStream) -> io::Result<bool> {
+        stream.set_nodelay(true)?;
+        let mut out = stream.try_clone()?;
+        let mut reader = PacketReader::new(stream);
+
+        loop {
+            let packet = match reader.read() {
+                Ok(Some(Incoming::Packet(p))) => p,
+                Ok(Some(Incoming::Interrupt)) => continue,
+                Ok(None) => return Ok(true),
+                Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                    io::Write::write_all(&mut out, b"-")?;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if !self.no_ack {
+                io::Write::write_all(&mut out, b"+")?;
+            }
+
+            let reply = match self.handle(&packet) {
+                Action::Reply(reply) => reply,
+                Action::Resume(how) => self.resume(how, || interrupted(&mut reader))?,
+                Action::Detach => {
+                    packet::write_packet(&mut out, "OK")?;
+                    return Ok(true);
+                }
+                Action::Kill => return Ok(false),
+            };
+
+            packet::write_packet(&mut out, &reply)?;
+
+            if packet == "QStartNoAckMode" {
+                self.no_ack = true;
+            }
+        }
+    }
+
+    fn handle(&mut self, packet: &str) -> Action {
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+
+        match cmd {
+            "?" => Action::Reply(format!("S{SIGTRAP:02x}")),
+            "g" => Action::Reply((0..NUM_REGISTERS).map(|r| self.read_register(r)).collect()),
+            "G" => self.write_all_registers(args),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .filter(|&r| r < NUM_REGISTERS)
+                .map_or_else(Action::error, |r| Action::Reply(self.read_register(r))),
+            "P" => args
+                .split_once('=')
+                .and_then(|(r, v)| Some((usize::from_str_radix(r, 16).ok()?, parse_hex(v)?)))
+                .filter(|&(r, ref v)| self.write_register(r, v))
+                .map_or_else(Action::error, |_| Action::ok()),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" | "z" => self.set_breakpoint(args, cmd == "Z"),
+            "s" => Action::Resume(Resume::Step),
+            "c" => Action::Resume(Resume::Continue),
+            "H" => Action::ok(),
+            "D" => Action::Detach,
+            "k" => Action::Kill,
+            _ => match packet.strip_prefix("qRcmd,") {
+                Some(command) => self.monitor(command),
+                None => self.handle_query(packet),
+            },
+        }
+    }
+
+    fn handle_query(&self, packet: &str) -> Action {
+        if packet.starts_with("qSupported") {
+            return Action::Reply(
+                "PacketSize=1000;qXfer:features:read+;swbreak+;QStartNoAckMode+".to_string(),
+            );
+        }
+
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            return read_chunk(TARGET_XML, range);
+        }
+
+        match packet {
+            "QStartNoAckMode" | "qSymbol::" => Action::ok(),
+            "qAttached" => Action::Reply("1".to_string()),
+            "qC" => Action::Reply("QC1".to_string()),
+            "qfThreadInfo" => Action::Reply("m1".to_string()),
+            "qsThreadInfo" => Action::Reply("l".to_string()),
+            _ => Action::unsupported(),
+        }
+    }
+
+    /// Runs a `monitor` command, given as hex, replying with its hex encoded output.
+    fn monitor(&mut self, hex: &str) -> Action {
+        let Some(command) = parse_hex(hex).and_then(|b| String::from_utf8(b).ok()) else {
+            return Action::error();
+        };
+
+        let symbols = self.runner.symbols();
+        let words: Vec<_> = command.split_whitespace().collect();
+        let output = match words[..] {
+            ["break" | "delete", location] => match symbols.resolve(location) {
+                Some(addr) => {
+                    let name = symbols.name(addr);
+                    if words[0] == "break" {
+                        self.runner.add_breakpoint(addr);
+                        format!("Breakpoint set at {addr:#05x} ({name})\n")
+                    } else {
+                        self.runner.remove_breakpoint(addr);
+                        format!("Breakpoint removed from {addr:#05x} ({name})\n")
+                    }
+                }
+                None => format!("No symbol {location:?}\n"),
+            },
+            ["info", "symbol", location] => match symbols.resolve(location) {
+                Some(addr) => match symbols.locate(addr) {
+                    Some(place) => format!("{addr:#05x} is {place}\n"),
+                    None => format!("No symbol matches {addr:#05x}\n"),
+                },
+                None => format!("No symbol {location:?}\n"),
+            },
+            ["symbols"] => symbols.iter().fold(String::new(), |mut s, (addr, label)| {
+                let _ = writeln!(s, "{addr:#05x} {label}");
+                s
+            }),
+            _ => "Commands: break LOCATION, delete LOCATION, info symbol LOCATION, symbols\n"
+                .to_string(),
+        };
+
+        Action::Reply(hex_bytes(output.as_bytes()))
+    }
+
+    fn read_register(&self, reg: usize) -> String {
+        let chip8 = &self.runner.chip8;
+        match reg {
+            0..=15 => format!("{:02x}", chip8.registers[reg]),
+            REG_I => hex_u16(chip8.index.get() as u16),
+            REG_PC => hex_u16(chip8.program_counter),
+            REG_SP => format!("{:02x}", chip8.stack_pointer),
+            REG_DT => format!("{:02x}", chip8.delay_timer.cur_count()),
+            REG_ST => format!("{:02x}", chip8.sound_timer.cur_count()),
+            _ => unreachable!("invalid register {reg}"),
+        }
+    }
+
+    /// Writes a register from its little-endian bytes. Returns false if the register does
+    /// not exist or the value is out of range for it.
+    fn write_register(&mut self, reg: usize, bytes: &[u8]) -> bool {
+        let chip8 = &mut self.runner.chip8;
+        let word = match *bytes {
+            [lo] => u16::from(lo),
+            [lo, hi] => u16::from_le_bytes([lo, hi]),
+            _ => return false,
+        };
+        let byte = u8::try_from(word).ok();
+
+        match (reg, byte) {
+            (0..=15, Some(b)) => chip8.registers[reg] = b,
+            (REG_I, _) if word <= 0xFFF => chip8.index.load(word),
+            (REG_PC, _) if (word as usize) < chip8.memory.len() => chip8.program_counter = word,
+            (REG_SP, Some(b)) if (b as usize) <= chip8.stack.len() => chip8.stack_pointer = b,
+            (REG_DT, Some(b)) => chip8.delay_timer.set(b),
+            (REG_ST, Some(b)) => chip8.sound_timer.set(b),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn write_all_registers(&mut self, hex: &str) -> Action {
+        let Some(bytes) = parse_hex(hex) else {
+            return Action::error();
+        };
+
+        let sizes = (0..NUM_REGISTERS).map(|r| if r == REG_I || r == REG_PC { 2 } else { 1 });
+        if bytes.len() != sizes.clone().sum::<usize>() {
+            return Action::error();
+        }
+
+        let mut offset = 0;
+        for (reg, size) in sizes.enumerate() {
+            if !self.write_register(reg, &bytes[offset..offset + size]) {
+                return Action::error();
+            }
+            offset += size;
+        }
+
+        Action::ok()
+    }
+
+    fn read_memory(&self, args: &str) -> Action {
+        let memory = &self.runner.chip8.memory;
+
+        parse_addr_len(args)
+            .and_then(|(addr, len)| memory.get(addr..addr.checked_add(len)?))
+            .map_or_else(Action::error, |bytes| Action::Reply(hex_bytes(bytes)))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Action {
+        let memory = &mut self.runner.chip8.memory;
+
+        let write = args.split_once(':').and_then(|(range, data)| {
+            let (addr, len) = parse_addr_len(range)?;
+            let data = parse_hex(data).filter(|d| d.len() == len)?;
+            memory
+                .get_mut(addr..addr.checked_add(len)?)?
+                .copy_from_slice(&data);
+            Some(())
+        });
+
+        write.map_or_else(Action::error, |()| Action::ok())
+    }
+
+    fn set_breakpoint(&mut self, args: &str, insert: bool) -> Action {
+        let mut parts = args.split(',');
+        let (Some(kind), Some(addr)) = (parts.next(), parts.next()) else {
+            return Action::error();
+        };
+
+        // Only software breakpoints are supported; hardware ones can't be told apart anyway.
+        if kind != "0" && kind != "1" {
+            return Action::unsupported();
+        }
+
+        let Ok(addr) = u16::from_str_radix(addr, 16) else {
+            return Action::error();
+        };
+
+        if insert {
+            self.runner.add_breakpoint(addr);
+        } else {
+            self.runner.remove_breakpoint(addr);
+        }
+
+        Action::ok()
+    }
+
+    /// Steps a single instruction, returning the signal to report if it faulted instead.
+    /// The program counter is then left at the instruction.
+    fn step(&mut self) -> Option<u8> {
+        self.runner.step();
+        self.runner.fault().map(|_| SIGILL)
+    }
+
+    /// Runs the target until it stops, returning the stop reply packet. `interrupted` is
+    /// polled now and then for whether the client asked to stop.
+    fn resume(
+        &mut self,
+        how: Resume,
+        mut interrupted: impl FnMut() -> io::Result<bool>,
+    ) -> io::Result<String> {
+        if how == Resume::Step {
+            let signal = self.step().unwrap_or(SIGTRAP);
+            return Ok(format!("S{signal:02x}"));
+        }
+
+        let tick_duration = Duration::from_secs_f64(1. / self.runner.tick_hz() as f64);
+        let started = Instant::now();
+        let mut steps = 0u32;
+
+        loop {
+            if let Some(signal) = self.step() {
+                return Ok(format!("S{signal:02x}"));
+            }
+
+            if self
+                .runner
+                .breakpoints()
+                .contains(&self.runner.chip8.program_counter)
+            {
+                return Ok(format!("T{SIGTRAP:02x}swbreak:;"));
+            }
+
+            steps += 1;
+            if steps.is_multiple_of(INTERRUPT_POLL_STEPS) {
+                if interrupted()? {
+                    return Ok(format!("S{SIGINT:02x}"));
+                }
+
+                // Keep executing at the runner's configured speed.
+                let target = tick_duration * steps;
+                if let Some(ahead) = target.checked_sub(started.elapsed()) {
+                    std::thread::sleep(ahead);
+                }
+            }
+        }
+    }
+}
+
+/// Checks, without blocking, whether the client sent an interrupt.
+fn interrupted(reader: &mut PacketReader<TcpStream>) -> io::Result<bool> {
+    let stream = reader.get_mut();
+    stream.set_nonblocking(true)?;
+
+    let mut buf = [0];
+    let result = match stream.read(&mut buf) {
+        Ok(1) => Ok(buf[0] == 0x03),
+        Ok(_) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Err(err) if err.kind() == io::ErrorKind::WouldBlock => Ok(false),
+        Err(err) => Err(err),
+    };
+
+    stream.set_nonblocking(false)?;
+    result
+}
+
+fn read_chunk(document: &str, range: &str) -> Action {
+    let Some((offset, len)) = parse_addr_len(range) else {
+        return Action::error();
+    };
+
+    let bytes = document.as_bytes();
+    let start = offset.min(bytes.len());
+    let end = start.saturating_add(len).min(bytes.len());
+    let prefix = if end == bytes.len() { 'l' } else { 'm' };
+
+    Action::Reply(format!(
+        "{prefix}{}",
+        String::from_utf8_lossy(&bytes[start..end])
+    ))
+}
+
+fn parse_addr_len(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((
+        usize::from_str_radix(addr, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut s, b| {
+        let _ = write!(s, "{b:02x}");
+        s
+    })
+}
+
+fn hex_u16(v: u16) -> String {
+    hex_bytes(&v.to_le_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{chip8::Chip8, symbols::Symbols};
+
+    fn stub() -> GdbStub {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(&[0x60, 0x2A, 0x12, 0x00]).unwrap();
+        GdbStub::new(Chip8Runner::new(chip8, 700).unwrap())
+    }
+
+    fn reply(stub: &mut GdbStub, packet: &str) -> String {
+        match stub.handle(packet) {
+            Action::Reply(r) => r,
+            action => panic!("expected a reply, got {action:?}"),
+        }
+    }
+
+    #[test]
+    fn read_registers() {
+        let mut stub = stub();
+        stub.runner.chip8.registers[0xF] = 0xAB;
+        stub.runner.chip8.index.load(0x123);
+
+        let regs = reply(&mut stub, "g");
+        assert_eq!(16 * 2 + 2 * 4 + 3 * 2, regs.len());
+        assert_eq!("ab", &regs[30..32]);
+        assert_eq!("2301", &regs[32..36]);
+        assert_eq!("0002", &regs[36..40]);
+
+        assert_eq!("0002", reply(&mut stub, "p11"));
+        assert_eq!("E01", reply(&mut stub, "p15"));
+    }
+
+    #[test]
+    fn write_registers() {
+        let mut stub = stub();
+
+        assert_eq!("OK", reply(&mut stub, "P3=7f"));
+        assert_eq!(0x7F, stub.runner.chip8.registers[3]);
+
+        assert_eq!("OK", reply(&mut stub, "P11=0403"));
+        assert_eq!(0x304, stub.runner.chip8.program_counter);
+
+        // I is only 12 bits wide, and SP can't exceed the stack size.
+        assert_eq!("E01", reply(&mut stub, "P10=00f0"));
+        assert_eq!("E01", reply(&mut stub, "P12=11"));
+
+        let all = format!("{}{}{}{}", "01".repeat(16), "0002", "0002", "000506");
+        assert_eq!("OK", reply(&mut stub, &format!("G{all}")));
+        assert_eq!([1; 16], stub.runner.chip8.registers);
+        assert_eq!(6, stub.runner.chip8.sound_timer.cur_count());
+    }
+
+    #[test]
+    fn memory_access() {
+        let mut stub = stub();
+
+        assert_eq!("602a", reply(&mut stub, "m200,2"));
+        assert_eq!("OK", reply(&mut stub, "M300,3:deadbe"));
+        assert_eq!([0xDE, 0xAD, 0xBE], stub.runner.chip8.memory[0x300..0x303]);
+
+        assert_eq!("E01", reply(&mut stub, "mfff,2"));
+        assert_eq!("E01", reply(&mut stub, "M300,2:de"));
+    }
+
+    #[test]
+    fn breakpoints_and_resume() {
+        let mut stub = stub();
+
+        assert_eq!("OK", reply(&mut stub, "Z0,202,2"));
+        assert!(stub.runner.breakpoints().contains(&0x202));
+        assert_eq!("OK", reply(&mut stub, "z0,202,2"));
+        assert!(stub.runner.breakpoints().is_empty());
+
+        assert_eq!(Action::Resume(Resume::Step), stub.handle("s"));
+        assert_eq!(Action::Resume(Resume::Continue), stub.handle("c"));
+    }
+
+    #[test]
+    fn faults_stop_with_sigill() {
+        // LD V0, 0x2A; then 0xFFFF, which isn't an instruction.
+        let faulting = || {
+            let mut stub = stub();
+            stub.runner.chip8.memory[0x202..0x204].copy_from_slice(&[0xFF, 0xFF]);
+            stub
+        };
+        let never = || Ok(false);
+
+        let mut stub = faulting();
+        assert_eq!("S05", stub.resume(Resume::Step, never).unwrap());
+        assert_eq!("S04", stub.resume(Resume::Step, never).unwrap());
+        assert_eq!(0x202, stub.runner.chip8.program_counter);
+        assert_eq!("S04", stub.resume(Resume::Continue, never).unwrap());
+
+        // Continuing runs up to the fault, and no further.
+        let mut stub = faulting();
+        assert_eq!("S04", stub.resume(Resume::Continue, never).unwrap());
+        assert_eq!(0x2A, stub.runner.chip8.registers[0]);
+        assert_eq!(0x202, stub.runner.chip8.program_counter);
+    }
+
+    #[test]
+    fn monitor_commands() {
+        let mut stub = stub();
+        stub.runner = Chip8Runner::new(Chip8::default(), 700)
+            .unwrap()
+            .with_symbols(Symbols::parse("200 main\n2a4 draw_player").unwrap());
+
+        let monitor = |stub: &mut GdbStub, command: &str| {
+            let hex = reply(stub, &format!("qRcmd,{}", hex_bytes(command.as_bytes())));
+            String::from_utf8(parse_hex(&hex).unwrap()).unwrap()
+        };
+
+        assert_eq!(
+            "Breakpoint set at 0x2a4 (draw_player)\n",
+            monitor(&mut stub, "break draw_player")
+        );
+        assert!(stub.runner.breakpoints().contains(&0x2A4));
+        assert_eq!("0x204 is main+4\n", monitor(&mut stub, "info symbol 204"));
+        assert_eq!(
+            "0x200 main\n0x2a4 draw_player\n",
+            monitor(&mut stub, "symbols")
+        );
+        assert_eq!("No symbol \"nope\"\n", monitor(&mut stub, "delete nope"));
+    }
+
+    #[test]
+    fn target_description() {
+        let mut stub = stub();
+
+        let first = reply(&mut stub, "qXfer:features:read:target.xml:0,10");
+        assert_eq!("m<?xml vers", &first[..11]);
+
+        let rest = reply(&mut stub, "qXfer:features:read:target.xml:10,fff");
+        assert!(rest.starts_with('l'));
+        assert!(rest.ends_with("</target>\n"));
+    }
+}
@@ -0,0 +1,146 @@
+//! Framing for GDB remote serial protocol packets: `$<data>#<checksum>`.
+
+use std::io::{self, Read, Write};
+
+const INTERRUPT: u8 = 0x03;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Incoming {
+    Packet(String),
+    /// Ctrl-C sent by the client while the target is running.
+    Interrupt,
+}
+
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+/// Wraps `data` into a packet, escaping the bytes that have special meaning in the protocol.
+pub fn encode(data: &str) -> Vec<u8> {
+    let mut body = Vec::with_capacity(data.len());
+    for &b in data.as_bytes() {
+        if matches!(b, b'$' | b'#' | b'}' | b'*') {
+            body.extend_from_slice(&[b'}', b ^ 0x20]);
+        } else {
+            body.push(b);
+        }
+    }
+
+    let mut packet = Vec::with_capacity(body.len() + 4);
+    packet.push(b'$');
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(format!("#{:02x}", checksum(&body)).as_bytes());
+    packet
+}
+
+/// Reads packets from a byte stream, skipping acknowledgements.
+pub struct PacketReader<R> {
+    inner: R,
+}
+
+impl<R: Read> PacketReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner }
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut buf = [0];
+        match self.inner.read(&mut buf)? {
+            0 => Ok(None),
+            _ => Ok(Some(buf[0])),
+        }
+    }
+
+    /// Reads the next packet or interrupt. Returns `None` once the stream is closed.
+    /// Packets with a bad checksum are reported as errors.
+    pub fn read(&mut self) -> io::Result<Option<Incoming>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(INTERRUPT) => return Ok(Some(Incoming::Interrupt)),
+                Some(b'$') => break,
+                // Acks (`+`/`-`) and line noise.
+                Some(_) => continue,
+            }
+        }
+
+        // The checksum covers the data as sent, i.e. before unescaping.
+        let mut raw = Vec::new();
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'#') => break,
+                Some(b) => raw.push(b),
+            }
+        }
+
+        let mut cs = [0; 2];
+        self.inner.read_exact(&mut cs)?;
+        let expected = std::str::from_utf8(&cs)
+            .ok()
+            .and_then(|s| u8::from_str_radix(s, 16).ok());
+
+        if expected != Some(checksum(&raw)) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "packet checksum mismatch",
+            ));
+        }
+
+        let mut body = Vec::with_capacity(raw.len());
+        let mut bytes = raw.into_iter();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'}' => body.extend(bytes.next().map(|b| b ^ 0x20)),
+                b => body.push(b),
+            }
+        }
+
+        Ok(Some(Incoming::Packet(
+            String::from_utf8_lossy(&body).into_owned(),
+        )))
+    }
+}
+
+pub fn write_packet(out: &mut impl Write, data: &str) -> io::Result<()> {
+    out.write_all(&encode(data))?;
+    out.flush()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_packet() {
+        assert_eq!(b"$OK#9a".to_vec(), encode("OK"));
+        assert_eq!(b"$}\x03#80".to_vec(), encode("#"));
+    }
+
+    #[test]
+    fn read_packets() {
+        let stream: &[u8] = b"+$g#67\x03$m200,2#5d";
+        let mut reader = PacketReader::new(stream);
+
+        assert_eq!(
+            Some(Incoming::Packet("g".to_string())),
+            reader.read().unwrap()
+        );
+        assert_eq!(Some(Incoming::Interrupt), reader.read().unwrap());
+        assert_eq!(
+            Some(Incoming::Packet("m200,2".to_string())),
+            reader.read().unwrap()
+        );
+        assert_eq!(None, reader.read().unwrap());
+    }
+
+    #[test]
+    fn bad_checksum() {
+        let stream: &[u8] = b"$g#00";
+        assert!(PacketReader::new(stream).read().is_err());
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+
+use crate::opcode::OpCode;
+
+/// A decoded CHIP-8 instruction, as listed in Cowgod's technical reference.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0nnn - SYS addr
+    Sys(u16),
+    /// 00E0 - CLS
+    Cls,
+    /// 00EE - RET
+    Ret,
+    /// 1nnn - JP addr
+    Jp(u16),
+    /// 2nnn - CALL addr
+    Call(u16),
+    /// 3xkk - SE Vx, byte
+    SeByte(u8, u8),
+    /// 4xkk - SNE Vx, byte
+    SneByte(u8, u8),
+    /// 5xy0 - SE Vx, Vy
+    SeReg(u8, u8),
+    /// 6xkk - LD Vx, byte
+    LdByte(u8, u8),
+    /// 7xkk - ADD Vx, byte
+    AddByte(u8, u8),
+    /// 8xy0 - LD Vx, Vy
+    LdReg(u8, u8),
+    /// 8xy1 - OR Vx, Vy
+    Or(u8, u8),
+    /// 8xy2 - AND Vx, Vy
+    And(u8, u8),
+    /// 8xy3 - XOR Vx, Vy
+    Xor(u8, u8),
+    /// 8xy4 - ADD Vx, Vy
+    AddReg(u8, u8),
+    /// 8xy5 - SUB Vx, Vy
+    Sub(u8, u8),
+    /// 8xy6 - SHR Vx {, Vy}
+    Shr(u8, u8),
+    /// 8xy7 - SUBN Vx, Vy
+    Subn(u8, u8),
+    /// 8xyE - SHL Vx {, Vy}
+    Shl(u8, u8),
+    /// 9xy0 - SNE Vx, Vy
+    SneReg(u8, u8),
+    /// Annn - LD I, addr
+    LdI(u16),
+    /// Bnnn - JP V0, addr
+    JpV0(u16),
+    /// Cxkk - RND Vx, byte
+    Rnd(u8, u8),
+    /// Dxyn - DRW Vx, Vy, nibble
+    Drw(u8, u8, u8),
+    /// Ex9E - SKP Vx
+    Skp(u8),
+    /// ExA1 - SKNP Vx
+    Sknp(u8),
+    /// Fx07 - LD Vx, DT
+    LdVxDt(u8),
+    /// Fx0A - LD Vx, K
+    LdVxK(u8),
+    /// Fx15 - LD DT, Vx
+    LdDtVx(u8),
+    /// Fx18 - LD ST, Vx
+    LdStVx(u8),
+    /// Fx1E - ADD I, Vx
+    AddIVx(u8),
+    /// Fx29 - LD F, Vx
+    LdFVx(u8),
+    /// Fx33 - LD B, Vx
+    LdBVx(u8),
+    /// Fx55 - LD [I], Vx
+    LdMemVx(u8),
+    /// Fx65 - LD Vx, [I]
+    LdVxMem(u8),
+}
+
+impl Instruction {
+    /// Decodes a raw 16-bit opcode, returning `None` if it is not a known instruction.
+    pub fn decode(raw: u16) -> Option<Self> {
+        let opcode = OpCode::from(raw);
+        let (x, y, n) = (opcode.x(), opcode.y(), opcode.n());
+        let (nnn, kk) = (opcode.nnn(), opcode.kk());
+
+        let instruction = match opcode.nibbles() {
+            (0x00, 0x00, 0x0E, 0x00) => Self::Cls,
+            (0x00, 0x00, 0x0E, 0x0E) => Self::Ret,
+            (0x00, _, _, _) => Self::Sys(nnn),
+            (0x01, _, _, _) => Self::Jp(nnn),
+            (0x02, _, _, _) => Self::Call(nnn),
+            (0x03, _, _, _) => Self::SeByte(x, kk),
+            (0x04, _, _, _) => Self::SneByte(x, kk),
+            (0x05, _, _, 0x00) => Self::SeReg(x, y),
+            (0x06, _, _, _) => Self::LdByte(x, kk),
+            (0x07, _, _, _) => Self::AddByte(x, kk),
+            (0x08, _, _, 0x00) => Self::LdReg(x, y),
+            (0x08, _, _, 0x01) => Self::Or(x, y),
+            (0x08, _, _, 0x02) => Self::And(x, y),
+            (0x08, _, _, 0x03) => Self::Xor(x, y),
+            (0x08, _, _, 0x04) => Self::AddReg(x, y),
+            (0x08, _, _, 0x05) => Self::Sub(x, y),
+            (0x08, _, _, 0x06) => Self::Shr(x, y),
+            (0x08, _, _, 0x07) => Self::Subn(x, y),
+            (0x08, _, _, 0x0E) => Self::Shl(x, y),
+            (0x09, _, _, 0x00) => Self::SneReg(x, y),
+            (0x0A, _, _, _) => Self::LdI(nnn),
+            (0x0B, _, _, _) => Self::JpV0(nnn),
+            (0x0C, _, _, _) => Self::Rnd(x, kk),
+            (0x0D, _, _, _) => Self::Drw(x, y, n),
+            (0x0E, _, 0x09, 0x0E) => Self::Skp(x),
+            (0x0E, _, 0x0A, 0x01) => Self::Sknp(x),
+            (0x0F, _, 0x00, 0x07) => Self::LdVxDt(x),
+            (0x0F, _, 0x00, 0x0A) => Self::LdVxK(x),
+            (0x0F, _, 0x01, 0x05) => Self::LdDtVx(x),
+            (0x0F, _, 0x01, 0x08) => Self::LdStVx(x),
+            (0x0F, _, 0x01, 0x0E) => Self::AddIVx(x),
+            (0x0F, _, 0x02, 0x09) => Self::LdFVx(x),
+            (0x0F, _, 0x03, 0x03) => Self::LdBVx(x),
+            (0x0F, _, 0x05, 0x05) => Self::LdMemVx(x),
+            (0x0F, _, 0x06, 0x05) => Self::LdVxMem(x),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    /// The raw opcode, the inverse of [`Instruction::decode`]. Operands are masked to their
+    /// field widths.
+    pub fn encode(self) -> u16 {
+        let nnn = |op: u16, nnn: u16| op | nnn & 0xFFF;
+        let xkk = |op: u16, x: u8, kk: u8| op | u16::from(x & 0xF) << 8 | u16::from(kk);
+        let xyn = |op: u16, x: u8, y: u8, n: u8| {
+            op | u16::from(x & 0xF) << 8 | u16::from(y & 0xF) << 4 | u16::from(n & 0xF)
+        };
+
+        match self {
+            Self::Sys(a) => nnn(0x0000, a),
+            Self::Cls => 0x00E0,
+            Self::Ret => 0x00EE,
+            Self::Jp(a) => nnn(0x1000, a),
+            Self::Call(a) => nnn(0x2000, a),
+            Self::SeByte(x, kk) => xkk(0x3000, x, kk),
+            Self::SneByte(x, kk) => xkk(0x4000, x, kk),
+            Self::SeReg(x, y) => xyn(0x5000, x, y, 0x0),
+            Self::LdByte(x, kk) => xkk(0x6000, x, kk),
+            Self::AddByte(x, kk) => xkk(0x7000, x, kk),
+            Self::LdReg(x, y) => xyn(0x8000, x, y, 0x0),
+            Self::Or(x, y) => xyn(0x8000, x, y, 0x1),
+            Self::And(x, y) => xyn(0x8000, x, y, 0x2),
+            Self::Xor(x, y) => xyn(0x8000, x, y, 0x3),
+            Self::AddReg(x, y) => xyn(0x8000, x, y, 0x4),
+            Self::Sub(x, y) => xyn(0x8000, x, y, 0x5),
+            Self::Shr(x, y) => xyn(0x8000, x, y, 0x6),
+            Self::Subn(x, y) => xyn(0x8000, x, y, 0x7),
+            Self::Shl(x, y) => xyn(0x8000, x, y, 0xE),
+            Self::SneReg(x, y) => xyn(0x9000, x, y, 0x0),
+            Self::LdI(a) => nnn(0xA000, a),
+            Self::JpV0(a) => nnn(0xB000, a),
+            Self::Rnd(x, kk) => xkk(0xC000, x, kk),
+            Self::Drw(x, y, n) => xyn(0xD000, x, y, n),
+            Self::Skp(x) => xkk(0xE000, x, 0x9E),
+            Self::Sknp(x) => xkk(0xE000, x, 0xA1),
+            Self::LdVxDt(x) => xkk(0xF000, x, 0x07),
+            Self::LdVxK(x) => xkk(0xF000, x, 0x0A),
+            Self::LdDtVx(x) => xkk(0xF000, x, 0x15),
+            Self::LdStVx(x) => xkk(0xF000, x, 0x18),
+            Self::AddIVx(x) => xkk(0xF000, x, 0x1E),
+            Self::LdFVx(x) => xkk(0xF000, x, 0x29),
+            Self::LdBVx(x) => xkk(0xF000, x, 0x33),
+            Self::LdMemVx(x) => xkk(0xF000, x, 0x55),
+            Self::LdVxMem(x) => xkk(0xF000, x, 0x65),
+        }
+    }
+
+    /// The address operand `nnn`, for the instructions that take one.
+    pub fn address(self) -> Option<u16> {
+        match self {
+            Self::Sys(nnn) | Self::Jp(nnn) | Self::Call(nnn) | Self::LdI(nnn) | Self::JpV0(nnn) => {
+                Some(nnn)
+            }
+            _ => None,
+        }
+    }
+
+    /// The instruction's mnemonic, e.g. `"DRW"`. Used to filter instructions by kind.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Self::Sys(_) => "SYS",
+            Self::Cls => "CLS",
+            Self::Ret => "RET",
+            Self::Jp(_) | Self::JpV0(_) => "JP",
+            Self::Call(_) => "CALL",
+            Self::SeByte(..) | Self::SeReg(..) => "SE",
+            Self::SneByte(..) | Self::SneReg(..) => "SNE",
+            Self::LdByte(..)
+            | Self::LdReg(..)
+            | Self::LdI(_)
+            | Self::LdVxDt(_)
+            | Self::LdVxK(_)
+            | Self::LdDtVx(_)
+            | Self::LdStVx(_)
+            | Self::LdFVx(_)
+            | Self::LdBVx(_)
+            | Self::LdMemVx(_)
+            | Self::LdVxMem(_) => "LD",
+            Self::AddByte(..) | Self::AddReg(..) | Self::AddIVx(_) => "ADD",
+            Self::Or(..) => "OR",
+            Self::And(..) => "AND",
+            Self::Xor(..) => "XOR",
+            Self::Sub(..) => "SUB",
+            Self::Shr(..) => "SHR",
+            Self::Subn(..) => "SUBN",
+            Self::Shl(..) => "SHL",
+            Self::Rnd(..) => "RND",
+            Self::Drw(..) => "DRW",
+            Self::Skp(_) => "SKP",
+            Self::Sknp(_) => "SKNP",
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let m = self.mnemonic();
+        match *self {
+            Self::Cls | Self::Ret => write!(f, "{m}"),
+            Self::Sys(nnn) | Self::Jp(nnn) | Self::Call(nnn) => write!(f, "{m} {nnn:#05x}"),
+            Self::JpV0(nnn) => write!(f, "{m} V0, {nnn:#05x}"),
+            Self::LdI(nnn) => write!(f, "{m} I, {nnn:#05x}"),
+            Self::SeByte(x, kk)
+            | Self::SneByte(x, kk)
+            | Self::LdByte(x, kk)
+            | Self::AddByte(x, kk)
+            | Self::Rnd(x, kk) => write!(f, "{m} V{x:X}, {kk:#04x}"),
+            Self::SeReg(x, y)
+            | Self::LdReg(x, y)
+            | Self::Or(x, y)
+            | Self::And(x, y)
+            | Self::Xor(x, y)
+            | Self::AddReg(x, y)
+            | Self::Sub(x, y)
+            | Self::Shr(x, y)
+            | Self::Subn(x, y)
+            | Self::Shl(x, y)
+            | Self::SneReg(x, y) => write!(f, "{m} V{x:X}, V{y:X}"),
+            Self::Drw(x, y, n) => write!(f, "{m} V{x:X}, V{y:X}, {n}"),
+            Self::Skp(x) | Self::Sknp(x) => write!(f, "{m} V{x:X}"),
+            Self::LdVxDt(x) => write!(f, "{m} V{x:X}, DT"),
+            Self::LdVxK(x) => write!(f, "{m} V{x:X}, K"),
+            Self::LdDtVx(x) => write!(f, "{m} DT, V{x:X}"),
+            Self::LdStVx(x) => write!(f, "{m} ST, V{x:X}"),
+            Self::AddIVx(x) => write!(f, "{m} I, V{x:X}"),
+            Self::LdFVx(x) => write!(f, "{m} F, V{x:X}"),
+            Self::LdBVx(x) => write!(f, "{m} B, V{x:X}"),
+            Self::LdMemVx(x) => write!(f, "{m} [I], V{x:X}"),
+            Self::LdVxMem(x) => write!(f, "{m} V{x:X}, [I]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn decode_known() {
+        assert_eq!(Some(Instruction::Cls), Instruction::decode(0x00E0));
+        assert_eq!(Some(Instruction::Call(0x2A4)), Instruction::decode(0x22A4));
+        assert_eq!(Some(Instruction::Drw(1, 2, 5)), Instruction::decode(0xD125));
+        assert_eq!(Some(Instruction::LdMemVx(3)), Instruction::decode(0xF355));
+        assert_eq!(Some(Instruction::LdVxMem(3)), Instruction::decode(0xF365));
+    }
+
+    #[test]
+    fn decode_unknown() {
+        assert_eq!(None, Instruction::decode(0x5121));
+        assert_eq!(None, Instruction::decode(0xFFFF));
+    }
+
+    #[test]
+    fn encode_inverts_decode() {
+        for raw in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::decode(raw) {
+                assert_eq!(raw, instruction.encode(), "{instruction}");
+            }
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!("LD VA, 0x02", Instruction::LdByte(0xA, 0x02).to_string());
+        assert_eq!("JP 0x2a4", Instruction::Jp(0x2A4).to_string());
+        assert_eq!("DRW V1, V2, 5", Instruction::Drw(1, 2, 5).to_string());
+        assert_eq!("LD [I], VF", Instruction::LdMemVx(0xF).to_string());
+    }
+}
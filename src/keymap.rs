@@ -0,0 +1,98 @@
+//! Which keyboard keys stand in for the 16 keys of the CHIP-8 keypad.
+//!
+//! Keymaps are written as the 16 characters covering the keypad, row by row, the way it's
+//! laid out on the COSMAC VIP:
+//!
+//! ```text
+//! 1 2 3 C      1 2 3 4
+//! 4 5 6 D  ->  q w e r
+//! 7 8 9 E      a s d f
+//! A 0 B F      z x c v
+//! ```
+
+use std::{fmt, str::FromStr};
+
+/// The keypad keys, row by row.
+pub const KEYPAD_LAYOUT: [u8; 16] = [
+    0x1, 0x2, 0x3, 0xC, //
+    0x4, 0x5, 0x6, 0xD, //
+    0x7, 0x8, 0x9, 0xE, //
+    0xA, 0x0, 0xB, 0xF,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keymap {
+    // The keyboard key at each position of `KEYPAD_LAYOUT`.
+    keys: [char; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        "1234qwerasdfzxcv".parse().unwrap()
+    }
+}
+
+impl Keymap {
+    /// The keypad key that a typed character stands for, ignoring case.
+    pub fn key_for_char(&self, c: char) -> Option<u8> {
+        let c = c.to_ascii_lowercase();
+        self.keys
+            .iter()
+            .position(|&k| k == c)
+            .map(|i| KEYPAD_LAYOUT[i])
+    }
+
+    /// The keyboard character standing for a keypad key.
+    pub fn char_for_key(&self, key: u8) -> char {
+        let i = KEYPAD_LAYOUT.iter().position(|&k| k == key).unwrap();
+        self.keys[i]
+    }
+}
+
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.keys.iter().try_for_each(|c| write!(f, "{c}"))
+    }
+}
+
+impl FromStr for Keymap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().map(|c| c.to_ascii_lowercase()).collect();
+        let keys: [char; 16] = chars
+            .clone()
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("expected 16 keys in a keymap, got {}", chars.len()))?;
+
+        if let Some(c) = keys
+            .iter()
+            .find(|c| keys.iter().filter(|k| k == c).count() > 1)
+        {
+            anyhow::bail!("{c:?} appears more than once in keymap {s:?}");
+        }
+
+        Ok(Self { keys })
+    }
+}
+
+serde_string!(Keymap);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn default_layout() {
+        let keymap = Keymap::default();
+
+        assert_eq!(Some(0xC), keymap.key_for_char('4'));
+        assert_eq!(Some(0x0), keymap.key_for_char('X'));
+        assert_eq!(None, keymap.key_for_char('p'));
+        assert_eq!('v', keymap.char_for_key(0xF));
+        assert_eq!("1234qwerasdfzxcv", keymap.to_string());
+
+        assert!("1234".parse::<Keymap>().is_err());
+        assert!("1134qwerasdfzxcv".parse::<Keymap>().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Where programs and the font go in memory, how much memory there is, and the size of
+//! the display.
+//!
+//! Layouts are written as a preset name (see [`MemoryLayout::PRESETS`]), optionally
+//! followed by overrides, e.g. `chip8,font=0x0,memory=0x2000`. The keys are `start` (where
+//! programs are loaded and start running), `font`, `memory` and `display` (`WxH`).
+
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Context};
+
+use crate::fonts::FONT_SET;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// Where programs are loaded, and start running.
+    pub program_start: u16,
+    /// Where the hex digit font is.
+    pub font_start: u16,
+    /// Bytes of memory, up to 16M. Code runs from the first 4K, but MEGA-CHIP's `01nn nnnn`
+    /// points I at any of it.
+    pub memory_size: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self::PRESETS[0].1
+    }
+}
+
+impl MemoryLayout {
+    pub const PRESETS: [(&'static str, MemoryLayout); 5] = [
+        (
+            "chip8",
+            MemoryLayout {
+                program_start: 0x200,
+                font_start: 0x50,
+                memory_size: 0x1000,
+                width: 64,
+                height: 32,
+            },
+        ),
+        (
+            // The ETI-660 learner's computer, whose interpreter took up memory until 0x600.
+            "eti660",
+            MemoryLayout {
+                program_start: 0x600,
+                font_start: 0x50,
+                memory_size: 0x1000,
+                width: 64,
+                height: 48,
+            },
+        ),
+        (
+            // Two pages of the COSMAC VIP's display memory, for its CHIP-8 Hi-Res interpreter.
+            "hires",
+            MemoryLayout {
+                program_start: 0x200,
+                font_start: 0x50,
+                memory_size: 0x1000,
+                width: 64,
+                height: 64,
+            },
+        ),
+        (
+            // The VIP's CHIP-8X interpreter, which is longer than the original.
+            "chip8x",
+            MemoryLayout {
+                program_start: 0x300,
+                font_start: 0x50,
+                memory_size: 0x1000,
+                width: 64,
+                height: 32,
+            },
+        ),
+        (
+            // MEGA-CHIP's 16M, for its large graphics and sound data. The display starts
+            // out at CHIP-8's size, until `0011` switches to 256x192.
+            "megachip",
+            MemoryLayout {
+                program_start: 0x200,
+                font_start: 0x50,
+                memory_size: 0x100_0000,
+                width: 64,
+                height: 32,
+            },
+        ),
+    ];
+
+    /// The largest ROM that fits.
+    pub const fn max_rom_size(&self) -> usize {
+        self.memory_size.saturating_sub(self.program_start as usize)
+    }
+
+    /// Checks that the font and programs fit in memory, and that the display can be drawn
+    /// on.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(0x200..=0x100_0000).contains(&self.memory_size) {
+            bail!(
+                "{:#x} bytes of memory is outside of 0x200 to 0x1000000",
+                self.memory_size
+            );
+        }
+        if self.program_start as usize >= self.memory_size || !self.program_start.is_multiple_of(2)
+        {
+            bail!(
+                "programs can't start at {:#05x} in {:#x} bytes of memory",
+                self.program_start,
+                self.memory_size
+            );
+        }
+        if self.font_start as usize + FONT_SET.len() > self.memory_size.min(0x1000) {
+            // `Fx29` points I at the font, and I only has 12 bits.
+            bail!(
+                "the font at {:#05x} runs past the end of memory or of the first 4K",
+                self.font_start
+            );
+        }
+        if !(8..=256).contains(&self.width) || !(1..=256).contains(&self.height) {
+            bail!(
+                "a {}x{} display isn't supported, it can be 8x1 to 256x256",
+                self.width,
+                self.height
+            );
+        }
+        Ok(())
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> anyhow::Result<()> {
+        let number = |value: &str| -> anyhow::Result<usize> {
+            match value.strip_prefix("0x") {
+                Some(hex) => usize::from_str_radix(hex, 16),
+                None => value.parse(),
+            }
+            .with_context(|| format!("invalid {key} {value:?}"))
+        };
+
+        match key {
+            "start" => self.program_start = number(value)?.try_into()?,
+            "font" => self.font_start = number(value)?.try_into()?,
+            "memory" => self.memory_size = number(value)?,
+            "display" => {
+                let (width, height) = value
+                    .split_once('x')
+                    .with_context(|| format!("invalid display {value:?}, expected WxH"))?;
+                self.width = number(width)?;
+                self.height = number(height)?;
+            }
+            _ => bail!("unknown layout key {key:?}, expected start, font, memory or display"),
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for MemoryLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match Self::PRESETS.iter().find(|(_, layout)| layout == self) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(
+                f,
+                "start={:#x},font={:#x},memory={:#x},display={}x{}",
+                self.program_start, self.font_start, self.memory_size, self.width, self.height
+            ),
+        }
+    }
+}
+
+impl FromStr for MemoryLayout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',').map(str::trim).filter(|p| !p.is_empty());
+        let mut layout = Self::default();
+
+        let mut first = parts.next();
+        if let Some(name) = first.filter(|part| !part.contains('=')) {
+            layout = Self::PRESETS
+                .iter()
+                .find(|(preset, _)| *preset == name)
+                .map(|(_, layout)| *layout)
+                .ok_or_else(|| {
+                    let names: Vec<_> = Self::PRESETS.iter().map(|(name, _)| *name).collect();
+                    anyhow::anyhow!(
+                        "unknown memory layout {name:?}, expected one of {}",
+                        names.join(", ")
+                    )
+                })?;
+            first = None;
+        }
+
+        for part in first.into_iter().chain(parts) {
+            let (key, value) = part
+                .split_once('=')
+                .with_context(|| format!("invalid layout setting {part:?}, expected KEY=VALUE"))?;
+            layout.set(key.trim(), value.trim())?;
+        }
+
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+serde_string!(MemoryLayout);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let eti660 = MemoryLayout::PRESETS[1].1;
+        assert_eq!("eti660", eti660.to_string());
+        assert_eq!(eti660, "eti660".parse().unwrap());
+        assert_eq!(MemoryLayout::default(), "".parse().unwrap());
+
+        let layout: MemoryLayout = "chip8, font=0x0, memory=8192".parse().unwrap();
+        assert_eq!(0, layout.font_start);
+        assert_eq!(0x2000, layout.memory_size);
+        assert_eq!(0x1E00, layout.max_rom_size());
+        assert_eq!(
+            "start=0x200,font=0x0,memory=0x2000,display=64x32",
+            layout.to_string()
+        );
+        assert_eq!(layout, layout.to_string().parse().unwrap());
+        assert_eq!(eti660, "start=0x600,display=64x48".parse().unwrap());
+    }
+
+    #[test]
+    fn invalid() {
+        for s in [
+            "vip",
+            "chip8,start",
+            "colour=red",
+            "start=0x1000",
+            "start=0x201",
+            "font=0xFFF0",
+            "memory=0x2000000",
+            "display=64",
+            "display=1000x32",
+        ] {
+            assert!(s.parse::<MemoryLayout>().is_err(), "{s}");
+        }
+    }
+}
@@ -1,16 +1,123 @@
-use log::{trace, warn};
-use std::time::{Duration, Instant};
+use anyhow::Context;
+use log::{error, info, trace, warn};
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
 
-use chip8::Chip8;
+use capture::{FrameCapture, RenderOptions};
+use chip8::{Chip8, Fault};
+use cosmac::Vip;
+use coverage::CoverageReport;
+use movie::{MovieEvent, MovieSession};
+use profile::{ProfileFormat, Profiler};
+use symbols::Symbols;
+use timing::{Timing, VIP_DISPLAY_CYCLES, VIP_FRAME_CYCLES};
+use trace::{CpuState, Tracer};
+
+/// Implements `Serialize` and `Deserialize` for types that are written as strings, through
+/// their `Display` and `FromStr`.
+macro_rules! serde_string {
+    ($ty:ty) => {
+        impl serde::Serialize for $ty {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> serde::Deserialize<'de> for $ty {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(serde::de::Error::custom)
+            }
+        }
+    };
+}
 
 mod fonts;
 mod opcode;
 mod subsystem;
 
+pub mod asm;
+pub mod capture;
 pub mod chip8;
+pub mod config;
+pub mod cosmac;
+pub mod coverage;
+pub mod gamepad;
+pub mod gdb;
+pub mod instruction;
+pub mod keymap;
+pub mod layout;
+pub mod movie;
+pub mod octo;
+pub mod palette;
 pub mod platform;
+pub mod profile;
+pub mod quirks;
+pub mod rom;
+pub mod romdb;
+pub mod symbols;
+pub mod timing;
+pub mod trace;
+#[cfg(unix)]
+pub mod tui;
 pub mod ui;
 
+/// The rate of the delay and sound timers, which ROMs also use to pace their frames.
+pub const FRAME_HZ: usize = 60;
+
+/// Reports to write once the emulator stops.
+#[derive(Debug, Clone, Default)]
+pub struct Reports {
+    pub coverage: Option<PathBuf>,
+    pub profile: Option<(PathBuf, ProfileFormat)>,
+    /// Where to save the movie being recorded.
+    pub movie: Option<PathBuf>,
+    /// Where to save a PNG of the final screen.
+    pub screenshot: Option<(PathBuf, RenderOptions)>,
+}
+
+impl Reports {
+    /// Turns on the counters that the requested reports need.
+    pub fn enable(&self, runner: &mut Chip8Runner) {
+        if self.coverage.is_some() {
+            runner.chip8.enable_coverage();
+        }
+        if self.profile.is_some() {
+            runner.enable_profiler();
+        }
+    }
+
+    /// Writes the reports, and finishes any capture in progress and the trace.
+    pub fn write(&self, runner: &mut Chip8Runner) -> anyhow::Result<()> {
+        runner.stop_capture()?;
+        runner.flush_trace().context("failed to write trace")?;
+
+        if let Some((path, options)) = &self.screenshot {
+            options.save_png(&runner.chip8.screen(), path)?;
+        }
+
+        if let (Some(path), Some(report)) = (&self.coverage, runner.coverage_report()) {
+            std::fs::write(path, report)
+                .with_context(|| format!("failed to write coverage report {}", path.display()))?;
+        }
+
+        if let (Some((path, format)), Some(profiler)) = (&self.profile, runner.profiler()) {
+            std::fs::write(path, profiler.report(*format, runner.symbols()))
+                .with_context(|| format!("failed to write profile {}", path.display()))?;
+        }
+
+        if let (Some(path), Some(session)) = (&self.movie, runner.movie()) {
+            if session.is_recording() {
+                session.movie().save(path)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RunnerEvent {
     Start,
@@ -29,6 +136,24 @@ pub struct Chip8Runner {
     pub chip8: Chip8,
     tick_hz: usize,
     state: RunnerState,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    movie: Option<MovieSession>,
+    capture: Option<FrameCapture>,
+    // Why the runner stopped, if the next instruction can't be executed.
+    fault: Option<Fault>,
+    // Instructions executed in the current frame, and frames completed.
+    frame_steps: usize,
+    frames: u64,
+    // Machine cycles run in the current frame, under VIP timing.
+    frame_cycles: u64,
+    // Keys held down according to the frontend. Movies apply them on frame boundaries.
+    keys: u16,
+    symbols: Symbols,
+    breakpoints: BTreeSet<u16>,
+    // Fraction of a tick carried over between calls to `run_for`. Under VIP timing, ticks
+    // are machine cycles, and an instruction can overrun what's left.
+    pending_ticks: f64,
 }
 
 impl Chip8Runner {
@@ -37,40 +162,369 @@ impl Chip8Runner {
             chip8,
             tick_hz,
             state: RunnerState::NotStarted,
+            tracer: None,
+            profiler: None,
+            movie: None,
+            capture: None,
+            fault: None,
+            frame_steps: 0,
+            frames: 0,
+            frame_cycles: 0,
+            keys: 0,
+            symbols: Symbols::default(),
+            breakpoints: BTreeSet::new(),
+            pending_ticks: 0.,
         })
     }
 
-    pub fn start(&mut self) {
-        match self.state {
-            RunnerState::Running => {
-                warn!("");
+    pub fn with_tracer(mut self, tracer: Tracer) -> Self {
+        self.tracer = Some(tracer);
+        self
+    }
+
+    /// Writes out the part of the trace that's still buffered.
+    pub fn flush_trace(&mut self) -> io::Result<()> {
+        match &mut self.tracer {
+            Some(tracer) => tracer.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// Records or replays a movie, starting from the next instruction.
+    pub fn with_movie(mut self, session: MovieSession) -> Self {
+        self.movie = Some(session);
+        self
+    }
+
+    pub fn movie(&self) -> Option<&MovieSession> {
+        self.movie.as_ref()
+    }
+
+    /// Starts saving frames as they're drawn, finishing any capture already in progress.
+    pub fn start_capture(&mut self, capture: FrameCapture) -> anyhow::Result<()> {
+        self.stop_capture()?;
+        self.capture = Some(capture);
+        Ok(())
+    }
+
+    pub fn capture(&self) -> Option<&FrameCapture> {
+        self.capture.as_ref()
+    }
+
+    pub fn stop_capture(&mut self) -> anyhow::Result<()> {
+        match self.capture.take() {
+            Some(capture) => capture.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Sets the keys held down, one bit per key. While a movie is recording, they are
+    /// applied at the next frame boundary; while one is replaying, they are ignored.
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys;
+        if self.movie.is_none() {
+            self.chip8.set_keys(keys);
+        }
+    }
+
+    /// Starts over with `chip8`, e.g. the ROM freshly loaded. Keeps the speed, symbols,
+    /// breakpoints, tracer and capture, and restarts coverage and profiling.
+    pub fn reset(&mut self, chip8: Chip8) {
+        let coverage = self.chip8.coverage().is_some();
+        self.chip8 = chip8;
+        if coverage {
+            self.chip8.enable_coverage();
+        }
+        if self.profiler.is_some() {
+            self.profiler = Some(Profiler::new(self.chip8.layout().program_start));
+        }
+        self.restart();
+    }
+
+    /// Starts the program in memory over, keeping memory as it is.
+    pub fn soft_reset(&mut self) {
+        self.chip8.reset();
+        self.restart();
+    }
+
+    fn restart(&mut self) {
+        // Movies replay from the first frame, so they can't carry on after a reset.
+        if self.movie.take().is_some() {
+            warn!("stopped the movie, which can't continue after a reset");
+        }
+        self.chip8.set_keys(self.keys);
+        self.fault = None;
+        self.frame_steps = 0;
+        self.frames = 0;
+        self.frame_cycles = 0;
+        self.pending_ticks = 0.;
+    }
+
+    /// Starts attributing executed instructions to subroutines, if not already profiling.
+    pub fn enable_profiler(&mut self) {
+        if self.profiler.is_none() {
+            self.profiler = Some(Profiler::new(self.chip8.layout().program_start));
+        }
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    pub fn profiler_mut(&mut self) -> Option<&mut Profiler> {
+        self.profiler.as_mut()
+    }
+
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn breakpoints(&self) -> &BTreeSet<u16> {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.remove(&addr);
+    }
+
+    /// Formats a coverage report with the ROM's symbols, if coverage is being counted.
+    pub fn coverage_report(&self) -> Option<String> {
+        let coverage = self.chip8.coverage()?;
+        let start = self.chip8.layout().program_start as usize;
+        let report = CoverageReport::new(coverage, &self.chip8.memory, start);
+        Some(report.to_text(&self.symbols))
+    }
+
+    pub fn tick_hz(&self) -> usize {
+        self.tick_hz
+    }
+
+    pub fn instructions_per_frame(&self) -> usize {
+        (self.tick_hz / FRAME_HZ).max(1)
+    }
+
+    pub fn set_instructions_per_frame(&mut self, ipf: usize) {
+        self.tick_hz = ipf.max(1) * FRAME_HZ;
+    }
+
+    /// Frames completed since the runner was created.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// The fault that stopped the runner, until the instruction it's about can execute.
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.state == RunnerState::Running
+    }
+
+    pub fn pause(&mut self) {
+        if self.state == RunnerState::Running {
+            trace!("Pausing runner");
+            self.state = RunnerState::Paused;
+        }
+    }
+
+    /// Marks the runner as running, without blocking. Instructions are then executed by
+    /// calling [`Chip8Runner::run_for`] periodically, e.g. once per UI frame.
+    pub fn resume(&mut self) {
+        if self.state != RunnerState::Running {
+            trace!("Resuming runner");
+            self.state = RunnerState::Running;
+        }
+    }
+
+    /// Executes as many instructions as fit into `elapsed` at the configured tick rate.
+    /// Does nothing unless the runner is running, and pauses when reaching a breakpoint.
+    pub fn run_for(&mut self, elapsed: Duration) {
+        if !self.is_running() {
+            return;
+        }
+
+        let rate = match self.chip8.timing {
+            Timing::Fixed => self.tick_hz as f64,
+            Timing::Vip => (VIP_FRAME_CYCLES * FRAME_HZ as u64) as f64,
+        };
+        self.pending_ticks += elapsed.as_secs_f64() * rate;
+
+        while self.pending_ticks >= 1. && self.is_running() {
+            self.pending_ticks -= self.execute() as f64;
+
+            let pc = self.chip8.program_counter;
+            if self.breakpoints.contains(&pc) {
+                info!("hit breakpoint at {}", self.symbols.name(pc));
+                self.pending_ticks = 0.;
+                self.pause();
+                break;
+            }
+        }
+    }
+
+    /// Runs `frames` frames as fast as possible, without a frontend. Stops early if the
+    /// runner gets paused, like when a replayed movie ends.
+    pub fn run_frames(&mut self, frames: u64) {
+        self.resume();
+
+        if self.chip8.timing == Timing::Vip {
+            let end = self.frames + frames;
+            while self.frames < end && self.is_running() {
+                self.step();
             }
-            RunnerState::NotStarted | RunnerState::Paused => {
-                trace!("Starting runner");
-                self.state = RunnerState::Running;
+            return;
+        }
+
+        let steps = frames * self.instructions_per_frame() as u64;
+        for _ in 0..steps {
+            if !self.is_running() {
+                break;
             }
+            self.step();
+        }
+    }
+
+    /// Executes a single instruction, recording it to the tracer if there is one. Pauses
+    /// instead if the instruction would fault.
+    pub fn step(&mut self) {
+        self.execute();
+    }
+
+    /// Executes a single instruction, and returns the ticks it took: 1, or under VIP timing
+    /// its machine cycles, along with any spent waiting for and running the display
+    /// interrupt.
+    fn execute(&mut self) -> u64 {
+        self.fault = self.chip8.fault();
+        if let Some(fault) = self.fault {
+            error!("{fault}");
+            self.pending_ticks = 0.;
+            self.pause();
+            return 0;
+        }
+
+        if let Some(session) = &mut self.movie {
+            match session.before_step(&mut self.chip8, self.keys) {
+                Some(MovieEvent::Desync(desync)) => {
+                    error!(
+                        "movie desynced at frame {}: state hash {:016x}, recorded {:016x}",
+                        desync.frame, desync.actual, desync.expected
+                    );
+                    self.pause();
+                }
+                Some(MovieEvent::Finished) => {
+                    info!("movie finished after {} frames", session.frame() - 1);
+                    self.movie = None;
+                    self.pause();
+                }
+                None => {}
+            }
+        }
+
+        let before = CpuState::capture(&self.chip8);
+        let opcode = self.chip8.peek_opcode();
+
+        // A VIP times itself, so only the model needs the cycles estimated.
+        let vip = self.chip8.timing == Timing::Vip && self.chip8.vip().is_none();
+        let mut ticks = 0;
+        if vip && timing::waits_for_display(opcode) {
+            ticks += self.wait_for_display();
+        }
+        let mut cycles = if vip {
+            timing::vip_cycles(&self.chip8, opcode)
+        } else {
+            1
         };
 
-        loop {
-            let now = Instant::now();
+        self.chip8.tick();
+
+        let vip_step = self.chip8.vip().map(Vip::last_step);
+        if let Some(step) = vip_step {
+            cycles = step.cycles;
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(before.pc, opcode, cycles, self.chip8.stack_pointer);
+        }
 
-            self.chip8.tick();
+        if let Some(tracer) = &mut self.tracer {
+            let after = CpuState::capture(&self.chip8);
+            if let Err(err) = tracer.record(opcode, before, after) {
+                error!("failed to write trace, disabling tracing: {err}");
+                self.tracer = None;
+            }
+        }
 
-            let duration = now.elapsed();
-            let sleep_time = self.tick_duration_nanos() - duration;
+        ticks += cycles;
+        if let Some(step) = vip_step {
+            for _ in 0..step.frames {
+                self.next_frame();
+            }
+            return ticks;
+        }
+        if vip {
+            self.frame_cycles += cycles;
+            while self.frame_cycles >= VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES {
+                self.frame_cycles -= VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+                self.next_frame();
+                ticks += VIP_DISPLAY_CYCLES;
+            }
+            return ticks;
+        }
 
-            trace!(
-                "Cycle took {}ns, sleeping for {}ms",
-                duration.as_nanos(),
-                sleep_time.as_millis()
-            );
+        self.frame_steps += 1;
+        if self.frame_steps == self.instructions_per_frame() {
+            self.frame_steps = 0;
+            self.next_frame();
+        }
+        ticks
+    }
+
+    /// Idles until the display interrupt, then runs it, returning the machine cycles that
+    /// took.
+    fn wait_for_display(&mut self) -> u64 {
+        let idle = (VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES).saturating_sub(self.frame_cycles);
+        self.frame_cycles = 0;
+        self.next_frame();
+        idle + VIP_DISPLAY_CYCLES
+    }
 
-            std::thread::sleep(sleep_time);
+    fn next_frame(&mut self) {
+        self.frames += 1;
+        // A VIP's interrupt routine counts its timers down itself.
+        if self.chip8.timing == Timing::Vip && self.chip8.vip().is_none() {
+            self.chip8.tick_timers();
         }
+        self.end_frame();
     }
 
-    fn tick_duration_nanos(&self) -> Duration {
-        let nanos_per_tick = 1000_000_000. / self.tick_hz as f32;
-        Duration::from_nanos(nanos_per_tick as u64)
+    fn end_frame(&mut self) {
+        let Some(capture) = &mut self.capture else {
+            return;
+        };
+
+        let mut result = capture.add_frame(&self.chip8.screen());
+        if result.is_ok() && capture.is_done() {
+            info!("finished capturing to {}", capture.path().display());
+            result = self.stop_capture();
+        }
+
+        if let Err(err) = result {
+            error!("failed to capture frame, stopping capture: {err:#}");
+            self.capture = None;
+        }
     }
 }
@@ -1,37 +1,879 @@
-use std::path::Path;
+use std::fmt::Write as _;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+#[cfg(unix)]
+use std::time::Duration;
 
 use anyhow::Context;
-use patata::chip8::Chip8;
+use clap::{Parser, Subcommand};
+use log::{info, warn};
+use patata::capture::{FrameCapture, RenderOptions, DEFAULT_SCALE};
+use patata::config::{Config, Settings, State};
+use patata::cosmac::Backend;
+use patata::gamepad::GamepadSettings;
+use patata::gdb::GdbStub;
+use patata::keymap::Keymap;
+use patata::layout::MemoryLayout;
+use patata::movie::{Movie, MovieSession};
+use patata::palette::Palette;
+use patata::platform::{PlayerInterface, SdlOptions};
+use patata::profile::ProfileFormat;
+use patata::quirks::{Platform, Quirks};
+use patata::rom::{Rom, RomLoader};
+use patata::symbols::Symbols;
+use patata::timing::Timing;
+use patata::trace::diff::{self, DiffOutcome};
+use patata::trace::{TraceFilter, TraceFormat, Tracer};
+#[cfg(unix)]
+use patata::tui::{PixelMode, TerminalInterface, TuiOptions, DEFAULT_KEY_TIMEOUT};
 use patata::ui::DebugInterface;
-use patata::Chip8Runner;
+use patata::{asm, Chip8Runner, Reports};
 
-fn main() -> anyhow::Result<()> {
+// Exit statuses besides success. Clap exits with 2 for invalid command lines.
+const EXIT_FAILURE: u8 = 1;
+const EXIT_FAULT: u8 = 3;
+const EXIT_TEST_FAILED: u8 = 4;
+
+const EXIT_STATUS_HELP: &str = "\
+Exit status:
+  0  Success
+  1  An error, like a file that can't be read, or traces that diverge
+  2  An invalid command line
+  3  The ROM faulted: an invalid opcode, a stack overflow or underflow, or a memory
+     access out of bounds
+  4  `test` found a different screen than expected";
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "A CHIP-8 emulator and debugger",
+    after_help = EXIT_STATUS_HELP
+)]
+struct Cli {
+    /// Defaults for options, as TOML with the keys `platform`, `quirks`, `layout`, `ipf`,
+    /// `timing`, `backend`, `vip-rom`, `vip-interpreter`, `seed`, `scale`, `palette`, `keymap`,
+    /// `rom-db` and `gamepad`, and overrides of them for ROMs in
+    /// `[roms.<SHA-1>]` tables. Defaults to `config.toml` in the config directory
+    /// (`$XDG_CONFIG_HOME/patata` or `~/.config/patata`), where the debugger saves its
+    /// changes. A file given here is only read. Recent ROMs and the debugger's layout are
+    /// kept in `state.toml` next to it.
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Play a ROM in an SDL window, in the terminal, or headless. In the window, Esc quits,
+    /// Space pauses, and F11 or Alt+Enter toggles fullscreen.
+    Run(RunArgs),
+
+    /// Open a ROM in the debugger, or debug it over the GDB remote protocol
+    Debug(DebugArgs),
+
+    /// Print a ROM's disassembly, in a form that `asm` assembles back into the same ROM
+    Disasm {
+        /// Path to the ROM file, or an Octo cartridge (`.gif`)
+        rom: PathBuf,
+
+        /// Memory layout the ROM is for, which sets the address it's listed from
+        #[arg(long)]
+        layout: Option<MemoryLayout>,
+
+        /// Symbol file naming addresses in the ROM. Defaults to a `.sym` or `.json` file
+        /// next to the ROM, if there is one.
+        #[arg(long, value_name = "FILE")]
+        symbols: Option<PathBuf>,
+    },
+
+    /// Assemble a program written with the mnemonics that `disasm` prints
+    Asm {
+        /// Path to the source file
+        source: PathBuf,
+
+        /// Memory layout the program is for, which sets the address it's assembled from
+        #[arg(long)]
+        layout: Option<MemoryLayout>,
+
+        /// Where to write the ROM
+        #[arg(short, long, value_name = "ROM")]
+        output: PathBuf,
+
+        /// Also write the program's labels to this symbol file
+        #[arg(long, value_name = "FILE")]
+        symbols: Option<PathBuf>,
+    },
+
+    /// Show a ROM's size, SHA-1, and what the ROM database knows about it
+    Info {
+        /// Path to the ROM file, or an Octo cartridge (`.gif`)
+        rom: PathBuf,
+
+        /// ROM database in the chip-8-database `programs.json` format
+        #[arg(long, value_name = "FILE")]
+        rom_db: Option<PathBuf>,
+    },
+
+    /// Run a ROM headless, and fail if it faults or doesn't end on the expected screen
+    Test(TestArgs),
+
+    /// Compare a patata trace with a reference trace and report the first divergent step
+    TraceDiff {
+        /// Trace written by `patata run --trace` (text or binary)
+        trace: PathBuf,
+
+        /// Trace from the reference emulator, in the `KEY=VALUE` line format
+        reference: PathBuf,
+
+        /// Number of preceding steps to show before the divergence
+        #[arg(long, default_value_t = 5)]
+        context: usize,
+
+        /// Re-run this ROM or Octo cartridge up to the divergent step to show memory around
+        /// PC and I. Pass the options the trace was run with, including `--seed` if the ROM
+        /// executes RND by then.
+        #[arg(long, value_name = "ROM")]
+        rom: Option<PathBuf>,
+
+        #[command(flatten)]
+        emulator: EmulatorArgs,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Run this many frames as fast as possible without a frontend, then write reports
+    /// and exit
+    #[arg(long, value_name = "FRAMES")]
+    headless: Option<u64>,
+
+    /// Start the SDL window fullscreen
+    #[arg(long, conflicts_with = "headless")]
+    fullscreen: bool,
+
+    /// Don't sync the SDL window to the display's refresh rate
+    #[arg(long, conflicts_with = "headless")]
+    no_vsync: bool,
+
+    /// Run in the terminal instead of an SDL window. Esc quits, Space pauses. Redirect
+    /// stderr to keep log messages off the screen.
+    #[cfg(unix)]
+    #[arg(long, conflicts_with_all = ["headless", "fullscreen", "no_vsync"])]
+    tui: bool,
+
+    /// How the terminal frontend draws pixels: `half-blocks` or `braille`
+    #[cfg(unix)]
+    #[arg(long, default_value = "half-blocks", requires = "tui")]
+    pixel_mode: PixelMode,
+
+    /// How long a key counts as held in the terminal frontend after its last keypress or
+    /// auto-repeat, in milliseconds
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = DEFAULT_KEY_TIMEOUT.as_millis() as u64,
+        requires = "tui"
+    )]
+    key_timeout: u64,
+}
+
+#[derive(clap::Args, Debug)]
+struct DebugArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    #[command(flatten)]
+    input: InputArgs,
+
+    /// Instead of opening the debugger UI, serve the GDB remote protocol on this
+    /// localhost port
+    #[arg(long, value_name = "PORT")]
+    gdb_port: Option<u16>,
+}
+
+#[derive(clap::Args, Debug)]
+struct TestArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    #[command(flatten)]
+    output: OutputArgs,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    /// Number of frames to run
+    #[arg(long, default_value_t = 600)]
+    frames: u64,
+
+    /// PNG the screen must match at the end, like one saved with `--screenshot` using the
+    /// same `--scale` and `--palette`
+    #[arg(long, value_name = "PNG")]
+    expect: Option<PathBuf>,
+}
+
+/// What to run, and how.
+#[derive(clap::Args, Debug)]
+struct MachineArgs {
+    /// Path to the ROM file to load, or an Octo cartridge (`.gif`)
+    rom: PathBuf,
+
+    #[command(flatten)]
+    emulator: EmulatorArgs,
+
+    /// Symbol file naming addresses in the ROM (`ADDRESS LABEL` lines, or Octo's JSON
+    /// export). Defaults to a `.sym` or `.json` file next to the ROM, if there is one.
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<PathBuf>,
+
+    /// Record keypad input to this movie file, saved on exit
+    #[arg(long, value_name = "FILE", conflicts_with = "play_movie")]
+    record_movie: Option<PathBuf>,
+
+    /// Replay a movie recorded with `--record-movie`, checking that it stays in sync
+    #[arg(long, value_name = "FILE")]
+    play_movie: Option<PathBuf>,
+}
+
+/// How the ROM is emulated.
+#[derive(clap::Args, Debug)]
+struct EmulatorArgs {
+    /// Platform whose quirks to emulate: `chip8`, `chip8-hires` (the COSMAC VIP's 64x64
+    /// interpreter), `chip8x` (the VIP's colour interpreter), `schip`, `megachip` or
+    /// `xochip`. Defaults to the ROM's platform in the ROM database.
+    #[arg(long)]
+    platform: Option<Platform>,
+
+    /// Quirks to emulate, overriding the platform's: `shift-vy`, `load-store-i`, `jump-vx`
+    /// and `vf-reset`, separated by commas, `none`, or a platform's name
+    #[arg(long)]
+    quirks: Option<Quirks>,
+
+    /// Where programs start and the font goes in memory, how much memory there is, and the
+    /// display size: `chip8`, `eti660` (programs at 0x600 and a 64x48 display), `hires` (a
+    /// 64x64 display), `chip8x` (programs at 0x300) or `megachip` (16M of memory),
+    /// optionally followed by overrides like `,start=0x200,font=0x0,memory=0x2000,
+    /// display=64x32`. Defaults to the platform's.
+    #[arg(long)]
+    layout: Option<MemoryLayout>,
+
+    /// Instructions per frame, at 60 frames per second. Defaults to the ROM's speed in the
+    /// ROM database, or 700 instructions per second.
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    ipf: Option<u32>,
+
+    /// How long instructions take: `fixed` (`--ipf` of them per frame) or `vip` (as long as
+    /// on the COSMAC VIP, including `DRW` waiting for the next frame)
+    #[arg(long)]
+    timing: Option<Timing>,
+
+    /// What runs the program: `native` (the built-in interpreter) or `vip` (the original
+    /// interpreter on an emulated COSMAC VIP, which runs `0nnn` machine code and implies
+    /// `--timing vip`)
+    #[arg(long)]
+    backend: Option<Backend>,
+
+    /// The COSMAC VIP's 512-byte monitor ROM, for `--backend vip`
+    #[arg(long, value_name = "FILE")]
+    vip_rom: Option<PathBuf>,
+
+    /// The VIP's CHIP-8 interpreter, as loaded into 0x000-0x1FF, for `--backend vip`
+    #[arg(long, value_name = "FILE")]
+    vip_interpreter: Option<PathBuf>,
+
+    /// Seed for the random number generator, to make `RND` reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// ROM database in the chip-8-database `programs.json` format, used to pick the
+    /// platform, quirks, speed, colours and gamepad keys of known ROMs. Defaults to
+    /// `chip-8-database/programs.json` in the config directory, or the built-in snapshot
+    /// when there's none there.
+    #[arg(long, value_name = "FILE")]
+    rom_db: Option<PathBuf>,
+}
+
+/// Traces, reports and images to write.
+#[derive(clap::Args, Debug)]
+struct OutputArgs {
+    /// Write a trace of every executed instruction to this file
+    #[arg(long, value_name = "FILE")]
+    trace: Option<PathBuf>,
+
+    /// Trace output format: `text` or `binary`
+    #[arg(long, default_value = "text")]
+    trace_format: TraceFormat,
+
+    /// Only trace instructions within this (hex) address range, e.g. `200-2ff`
+    #[arg(long, value_name = "START-END", value_parser = parse_address_range)]
+    trace_range: Option<RangeInclusive<u16>>,
+
+    /// Only trace these instruction kinds, e.g. `DRW,CALL,RET`
+    #[arg(long, value_name = "MNEMONICS", value_delimiter = ',')]
+    trace_kinds: Vec<String>,
+
+    /// Only trace steps within this window, e.g. `1000-2000`
+    #[arg(long, value_name = "START-END", value_parser = parse_step_range)]
+    trace_steps: Option<RangeInclusive<u64>>,
+
+    /// Count executed instructions and memory accesses, and write a coverage report to
+    /// this file on exit
+    #[arg(long, value_name = "FILE")]
+    coverage: Option<PathBuf>,
+
+    /// Attribute executed instructions to subroutines, and write a profile to this file on
+    /// exit
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Profile format: `text` (flat profile and call graph) or `folded` (for flamegraphs)
+    #[arg(long, default_value = "text")]
+    profile_format: ProfileFormat,
+
+    /// Save a PNG of the screen to this file on exit. In the debugger, F12 saves a
+    /// screenshot at any time.
+    #[arg(long, value_name = "FILE")]
+    screenshot: Option<PathBuf>,
+
+    /// Save frames as they're drawn, to an animated GIF if FILE ends in `.gif`, and
+    /// otherwise to numbered PNGs in the directory FILE. In the debugger, Shift+F12 starts
+    /// and stops recording a GIF.
+    #[arg(long, value_name = "FILE")]
+    capture: Option<PathBuf>,
+
+    /// Only capture these frames, counting from 0 at 60 frames per second, e.g. `60-359`
+    #[arg(long, value_name = "START-END", value_parser = parse_step_range, requires = "capture")]
+    capture_frames: Option<RangeInclusive<u64>>,
+}
+
+/// How the display is drawn.
+#[derive(clap::Args, Debug)]
+struct RenderArgs {
+    /// Size of a CHIP-8 pixel in screenshots, captures and the SDL window, in image
+    /// pixels. Defaults to 8.
+    #[arg(long)]
+    scale: Option<u32>,
+
+    /// Display colours: `mono`, `octo`, `green`, `amber`, or `BACKGROUND,FOREGROUND` in
+    /// hex, e.g. `#000000,#33ff66`. Defaults to the ROM's colours in the ROM database, or
+    /// `mono`.
+    #[arg(long)]
+    palette: Option<Palette>,
+}
+
+/// How the keypad is played.
+#[derive(clap::Args, Debug)]
+struct InputArgs {
+    /// Keyboard keys for the keypad, as the 16 characters covering it row by row (`123C`,
+    /// `456D`, `789E`, `A0BF`). Defaults to `1234qwerasdfzxcv`.
+    #[arg(long)]
+    keymap: Option<Keymap>,
+}
+
+/// A ROM loaded into a runner.
+struct Machine {
+    runner: Chip8Runner,
+    rom: Rom,
+}
+
+fn main() -> ExitCode {
     env_logger::init();
 
-    let rom_file = std::env::args()
-        .nth(1)
-        .context("no ROM file name specified")?;
+    let cli = Cli::parse();
+
+    match run(cli) {
+        Ok(status) => status,
+        Err(err) => {
+            eprintln!("Error: {err:#}");
+            ExitCode::from(EXIT_FAILURE)
+        }
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<ExitCode> {
+    let path = cli.config.clone().or_else(Config::default_path);
+    let config = match (&cli.config, &path) {
+        (Some(path), _) => Config::load(path)?,
+        (None, Some(path)) if path.is_file() => Config::load(path)?,
+        (None, _) => Config::default(),
+    };
+    let state_path = State::default_path();
+    let state = match &state_path {
+        Some(path) => State::load(path).unwrap_or_else(|err| {
+            warn!("ignoring the saved state: {err:#}");
+            State::default()
+        }),
+        None => State::default(),
+    };
+    let settings = Settings {
+        path: path.filter(|_| cli.config.is_none()),
+        config,
+        state_path,
+        state,
+        ..Settings::default()
+    };
+    let config = &settings.config;
+
+    match &cli.command {
+        Command::Run(args) => run_rom(args, &settings),
+        Command::Debug(args) => debug(args, &settings),
+        Command::Disasm {
+            rom,
+            layout,
+            symbols,
+        } => disasm(rom, *layout, symbols.as_deref()),
+        Command::Asm {
+            source,
+            layout,
+            output,
+            symbols,
+        } => assemble(
+            source,
+            layout.unwrap_or_default(),
+            output,
+            symbols.as_deref(),
+        ),
+        Command::Info { rom, rom_db } => rom_info(rom, rom_db.as_deref(), config),
+        Command::Test(args) => test(args, config),
+        Command::TraceDiff {
+            trace,
+            reference,
+            context,
+            rom,
+            emulator,
+        } => {
+            let loader = rom_loader(emulator, None, None, config);
+            trace_diff(trace, reference, *context, rom.as_deref(), &loader)
+        }
+    }
+}
+
+fn run_rom(args: &RunArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let loader = rom_loader(
+        &args.machine.emulator,
+        args.render.palette,
+        args.input.keymap,
+        &settings.config,
+    );
+    let mut machine = load_machine(&loader, &args.machine, &args.output)?;
+    let render = render_options(&args.render, &machine);
+    let reports = start_output(&mut machine, &args.machine, &args.output, render)?;
+
+    if let Some(frames) = args.headless {
+        return Ok(match run_headless(&mut machine, frames, &reports)? {
+            true => ExitCode::SUCCESS,
+            false => ExitCode::from(EXIT_FAULT),
+        });
+    }
 
-    let path = std::path::PathBuf::from(&rom_file);
-    let rom_bytes = std::fs::read(&path)?;
+    let keymap = machine.rom.keymap;
+    remember_rom(settings, &machine.rom);
 
-    let mut chip8 = Chip8::default();
-    chip8.load_rom(&rom_bytes)?;
+    #[cfg(unix)]
+    if args.tui {
+        let options = TuiOptions {
+            pixel_mode: args.pixel_mode,
+            key_timeout: Duration::from_millis(args.key_timeout),
+            palette: render.palette,
+            keymap,
+        };
+        TerminalInterface::new(machine.runner)
+            .with_options(options)
+            .with_reports(reports)
+            .run()?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let options = SdlOptions {
+        scale: render.scale,
+        palette: render.palette,
+        keymap,
+        vsync: !args.no_vsync,
+        fullscreen: args.fullscreen,
+    };
+    let gamepad = gamepad_settings(&settings.config, &machine.rom);
+    PlayerInterface::new(&machine.rom.title(), machine.runner)
+        .with_options(options)
+        .with_reports(reports)
+        .with_gamepad(gamepad)
+        .run()?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn debug(args: &DebugArgs, settings: &Settings) -> anyhow::Result<ExitCode> {
+    let loader = rom_loader(
+        &args.machine.emulator,
+        args.render.palette,
+        args.input.keymap,
+        &settings.config,
+    );
+    let mut machine = load_machine(&loader, &args.machine, &args.output)?;
+    let render = render_options(&args.render, &machine);
+    let reports = start_output(&mut machine, &args.machine, &args.output, render)?;
+
+    if let Some(port) = args.gdb_port {
+        reports.enable(&mut machine.runner);
+        let mut stub = GdbStub::new(machine.runner);
+        stub.listen(port)?;
+        reports.write(stub.runner_mut())?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    let gamepad = gamepad_settings(&settings.config, &machine.rom);
+    let settings = remember_rom(settings, &machine.rom);
+    DebugInterface::new(machine.rom, machine.runner)
+        .with_loader(loader)
+        .with_settings(settings)
+        .with_reports(reports)
+        .with_render_options(render)
+        .with_gamepad(gamepad)
+        .run()?;
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn test(args: &TestArgs, config: &Config) -> anyhow::Result<ExitCode> {
+    let loader = rom_loader(&args.machine.emulator, args.render.palette, None, config);
+    let mut machine = load_machine(&loader, &args.machine, &args.output)?;
+    let render = render_options(&args.render, &machine);
+    let reports = start_output(&mut machine, &args.machine, &args.output, render)?;
+
+    if !run_headless(&mut machine, args.frames, &reports)? {
+        return Ok(ExitCode::from(EXIT_FAULT));
+    }
+
+    if let Some(expected) = &args.expect {
+        if !render.matches_png(&machine.runner.chip8.screen(), expected)? {
+            eprintln!(
+                "FAIL {}: the screen doesn't match {} after {} frames",
+                machine.rom.title(),
+                expected.display(),
+                machine.runner.frames()
+            );
+            return Ok(ExitCode::from(EXIT_TEST_FAILED));
+        }
+    }
+
+    println!(
+        "PASS {}: {} frames",
+        machine.rom.title(),
+        machine.runner.frames()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs `frames` frames and writes the reports. Returns whether the ROM ran without
+/// faulting.
+fn run_headless(machine: &mut Machine, frames: u64, reports: &Reports) -> anyhow::Result<bool> {
+    let runner = &mut machine.runner;
+    reports.enable(runner);
+    runner.run_frames(frames);
+    reports.write(runner)?;
+
+    match runner.fault() {
+        Some(fault) => {
+            eprintln!(
+                "FAIL {}: {fault} in frame {}",
+                machine.rom.title(),
+                runner.frames()
+            );
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// Settings from the command line, which win over the config file's.
+fn rom_loader(
+    args: &EmulatorArgs,
+    palette: Option<Palette>,
+    keymap: Option<Keymap>,
+    config: &Config,
+) -> RomLoader {
+    RomLoader {
+        platform: args.platform,
+        quirks: args.quirks,
+        layout: args.layout,
+        ipf: args.ipf,
+        timing: args.timing,
+        backend: args.backend,
+        vip_rom: args.vip_rom.clone(),
+        vip_interpreter: args.vip_interpreter.clone(),
+        seed: args.seed,
+        palette,
+        keymap,
+        rom_db: args.rom_db.clone(),
+        config: config.clone(),
+    }
+}
 
-    let runner = Chip8Runner::new(chip8, 700)?;
+fn load_machine(
+    loader: &RomLoader,
+    args: &MachineArgs,
+    output: &OutputArgs,
+) -> anyhow::Result<Machine> {
+    let mut rom = loader.load(&args.rom)?;
+    if let Some(path) = &args.symbols {
+        rom.symbols = Symbols::load(path)?;
+    }
+    let movie = args.play_movie.as_deref().map(Movie::load).transpose()?;
+    if let Some(movie) = &movie {
+        movie.prepare(&mut rom, loader)?;
+    }
+    let mut runner = rom.runner()?;
 
-    DebugInterface::new(rom_file_name(&path), runner)
-        .run()
-        .unwrap();
+    if let Some(movie) = movie {
+        runner = runner.with_movie(MovieSession::replay(movie));
+    } else if args.record_movie.is_some() {
+        let ipf = runner.instructions_per_frame() as u32;
+        let mut movie = Movie::new(rom.sha1.clone(), rom.seed, rom.quirks, ipf);
+        movie.platform = rom.platform;
+        movie.layout = rom.layout;
+        movie.timing = rom.timing;
+        movie.backend = rom.backend;
+        runner = runner.with_movie(MovieSession::record(movie));
+    }
+
+    if let Some(trace_path) = &output.trace {
+        let filter = TraceFilter {
+            addresses: output.trace_range.clone(),
+            kinds: output.trace_kinds.clone(),
+            steps: output.trace_steps.clone(),
+        };
+        let tracer = Tracer::create(trace_path, output.trace_format, filter)
+            .with_context(|| format!("failed to create trace file {}", trace_path.display()))?
+            .with_symbols(rom.symbols.clone());
+        runner = runner.with_tracer(tracer);
+    }
+
+    Ok(Machine { runner, rom })
+}
+
+fn render_options(args: &RenderArgs, machine: &Machine) -> RenderOptions {
+    RenderOptions {
+        scale: args
+            .scale
+            .or(machine.rom.config.scale)
+            .unwrap_or(DEFAULT_SCALE),
+        palette: machine.rom.palette,
+    }
+}
+
+/// Starts any capture, and returns the reports to write on exit.
+fn start_output(
+    machine: &mut Machine,
+    machine_args: &MachineArgs,
+    args: &OutputArgs,
+    render: RenderOptions,
+) -> anyhow::Result<Reports> {
+    if let Some(path) = &args.capture {
+        let frames = args.capture_frames.clone().unwrap_or(0..=u64::MAX);
+        machine
+            .runner
+            .start_capture(FrameCapture::new(path, render, frames)?)?;
+    }
+
+    Ok(Reports {
+        coverage: args.coverage.clone(),
+        profile: args.profile.clone().map(|path| (path, args.profile_format)),
+        movie: machine_args.record_movie.clone(),
+        screenshot: args.screenshot.clone().map(|path| (path, render)),
+    })
+}
+
+/// Puts the ROM first in the recent ROMs, and returns the settings for the ROM.
+fn remember_rom(settings: &Settings, rom: &Rom) -> Settings {
+    let mut settings = Settings {
+        rom_sha1: rom.sha1.clone(),
+        rom_name: rom.name(),
+        ..settings.clone()
+    };
+    if let Err(err) = settings.update_state(|state| state.remember_rom(&rom.path)) {
+        warn!("failed to update the recent ROMs: {err:#}");
+    }
+    settings
+}
+
+fn gamepad_settings(config: &Config, rom: &Rom) -> GamepadSettings {
+    let mut settings = GamepadSettings::new(config, &rom.sha1);
+    if let Some(info) = &rom.info {
+        settings.apply_rom_keys(&info.keys);
+    }
+    settings
+}
+
+fn disasm(
+    rom: &Path,
+    layout: Option<MemoryLayout>,
+    symbols: Option<&Path>,
+) -> anyhow::Result<ExitCode> {
+    let loader = RomLoader {
+        layout,
+        ..RomLoader::default()
+    };
+    let rom = loader.load(rom)?;
+    let symbols = match symbols {
+        Some(path) => Symbols::load(path)?,
+        None => rom.symbols,
+    };
+
+    print!(
+        "{}",
+        asm::listing_at(&rom.bytes, rom.layout.program_start, &symbols)
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn assemble(
+    source: &Path,
+    layout: MemoryLayout,
+    output: &Path,
+    symbols: Option<&Path>,
+) -> anyhow::Result<ExitCode> {
+    let text = std::fs::read_to_string(source)
+        .with_context(|| format!("failed to read {}", source.display()))?;
+    let program = asm::assemble_at(&text, layout.program_start)
+        .with_context(|| format!("failed to assemble {}", source.display()))?;
+
+    std::fs::write(output, &program.bytes)
+        .with_context(|| format!("failed to write ROM file {}", output.display()))?;
+
+    if let Some(path) = symbols {
+        let mut sym = String::new();
+        for (addr, label) in program.symbols.iter() {
+            let _ = writeln!(sym, "{addr:03x} {label}");
+        }
+        std::fs::write(path, sym)
+            .with_context(|| format!("failed to write symbol file {}", path.display()))?;
+    }
+
+    info!(
+        "assembled {} bytes into {}",
+        program.bytes.len(),
+        output.display()
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+fn rom_info(path: &Path, rom_db: Option<&Path>, config: &Config) -> anyhow::Result<ExitCode> {
+    let loader = RomLoader {
+        rom_db: rom_db.map(Path::to_path_buf),
+        config: config.clone(),
+        ..RomLoader::default()
+    };
+    let rom = loader.load(path)?;
+
+    println!("File      {}", path.display());
+    println!("Size      {} bytes", rom.bytes.len());
+    println!("SHA-1     {}", rom.sha1);
+    println!("Layout    {}", rom.layout);
+
+    match rom.info {
+        Some(info) => {
+            println!("Title     {}", info.title);
+            println!("Platform  {}", info.platform);
+            println!("Quirks    {}", info.quirks);
+            if let Some(ipf) = info.ipf {
+                println!("Speed     {ipf} instructions per frame");
+            }
+            if let Some(palette) = info.palette {
+                println!("Palette   {palette}");
+            }
+        }
+        None => println!("Not in the ROM database"),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+fn trace_diff(
+    trace: &Path,
+    reference: &Path,
+    context: usize,
+    rom: Option<&Path>,
+    loader: &RomLoader,
+) -> anyhow::Result<ExitCode> {
+    let left = diff::load_trace(trace)?;
+    let right = diff::load_trace(reference)?;
+
+    let divergence = match diff::diff(&left, &right)? {
+        DiffOutcome::Identical { compared } => {
+            println!(
+                "No divergence in {compared} compared steps ({} vs {} steps in total)",
+                left.len(),
+                right.len()
+            );
+            return Ok(ExitCode::SUCCESS);
+        }
+        DiffOutcome::Diverged(divergence) => divergence,
+    };
+
+    print!("{}", diff::report(&left, &right, &divergence, context));
+
+    if let (Some(rom), Some(step)) = (rom, left[divergence.left].step) {
+        let mut runner = loader.load(rom)?.runner()?;
+        for n in 0..step {
+            runner.step();
+            if let Some(fault) = runner.fault() {
+                println!("\nThe ROM faulted at step {n}: {fault}");
+                break;
+            }
+        }
+
+        let chip8 = &runner.chip8;
+        let addresses = [
+            ("PC", chip8.program_counter as usize),
+            ("I", chip8.index.get()),
+        ];
+        println!("\n{}", diff::memory_context(&chip8.memory, &addresses));
+    }
+
+    Ok(ExitCode::from(EXIT_FAILURE))
+}
+
+fn parse_range<T>(
+    s: &str,
+    parse: impl Fn(&str) -> anyhow::Result<T>,
+) -> anyhow::Result<RangeInclusive<T>> {
+    let (start, end) = s
+        .split_once('-')
+        .context("expected a range in the form START-END")?;
+
+    Ok(parse(start.trim())?..=parse(end.trim())?)
+}
 
-    Ok(())
+fn parse_address_range(s: &str) -> anyhow::Result<RangeInclusive<u16>> {
+    parse_range(s, |a| {
+        let a = a.trim_start_matches("0x");
+        u16::from_str_radix(a, 16).with_context(|| format!("invalid hex address {a:?}"))
+    })
 }
 
-fn rom_file_name(rom_path: &Path) -> &'static str {
-    rom_path
-        .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string()
-        .leak()
+fn parse_step_range(s: &str) -> anyhow::Result<RangeInclusive<u64>> {
+    parse_range(s, |n| {
+        n.parse().with_context(|| format!("invalid step {n:?}"))
+    })
 }
@@ -0,0 +1,529 @@
+//! Input movies: the keypad state of every frame, together with everything else needed to
+//! replay a run bit-exactly (the ROM, the RNG seed and the quirks).
+//!
+//! A frame is a fixed number of instructions (`ipf`), so keypad changes land on the same
+//! instruction when replaying, regardless of how fast the replay runs. While recording,
+//! the state hash at the start of every `hash-interval`th frame is stored too, and checked
+//! when replaying to catch desyncs close to where they happen. Movies recorded with VIP
+//! timing say so in a `timing vip` line, as it changes what the instructions do, and those
+//! recorded on an emulated VIP in a `backend vip` line. Likewise, `platform` and `layout`
+//! lines hold the platform and memory layout, unless they're plain CHIP-8's.
+//!
+//! # File format
+//!
+//! Text, so that movies attached to bug reports can be read and diffed. A header of
+//! `KEY VALUE` lines, then one line per frame after `frames`, holding the keypad state as a
+//! hex bit mask (bit `n` set when key `n` is down), and optionally the state hash:
+//!
+//! ```text
+//! patata-movie 1
+//! rom-sha1 0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b
+//! seed 1234
+//! quirks shift-vy,load-store-i
+//! ipf 11
+//! hash-interval 60
+//! frames
+//! 0000 9c1185a5c5e9fc54
+//! 0000
+//! 0010
+//! ```
+
+use std::{fmt::Write as _, path::Path};
+
+use anyhow::{bail, Context};
+
+use crate::{
+    chip8::Chip8,
+    cosmac::Backend,
+    layout::MemoryLayout,
+    quirks::{Platform, Quirks},
+    rom::{Rom, RomLoader},
+    timing::Timing,
+};
+
+const MAGIC: &str = "patata-movie";
+const VERSION: u32 = 1;
+pub const DEFAULT_HASH_INTERVAL: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    pub keys: u16,
+    /// Hash of the machine state at the start of the frame, before its keys are applied.
+    pub state_hash: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_sha1: String,
+    pub seed: u64,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub ipf: u32,
+    pub platform: Platform,
+    pub layout: MemoryLayout,
+    pub timing: Timing,
+    pub backend: Backend,
+    pub hash_interval: u32,
+    pub frames: Vec<Frame>,
+}
+
+impl Movie {
+    pub fn new(rom_sha1: String, seed: u64, quirks: Quirks, ipf: u32) -> Self {
+        Self {
+            rom_sha1,
+            seed,
+            quirks,
+            ipf: ipf.max(1),
+            platform: Platform::default(),
+            layout: MemoryLayout::default(),
+            timing: Timing::default(),
+            backend: Backend::default(),
+            hash_interval: DEFAULT_HASH_INTERVAL,
+            frames: Vec::new(),
+        }
+    }
+
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        let mut lines = s
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, line)) if line == format!("{MAGIC} {VERSION}") => {}
+            Some((_, line)) if line.starts_with(MAGIC) => bail!("unsupported movie {line:?}"),
+            _ => bail!("not a patata movie"),
+        }
+
+        let (mut rom_sha1, mut seed, mut quirks, mut ipf) = (None, None, None, None);
+        let mut hash_interval = DEFAULT_HASH_INTERVAL;
+        let mut platform = Platform::default();
+        let mut layout = MemoryLayout::default();
+        let mut timing = Timing::default();
+        let mut backend = Backend::default();
+
+        for (n, line) in lines.by_ref() {
+            if line == "frames" {
+                break;
+            }
+
+            let (key, value) = line
+                .split_once(' ')
+                .with_context(|| format!("line {n}: expected `KEY VALUE`"))?;
+            let value = value.trim();
+            let context = || format!("line {n}: invalid {key}");
+
+            match key {
+                "rom-sha1" => rom_sha1 = Some(value.to_string()),
+                "seed" => seed = Some(value.parse().with_context(context)?),
+                "quirks" => quirks = Some(value.parse().with_context(context)?),
+                "ipf" => ipf = Some(value.parse().with_context(context)?),
+                "platform" => platform = value.parse().with_context(context)?,
+                "layout" => layout = value.parse().with_context(context)?,
+                "timing" => timing = value.parse().with_context(context)?,
+                "backend" => backend = value.parse().with_context(context)?,
+                "hash-interval" => hash_interval = value.parse().with_context(context)?,
+                _ => bail!("line {n}: unknown header {key:?}"),
+            }
+        }
+
+        let mut frames = Vec::new();
+        for (n, line) in lines {
+            let mut fields = line.split_whitespace();
+            let keys = fields.next().and_then(|k| u16::from_str_radix(k, 16).ok());
+            let state_hash = fields.next().map(|h| u64::from_str_radix(h, 16));
+
+            match (keys, state_hash) {
+                (Some(keys), None) => frames.push(Frame {
+                    keys,
+                    state_hash: None,
+                }),
+                (Some(keys), Some(Ok(hash))) => frames.push(Frame {
+                    keys,
+                    state_hash: Some(hash),
+                }),
+                _ => bail!("line {n}: invalid frame {line:?}"),
+            }
+        }
+
+        Ok(Self {
+            rom_sha1: rom_sha1.context("missing rom-sha1")?,
+            seed: seed.context("missing seed")?,
+            quirks: quirks.context("missing quirks")?,
+            ipf: ipf.filter(|&ipf| ipf > 0).context("missing or zero ipf")?,
+            platform,
+            layout,
+            timing,
+            backend,
+            hash_interval: hash_interval.max(1),
+            frames,
+        })
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read movie {}", path.display()))?;
+        Self::parse(&s).with_context(|| format!("invalid movie {}", path.display()))
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut s = format!(
+            "{MAGIC} {VERSION}\nrom-sha1 {}\nseed {}\nquirks {}\nipf {}\n",
+            self.rom_sha1, self.seed, self.quirks, self.ipf
+        );
+        if self.platform != Platform::default() {
+            let _ = writeln!(s, "platform {}", self.platform);
+        }
+        if self.layout != MemoryLayout::default() {
+            let _ = writeln!(s, "layout {}", self.layout);
+        }
+        if self.timing != Timing::Fixed {
+            let _ = writeln!(s, "timing {}", self.timing);
+        }
+        if self.backend != Backend::Native {
+            let _ = writeln!(s, "backend {}", self.backend);
+        }
+        let _ = writeln!(s, "hash-interval {}\nframes", self.hash_interval);
+
+        for frame in &self.frames {
+            let _ = match frame.state_hash {
+                Some(hash) => writeln!(s, "{:04x} {hash:016x}", frame.keys),
+                None => writeln!(s, "{:04x}", frame.keys),
+            };
+        }
+
+        s
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_text())
+            .with_context(|| format!("failed to write movie {}", path.display()))
+    }
+
+    /// Checks that the movie was recorded with the ROM, and with the settings `loader` was
+    /// explicitly given, and sets up `rom` to run the way it did when recording started.
+    pub fn prepare(&self, rom: &mut Rom, loader: &RomLoader) -> anyhow::Result<()> {
+        if self.rom_sha1 != rom.sha1 {
+            bail!(
+                "movie was recorded with ROM {}, but this ROM is {}",
+                self.rom_sha1,
+                rom.sha1
+            );
+        }
+        if self.backend != rom.backend {
+            bail!(
+                "movie was recorded with the {} backend, but this is the {} backend",
+                self.backend,
+                rom.backend
+            );
+        }
+
+        check_setting("platform", self.platform, loader.platform)?;
+        check_setting("quirks", self.quirks, loader.quirks)?;
+        check_setting("layout", self.layout, loader.layout)?;
+        check_setting("seed", self.seed, loader.seed)?;
+        check_setting("timing", self.timing, loader.timing)?;
+
+        rom.platform = self.platform;
+        rom.quirks = self.quirks;
+        rom.layout = self.layout;
+        rom.seed = self.seed;
+        rom.timing = self.timing;
+        Ok(())
+    }
+}
+
+/// Fails if the setting was explicitly given, and differs from the one recorded.
+fn check_setting<T: PartialEq + std::fmt::Display>(
+    name: &str,
+    recorded: T,
+    explicit: Option<T>,
+) -> anyhow::Result<()> {
+    match explicit {
+        Some(explicit) if explicit != recorded => {
+            bail!("movie was recorded with {name} {recorded}, but {explicit} was given")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// A replayed frame whose state hash differs from the recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Desync {
+    pub frame: usize,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieEvent {
+    Desync(Desync),
+    /// The replay ran out of frames.
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Recording,
+    Replaying,
+}
+
+/// Records or replays a [`Movie`], driving the keypad at frame boundaries.
+#[derive(Debug, Clone)]
+pub struct MovieSession {
+    movie: Movie,
+    mode: Mode,
+    frame: usize,
+    step: u64,
+    desync: Option<Desync>,
+}
+
+impl MovieSession {
+    pub fn record(movie: Movie) -> Self {
+        Self {
+            movie,
+            mode: Mode::Recording,
+            frame: 0,
+            step: 0,
+            desync: None,
+        }
+    }
+
+    pub fn replay(movie: Movie) -> Self {
+        Self {
+            movie,
+            mode: Mode::Replaying,
+            frame: 0,
+            step: 0,
+            desync: None,
+        }
+    }
+
+    pub fn movie(&self) -> &Movie {
+        &self.movie
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.mode == Mode::Recording
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    /// The first desync found while replaying, if any.
+    pub fn desync(&self) -> Option<Desync> {
+        self.desync
+    }
+
+    /// Called before every instruction. At frame boundaries, applies the keys of the frame
+    /// to `chip8`: `input` when recording, the recorded keys when replaying.
+    pub fn before_step(&mut self, chip8: &mut Chip8, input: u16) -> Option<MovieEvent> {
+        let at_boundary = self.step.is_multiple_of(u64::from(self.movie.ipf));
+        self.step += 1;
+        if !at_boundary {
+            return None;
+        }
+
+        let frame = self.frame;
+        let hashed = frame.is_multiple_of(self.movie.hash_interval as usize);
+        self.frame += 1;
+
+        match self.mode {
+            Mode::Recording => {
+                self.movie.frames.push(Frame {
+                    keys: input,
+                    state_hash: hashed.then(|| chip8.state_hash()),
+                });
+                chip8.set_keys(input);
+                None
+            }
+            Mode::Replaying => {
+                let Some(recorded) = self.movie.frames.get(frame) else {
+                    return Some(MovieEvent::Finished);
+                };
+
+                // Hash before applying the keys, like the recording did.
+                let hashes = recorded.state_hash.map(|h| (h, chip8.state_hash()));
+                chip8.set_keys(recorded.keys);
+
+                let (expected, actual) = hashes?;
+                if expected == actual {
+                    return None;
+                }
+
+                let desync = Desync {
+                    frame,
+                    expected,
+                    actual,
+                };
+                self.desync.get_or_insert(desync);
+                Some(MovieEvent::Desync(desync))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Counts frames in V0, and adds the pressed key to V1 whenever a key is down.
+    //   200: ADD V0, 1; 202: LD V2, 0; 204: SKNP V2; 206: ADD V1, 1; 208: JP 200
+    const ROM: [u8; 10] = [0x70, 0x01, 0x62, 0x00, 0xE2, 0xA1, 0x71, 0x01, 0x12, 0x00];
+
+    fn chip8() -> Chip8 {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(&ROM).unwrap();
+        chip8
+    }
+
+    /// The ROM as loaded from the file `name`, with the SHA-1 the movies are recorded with.
+    fn rom(name: &str) -> Rom {
+        let dir = std::env::temp_dir().join(format!("patata-movie-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        std::fs::write(&path, ROM).unwrap();
+        std::fs::write(dir.join("programs.json"), "[]").unwrap();
+
+        let loader = RomLoader {
+            rom_db: Some(dir.join("programs.json")),
+            ..RomLoader::default()
+        };
+        let mut rom = loader.load(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        rom.sha1 = "abc".to_string();
+        rom
+    }
+
+    fn record() -> (Movie, Chip8) {
+        let mut chip8 = chip8();
+        let mut session =
+            MovieSession::record(Movie::new("abc".to_string(), 7, Quirks::default(), 5));
+        session.movie.hash_interval = 2;
+
+        for step in 0..50 {
+            let input = if (10..25).contains(&step) { 0b1 } else { 0 };
+            assert_eq!(None, session.before_step(&mut chip8, input));
+            chip8.tick();
+        }
+
+        (session.movie, chip8)
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let (movie, _) = record();
+        assert_eq!(10, movie.frames.len());
+        assert_eq!(Some(movie.clone()), Movie::parse(&movie.to_text()).ok());
+
+        let vip = Movie {
+            timing: Timing::Vip,
+            ..movie.clone()
+        };
+        assert!(vip.to_text().contains("\ntiming vip\n"));
+        assert_eq!(Some(vip.clone()), Movie::parse(&vip.to_text()).ok());
+
+        let hires = Movie {
+            platform: Platform::Chip8HiRes,
+            layout: Platform::Chip8HiRes.layout(),
+            ..movie
+        };
+        assert!(hires
+            .to_text()
+            .contains("\nplatform chip8-hires\nlayout hires\n"));
+        assert_eq!(Some(hires.clone()), Movie::parse(&hires.to_text()).ok());
+    }
+
+    #[test]
+    fn replay_is_exact() {
+        let (movie, recorded) = record();
+
+        let mut rom = rom("replay.ch8");
+        rom.seed = 1;
+        rom.quirks = Platform::SuperChip.quirks();
+        movie.prepare(&mut rom, &RomLoader::default()).unwrap();
+        let mut chip8 = rom.chip8().unwrap();
+        let mut session = MovieSession::replay(movie);
+        for _ in 0..50 {
+            assert_eq!(None, session.before_step(&mut chip8, 0xFFFF));
+            chip8.tick();
+        }
+
+        assert_eq!(recorded.state_hash(), chip8.state_hash());
+        assert_eq!(
+            Some(MovieEvent::Finished),
+            session.before_step(&mut chip8, 0)
+        );
+    }
+
+    #[test]
+    fn replay_detects_desync() {
+        let (movie, _) = record();
+
+        let mut chip8 = chip8();
+        let mut session = MovieSession::replay(movie);
+        for step in 0..10 {
+            if step == 7 {
+                chip8.registers[5] = 0x55;
+            }
+            assert_eq!(None, session.before_step(&mut chip8, 0));
+            chip8.tick();
+        }
+
+        // Frame 2, which starts at step 10, is the first hashed frame after the change.
+        assert!(matches!(
+            session.before_step(&mut chip8, 0),
+            Some(MovieEvent::Desync(Desync { frame: 2, .. }))
+        ));
+    }
+
+    #[test]
+    fn rejects_wrong_rom() {
+        let (movie, _) = record();
+        let mut rom = rom("wrong.ch8");
+        rom.sha1 = "def".to_string();
+        assert!(movie.prepare(&mut rom, &RomLoader::default()).is_err());
+    }
+
+    #[test]
+    fn applies_settings_unless_given_others() {
+        let (movie, _) = record();
+        let movie = Movie {
+            platform: Platform::Chip8HiRes,
+            layout: Platform::Chip8HiRes.layout(),
+            ..movie
+        };
+
+        let mut rom = rom("settings.ch8");
+        movie.prepare(&mut rom, &RomLoader::default()).unwrap();
+        assert_eq!(Platform::Chip8HiRes, rom.platform);
+        assert_eq!(Platform::Chip8HiRes.layout(), rom.layout);
+        assert_eq!(7, rom.seed);
+
+        let same = RomLoader {
+            platform: Some(Platform::Chip8HiRes),
+            seed: Some(7),
+            ..RomLoader::default()
+        };
+        assert!(movie.prepare(&mut rom, &same).is_ok());
+
+        for other in [
+            RomLoader {
+                platform: Some(Platform::SuperChip),
+                ..RomLoader::default()
+            },
+            RomLoader {
+                layout: Some(MemoryLayout::default()),
+                ..RomLoader::default()
+            },
+            RomLoader {
+                seed: Some(8),
+                ..RomLoader::default()
+            },
+        ] {
+            assert!(movie.prepare(&mut rom, &other).is_err());
+        }
+    }
+}
@@ -0,0 +1,235 @@
+//! Octo cartridges: GIF images with an Octo program and its settings hidden in them.
+//!
+//! The low two bits of every pixel's colour index are payload, most significant pair
+//! first, four pixels to a byte, frame after frame. The payload is a 32-bit big-endian
+//! length followed by that many bytes of JSON:
+//!
+//! ```json
+//! {
+//!   "program": ": main ...",
+//!   "options": {
+//!     "tickrate": 20,
+//!     "backgroundColor": "#996600",
+//!     "fillColor": "#FFCC00",
+//!     "shiftQuirks": false,
+//!     "loadStoreQuirks": false,
+//!     "jumpQuirks": false,
+//!     "logicQuirks": true,
+//!     "maxSize": 3584
+//!   }
+//! }
+//! ```
+//!
+//! Options this emulator has no use for are ignored.
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::{
+    palette::{parse_color, Palette},
+    quirks::{Platform, Quirks},
+    romdb::{RomInfo, RomKeys},
+};
+
+const MAGIC: &[u8] = b"GIF8";
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Cartridge {
+    /// Octo source, see [`super::compile`].
+    pub program: String,
+    #[serde(default)]
+    pub options: CartridgeOptions,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CartridgeOptions {
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    pub background_color: Option<String>,
+    pub fill_color: Option<String>,
+    /// `8xy6`/`8xyE` shift Vx in place.
+    pub shift_quirks: bool,
+    /// `Fx55`/`Fx65` leave I alone.
+    pub load_store_quirks: bool,
+    /// `Bxnn` jumps to `xnn + Vx`.
+    pub jump_quirks: bool,
+    /// `8xy1`, `8xy2` and `8xy3` reset VF.
+    pub logic_quirks: bool,
+    /// The largest program the platform has room for.
+    pub max_size: Option<u32>,
+}
+
+impl Default for CartridgeOptions {
+    fn default() -> Self {
+        // Octo's own defaults.
+        Self {
+            tickrate: None,
+            background_color: None,
+            fill_color: None,
+            shift_quirks: false,
+            load_store_quirks: false,
+            jump_quirks: false,
+            logic_quirks: true,
+            max_size: None,
+        }
+    }
+}
+
+/// Whether `bytes` look like a GIF, and so maybe an Octo cartridge.
+pub fn is_cartridge(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+impl Cartridge {
+    pub fn decode(gif: &[u8]) -> anyhow::Result<Self> {
+        let payload = payload(gif)?;
+        if payload.len() < 4 {
+            bail!("the image holds no program");
+        }
+        let (len, json) = payload.split_at(4);
+        let len = u32::from_be_bytes(len.try_into().unwrap()) as usize;
+        if len > json.len() {
+            bail!(
+                "the image holds {} bytes of its {len}-byte program",
+                json.len()
+            );
+        }
+        serde_json::from_slice(&json[..len]).context("invalid cartridge data")
+    }
+
+    pub fn platform(&self) -> Platform {
+        match self.options.max_size {
+            Some(65024) => Platform::XoChip,
+            Some(3583) => Platform::SuperChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        let options = &self.options;
+        Quirks {
+            shift_vy: !options.shift_quirks,
+            load_store_i: !options.load_store_quirks,
+            jump_vx: options.jump_quirks,
+            vf_reset: options.logic_quirks,
+        }
+    }
+
+    pub fn palette(&self) -> anyhow::Result<Option<Palette>> {
+        let options = &self.options;
+        let (Some(background), Some(foreground)) = (&options.background_color, &options.fill_color)
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Palette {
+            background: parse_color(background)?,
+            foreground: parse_color(foreground)?,
+        }))
+    }
+
+    /// The cartridge's settings, as if the ROM database knew them.
+    pub fn info(&self, title: &str) -> anyhow::Result<RomInfo> {
+        Ok(RomInfo {
+            title: title.to_string(),
+            platform: self.platform(),
+            quirks: self.quirks(),
+            ipf: self.options.tickrate,
+            palette: self.palette()?,
+            keys: RomKeys::default(),
+        })
+    }
+}
+
+fn payload(gif: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(gif).context("invalid GIF")?;
+
+    let mut pairs = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().context("invalid GIF")? {
+        pairs.extend(frame.buffer.iter().map(|index| index & 0b11));
+    }
+    Ok(pairs
+        .chunks_exact(4)
+        .map(|pairs| pairs.iter().fold(0, |byte, pair| byte << 2 | pair))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use std::borrow::Cow;
+
+    use super::*;
+
+    /// A cartridge image carrying `json`.
+    fn encode(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend(json.as_bytes());
+        let mut pixels: Vec<u8> = payload
+            .iter()
+            .flat_map(|byte| [6, 4, 2, 0].map(|shift| byte >> shift & 0b11 | 0b100))
+            .collect();
+        let (width, height) = (64u16, pixels.len().div_ceil(64) as u16);
+        pixels.resize(width as usize * height as usize, 0b100);
+
+        let palette: Vec<u8> = (0..8).flat_map(|i| [i * 30; 3]).collect();
+        let mut gif = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut gif, width, height, &palette).unwrap();
+        encoder
+            .write_frame(&gif::Frame {
+                width,
+                height,
+                buffer: Cow::Owned(pixels),
+                ..Default::default()
+            })
+            .unwrap();
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn decode() {
+        let gif = encode(
+            r##"{
+                "program": ": main jump main",
+                "options": {
+                    "tickrate": 20,
+                    "backgroundColor": "#996600",
+                    "fillColor": "#FFCC00",
+                    "shiftQuirks": true,
+                    "maxSize": 3583,
+                    "screenRotation": 0
+                }
+            }"##,
+        );
+        assert!(is_cartridge(&gif));
+
+        let cartridge = Cartridge::decode(&gif).unwrap();
+        assert_eq!(": main jump main", cartridge.program);
+        let info = cartridge.info("Example").unwrap();
+        assert_eq!(
+            RomInfo {
+                title: "Example".to_string(),
+                platform: Platform::SuperChip,
+                quirks: Quirks {
+                    shift_vy: false,
+                    load_store_i: true,
+                    jump_vx: false,
+                    vf_reset: true,
+                },
+                ipf: Some(20),
+                palette: Some(Palette::PRESETS[1].1),
+                keys: RomKeys::default(),
+            },
+            info
+        );
+    }
+
+    #[test]
+    fn not_a_cartridge() {
+        assert!(!is_cartridge(b"\x12\x00"));
+        assert!(Cartridge::decode(b"GIF89a").is_err());
+        assert!(Cartridge::decode(&encode("{}")).is_err());
+    }
+}
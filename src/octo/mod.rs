@@ -0,0 +1,1139 @@
+//! Compiling programs written in [Octo](https://github.com/JohnEarnest/Octo)'s language,
+//! which is what Octo cartridges carry instead of ROMs.
+//!
+//! ```text
+//! : main
+//!     i := smile
+//!     loop
+//!         v0 += 1
+//!         if v0 == 60 then v0 := 0
+//!         sprite v0 v1 5
+//!     again
+//! : smile
+//!     0x00 0x24 0x00 0x42 0x3C
+//! ```
+//!
+//! Everything but `:stringmode` is supported: all the statements of CHIP-8, SUPER-CHIP and
+//! XO-CHIP, labels, `:alias`, `:const`, `:org`, `:byte`, `:pointer`, `:call`, `:unpack`,
+//! `:next`, `:macro`, `:assert`, `:calc` and `{ }` expressions, and `if`, `loop` and
+//! `while` blocks. `:breakpoint` and `:monitor` are ignored. As in Octo, expressions are
+//! evaluated from right to left without operator precedence, and comparisons like `<`
+//! clobber VF.
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{anyhow, bail};
+
+use crate::{asm::Program, chip8::PROG_CTR_START_ADDR, symbols::Symbols};
+
+pub mod cartridge;
+
+// XO-CHIP programs can fill 64K of memory.
+const MEMORY_END: usize = 0x10000;
+// How deeply parentheses and unary operators can nest in a `:calc` expression.
+const MAX_EXPRESSION_DEPTH: usize = 256;
+
+pub fn compile(source: &str) -> anyhow::Result<Program> {
+    let tokens = tokenize(source)?;
+    let mut compiler = Compiler::new(tokens);
+    compiler.compile().map_err(|err| match compiler.line {
+        0 => err,
+        line => anyhow!("line {line}: {err}"),
+    })?;
+    Ok(compiler.into_program())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Token {
+    text: String,
+    line: usize,
+    /// Whether the token was a quoted string.
+    quoted: bool,
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let mut rest = line;
+        loop {
+            rest = rest.trim_start();
+            if rest.is_empty() || rest.starts_with('#') {
+                break;
+            }
+
+            let (text, quoted, len) = match rest.strip_prefix('"') {
+                Some(string) => {
+                    let end = string
+                        .find('"')
+                        .ok_or_else(|| anyhow!("line {}: unterminated string", i + 1))?;
+                    (&string[..end], true, end + 2)
+                }
+                None => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    (&rest[..end], false, end)
+                }
+            };
+            tokens.push(Token {
+                text: text.to_string(),
+                line: i + 1,
+                quoted,
+            });
+            rest = &rest[len..];
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let (negative, digits) = match s.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, s),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// How a name used before it's defined is filled in once it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fixup {
+    /// The low 12 bits of the instruction at the address.
+    Nnn,
+    /// The 16-bit word at the address.
+    Word,
+    /// The `v0 := nn` and `v1 := nn` of an `:unpack` at the address, keeping the high
+    /// nibble of `v0`'s byte unless it's `:unpack long`.
+    Unpack { long: bool },
+}
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Key,
+    NotKey,
+}
+
+impl Comparison {
+    fn negate(self) -> Self {
+        match self {
+            Self::Eq => Self::Ne,
+            Self::Ne => Self::Eq,
+            Self::Lt => Self::Ge,
+            Self::Ge => Self::Lt,
+            Self::Gt => Self::Le,
+            Self::Le => Self::Gt,
+            Self::Key => Self::NotKey,
+            Self::NotKey => Self::Key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Condition {
+    register: u8,
+    comparison: Comparison,
+    operand: Operand,
+}
+
+impl Condition {
+    fn negate(self) -> Self {
+        Self {
+            comparison: self.comparison.negate(),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Loop {
+    start: usize,
+    /// The jumps out of the loop that `while`s emitted.
+    exits: Vec<usize>,
+}
+
+struct Compiler {
+    tokens: VecDeque<Token>,
+    /// The line of the token being compiled.
+    line: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, u16>,
+    /// Label names in the order they were defined.
+    label_order: Vec<String>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    forward: HashMap<String, Vec<(usize, Fixup, usize)>>,
+    /// The jumps past the open `begin` and `else` blocks.
+    branches: Vec<usize>,
+    loops: Vec<Loop>,
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens: tokens.into(),
+            line: 0,
+            rom: Vec::new(),
+            here: PROG_CTR_START_ADDR as usize,
+            labels: HashMap::new(),
+            label_order: Vec::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            forward: HashMap::new(),
+            branches: Vec::new(),
+            loops: Vec::new(),
+        }
+    }
+
+    fn into_program(self) -> Program {
+        let mut symbols = Symbols::default();
+        for name in &self.label_order {
+            symbols.insert(self.labels[name], name);
+        }
+        Program {
+            bytes: self.rom,
+            symbols,
+        }
+    }
+
+    fn compile(&mut self) -> anyhow::Result<()> {
+        // Programs start at `main`, wherever it is.
+        self.inst(0x10, 0x00)?;
+        self.forward.entry("main".to_string()).or_default().push((
+            PROG_CTR_START_ADDR as usize,
+            Fixup::Nnn,
+            0,
+        ));
+
+        while !self.tokens.is_empty() {
+            self.statement()?;
+        }
+
+        if !self.branches.is_empty() {
+            bail!("a `begin` is missing its `end`");
+        }
+        if !self.loops.is_empty() {
+            bail!("a `loop` is missing its `again`");
+        }
+        self.line = 0;
+        let mut undefined: Vec<_> = self.forward.iter().collect();
+        undefined.sort_by_key(|(_, uses)| uses.iter().map(|&(_, _, line)| line).min());
+        if let Some((name, uses)) = undefined.first() {
+            if *name == "main" {
+                bail!("the program has no `main` label");
+            }
+            bail!("line {}: undefined name {name:?}", uses[0].2);
+        }
+        Ok(())
+    }
+
+    fn next(&mut self) -> anyhow::Result<Token> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| anyhow!("unexpected end of program"))?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn next_text(&mut self) -> anyhow::Result<String> {
+        Ok(self.next()?.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.front().map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> anyhow::Result<()> {
+        let text = self.next_text()?;
+        if text != expected {
+            bail!("expected `{expected}`, got {text:?}");
+        }
+        Ok(())
+    }
+
+    fn emit(&mut self, byte: u8) -> anyhow::Result<()> {
+        if self.here >= MEMORY_END {
+            bail!("the program doesn't fit into memory");
+        }
+        let offset = self.here - PROG_CTR_START_ADDR as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn inst(&mut self, hi: u8, lo: u8) -> anyhow::Result<()> {
+        self.emit(hi)?;
+        self.emit(lo)
+    }
+
+    /// Emits an instruction with a 12-bit address, filled in later if it's a name that
+    /// isn't defined yet.
+    fn inst_nnn(&mut self, op: u8) -> anyhow::Result<()> {
+        let at = self.here;
+        let addr = self.address(at, Fixup::Nnn)?;
+        if addr > 0xFFF {
+            bail!("address {addr:#x} doesn't fit into 12 bits");
+        }
+        self.inst(op << 4 | (addr >> 8) as u8, addr as u8)
+    }
+
+    fn patch(&mut self, at: usize, fixup: Fixup, value: u16) -> anyhow::Result<()> {
+        let offset = at - PROG_CTR_START_ADDR as usize;
+        match fixup {
+            Fixup::Nnn => {
+                if value > 0xFFF {
+                    bail!("address {value:#x} doesn't fit into 12 bits");
+                }
+                self.rom[offset] = self.rom[offset] & 0xF0 | (value >> 8) as u8;
+                self.rom[offset + 1] = value as u8;
+            }
+            Fixup::Word => {
+                self.rom[offset..offset + 2].copy_from_slice(&value.to_be_bytes());
+            }
+            Fixup::Unpack { long } => {
+                let [high, low] = value.to_be_bytes();
+                self.rom[offset + 1] = match long {
+                    true => high,
+                    false => self.rom[offset + 1] & 0xF0 | high & 0x0F,
+                };
+                self.rom[offset + 3] = low;
+            }
+        }
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String, addr: usize) -> anyhow::Result<()> {
+        if self.labels.contains_key(&name) {
+            bail!("label {name:?} is already defined");
+        }
+        if self.is_register(&name) {
+            bail!("{name:?} is a register, not a label name");
+        }
+        let addr = addr as u16;
+        for (at, fixup, _) in self.forward.remove(&name).unwrap_or_default() {
+            self.patch(at, fixup, addr)?;
+        }
+        self.labels.insert(name.clone(), addr);
+        self.label_order.push(name);
+        Ok(())
+    }
+
+    fn is_register(&self, name: &str) -> bool {
+        register_number(name).is_some() || self.aliases.contains_key(name)
+    }
+
+    fn register(&mut self) -> anyhow::Result<u8> {
+        let name = self.next_text()?;
+        register_number(&name)
+            .or_else(|| self.aliases.get(&name).copied())
+            .ok_or_else(|| anyhow!("expected a register, got {name:?}"))
+    }
+
+    fn peek_is_register(&self) -> bool {
+        self.peek().is_some_and(|name| self.is_register(name))
+    }
+
+    /// The value of a number, constant, label or `{ }` expression.
+    fn value(&mut self) -> anyhow::Result<i64> {
+        let text = self.next_text()?;
+        if text == "{" {
+            return Ok(self.calc_block()? as i64);
+        }
+        self.known_value(&text)
+            .map(|value| value as i64)
+            .ok_or_else(|| anyhow!("undefined name {text:?}"))
+    }
+
+    fn known_value(&self, name: &str) -> Option<f64> {
+        parse_number(name)
+            .map(|value| value as f64)
+            .or_else(|| self.constants.get(name).copied())
+            .or_else(|| self.labels.get(name).map(|&addr| addr as f64))
+    }
+
+    /// An address, which may be a label defined further on.
+    fn address(&mut self, at: usize, fixup: Fixup) -> anyhow::Result<u16> {
+        let text = match self.peek() {
+            Some("{") => return Ok(self.value()? as u16),
+            Some(text) => text.to_string(),
+            None => bail!("unexpected end of program"),
+        };
+        if let Some(value) = self.known_value(&text) {
+            self.next()?;
+            return Ok(value as u16);
+        }
+
+        let token = self.next()?;
+        if token.quoted || self.is_register(&token.text) {
+            bail!("expected an address, got {:?}", token.text);
+        }
+        self.forward
+            .entry(token.text)
+            .or_default()
+            .push((at, fixup, token.line));
+        Ok(0)
+    }
+
+    fn byte(&mut self) -> anyhow::Result<u8> {
+        let value = self.value()?;
+        if !(-128..=255).contains(&value) {
+            bail!("{value} doesn't fit into a byte");
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> anyhow::Result<u8> {
+        let value = self.value()?;
+        if !(0..=15).contains(&value) {
+            bail!("{value} doesn't fit into 4 bits");
+        }
+        Ok(value as u8)
+    }
+
+    fn statement(&mut self) -> anyhow::Result<()> {
+        let token = self.next()?;
+        if token.quoted {
+            bail!("unexpected string {:?}", token.text);
+        }
+
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next_text()?;
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.next_text()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.next_text()?;
+                let register = if self.peek() == Some("{") {
+                    self.next()?;
+                    let value = self.calc_block()?;
+                    if !(0.0..16.0).contains(&value) {
+                        bail!("register {value} doesn't exist");
+                    }
+                    value as u8
+                } else {
+                    self.register()?
+                };
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.next_text()?;
+                let value = self.value()?;
+                self.constants.insert(name, value as f64);
+            }
+            ":calc" => {
+                let name = self.next_text()?;
+                self.expect("{")?;
+                let value = self.calc_block()?;
+                self.constants.insert(name, value);
+            }
+            ":org" => {
+                let addr = self.value()?;
+                if !(PROG_CTR_START_ADDR as i64..MEMORY_END as i64).contains(&addr) {
+                    bail!("can't place code at {addr:#x}");
+                }
+                self.here = addr as usize;
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit(byte)?;
+            }
+            ":pointer" => {
+                let at = self.here;
+                let addr = self.address(at, Fixup::Word)?;
+                self.inst((addr >> 8) as u8, addr as u8)?;
+            }
+            ":call" => self.inst_nnn(0x2)?,
+            ":unpack" => {
+                let (high, long) = if self.peek() == Some("long") {
+                    self.next()?;
+                    (0, true)
+                } else {
+                    (self.nibble()?, false)
+                };
+                let at = self.here;
+                let addr = self.address(at, Fixup::Unpack { long })?;
+                self.inst(0x60, high << 4)?;
+                self.inst(0x61, 0x00)?;
+                self.patch(at, Fixup::Unpack { long }, addr)?;
+            }
+            ":macro" => self.define_macro()?,
+            ":assert" => {
+                let message = match self.tokens.front() {
+                    Some(token) if token.quoted => Some(self.next_text()?),
+                    _ => None,
+                };
+                self.expect("{")?;
+                if self.calc_block()? == 0.0 {
+                    match message {
+                        Some(message) => bail!("assertion failed: {message}"),
+                        None => bail!("assertion failed"),
+                    }
+                }
+            }
+            ":breakpoint" | ":proto" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            ":stringmode" => bail!("`:stringmode` isn't supported"),
+
+            "return" | ";" => self.inst(0x00, 0xEE)?,
+            "clear" => self.inst(0x00, 0xE0)?,
+            "hires" => self.inst(0x00, 0xFF)?,
+            "lores" => self.inst(0x00, 0xFE)?,
+            "exit" => self.inst(0x00, 0xFD)?,
+            "scroll-left" => self.inst(0x00, 0xFC)?,
+            "scroll-right" => self.inst(0x00, 0xFB)?,
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.inst(0x00, 0xC0 | n)?;
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.inst(0x00, 0xD0 | n)?;
+            }
+            "audio" => self.inst(0xF0, 0x02)?,
+            "plane" => {
+                let n = self.nibble()?;
+                self.inst(0xF0 | n, 0x01)?;
+            }
+            "bcd" => self.register_inst(0x33)?,
+            "saveflags" => self.register_inst(0x75)?,
+            "loadflags" => self.register_inst(0x85)?,
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.next()?;
+                    let y = self.register()?;
+                    let n = if token.text == "save" { 0x2 } else { 0x3 };
+                    self.inst(0x50 | x, y << 4 | n)?;
+                } else {
+                    let nn = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.inst(0xF0 | x, nn)?;
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.inst(0xD0 | x, y << 4 | n)?;
+            }
+            "jump" => self.inst_nnn(0x1)?,
+            "jump0" => self.inst_nnn(0xB)?,
+            "native" => self.inst_nnn(0x0)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let nn = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.register_inst(nn)?;
+            }
+            "i" => self.index_statement()?,
+
+            "if" => self.if_statement()?,
+            "else" => {
+                let branch = self
+                    .branches
+                    .pop()
+                    .ok_or_else(|| anyhow!("`else` without `begin`"))?;
+                let jump = self.here;
+                self.inst(0x10, 0x00)?;
+                self.patch(branch, Fixup::Nnn, self.here as u16)?;
+                self.branches.push(jump);
+            }
+            "end" => {
+                let branch = self
+                    .branches
+                    .pop()
+                    .ok_or_else(|| anyhow!("`end` without `begin`"))?;
+                self.patch(branch, Fixup::Nnn, self.here as u16)?;
+            }
+            "loop" => self.loops.push(Loop {
+                start: self.here,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                if self.loops.is_empty() {
+                    bail!("`while` outside of a loop");
+                }
+                let condition = self.condition()?;
+                self.skip_unless(condition.negate())?;
+                let jump = self.here;
+                self.inst(0x10, 0x00)?;
+                self.loops.last_mut().unwrap().exits.push(jump);
+            }
+            "again" => {
+                let lp = self
+                    .loops
+                    .pop()
+                    .ok_or_else(|| anyhow!("`again` without `loop`"))?;
+                self.inst(0x10 | (lp.start >> 8) as u8, lp.start as u8)?;
+                for exit in lp.exits {
+                    self.patch(exit, Fixup::Nnn, self.here as u16)?;
+                }
+            }
+
+            "{" => {
+                let value = self.calc_block()?;
+                self.emit(value as i64 as u8)?;
+            }
+            text if self.is_register(text) => {
+                self.tokens.push_front(token);
+                self.register_statement()?;
+            }
+            text if self.macros.contains_key(text) => self.expand_macro(text)?,
+            text => {
+                if let Some(value) = parse_number(text) {
+                    if !(-128..=255).contains(&value) {
+                        bail!("{value} doesn't fit into a byte");
+                    }
+                    self.emit(value as u8)?;
+                } else if let Some(&value) = self.constants.get(text) {
+                    self.emit(value as i64 as u8)?;
+                } else {
+                    // Anything else is a subroutine call.
+                    self.tokens.push_front(token);
+                    self.inst_nnn(0x2)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `Fx..` with a register.
+    fn register_inst(&mut self, nn: u8) -> anyhow::Result<()> {
+        let x = self.register()?;
+        self.inst(0xF0 | x, nn)
+    }
+
+    fn index_statement(&mut self) -> anyhow::Result<()> {
+        match self.next_text()?.as_str() {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.next()?;
+                    self.register_inst(0x29)
+                }
+                Some("bighex") => {
+                    self.next()?;
+                    self.register_inst(0x30)
+                }
+                Some("long") => {
+                    self.next()?;
+                    self.inst(0xF0, 0x00)?;
+                    let at = self.here;
+                    let addr = self.address(at, Fixup::Word)?;
+                    self.inst((addr >> 8) as u8, addr as u8)
+                }
+                _ => self.inst_nnn(0xA),
+            },
+            "+=" => self.register_inst(0x1E),
+            op => bail!("unknown operator `i {op}`"),
+        }
+    }
+
+    fn register_statement(&mut self) -> anyhow::Result<()> {
+        let x = self.register()?;
+        let op = self.next_text()?;
+
+        if op == ":=" {
+            match self.peek() {
+                Some("delay") => {
+                    self.next()?;
+                    return self.inst(0xF0 | x, 0x07);
+                }
+                Some("key") => {
+                    self.next()?;
+                    return self.inst(0xF0 | x, 0x0A);
+                }
+                Some("random") => {
+                    self.next()?;
+                    let nn = self.byte()?;
+                    return self.inst(0xC0 | x, nn);
+                }
+                _ => {}
+            }
+        }
+
+        if self.peek_is_register() {
+            let y = self.register()?;
+            let n = match op.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => bail!("unknown operator `{op}`"),
+            };
+            return self.inst(0x80 | x, y << 4 | n);
+        }
+
+        let nn = self.byte()?;
+        match op.as_str() {
+            ":=" => self.inst(0x60 | x, nn),
+            "+=" => self.inst(0x70 | x, nn),
+            "-=" => self.inst(0x70 | x, nn.wrapping_neg()),
+            _ => bail!("`{op}` needs a register on the right"),
+        }
+    }
+
+    fn condition(&mut self) -> anyhow::Result<Condition> {
+        let register = self.register()?;
+        let comparison = match self.next_text()?.as_str() {
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            "==" => Comparison::Eq,
+            "!=" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            ">" => Comparison::Gt,
+            "<=" => Comparison::Le,
+            ">=" => Comparison::Ge,
+            op => bail!("unknown comparison `{op}`"),
+        };
+
+        let operand = match comparison {
+            Comparison::Key | Comparison::NotKey => Operand::Byte(0),
+            _ if self.peek_is_register() => Operand::Register(self.register()?),
+            _ => Operand::Byte(self.byte()?),
+        };
+        Ok(Condition {
+            register,
+            comparison,
+            operand,
+        })
+    }
+
+    fn if_statement(&mut self) -> anyhow::Result<()> {
+        let condition = self.condition()?;
+        match self.next_text()?.as_str() {
+            "then" => self.skip_unless(condition),
+            "begin" => {
+                self.skip_unless(condition.negate())?;
+                self.branches.push(self.here);
+                self.inst(0x10, 0x00)
+            }
+            other => bail!("expected `then` or `begin`, got {other:?}"),
+        }
+    }
+
+    /// Emits instructions that skip the next one unless `condition` holds.
+    fn skip_unless(&mut self, condition: Condition) -> anyhow::Result<()> {
+        let x = condition.register;
+        match (condition.comparison, condition.operand) {
+            (Comparison::Eq, Operand::Byte(nn)) => self.inst(0x40 | x, nn),
+            (Comparison::Eq, Operand::Register(y)) => self.inst(0x90 | x, y << 4),
+            (Comparison::Ne, Operand::Byte(nn)) => self.inst(0x30 | x, nn),
+            (Comparison::Ne, Operand::Register(y)) => self.inst(0x50 | x, y << 4),
+            (Comparison::Key, _) => self.inst(0xE0 | x, 0xA1),
+            (Comparison::NotKey, _) => self.inst(0xE0 | x, 0x9E),
+            (comparison, operand) => {
+                // VF := operand, then VF := Vx - VF (SUBN) for < and >=, or VF -= Vx (SUB)
+                // for > and <=. Either way VF ends up 1 when the condition doesn't hold
+                // for < and >, and when it does for >= and <=.
+                match operand {
+                    Operand::Byte(nn) => self.inst(0x6F, nn)?,
+                    Operand::Register(y) => self.inst(0x8F, y << 4)?,
+                }
+                let (n, skip_if) = match comparison {
+                    Comparison::Lt => (0x7, 1),
+                    Comparison::Ge => (0x7, 0),
+                    Comparison::Gt => (0x5, 1),
+                    _ => (0x5, 0),
+                };
+                self.inst(0x8F, x << 4 | n)?;
+                self.inst(0x3F, skip_if)
+            }
+        }
+    }
+
+    fn define_macro(&mut self) -> anyhow::Result<()> {
+        let name = self.next_text()?;
+        let mut params = Vec::new();
+        loop {
+            let text = self.next_text()?;
+            if text == "{" {
+                break;
+            }
+            params.push(text);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.next()?;
+            if !token.quoted {
+                match token.text.as_str() {
+                    "{" => depth += 1,
+                    "}" => depth -= 1,
+                    _ => {}
+                }
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> anyhow::Result<()> {
+        let mac = self.macros[name].clone();
+        let mut args = HashMap::new();
+        for param in &mac.params {
+            args.insert(param.as_str(), self.next()?);
+        }
+
+        for token in mac.body.iter().rev() {
+            let token = match args.get(token.text.as_str()) {
+                Some(arg) if !token.quoted => arg.clone(),
+                _ => token.clone(),
+            };
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    /// Evaluates the expression up to the `}` closing a `{`.
+    fn calc_block(&mut self) -> anyhow::Result<f64> {
+        let mut tokens = Vec::new();
+        let mut depth = 1;
+        loop {
+            let text = self.next_text()?;
+            match text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            tokens.push(text);
+        }
+
+        let mut pos = 0;
+        let value = self.calc_expr(&tokens, &mut pos, 0)?;
+        if pos != tokens.len() {
+            bail!("unexpected {:?} in expression", tokens[pos]);
+        }
+        Ok(value)
+    }
+
+    /// Evaluates terms joined by binary operators, which have no precedence and group from
+    /// the right, as in Octo. `depth` is how deeply nested in other terms this is.
+    fn calc_expr(&self, tokens: &[String], pos: &mut usize, depth: usize) -> anyhow::Result<f64> {
+        let mut terms = vec![self.calc_term(tokens, pos, depth)?];
+        let mut ops = Vec::new();
+        while let Some(op) = tokens.get(*pos).filter(|op| is_binary_op(op)) {
+            *pos += 1;
+            ops.push(op.as_str());
+            terms.push(self.calc_term(tokens, pos, depth)?);
+        }
+
+        let mut value = terms.pop().unwrap();
+        while let Some(op) = ops.pop() {
+            value = apply_binary_op(op, terms.pop().unwrap(), value)?;
+        }
+        Ok(value)
+    }
+
+    fn calc_term(&self, tokens: &[String], pos: &mut usize, depth: usize) -> anyhow::Result<f64> {
+        if depth == MAX_EXPRESSION_DEPTH {
+            bail!("expression nested more than {MAX_EXPRESSION_DEPTH} deep");
+        }
+        let token = tokens
+            .get(*pos)
+            .ok_or_else(|| anyhow!("incomplete expression"))?;
+        *pos += 1;
+
+        let depth = depth + 1;
+        let unary = |f: fn(f64) -> f64, pos: &mut usize| Ok(f(self.calc_term(tokens, pos, depth)?));
+        match token.as_str() {
+            "(" => {
+                let value = self.calc_expr(tokens, pos, depth)?;
+
+                if tokens.get(*pos).map(String::as_str) != Some(")") {
+                    bail!("expected `)` in expression");
+                }
+                *pos += 1;
+                Ok(value)
+            }
+            "-" => unary(|x| -x, pos),
+            "~" => unary(|x| !(x as i64) as f64, pos),
+            "!" => unary(|x| if x == 0.0 { 1.0 } else { 0.0 }, pos),
+            "sin" => unary(f64::sin, pos),
+            "cos" => unary(f64::cos, pos),
+            "tan" => unary(f64::tan, pos),
+            "exp" => unary(f64::exp, pos),
+            "log" => unary(f64::ln, pos),
+            "abs" => unary(f64::abs, pos),
+            "sqrt" => unary(f64::sqrt, pos),
+            "sign" => unary(f64::signum, pos),
+            "ceil" => unary(f64::ceil, pos),
+            "floor" => unary(f64::floor, pos),
+            "@" => {
+                let addr = self.calc_term(tokens, pos, depth)? as usize;
+                let byte = addr
+                    .checked_sub(PROG_CTR_START_ADDR as usize)
+                    .and_then(|offset| self.rom.get(offset));
+                Ok(byte.copied().unwrap_or(0) as f64)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            name => self
+                .known_value(name)
+                .or_else(|| {
+                    let register = register_number(name).or(self.aliases.get(name).copied());
+                    register.map(f64::from)
+                })
+                .ok_or_else(|| anyhow!("undefined name {name:?} in expression")),
+        }
+    }
+}
+
+fn is_binary_op(op: &str) -> bool {
+    matches!(
+        op,
+        "+" | "-"
+            | "*"
+            | "/"
+            | "%"
+            | "&"
+            | "|"
+            | "^"
+            | "<<"
+            | ">>"
+            | "pow"
+            | "min"
+            | "max"
+            | "<"
+            | ">"
+            | "<="
+            | ">="
+            | "=="
+            | "!="
+    )
+}
+
+/// Applies the binary operator `op`, one [`is_binary_op`] accepts.
+fn apply_binary_op(op: &str, lhs: f64, rhs: f64) -> anyhow::Result<f64> {
+    let int = |value: f64| value as i64;
+    let bool = |value: bool| if value { 1.0 } else { 0.0 };
+    let shift = |f: fn(i64, u32) -> Option<i64>| {
+        u32::try_from(int(rhs))
+            .ok()
+            .and_then(|bits| f(int(lhs), bits))
+            .map(|value| value as f64)
+            .ok_or_else(|| anyhow!("can't shift {lhs} by {rhs} in expression"))
+    };
+    Ok(match op {
+        "+" => lhs + rhs,
+        "-" => lhs - rhs,
+        "*" => lhs * rhs,
+        "/" => lhs / rhs,
+        "%" => lhs % rhs,
+        "&" => (int(lhs) & int(rhs)) as f64,
+        "|" => (int(lhs) | int(rhs)) as f64,
+        "^" => (int(lhs) ^ int(rhs)) as f64,
+        "<<" => shift(i64::checked_shl)?,
+        ">>" => shift(i64::checked_shr)?,
+        "pow" => lhs.powf(rhs),
+        "min" => lhs.min(rhs),
+        "max" => lhs.max(rhs),
+        "<" => bool(lhs < rhs),
+        ">" => bool(lhs > rhs),
+        "<=" => bool(lhs <= rhs),
+        ">=" => bool(lhs >= rhs),
+        "==" => bool(lhs == rhs),
+        _ => bool(lhs != rhs),
+    })
+}
+
+fn register_number(name: &str) -> Option<u8> {
+    let digit = name.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::Chip8;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        compile(source).unwrap().bytes
+    }
+
+    /// Runs `source` for `steps` instructions.
+    fn run(source: &str, steps: usize) -> Chip8 {
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(&bytes(source)).unwrap();
+        for _ in 0..steps {
+            chip8.tick();
+        }
+        chip8
+    }
+
+    #[test]
+    fn statements() {
+        assert_eq!(
+            vec![
+                0x12, 0x02, // jump main
+                0x00, 0xE0, // clear
+                0x60, 0x05, // v0 := 5
+                0x71, 0xFF, // v1 -= 1
+                0x82, 0x34, // v2 += v3
+                0xA2, 0x12, // i := data
+                0xD0, 0x15, // sprite v0 v1 5
+                0xF3, 0x33, // bcd v3
+                0x22, 0x12, // data
+                0x00, 0xEE, // : data return
+            ],
+            bytes(
+                ": main clear v0 := 5 v1 -= 1 v2 += v3 i := data sprite v0 v1 5 bcd v3
+                 data : data ;"
+            )
+        );
+    }
+
+    #[test]
+    fn directives() {
+        let program = compile(
+            ":const SIZE 4
+             :calc DOUBLE { SIZE * 2 + 1 }
+             :alias x v3
+             :macro twice op { op op }
+             : main
+                 x := DOUBLE
+                 twice clear
+                 :unpack 0xA data
+                 :next target
+                 v0 := 0
+                 { SIZE << 1 } 0x10
+             : data",
+        )
+        .unwrap();
+
+        assert_eq!(
+            vec![
+                0x12, 0x02, 0x63, 0x0C, 0x00, 0xE0, 0x00, 0xE0, 0x60, 0xA2, 0x61, 0x10, 0x60, 0x00,
+                0x08, 0x10
+            ],
+            program.bytes
+        );
+        assert_eq!(Some(0x20D), program.symbols.address("target"));
+        assert_eq!(Some(0x210), program.symbols.address("data"));
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let calc = |expr: &str| compile(&format!(":calc X {{ {expr} }} : main")).map(|_| ());
+
+        assert!(calc("1 << 63").is_ok());
+        assert!(calc("1 << 64").is_err());
+        assert!(calc("1 >> -1").is_err());
+        assert!(calc(&format!("{}1{}", "( ".repeat(100), " )".repeat(100))).is_ok());
+        assert!(calc(&"( ".repeat(100_000)).is_err());
+        assert!(calc(&"- ".repeat(100_000)).is_err());
+        assert!(calc(&"1 + ".repeat(100_000)).is_err());
+        assert!(calc(&format!("{}1", "1 + ".repeat(100_000))).is_ok());
+    }
+
+    #[test]
+    fn control_flow() {
+        // Counts v0 up to 10 in a loop, adding 2 to v1 whenever v0 is odd and 1 when it's
+        // even.
+        let chip8 = run(
+            ": main
+                 loop
+                     v0 += 1
+                     v2 := v0
+                     v3 := 1
+                     v2 &= v3
+                     if v2 == 1 begin
+                         v1 += 2
+                     else
+                         v1 += 1
+                     end
+                     while v0 < 10
+                 again
+                 v4 := 1
+                 if v0 >= 10 then v4 := 2
+                 if v0 > 10 then v4 := 3
+                 if v0 <= 9 then v4 := 4
+             : halt jump halt",
+            200,
+        );
+        assert_eq!(10, chip8.registers[0]);
+        assert_eq!(15, chip8.registers[1]);
+        assert_eq!(2, chip8.registers[4]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |source| format!("{:#}", compile(source).unwrap_err());
+
+        assert_eq!("the program has no `main` label", error("clear"));
+        assert_eq!(
+            "line 2: undefined name \"nowhere\"",
+            error(": main\njump nowhere")
+        );
+        assert_eq!(
+            "line 1: 300 doesn't fit into a byte",
+            error(": main v0 := 300")
+        );
+        assert_eq!(
+            "line 1: a `loop` is missing its `again`",
+            error(": main loop")
+        );
+        assert_eq!(
+            "line 2: assertion failed: too big",
+            error(": main\n:assert \"too big\" { 1 > 2 }")
+        );
+    }
+}
@@ -42,6 +42,18 @@ impl From<(u8, u8)> for OpCode {
     }
 }
 
+impl From<u16> for OpCode {
+    fn from(inner: u16) -> Self {
+        Self { inner }
+    }
+}
+
+impl From<OpCode> for u16 {
+    fn from(opcode: OpCode) -> Self {
+        opcode.inner
+    }
+}
+
 impl std::fmt::Debug for OpCode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#2X}", self.inner)
@@ -58,6 +70,13 @@ mod test {
         assert_eq!(0xDEAD, opcode.inner)
     }
 
+    #[test]
+    fn from_u16() {
+        let opcode = OpCode::from(0xDEAD);
+        assert_eq!(OpCode::from((0xDE, 0xAD)), opcode);
+        assert_eq!(0xDEAD, u16::from(opcode));
+    }
+
     #[test]
     fn nibbles() {
         let opcode = OpCode::from((0xDE, 0xAD));
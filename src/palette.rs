@@ -0,0 +1,153 @@
+//! Colours that the display is drawn with.
+//!
+//! Palettes are written as a preset name (see [`Palette::PRESETS`]), or as the background
+//! and foreground colours in hex, e.g. `#000000,#33ff66`.
+
+use std::{fmt, str::FromStr};
+
+use anyhow::Context;
+
+pub type Rgb = [u8; 3];
+
+/// CHIP-8X's foreground colours, from the COSMAC VIP's VP-590 colour board: black, red,
+/// blue, violet, green, yellow, aqua and white.
+pub const VP590_FOREGROUND: [Rgb; 8] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// CHIP-8X's background colours: dark blue, black, dark green and dark red.
+pub const VP590_BACKGROUND: [Rgb; 4] = [
+    [0x00, 0x00, 0x80],
+    [0x00, 0x00, 0x00],
+    [0x00, 0x80, 0x00],
+    [0x80, 0x00, 0x00],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Palette {
+    pub background: Rgb,
+    pub foreground: Rgb,
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::PRESETS[0].1
+    }
+}
+
+impl Palette {
+    pub const PRESETS: [(&'static str, Palette); 4] = [
+        (
+            "mono",
+            Palette {
+                background: [0x00, 0x00, 0x00],
+                foreground: [0xFF, 0xFF, 0xFF],
+            },
+        ),
+        (
+            "octo",
+            Palette {
+                background: [0x99, 0x66, 0x00],
+                foreground: [0xFF, 0xCC, 0x00],
+            },
+        ),
+        (
+            "green",
+            Palette {
+                background: [0x0A, 0x1A, 0x0A],
+                foreground: [0x33, 0xFF, 0x66],
+            },
+        ),
+        (
+            "amber",
+            Palette {
+                background: [0x1A, 0x10, 0x00],
+                foreground: [0xFF, 0xB0, 0x00],
+            },
+        ),
+    ];
+
+    /// The colour of a pixel in the display buffer.
+    pub fn color(&self, pixel: u8) -> Rgb {
+        if pixel == 0 {
+            self.background
+        } else {
+            self.foreground
+        }
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some((name, _)) = Self::PRESETS.iter().find(|(_, p)| p == self) {
+            return write!(f, "{name}");
+        }
+
+        let [r, g, b] = self.background;
+        let [fr, fg, fb] = self.foreground;
+        write!(f, "#{r:02x}{g:02x}{b:02x},#{fr:02x}{fg:02x}{fb:02x}")
+    }
+}
+
+impl FromStr for Palette {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, palette)) = Self::PRESETS.iter().find(|(name, _)| *name == s) {
+            return Ok(*palette);
+        }
+
+        let (background, foreground) = s.split_once(',').with_context(|| {
+            let names: Vec<_> = Self::PRESETS.iter().map(|(name, _)| *name).collect();
+            format!(
+                "unknown palette {s:?}, expected one of {} or `BACKGROUND,FOREGROUND` hex colours",
+                names.join(", ")
+            )
+        })?;
+
+        Ok(Self {
+            background: parse_color(background)?,
+            foreground: parse_color(foreground)?,
+        })
+    }
+}
+
+serde_string!(Palette);
+
+/// Parses a `#rrggbb` colour, with or without the `#`.
+pub fn parse_color(s: &str) -> anyhow::Result<Rgb> {
+    let hex = s.trim().trim_start_matches('#');
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .with_context(|| format!("invalid colour {s:?}, expected `#rrggbb`"))?;
+
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok([r, g, b])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(Palette::default(), "mono".parse().unwrap());
+
+        let palette: Palette = "#102030, 405060".parse().unwrap();
+        assert_eq!([0x10, 0x20, 0x30], palette.background);
+        assert_eq!([0x40, 0x50, 0x60], palette.foreground);
+        assert_eq!("#102030,#405060", palette.to_string());
+        assert_eq!("octo", Palette::PRESETS[1].1.to_string());
+
+        assert!("sepia".parse::<Palette>().is_err());
+        assert!("#12345,#000000".parse::<Palette>().is_err());
+    }
+}
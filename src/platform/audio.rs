@@ -0,0 +1,195 @@
+use sdl2::{
+    audio::{AudioCallback, AudioDevice, AudioSpecDesired},
+    Sdl,
+};
+
+use super::into_anyhow;
+use crate::chip8::Sample;
+
+const SAMPLE_RATE: i32 = 44_100;
+const BEEP_HZ: f32 = 440.;
+const VOLUME: f32 = 0.15;
+
+/// Beeps while the sound timer is running, and plays MEGA-CHIP's digitised sound.
+pub struct AudioPlatform {
+    device: AudioDevice<Output>,
+    playing: bool,
+    // Which sample is playing, to start it only once.
+    sample_serial: Option<u32>,
+}
+
+impl AudioPlatform {
+    pub fn init(sdl_context: &Sdl) -> anyhow::Result<Self> {
+        let audio_subsystem = sdl_context.audio().map_err(into_anyhow)?;
+
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: None,
+        };
+        let device = audio_subsystem
+            .open_playback(None, &spec, |spec| Output {
+                square: SquareWave::new(BEEP_HZ / spec.freq as f32, VOLUME),
+                beeping: false,
+                sample: None,
+                freq: spec.freq as f32,
+            })
+            .map_err(into_anyhow)?;
+
+        Ok(Self {
+            device,
+            playing: false,
+            sample_serial: None,
+        })
+    }
+
+    /// Beeps if `beeping`, and plays `sample` instead if there is one, unless `paused`.
+    pub fn set_sound(&mut self, beeping: bool, sample: Option<&Sample>, paused: bool) {
+        let serial = sample.map(|sample| sample.serial);
+        {
+            let mut output = self.device.lock();
+            output.beeping = beeping;
+            if serial != self.sample_serial {
+                let freq = output.freq;
+                output.sample = sample.map(|sample| SamplePlayer::new(sample, freq));
+            }
+        }
+        self.sample_serial = serial;
+
+        let playing = !paused && (beeping || sample.is_some());
+        if playing == self.playing {
+            return;
+        }
+
+        if playing {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+        self.playing = playing;
+    }
+}
+
+/// What the audio device plays: the sample if there is one, and otherwise the beep.
+struct Output {
+    square: SquareWave,
+    beeping: bool,
+    sample: Option<SamplePlayer>,
+    freq: f32,
+}
+
+impl AudioCallback for Output {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        if let Some(player) = &mut self.sample {
+            player.fill(out);
+        } else if self.beeping {
+            self.square.callback(out);
+        } else {
+            out.fill(0.);
+        }
+    }
+}
+
+/// Plays a sample at the output rate, by repeating or skipping its samples.
+struct SamplePlayer {
+    data: Vec<u8>,
+    looping: bool,
+    // Sample data per output sample.
+    step: f32,
+    position: f32,
+}
+
+impl SamplePlayer {
+    fn new(sample: &Sample, freq: f32) -> Self {
+        Self {
+            data: sample.data.clone(),
+            looping: sample.looping,
+            step: f32::from(sample.rate) / freq,
+            position: 0.,
+        }
+    }
+
+    /// Fills `out` with the sample, and with silence once a sample that doesn't loop ends.
+    fn fill(&mut self, out: &mut [f32]) {
+        for sample in out {
+            let len = self.data.len() as f32;
+            if self.looping && len > 0. {
+                self.position %= len;
+            }
+            *sample = match self.data.get(self.position as usize) {
+                Some(&byte) => (f32::from(byte) - 128.) / 128. * VOLUME,
+                None => 0.,
+            };
+            self.position += self.step;
+        }
+    }
+}
+
+struct SquareWave {
+    // Fraction of a period per sample.
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+}
+
+impl SquareWave {
+    fn new(phase_inc: f32, volume: f32) -> Self {
+        Self {
+            phase_inc,
+            phase: 0.,
+            volume,
+        }
+    }
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out {
+            *sample = if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn square_wave() {
+        let mut wave = SquareWave::new(0.25, 0.5);
+        let mut out = [0.; 8];
+        wave.callback(&mut out);
+
+        assert_eq!([0.5, 0.5, -0.5, -0.5, 0.5, 0.5, -0.5, -0.5], out);
+    }
+
+    #[test]
+    fn sample_player() {
+        let mut sample = Sample {
+            rate: 22_050,
+            data: vec![0x80, 0xFF],
+            looping: false,
+            serial: 0,
+        };
+        let mut out = [1.; 6];
+        SamplePlayer::new(&sample, 44_100.).fill(&mut out);
+
+        let high = 127. / 128. * VOLUME;
+        assert_eq!([0., 0., high, high, 0., 0.], out);
+
+        sample.looping = true;
+        SamplePlayer::new(&sample, 44_100.).fill(&mut out);
+        assert_eq!([0., 0., high, high, 0., 0.], out);
+        SamplePlayer::new(&sample, 88_200.).fill(&mut out);
+        assert_eq!([0., 0., 0., 0., high, high], out);
+    }
+}
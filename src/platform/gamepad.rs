@@ -0,0 +1,129 @@
+use std::collections::BTreeSet;
+
+use log::{info, warn};
+use sdl2::{
+    controller::{Axis, GameController},
+    event::Event,
+    EventPump, GameControllerSubsystem, Sdl,
+};
+
+use super::into_anyhow;
+
+// How far a stick has to be pushed to count as pressed in that direction, out of 32767.
+const STICK_DEADZONE: i16 = 16_000;
+
+/// Tracks the controls held down on all connected game controllers.
+pub struct GamepadPlatform {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    // Held controls by the joystick id of their controller.
+    pressed: BTreeSet<(u32, String)>,
+    last_pressed: Option<String>,
+}
+
+impl GamepadPlatform {
+    /// Controllers connected at startup are opened once their `ControllerDeviceAdded`
+    /// events are handled.
+    pub fn init(sdl_context: &Sdl) -> anyhow::Result<Self> {
+        Ok(Self {
+            subsystem: sdl_context.game_controller().map_err(into_anyhow)?,
+            controllers: Vec::new(),
+            pressed: BTreeSet::new(),
+            last_pressed: None,
+        })
+    }
+
+    pub fn controllers(&self) -> impl Iterator<Item = String> + '_ {
+        self.controllers.iter().map(GameController::name)
+    }
+
+    /// The held controls, named as in [`crate::gamepad::CONTROLS`].
+    pub fn pressed(&self) -> impl Iterator<Item = &str> {
+        self.pressed.iter().map(|(_, control)| control.as_str())
+    }
+
+    /// The most recently pressed control, taken so that each press is reported once.
+    pub fn take_last_pressed(&mut self) -> Option<String> {
+        self.last_pressed.take()
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) => {
+                    info!("connected controller {}", controller.name());
+                    self.controllers.push(controller);
+                }
+                Err(err) => warn!("failed to open controller {which}: {err}"),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers.retain(|c| c.instance_id() != which);
+                self.pressed.retain(|(id, _)| *id != which);
+            }
+            Event::ControllerButtonDown { which, button, .. } => {
+                self.press(which, button.string());
+            }
+            Event::ControllerButtonUp { which, button, .. } => {
+                self.pressed.remove(&(which, button.string()));
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => self.move_axis(which, axis, value),
+            _ => {}
+        }
+    }
+
+    fn press(&mut self, which: u32, control: String) {
+        if self.pressed.insert((which, control.clone())) {
+            self.last_pressed = Some(control);
+        }
+    }
+
+    fn move_axis(&mut self, which: u32, axis: Axis, value: i16) {
+        // Triggers only go one way, and are buttons as far as the keypad is concerned.
+        if matches!(axis, Axis::TriggerLeft | Axis::TriggerRight) {
+            return;
+        }
+
+        let name = axis.string();
+        let (negative, positive) = (format!("{name}-"), format!("{name}+"));
+        self.pressed.remove(&(which, negative.clone()));
+        self.pressed.remove(&(which, positive.clone()));
+
+        if value <= -STICK_DEADZONE {
+            self.press(which, negative);
+        } else if value >= STICK_DEADZONE {
+            self.press(which, positive);
+        }
+    }
+}
+
+/// Game controllers for frontends that don't otherwise use SDL, with their own event pump.
+pub struct StandaloneGamepads {
+    // Keeps SDL initialised.
+    _sdl_context: Sdl,
+    events: EventPump,
+    gamepads: GamepadPlatform,
+}
+
+impl StandaloneGamepads {
+    pub fn init() -> anyhow::Result<Self> {
+        let sdl_context = sdl2::init().map_err(into_anyhow)?;
+        let gamepads = GamepadPlatform::init(&sdl_context)?;
+        let events = sdl_context.event_pump().map_err(into_anyhow)?;
+
+        Ok(Self {
+            _sdl_context: sdl_context,
+            events,
+            gamepads,
+        })
+    }
+
+    /// Handles the events that arrived since the last poll.
+    pub fn poll(&mut self) -> &mut GamepadPlatform {
+        for event in self.events.poll_iter() {
+            self.gamepads.handle_event(&event);
+        }
+        &mut self.gamepads
+    }
+}
@@ -1 +1,222 @@
+//! A lightweight SDL player: just the display, keypad and beeper, without the debugger.
+//!
+//! Esc quits, Space pauses and resumes, and F11 or Alt+Enter toggles fullscreen.
+
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use log::{error, warn};
+use sdl2::{
+    event::Event,
+    keyboard::{Keycode, Mod},
+};
+
+use crate::{
+    capture::DEFAULT_SCALE, gamepad::GamepadSettings, keymap::Keymap, palette::Palette,
+    Chip8Runner, Reports, FRAME_HZ,
+};
+use audio::AudioPlatform;
+use gamepad::GamepadPlatform;
+use video::VideoPlatform;
+
+pub mod audio;
+pub mod gamepad;
 pub mod video;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SdlOptions {
+    /// Initial size of a CHIP-8 pixel, in window pixels.
+    pub scale: u32,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    pub vsync: bool,
+    pub fullscreen: bool,
+}
+
+impl Default for SdlOptions {
+    fn default() -> Self {
+        Self {
+            scale: DEFAULT_SCALE,
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+            vsync: true,
+            fullscreen: false,
+        }
+    }
+}
+
+pub struct PlayerInterface {
+    rom_name: String,
+    runner: Chip8Runner,
+    options: SdlOptions,
+    reports: Reports,
+    gamepad: Option<GamepadSettings>,
+}
+
+impl PlayerInterface {
+    pub fn new(rom_name: &str, runner: Chip8Runner) -> Self {
+        Self {
+            rom_name: rom_name.to_string(),
+            runner,
+            options: SdlOptions::default(),
+            reports: Reports::default(),
+            gamepad: None,
+        }
+    }
+
+    /// Reads game controllers, mapped the way `settings` say.
+    pub fn with_gamepad(mut self, settings: GamepadSettings) -> Self {
+        self.gamepad = Some(settings);
+        self
+    }
+
+    pub fn with_options(mut self, options: SdlOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Writes `reports` when the window is closed.
+    pub fn with_reports(mut self, reports: Reports) -> Self {
+        reports.enable(&mut self.runner);
+        self.reports = reports;
+        self
+    }
+
+    /// Runs until the window is closed or Esc is pressed.
+    pub fn run(mut self) -> anyhow::Result<()> {
+        let frame_time = Duration::from_secs(1) / FRAME_HZ as u32;
+
+        let sdl_context = sdl2::init().map_err(into_anyhow)?;
+        let screen = self.runner.chip8.screen();
+        let size = (screen.width as u32, screen.height as u32);
+        let title = format!("patata - {}", self.rom_name);
+        let mut video = VideoPlatform::init(&sdl_context, &title, size, &self.options)?;
+        // Play silently rather than not at all without an audio device.
+        let mut audio = AudioPlatform::init(&sdl_context)
+            .map_err(|err| warn!("no audio: {err:#}"))
+            .ok();
+        let mut gamepads = match &self.gamepad {
+            Some(_) => GamepadPlatform::init(&sdl_context)
+                .map_err(|err| warn!("no gamepad support: {err:#}"))
+                .ok(),
+            None => None,
+        };
+        let mut events = sdl_context.event_pump().map_err(into_anyhow)?;
+
+        let mut keys = 0u16;
+        let mut last_frame = Instant::now();
+        self.runner.resume();
+
+        'frames: loop {
+            let now = Instant::now();
+
+            for event in events.poll_iter() {
+                if let Some(gamepads) = &mut gamepads {
+                    gamepads.handle_event(&event);
+                }
+
+                match event {
+                    Event::Quit { .. }
+                    | Event::KeyDown {
+                        keycode: Some(Keycode::ESCAPE),
+                        ..
+                    } => break 'frames,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::F11),
+                        ..
+                    } => toggle_fullscreen(&mut video),
+                    Event::KeyDown {
+                        keycode: Some(Keycode::RETURN),
+                        keymod,
+                        ..
+                    } if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                        toggle_fullscreen(&mut video);
+                    }
+                    Event::KeyDown {
+                        keycode: Some(Keycode::SPACE),
+                        repeat: false,
+                        ..
+                    } => {
+                        if self.runner.is_running() {
+                            self.runner.pause();
+                        } else {
+                            self.runner.resume();
+                        }
+                    }
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(key) = keypad_key(keycode, &self.options.keymap) {
+                            keys |= 1 << key;
+                        }
+                    }
+                    Event::KeyUp {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if let Some(key) = keypad_key(keycode, &self.options.keymap) {
+                            keys &= !(1 << key);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+
+            let gamepad_keys = match (&gamepads, &self.gamepad) {
+                (Some(gamepads), Some(settings)) => settings.mapping().keys(gamepads.pressed()),
+                _ => 0,
+            };
+            self.runner.set_keys(keys | gamepad_keys);
+            self.runner.run_for(now - last_frame);
+            last_frame = now;
+
+            if let Some(audio) = &mut audio {
+                let sound_timer = self.runner.chip8.sound_timer.cur_count();
+                let sample = self.runner.chip8.sample();
+                audio.set_sound(sound_timer > 0, sample, !self.runner.is_running());
+            }
+
+            video.draw(&self.runner.chip8.screen(), &self.options.palette)?;
+
+            if !self.options.vsync {
+                std::thread::sleep(frame_time.saturating_sub(now.elapsed()));
+            }
+        }
+
+        self.reports.write(&mut self.runner)
+    }
+}
+
+fn toggle_fullscreen(video: &mut VideoPlatform) {
+    if let Err(err) = video.toggle_fullscreen() {
+        error!("failed to toggle fullscreen: {err:#}");
+    }
+}
+
+/// The keypad key that a keyboard key stands for. SDL keycodes of printable keys are their
+/// (lowercase) characters.
+fn keypad_key(keycode: Keycode, keymap: &Keymap) -> Option<u8> {
+    let c = char::from_u32(keycode.into_i32() as u32)?;
+    keymap.key_for_char(c)
+}
+
+fn into_anyhow(err: String) -> anyhow::Error {
+    anyhow!(err)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keycodes_to_keypad() {
+        let keymap = Keymap::default();
+
+        assert_eq!(Some(0xC), keypad_key(Keycode::NUM_4, &keymap));
+        assert_eq!(Some(0x5), keypad_key(Keycode::W, &keymap));
+        assert_eq!(Some(0xF), keypad_key(Keycode::V, &keymap));
+        assert_eq!(None, keypad_key(Keycode::P, &keymap));
+        assert_eq!(None, keypad_key(Keycode::F1, &keymap));
+    }
+}
@@ -1,22 +1,86 @@
-use anyhow::anyhow;
-use sdl2::render::WindowCanvas;
+use sdl2::{pixels::Color, rect::Rect, render::WindowCanvas, video::FullscreenType, Sdl};
 
-pub struct VideoPlatform;
+use super::{into_anyhow, SdlOptions};
+use crate::{chip8::Screen, palette::Palette};
+
+pub struct VideoPlatform {
+    canvas: WindowCanvas,
+    // Logical size the canvas scales from, i.e. the display resolution last drawn.
+    size: (u32, u32),
+}
 
 impl VideoPlatform {
-    pub fn init() -> anyhow::Result<WindowCanvas> {
-        let sdl_context = sdl2::init().map_err(into_anyhow)?;
+    /// Opens a window sized to `width`x`height` display pixels at the configured scale.
+    pub fn init(
+        sdl_context: &Sdl,
+        title: &str,
+        (width, height): (u32, u32),
+        options: &SdlOptions,
+    ) -> anyhow::Result<Self> {
         let video_subsystem = sdl_context.video().map_err(into_anyhow)?;
 
-        let window = video_subsystem
-            .window("Chip8", 800, 600)
-            .position_centered()
-            .build()?;
+        let scale = options.scale.max(1);
+        let mut window = video_subsystem.window(title, width * scale, height * scale);
+        window.position_centered().resizable();
+        if options.fullscreen {
+            window.fullscreen_desktop();
+        }
+
+        let mut canvas = window.build()?.into_canvas();
+        if options.vsync {
+            canvas = canvas.present_vsync();
+        }
+        let mut canvas = canvas.build()?;
+        // Let SDL scale the display to the window, keeping the aspect ratio.
+        canvas.set_logical_size(width, height)?;
 
-        Ok(window.into_canvas().build()?)
+        Ok(Self {
+            canvas,
+            size: (width, height),
+        })
     }
-}
 
-fn into_anyhow(err: String) -> anyhow::Error {
-    anyhow!(err)
+    pub fn draw(&mut self, screen: &Screen, palette: &Palette) -> anyhow::Result<()> {
+        let size = (screen.width as u32, screen.height as u32);
+        if size != self.size {
+            self.canvas.set_logical_size(size.0, size.1)?;
+            self.size = size;
+        }
+
+        let colors = screen.color_table(palette);
+        let background = screen.background_index();
+        let [r, g, b] = colors[background as usize];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+
+        // One batch of pixels per colour other than the background.
+        let mut lit = vec![Vec::new(); colors.len()];
+        for i in 0..screen.pixels.len() {
+            let color = screen.color_index(i);
+            if color != background {
+                let (x, y) = (i % screen.width, i / screen.width);
+                lit[color as usize].push(Rect::new(x as i32, y as i32, 1, 1));
+            }
+        }
+
+        for (&[r, g, b], rects) in colors.iter().zip(&lit) {
+            if !rects.is_empty() {
+                self.canvas.set_draw_color(Color::RGB(r, g, b));
+                self.canvas.fill_rects(rects).map_err(into_anyhow)?;
+            }
+        }
+
+        // Blocks until the next vertical blank when vsync is on.
+        self.canvas.present();
+        Ok(())
+    }
+
+    pub fn toggle_fullscreen(&mut self) -> anyhow::Result<()> {
+        let window = self.canvas.window_mut();
+        let state = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            FullscreenType::True | FullscreenType::Desktop => FullscreenType::Off,
+        };
+        window.set_fullscreen(state).map_err(into_anyhow)
+    }
 }
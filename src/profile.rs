@@ -0,0 +1,308 @@
+//! A sampling-free profiler that attributes every executed instruction to the subroutine
+//! it ran in, tracking subroutines through `CALL` and `RET`.
+//!
+//! Reports come as text (a flat profile followed by a call graph) or in the folded-stack
+//! format read by flamegraph tools such as `inferno-flamegraph` and `flamegraph.pl`, one
+//! `main;update;draw_player 1234` line per distinct call stack.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::Write as _,
+    str::FromStr,
+};
+
+use crate::{instruction::Instruction, symbols::Symbols};
+
+// How many of the hottest instructions the text report lists.
+const HOT_INSTRUCTIONS: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ProfileFormat {
+    /// Flat profile and call graph.
+    #[default]
+    Text,
+    /// Folded stacks, for flamegraph tools.
+    Folded,
+}
+
+impl FromStr for ProfileFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "folded" => Ok(Self::Folded),
+            _ => anyhow::bail!("unknown profile format {s:?}, expected `text` or `folded`"),
+        }
+    }
+}
+
+/// Time spent in a subroutine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FunctionProfile {
+    /// Cycles spent in the subroutine itself.
+    pub self_cycles: u64,
+    /// Cycles spent in the subroutine and everything it called.
+    pub total_cycles: u64,
+    pub calls: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    entry: u16,
+    // Entry addresses of the active subroutines, outermost first. The first frame is the
+    // program's entry point, which is never returned from.
+    frames: Vec<u16>,
+    stacks: HashMap<Vec<u16>, u64>,
+    instructions: HashMap<u16, (u16, u64)>,
+    calls: BTreeMap<(u16, u16), u64>,
+    total_cycles: u64,
+}
+
+impl Profiler {
+    /// Creates a profiler for a program starting at `entry`.
+    pub fn new(entry: u16) -> Self {
+        Self {
+            entry,
+            frames: vec![entry],
+            stacks: HashMap::new(),
+            instructions: HashMap::new(),
+            calls: BTreeMap::new(),
+            total_cycles: 0,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new(self.entry);
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// Records an instruction that took `cycles` to execute at `pc`. `stack_pointer` is the
+    /// stack pointer after it ran, used to follow calls and returns.
+    pub fn record(&mut self, pc: u16, opcode: u16, cycles: u64, stack_pointer: u8) {
+        self.total_cycles += cycles;
+        // This runs for every instruction, so only clone the stack the first time it's seen.
+        match self.stacks.get_mut(&self.frames[..]) {
+            Some(total) => *total += cycles,
+            None => {
+                self.stacks.insert(self.frames.clone(), cycles);
+            }
+        }
+
+        let instruction = self.instructions.entry(pc).or_insert((opcode, 0));
+        *instruction = (opcode, instruction.1 + cycles);
+
+        let depth = stack_pointer as usize + 1;
+        match Instruction::decode(opcode) {
+            Some(Instruction::Call(nnn)) if depth > self.frames.len() => {
+                let caller = *self.frames.last().unwrap();
+                *self.calls.entry((caller, nnn)).or_default() += 1;
+                self.frames.push(nnn);
+            }
+            _ => {}
+        }
+
+        // Returns pop a frame here. So does anything else that unwinds the stack, like a
+        // debugger writing to SP.
+        self.frames.truncate(depth.max(1));
+        while self.frames.len() < depth {
+            self.frames.push(pc);
+        }
+    }
+
+    /// Per-subroutine totals, keyed by entry address.
+    pub fn functions(&self) -> HashMap<u16, FunctionProfile> {
+        let mut functions: HashMap<u16, FunctionProfile> = HashMap::new();
+
+        for (stack, &cycles) in &self.stacks {
+            functions
+                .entry(*stack.last().unwrap())
+                .or_default()
+                .self_cycles += cycles;
+
+            // Recursive subroutines only count once per stack.
+            let unique: BTreeSet<_> = stack.iter().collect();
+            for &addr in unique {
+                functions.entry(addr).or_default().total_cycles += cycles;
+            }
+        }
+
+        for (&(_, callee), &calls) in &self.calls {
+            functions.entry(callee).or_default().calls += calls;
+        }
+
+        functions
+    }
+
+    pub fn report(&self, format: ProfileFormat, symbols: &Symbols) -> String {
+        match format {
+            ProfileFormat::Text => self.text_report(symbols),
+            ProfileFormat::Folded => self.folded(symbols),
+        }
+    }
+
+    /// One `outer;inner cycles` line per distinct call stack, sorted for stable output.
+    pub fn folded(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<_> = stack.iter().map(|&a| function_name(a, symbols)).collect();
+                format!("{} {cycles}", names.join(";"))
+            })
+            .collect();
+        lines.sort();
+
+        lines.iter().fold(String::new(), |mut s, line| {
+            let _ = writeln!(s, "{line}");
+            s
+        })
+    }
+
+    fn text_report(&self, symbols: &Symbols) -> String {
+        let percent = |cycles: u64| 100. * cycles as f64 / self.total_cycles.max(1) as f64;
+        let functions = self.functions();
+
+        let mut flat: Vec<_> = functions.iter().collect();
+        flat.sort_by_key(|&(addr, f)| (std::cmp::Reverse(f.self_cycles), *addr));
+
+        let mut report = format!("Flat profile, {} cycles in total\n\n", self.total_cycles);
+        let _ = writeln!(
+            report,
+            "{:>7} {:>10} {:>7} {:>10} {:>8}  function",
+            "self%", "self", "total%", "total", "calls"
+        );
+        for (&addr, f) in &flat {
+            let _ = writeln!(
+                report,
+                "{:>6.2}% {:>10} {:>6.2}% {:>10} {:>8}  {}",
+                percent(f.self_cycles),
+                f.self_cycles,
+                percent(f.total_cycles),
+                f.total_cycles,
+                f.calls,
+                function_name(addr, symbols)
+            );
+        }
+
+        report.push_str("\nCall graph\n");
+        for (&addr, _) in &flat {
+            let _ = writeln!(report, "\n{}", function_name(addr, symbols));
+            for (&(caller, callee), calls) in &self.calls {
+                if callee == addr {
+                    let name = function_name(caller, symbols);
+                    let _ = writeln!(report, "  called by {name} ({calls}x)");
+                }
+            }
+            for (&(caller, callee), calls) in &self.calls {
+                if caller == addr {
+                    let name = function_name(callee, symbols);
+                    let _ = writeln!(report, "  calls {name} ({calls}x)");
+                }
+            }
+        }
+
+        let mut hot: Vec<_> = self.instructions.iter().collect();
+        hot.sort_by_key(|&(pc, &(_, cycles))| (std::cmp::Reverse(cycles), *pc));
+
+        report.push_str("\nHottest instructions\n\n");
+        for (&pc, &(opcode, cycles)) in hot.iter().take(HOT_INSTRUCTIONS) {
+            let instruction = Instruction::decode(opcode)
+                .map_or_else(|| format!("DW {opcode:#06x}"), |i| symbols.disassemble(&i));
+            let location = symbols
+                .locate(pc)
+                .map_or_else(String::new, |place| format!(" ({place})"));
+            let _ = writeln!(
+                report,
+                "{:>6.2}% {cycles:>10}  {pc:03x}{location}  {instruction}",
+                percent(cycles)
+            );
+        }
+
+        report
+    }
+}
+
+/// Names a subroutine by its label, or `sub_2a4` if it has none.
+fn function_name(addr: u16, symbols: &Symbols) -> String {
+    symbols
+        .label(addr)
+        .map_or_else(|| format!("sub_{addr:03x}"), str::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // main (200) calls draw (300) twice, which calls plot (400) once per call.
+    fn profile() -> Profiler {
+        let mut p = Profiler::new(0x200);
+        for _ in 0..2 {
+            p.record(0x200, 0x2300, 1, 1); // CALL draw
+            p.record(0x300, 0x6001, 1, 1);
+            p.record(0x302, 0x2400, 1, 2); // CALL plot
+            p.record(0x400, 0xD001, 1, 2);
+            p.record(0x402, 0x00EE, 1, 1); // RET
+            p.record(0x304, 0x00EE, 1, 0); // RET
+        }
+        p.record(0x202, 0x1202, 1, 0);
+        p
+    }
+
+    #[test]
+    fn functions() {
+        let functions = profile().functions();
+
+        assert_eq!(
+            FunctionProfile {
+                self_cycles: 3,
+                total_cycles: 13,
+                calls: 0
+            },
+            functions[&0x200]
+        );
+        assert_eq!(
+            FunctionProfile {
+                self_cycles: 6,
+                total_cycles: 10,
+                calls: 2
+            },
+            functions[&0x300]
+        );
+        assert_eq!(4, functions[&0x400].total_cycles);
+    }
+
+    #[test]
+    fn folded() {
+        let symbols = Symbols::parse("200 main\n300 draw").unwrap();
+
+        assert_eq!(
+            "main 3\nmain;draw 6\nmain;draw;sub_400 4\n",
+            profile().folded(&symbols)
+        );
+    }
+
+    #[test]
+    fn text_report() {
+        let symbols = Symbols::parse("200 main\n300 draw\n400 plot").unwrap();
+        let report = profile().report(ProfileFormat::Text, &symbols);
+
+        assert!(report.starts_with("Flat profile, 13 cycles in total"));
+        assert!(report.contains(" 46.15%          6  76.92%         10        2  draw\n"));
+        assert!(report.contains("\ndraw\n  called by main (2x)\n  calls plot (2x)\n"));
+    }
+
+    #[test]
+    fn resyncs_when_stack_unwinds() {
+        let mut p = Profiler::new(0x200);
+        p.record(0x200, 0x2300, 1, 1);
+        // The stack pointer was reset behind the profiler's back.
+        p.record(0x300, 0x6001, 1, 0);
+        p.record(0x302, 0x6001, 1, 0);
+
+        assert_eq!(2, p.functions()[&0x200].self_cycles);
+    }
+}
@@ -0,0 +1,234 @@
+//! Behaviours that differ between CHIP-8 interpreters, and that ROMs were written against.
+//!
+//! The defaults match this emulator's original behaviour. Quirks are written as a comma
+//! separated list of the enabled ones, e.g. `shift-vy,load-store-i`, or `none`.
+//!
+//! [`Platform`]s name the interpreters whose quirks ROMs most often expect. A platform's
+//! name, e.g. `schip`, also parses as its quirks.
+
+use std::{fmt, str::FromStr};
+
+use crate::layout::MemoryLayout;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quirks {
+    /// `8xy6`/`8xyE` shift Vy into Vx, instead of shifting Vx in place.
+    pub shift_vy: bool,
+    /// `Fx55`/`Fx65` leave I pointing past the last register stored or loaded.
+    pub load_store_i: bool,
+    /// `Bxnn` jumps to `xnn + Vx`, instead of `nnn + V0`.
+    pub jump_vx: bool,
+    /// `8xy1`, `8xy2` and `8xy3` reset VF to 0.
+    pub vf_reset: bool,
+}
+
+impl Quirks {
+    const NAMES: [&'static str; 4] = ["shift-vy", "load-store-i", "jump-vx", "vf-reset"];
+
+    fn flags(&self) -> [bool; 4] {
+        [
+            self.shift_vy,
+            self.load_store_i,
+            self.jump_vx,
+            self.vf_reset,
+        ]
+    }
+
+    fn flag_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "shift-vy" => Some(&mut self.shift_vy),
+            "load-store-i" => Some(&mut self.load_store_i),
+            "jump-vx" => Some(&mut self.jump_vx),
+            "vf-reset" => Some(&mut self.vf_reset),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Quirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let enabled: Vec<_> = Self::NAMES
+            .iter()
+            .zip(self.flags())
+            .filter_map(|(name, on)| on.then_some(*name))
+            .collect();
+
+        if enabled.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", enabled.join(","))
+        }
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(platform) = s.trim().parse::<Platform>() {
+            return Ok(platform.quirks());
+        }
+
+        let mut quirks = Self::default();
+
+        for name in s.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            if name == "none" {
+                continue;
+            }
+
+            match quirks.flag_mut(name) {
+                Some(flag) => *flag = true,
+                None => anyhow::bail!(
+                    "unknown quirk {name:?}, expected one of {}",
+                    Self::NAMES.join(", ")
+                ),
+            }
+        }
+
+        Ok(quirks)
+    }
+}
+
+serde_string!(Quirks);
+
+/// A family of interpreters, with the quirks of its best known member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original COSMAC VIP interpreter.
+    #[default]
+    Chip8,
+    /// The COSMAC VIP's two-page "CHIP-8 Hi-Res" interpreter, with a 64x64 display. Its
+    /// ROMs start by jumping past a patch to the interpreter to 0x260, and clear the screen
+    /// with `0230`.
+    Chip8HiRes,
+    /// The COSMAC VIP's CHIP-8X interpreter, for the VP-590 colour board and a second
+    /// keypad. Programs start at 0x300.
+    Chip8X,
+    /// SUPER-CHIP 1.1 on the HP 48.
+    SuperChip,
+    /// MEGA-CHIP, which adds a 256x192 display with 256 colours and digitised sound to
+    /// SUPER-CHIP, and up to 16M of memory.
+    MegaChip,
+    /// Octo's XO-CHIP.
+    XoChip,
+}
+
+impl Platform {
+    const NAMES: [(&'static str, Self); 6] = [
+        ("chip8", Self::Chip8),
+        ("chip8-hires", Self::Chip8HiRes),
+        ("chip8x", Self::Chip8X),
+        ("schip", Self::SuperChip),
+        ("megachip", Self::MegaChip),
+        ("xochip", Self::XoChip),
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Self::Chip8 | Self::Chip8HiRes | Self::Chip8X => Quirks {
+                shift_vy: true,
+                load_store_i: true,
+                jump_vx: false,
+                vf_reset: true,
+            },
+            Self::SuperChip | Self::MegaChip => Quirks {
+                shift_vy: false,
+                load_store_i: false,
+                jump_vx: true,
+                vf_reset: false,
+            },
+            Self::XoChip => Quirks {
+                shift_vy: true,
+                load_store_i: true,
+                jump_vx: false,
+                vf_reset: false,
+            },
+        }
+    }
+
+    /// The memory layout and display size of the platform's interpreter.
+    pub fn layout(self) -> MemoryLayout {
+        let name = match self {
+            Self::Chip8HiRes => "hires",
+            Self::Chip8X => "chip8x",
+            Self::MegaChip => "megachip",
+            _ => "chip8",
+        };
+        let (_, layout) = MemoryLayout::PRESETS
+            .iter()
+            .find(|(preset, _)| *preset == name)
+            .unwrap();
+        *layout
+    }
+
+    /// Whether the emulator runs all of the platform's instructions, and not just CHIP-8's.
+    pub fn is_supported(self) -> bool {
+        matches!(
+            self,
+            Self::Chip8 | Self::Chip8HiRes | Self::Chip8X | Self::SuperChip | Self::MegaChip
+        )
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Self::NAMES.iter().find(|(_, p)| p == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, platform)| *platform)
+            .ok_or_else(|| {
+                let names: Vec<_> = Self::NAMES.iter().map(|(name, _)| *name).collect();
+                anyhow::anyhow!(
+                    "unknown platform {s:?}, expected one of {}",
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+serde_string!(Platform);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let quirks = Quirks {
+            shift_vy: true,
+            jump_vx: true,
+            ..Quirks::default()
+        };
+
+        assert_eq!("shift-vy,jump-vx", quirks.to_string());
+        assert_eq!(quirks, "shift-vy, jump-vx".parse().unwrap());
+        assert_eq!("none", Quirks::default().to_string());
+        assert_eq!(Quirks::default(), "none".parse().unwrap());
+        assert!("sideways".parse::<Quirks>().is_err());
+        assert_eq!(Platform::SuperChip.quirks(), "schip".parse().unwrap());
+    }
+
+    #[test]
+    fn platform_names() {
+        for platform in [
+            Platform::Chip8,
+            Platform::Chip8HiRes,
+            Platform::Chip8X,
+            Platform::SuperChip,
+            Platform::MegaChip,
+            Platform::XoChip,
+        ] {
+            assert_eq!(platform, platform.to_string().parse().unwrap());
+        }
+        assert!("vip".parse::<Platform>().is_err());
+    }
+}
@@ -0,0 +1,359 @@
+//! Identifying ROMs, and loading them with the settings to run them with.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use log::{info, warn};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    chip8::Chip8,
+    config::Config,
+    cosmac::{Backend, VipImages},
+    keymap::Keymap,
+    layout::MemoryLayout,
+    octo::{self, cartridge::Cartridge},
+    palette::Palette,
+    quirks::{Platform, Quirks},
+    romdb::{RomDatabase, RomInfo},
+    symbols::Symbols,
+    timing::Timing,
+    Chip8Runner, FRAME_HZ,
+};
+
+/// Extensions of CHIP-8, SUPER-CHIP and XO-CHIP ROM files, and of Octo cartridges.
+pub const ROM_EXTENSIONS: [&str; 4] = ["ch8", "sc8", "xo8", "gif"];
+
+/// The speed of ROMs that neither the loader, the config file nor the ROM database give
+/// one for.
+pub const DEFAULT_TICK_HZ: usize = 700;
+
+/// Where a ROM database came from: its file and when that was modified, or `None` for the
+/// embedded one.
+type RomDbSource = Option<(PathBuf, SystemTime)>;
+
+/// The ROM database last loaded, so that it's only parsed again when its source changes.
+static ROM_DB: Mutex<Option<(RomDbSource, Arc<RomDatabase>)>> = Mutex::new(None);
+
+/// The SHA-1 of a ROM as lowercase hex, the way movie files and ROM databases identify it.
+pub fn sha1(bytes: &[u8]) -> String {
+    Sha1::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Loads ROMs with the settings from, in order of precedence, the loader itself (usually
+/// from the command line), the ROM's overrides in the config file, the rest of the config
+/// file and the ROM database.
+#[derive(Debug, Clone, Default)]
+pub struct RomLoader {
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub layout: Option<MemoryLayout>,
+    /// Instructions per frame.
+    pub ipf: Option<u32>,
+    pub timing: Option<Timing>,
+    pub backend: Option<Backend>,
+    /// The COSMAC VIP's monitor ROM and CHIP-8 interpreter, for the `vip` backend. Both
+    /// default to the config file's.
+    pub vip_rom: Option<PathBuf>,
+    pub vip_interpreter: Option<PathBuf>,
+    pub seed: Option<u64>,
+    pub palette: Option<Palette>,
+    pub keymap: Option<Keymap>,
+    /// ROM database in the chip-8-database `programs.json` format, which must exist.
+    /// Defaults to the config file's, and then to the one in the config directory or the
+    /// embedded snapshot.
+    pub rom_db: Option<PathBuf>,
+    pub config: Config,
+}
+
+impl RomLoader {
+    /// Loads a binary ROM, or compiles the program in an Octo cartridge and loads it with
+    /// the cartridge's settings in place of the ROM database's.
+    pub fn load(&self, path: &Path) -> anyhow::Result<Rom> {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read ROM file {}", path.display()))?;
+        let (bytes, info, program_symbols) = if octo::cartridge::is_cartridge(&bytes) {
+            let cartridge = Cartridge::decode(&bytes)
+                .with_context(|| format!("failed to load cartridge {}", path.display()))?;
+            let program = octo::compile(&cartridge.program)
+                .with_context(|| format!("failed to compile cartridge {}", path.display()))?;
+            let title = path.file_stem().unwrap_or_default().to_string_lossy();
+            (
+                program.bytes,
+                Some(cartridge.info(&title)?),
+                program.symbols,
+            )
+        } else {
+            let info = self.lookup(&sha1(&bytes))?;
+            (bytes, info, Symbols::default())
+        };
+        let sha1 = sha1(&bytes);
+        let config = self.config.for_rom(&sha1);
+
+        let platform = self
+            .platform
+            .or(config.platform)
+            .or(info.as_ref().map(|info| info.platform))
+            .unwrap_or_default();
+        if !platform.is_supported() {
+            warn!("emulating {platform} quirks, but not all of its instructions are supported");
+        }
+        let quirks = self
+            .quirks
+            .or(self.platform.map(Platform::quirks))
+            .or(config.quirks)
+            .or(config.platform.map(Platform::quirks))
+            .or(info.as_ref().map(|info| info.quirks))
+            .unwrap_or_default();
+
+        let tick_hz = self
+            .ipf
+            .or(config.ipf)
+            .or(info.as_ref().and_then(|info| info.ipf))
+            .map_or(DEFAULT_TICK_HZ, |ipf| ipf as usize * FRAME_HZ);
+
+        let backend = self.backend.or(config.backend).unwrap_or_default();
+        let vip = match backend {
+            Backend::Native => None,
+            Backend::Vip => Some(self.vip_images(&config)?),
+        };
+        let timing = match backend {
+            Backend::Native => self.timing.or(config.timing).unwrap_or_default(),
+            Backend::Vip => Timing::Vip,
+        };
+
+        // A file that merely sits next to the ROM may not be a symbol file at all, so it
+        // shouldn't stop the ROM from loading.
+        let symbols = match Symbols::find_for_rom(path).map(|path| Symbols::load(&path)) {
+            Some(Ok(symbols)) => symbols,
+            Some(Err(err)) => {
+                warn!("ignoring symbols: {err:#}");
+                program_symbols
+            }
+            None => program_symbols,
+        };
+
+        Ok(Rom {
+            path: path.to_path_buf(),
+            platform,
+            quirks,
+            layout: self
+                .layout
+                .or(config.layout)
+                .unwrap_or_else(|| platform.layout()),
+            tick_hz,
+            timing,
+            backend,
+            vip,
+            seed: self.seed.or(config.seed).unwrap_or_else(rand::random),
+            palette: self
+                .palette
+                .or(config.palette)
+                .or(info.as_ref().and_then(|info| info.palette))
+                .unwrap_or_default(),
+            keymap: self.keymap.or(config.keymap).unwrap_or_default(),
+            symbols,
+            bytes,
+            sha1,
+            info,
+            config,
+        })
+    }
+
+    fn vip_images(&self, config: &Config) -> anyhow::Result<VipImages> {
+        let monitor = self.vip_rom.as_ref().or(config.vip_rom.as_ref());
+        let interpreter = self
+            .vip_interpreter
+            .as_ref()
+            .or(config.vip_interpreter.as_ref());
+        let (Some(monitor), Some(interpreter)) = (monitor, interpreter) else {
+            anyhow::bail!("the vip backend needs the VIP's ROM and CHIP-8 interpreter");
+        };
+        VipImages::load(monitor, interpreter)
+    }
+
+    /// Looks up the ROM with SHA-1 `sha1` in the ROM database.
+    pub fn lookup(&self, sha1: &str) -> anyhow::Result<Option<RomInfo>> {
+        let db = self.database()?;
+
+        let info = db.lookup(sha1).cloned();
+        if let Some(info) = &info {
+            info!("found {} in the ROM database", info.title);
+        }
+        Ok(info)
+    }
+
+    /// The ROM database, parsed again only if it's another file, or the file changed.
+    fn database(&self) -> anyhow::Result<Arc<RomDatabase>> {
+        // A database given explicitly has to be there, while the default one may not be.
+        let path = match self.rom_db.clone().or_else(|| self.config.rom_db.clone()) {
+            Some(path) => Some(path),
+            None => RomDatabase::default_path().filter(|path| path.is_file()),
+        };
+        let source: RomDbSource = match path {
+            Some(path) => {
+                let modified = std::fs::metadata(&path)
+                    .and_then(|metadata| metadata.modified())
+                    .with_context(|| format!("failed to read ROM database {}", path.display()))?;
+                Some((path, modified))
+            }
+            None => None,
+        };
+
+        let mut cached = ROM_DB.lock().unwrap();
+        if let Some((cached_source, db)) = &*cached {
+            if *cached_source == source {
+                return Ok(db.clone());
+            }
+        }
+
+        let db = Arc::new(match &source {
+            Some((path, _)) => RomDatabase::load(path)?,
+            None => RomDatabase::embedded(),
+        });
+        *cached = Some((source, db.clone()));
+        Ok(db)
+    }
+}
+
+/// A ROM, and the settings to run it with.
+#[derive(Debug, Clone)]
+pub struct Rom {
+    pub path: PathBuf,
+    pub bytes: Vec<u8>,
+    pub sha1: String,
+    /// What the ROM database knows about the ROM.
+    pub info: Option<RomInfo>,
+    /// The config file with the ROM's overrides applied.
+    pub config: Config,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub layout: MemoryLayout,
+    pub tick_hz: usize,
+    pub timing: Timing,
+    pub backend: Backend,
+    /// What the VIP runs, with the `vip` backend.
+    pub vip: Option<VipImages>,
+    pub seed: u64,
+    pub palette: Palette,
+    pub keymap: Keymap,
+    pub symbols: Symbols,
+}
+
+impl Rom {
+    /// The ROM's file name.
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(
+            || self.path.display().to_string(),
+            |name| name.to_string_lossy().into_owned(),
+        )
+    }
+
+    /// The ROM's title in the ROM database, or its file name.
+    pub fn title(&self) -> String {
+        self.info
+            .as_ref()
+            .map_or_else(|| self.name(), |info| info.title.clone())
+    }
+
+    /// A machine with the ROM loaded, about to run its first instruction.
+    pub fn chip8(&self) -> anyhow::Result<Chip8> {
+        let mut chip8 = Chip8::new(self.layout);
+        chip8.load_rom(&self.bytes)?;
+        chip8.quirks = self.quirks;
+        chip8.set_platform(self.platform);
+        chip8.timing = self.timing;
+        if let Some(images) = &self.vip {
+            chip8.attach_vip(images.clone())?;
+        }
+        chip8.seed_rng(self.seed);
+        Ok(chip8)
+    }
+
+    pub fn runner(&self) -> anyhow::Result<Chip8Runner> {
+        info!(
+            "running {} with quirks {} and memory layout {}",
+            self.name(),
+            self.quirks,
+            self.layout
+        );
+        Ok(Chip8Runner::new(self.chip8()?, self.tick_hz)?.with_symbols(self.symbols.clone()))
+    }
+}
+
+/// Whether `path` has the extension of a ROM file.
+pub fn is_rom_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ROM_EXTENSIONS
+                .iter()
+                .any(|rom_ext| ext.eq_ignore_ascii_case(rom_ext))
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sha1_hex() {
+        assert_eq!("a9993e364706816aba3e25717850c26c9cd0d89d", sha1(b"abc"));
+    }
+
+    #[test]
+    fn load() {
+        let dir = std::env::temp_dir().join(format!("patata-rom-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("pong.ch8");
+        std::fs::write(&path, [0x00, 0xE0, 0x12, 0x02]).unwrap();
+        std::fs::write(dir.join("pong.sym"), "0x202 loop\n").unwrap();
+        std::fs::write(dir.join("programs.json"), "[]").unwrap();
+
+        let sha1 = sha1(&[0x00, 0xE0, 0x12, 0x02]);
+        let mut loader = RomLoader {
+            seed: Some(7),
+            rom_db: Some(dir.join("programs.json")),
+            ..RomLoader::default()
+        };
+        loader.config.ipf = Some(20);
+        loader.config.roms.insert(
+            sha1.clone(),
+            crate::config::RomConfig {
+                platform: Some(Platform::SuperChip),
+                ..Default::default()
+            },
+        );
+
+        let rom = loader.load(&path).unwrap();
+        assert_eq!("pong.ch8", rom.name());
+        assert_eq!("pong.ch8", rom.title());
+        assert_eq!(sha1, rom.sha1);
+        assert_eq!(Platform::SuperChip.quirks(), rom.quirks);
+        assert_eq!(20 * FRAME_HZ, rom.tick_hz);
+        assert_eq!(Some(0x202), rom.symbols.address("loop"));
+        assert_eq!(0x12, rom.chip8().unwrap().memory[0x202]);
+
+        loader.quirks = Some(Quirks::default());
+        assert_eq!(Quirks::default(), loader.load(&path).unwrap().quirks);
+
+        std::fs::remove_file(dir.join("pong.sym")).unwrap();
+        std::fs::write(dir.join("pong.json"), "[\"not symbols\"]").unwrap();
+        assert!(loader.load(&path).unwrap().symbols.is_empty());
+
+        loader.rom_db = Some(dir.join("missing.json"));
+        assert!(loader.load(&path).is_err());
+
+        assert!(is_rom_file(Path::new("a/b.SC8")));
+        assert!(!is_rom_file(Path::new("b.sym")));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,342 @@
+//! Settings for known ROMs, looked up by SHA-1 in a database in the format of the
+//! community [chip-8-database](https://github.com/chip-8/chip-8-database).
+//!
+//! The database is its `programs.json`: a list of programs, each with its ROMs by SHA-1.
+//! A snapshot of it is built in, from `data/programs.json`. A
+//! `chip-8-database/programs.json` in the config directory takes its place, so copying a
+//! newer one there updates it, and so does the file `rom-db` names. A ROM entry gives the platforms it runs on, quirks that differ from them,
+//! the instructions per frame (`tickrate`), colours and which keys move and fire:
+//!
+//! ```json
+//! [{
+//!   "title": "Pong",
+//!   "roms": {
+//!     "0f6e5e8e2a2cd5b7bd0e6da5f4e7d5ac9e0e3e6b": {
+//!       "platforms": ["originalChip8"],
+//!       "quirkyPlatforms": { "originalChip8": { "logic": false } },
+//!       "tickrate": 7,
+//!       "colors": { "pixels": ["#000000", "#ffffff"] },
+//!       "keys": { "player1Up": 1, "player1Down": 4 }
+//!     }
+//!   }
+//! }]
+//! ```
+//!
+//! Fields this emulator has no use for are ignored.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use log::debug;
+use serde::Deserialize;
+
+use crate::{
+    config::config_dir,
+    palette::{parse_color, Palette},
+    quirks::{Platform, Quirks},
+};
+
+/// What the database knows about a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    pub title: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub ipf: Option<u32>,
+    pub palette: Option<Palette>,
+    pub keys: RomKeys,
+}
+
+/// The keypad keys a game moves and fires with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RomKeys {
+    pub up: Option<u8>,
+    pub down: Option<u8>,
+    pub left: Option<u8>,
+    pub right: Option<u8>,
+    pub a: Option<u8>,
+    pub b: Option<u8>,
+}
+
+/// The snapshot of the database built into the emulator.
+const EMBEDDED: &str = include_str!("../data/programs.json");
+
+#[derive(Debug, Clone, Default)]
+pub struct RomDatabase {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl RomDatabase {
+    pub fn default_path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join("chip-8-database").join("programs.json"))
+    }
+
+    /// The snapshot of the database built into the emulator.
+    pub fn embedded() -> Self {
+        Self::from_json(EMBEDDED).expect("the embedded ROM database is valid")
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read ROM database {}", path.display()))?;
+        Self::from_json(&json).with_context(|| format!("invalid ROM database {}", path.display()))
+    }
+
+    pub fn from_json(json: &str) -> anyhow::Result<Self> {
+        let programs: Vec<Program> = serde_json::from_str(json)?;
+
+        let mut roms = HashMap::new();
+        for program in programs {
+            for (sha1, rom) in program.roms {
+                match rom.info(&program.title) {
+                    Some(info) => {
+                        roms.insert(sha1.to_ascii_lowercase(), info);
+                    }
+                    None => debug!(
+                        "skipping {} ({sha1}): no supported platform in {:?}",
+                        program.title, rom.platforms
+                    ),
+                }
+            }
+        }
+
+        Ok(Self { roms })
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<&RomInfo> {
+        self.roms.get(sha1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+}
+
+/// The platform a database platform id belongs to, and that platform's quirks.
+fn database_platform(id: &str) -> Option<(Platform, Quirks)> {
+    let platform = match id {
+        "originalChip8" | "hybridVIP" => Platform::Chip8,
+        "modernChip8" => {
+            let quirks = Quirks {
+                vf_reset: false,
+                ..Platform::Chip8.quirks()
+            };
+            return Some((Platform::Chip8, quirks));
+        }
+        "chip48" | "superchip1" => {
+            // These increment I by X, not X + 1, which is closer to incrementing it than
+            // to leaving it alone.
+            let quirks = Quirks {
+                load_store_i: true,
+                ..Platform::SuperChip.quirks()
+            };
+            return Some((Platform::SuperChip, quirks));
+        }
+        "chip8x" => Platform::Chip8X,
+        "superchip" => Platform::SuperChip,
+        "megachip8" => Platform::MegaChip,
+        "xochip" => Platform::XoChip,
+        _ => return None,
+    };
+
+    Some((platform, platform.quirks()))
+}
+
+#[derive(Debug, Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    roms: BTreeMap<String, DatabaseRom>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatabaseRom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    #[serde(default)]
+    quirky_platforms: BTreeMap<String, DatabaseQuirks>,
+    tickrate: Option<u32>,
+    colors: Option<DatabaseColors>,
+    #[serde(default)]
+    keys: BTreeMap<String, u8>,
+}
+
+impl DatabaseRom {
+    /// Uses the first of the ROM's platforms that's supported.
+    fn info(&self, title: &str) -> Option<RomInfo> {
+        let (id, (platform, mut quirks)) = self
+            .platforms
+            .iter()
+            .find_map(|id| Some((id, database_platform(id)?)))?;
+
+        if let Some(overrides) = self.quirky_platforms.get(id) {
+            overrides.apply(&mut quirks);
+        }
+
+        Some(RomInfo {
+            title: title.to_string(),
+            platform,
+            quirks,
+            ipf: self.tickrate.filter(|&ipf| ipf > 0),
+            palette: self.colors.as_ref().and_then(DatabaseColors::palette),
+            keys: self.keys(),
+        })
+    }
+
+    /// Player 1's keys, which are named either `up` or `player1Up`.
+    fn keys(&self) -> RomKeys {
+        let key = |name: &str| {
+            let player1 = format!("player1{}{}", name[..1].to_uppercase(), &name[1..]);
+            self.keys
+                .get(name)
+                .or_else(|| self.keys.get(&player1))
+                .copied()
+                .filter(|&key| key < 16)
+        };
+
+        RomKeys {
+            up: key("up"),
+            down: key("down"),
+            left: key("left"),
+            right: key("right"),
+            a: key("a"),
+            b: key("b"),
+        }
+    }
+}
+
+/// Quirks as the database names them. `true` is the SUPER-CHIP behaviour for all of them
+/// except `logic`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DatabaseQuirks {
+    /// `8xy6`/`8xyE` shift Vx in place.
+    shift: Option<bool>,
+    /// `Fx55`/`Fx65` leave I unchanged.
+    memory_leave_i_unchanged: Option<bool>,
+    /// `Bxnn` jumps to `xnn + Vx`.
+    jump: Option<bool>,
+    /// `8xy1`, `8xy2` and `8xy3` reset VF.
+    logic: Option<bool>,
+}
+
+impl DatabaseQuirks {
+    fn apply(&self, quirks: &mut Quirks) {
+        if let Some(shift) = self.shift {
+            quirks.shift_vy = !shift;
+        }
+        if let Some(unchanged) = self.memory_leave_i_unchanged {
+            quirks.load_store_i = !unchanged;
+        }
+        if let Some(jump) = self.jump {
+            quirks.jump_vx = jump;
+        }
+        if let Some(logic) = self.logic {
+            quirks.vf_reset = logic;
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DatabaseColors {
+    /// Background first, then the colours of the planes.
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+impl DatabaseColors {
+    fn palette(&self) -> Option<Palette> {
+        let [background, foreground, ..] = self.pixels.as_slice() else {
+            return None;
+        };
+
+        Some(Palette {
+            background: parse_color(background).ok()?,
+            foreground: parse_color(foreground).ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const PROGRAMS: &str = r##"[
+        {
+            "title": "Pong",
+            "authors": ["Paul Vervalin"],
+            "roms": {
+                "AAAA": {
+                    "file": "pong.ch8",
+                    "platforms": ["originalChip8", "superchip"],
+                    "quirkyPlatforms": { "originalChip8": { "logic": false, "wrap": true } },
+                    "tickrate": 7,
+                    "colors": { "pixels": ["#102030", "#ffcc00"], "buzzer": "#ffffff" },
+                    "keys": { "player1Up": 1, "player1Down": 4, "player2Up": 12 }
+                }
+            }
+        },
+        {
+            "title": "Unknown",
+            "roms": { "bbbb": { "platforms": ["fantasyConsole"] } }
+        },
+        {
+            "title": "Mega",
+            "roms": { "dddd": { "platforms": ["megachip8"] } }
+        },
+        {
+            "title": "Octojam",
+            "roms": { "cccc": { "platforms": ["fantasyConsole", "xochip"], "keys": { "a": 6 } } }
+        }
+    ]"##;
+
+    #[test]
+    fn lookup() {
+        let db = RomDatabase::from_json(PROGRAMS).unwrap();
+        assert_eq!(3, db.len());
+
+        let pong = db.lookup("aaaa").unwrap();
+        assert_eq!("Pong", pong.title);
+        assert_eq!(Platform::Chip8, pong.platform);
+        assert_eq!(
+            Quirks {
+                vf_reset: false,
+                ..Platform::Chip8.quirks()
+            },
+            pong.quirks
+        );
+        assert_eq!(Some(7), pong.ipf);
+        assert_eq!(Some("#102030,#ffcc00".parse().unwrap()), pong.palette);
+        assert_eq!(
+            RomKeys {
+                up: Some(1),
+                down: Some(4),
+                ..RomKeys::default()
+            },
+            pong.keys
+        );
+
+        assert!(db.lookup("bbbb").is_none());
+        assert_eq!(Platform::MegaChip, db.lookup("dddd").unwrap().platform);
+
+        let octojam = db.lookup("cccc").unwrap();
+        assert_eq!(Platform::XoChip, octojam.platform);
+        assert_eq!(None, octojam.palette);
+        assert_eq!(Some(6), octojam.keys.a);
+    }
+
+    #[test]
+    fn embedded() {
+        RomDatabase::embedded();
+        assert!(RomDatabase::load(Path::new("/nonexistent/programs.json")).is_err());
+    }
+}
@@ -6,6 +6,15 @@ pub struct Keypad {
 }
 
 impl Keypad {
+    /// The state of all keys, one bit per key.
+    pub fn keys(&self) -> u16 {
+        self.keys
+    }
+
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys;
+    }
+
     pub fn is_key_pressed(&self, key: u8) -> bool {
         assert!(key < NUM_KEYS);
 
@@ -22,6 +31,7 @@ mod test {
     use super::*;
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn is_key_pressed() {
         let mut keypad = Keypad::default();
 
@@ -45,6 +55,7 @@ mod test {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn pressed_key() {
         let mut keypad = Keypad::default();
 
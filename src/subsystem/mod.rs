@@ -1,4 +1,5 @@
 pub mod keypad;
 pub mod reg;
+pub mod sample;
 pub mod timer;
 pub mod video;
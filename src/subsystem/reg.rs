@@ -1,13 +1,14 @@
 use std::ops::{Add, AddAssign};
 
 const U12_MAX: u16 = 0xFFF;
+const U24_MAX: u32 = 0xFF_FFFF;
 
 #[derive(Default, Clone, Copy, Eq, PartialEq)]
 pub struct IndexRegister {
-    // We will only ever need 12 bits, but Rust does not have a native u12 type.
-    // Storing valid values into this field will be enforced at runtime,
-    // via the `IndexRegister::Load` function.
-    inner: u16,
+    // CHIP-8 only ever needs 12 bits, and MEGA-CHIP's `01nn nnnn` 24, but Rust does not
+    // have native u12 or u24 types. Storing valid values into this field will be enforced
+    // at runtime, via the `IndexRegister::load` and `IndexRegister::load_long` functions.
+    inner: u32,
 }
 
 impl std::fmt::Debug for IndexRegister {
@@ -22,6 +23,15 @@ impl IndexRegister {
             v <= U12_MAX,
             "tried to load too-large value {v} into IndexRegister"
         );
+        self.inner = v as u32;
+    }
+
+    /// Loads a 24-bit address, as MEGA-CHIP's `01nn nnnn` does.
+    pub fn load_long(&mut self, v: u32) {
+        assert!(
+            v <= U24_MAX,
+            "tried to load too-large value {v} into IndexRegister"
+        );
         self.inner = v;
     }
 
@@ -35,14 +45,14 @@ impl Add<u8> for IndexRegister {
 
     fn add(self, rhs: u8) -> Self::Output {
         Self {
-            inner: self.inner + rhs as u16,
+            inner: self.inner + rhs as u32,
         }
     }
 }
 
 impl AddAssign<u8> for IndexRegister {
     fn add_assign(&mut self, rhs: u8) {
-        self.inner += rhs as u16;
+        self.inner += rhs as u32;
     }
 }
 
@@ -66,4 +76,13 @@ mod test {
 
         i.load(U12_MAX + 1);
     }
+
+    #[test]
+    fn load_long() {
+        let mut i = IndexRegister::default();
+
+        i.load_long(0x12_3456);
+
+        assert_eq!(0x12_3456, i.get());
+    }
 }
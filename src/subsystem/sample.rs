@@ -0,0 +1,62 @@
+/// Digitised sound that MEGA-CHIP's `060n` plays: unsigned 8-bit mono samples.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample {
+    /// Samples per second.
+    pub rate: u16,
+    pub data: Vec<u8>,
+    pub looping: bool,
+    /// How many samples were started before this one, so that playing the same sound again
+    /// starts it over.
+    pub serial: u32,
+}
+
+impl Sample {
+    /// The sample at the start of `memory`: the rate as 2 big-endian bytes, the length as 3,
+    /// a zero byte, then the data. `None` if it runs past the end of memory.
+    pub fn read(memory: &[u8], looping: bool, serial: u32) -> Option<Self> {
+        let &[rate_hi, rate_lo, len_hi, len_mid, len_lo, _, ..] = memory else {
+            return None;
+        };
+        let len = u32::from_be_bytes([0, len_hi, len_mid, len_lo]) as usize;
+
+        Some(Self {
+            rate: u16::from_be_bytes([rate_hi, rate_lo]),
+            data: memory.get(6..6 + len)?.to_vec(),
+            looping,
+            serial,
+        })
+    }
+
+    /// How many bytes of memory the sample at the start of `memory` takes up, including
+    /// its header.
+    pub fn size(memory: &[u8]) -> usize {
+        match memory {
+            &[_, _, len_hi, len_mid, len_lo, ..] => {
+                6 + u32::from_be_bytes([0, len_hi, len_mid, len_lo]) as usize
+            }
+            _ => 6,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn read() {
+        let memory = [0x1F, 0x40, 0, 0, 2, 0, 0x80, 0xFF, 0x12];
+
+        assert_eq!(8, Sample::size(&memory));
+        assert_eq!(
+            Some(Sample {
+                rate: 8000,
+                data: vec![0x80, 0xFF],
+                looping: true,
+                serial: 1,
+            }),
+            Sample::read(&memory, true, 1)
+        );
+        assert_eq!(None, Sample::read(&memory[..7], true, 1));
+    }
+}
@@ -0,0 +1,89 @@
+use std::ops::Range;
+
+/// Pixels side by side that share a foreground colour.
+pub const ZONE_WIDTH: usize = 8;
+
+/// The foreground colours of CHIP-8X's VP-590 colour board, one per block of 8x1 pixels,
+/// and its background colour.
+///
+/// Colours are indices into [`crate::palette::VP590_FOREGROUND`] and
+/// [`crate::palette::VP590_BACKGROUND`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColorLayer {
+    zones: Vec<u8>,
+    columns: usize,
+    background: u8,
+}
+
+impl ColorLayer {
+    /// Red, which the colour board starts with.
+    const DEFAULT_FOREGROUND: u8 = 1;
+    const BACKGROUNDS: u8 = 4;
+
+    pub fn new(width: usize, height: usize) -> Self {
+        let columns = width.div_ceil(ZONE_WIDTH);
+        Self {
+            zones: vec![Self::DEFAULT_FOREGROUND; columns * height],
+            columns,
+            background: 0,
+        }
+    }
+
+    pub fn background(&self) -> u8 {
+        self.background
+    }
+
+    /// The foreground colour of the pixel at (`x`, `y`).
+    pub fn foreground(&self, x: usize, y: usize) -> u8 {
+        self.zones[y * self.columns + x / ZONE_WIDTH]
+    }
+
+    /// Steps the background through blue, black, green and red.
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % Self::BACKGROUNDS;
+    }
+
+    pub fn reset_background(&mut self) {
+        self.background = 0;
+    }
+
+    /// Colours the zones in `columns` (of [`ZONE_WIDTH`] pixels) and `rows` (of pixels),
+    /// clipped to the display.
+    pub fn fill(&mut self, columns: Range<usize>, rows: Range<usize>, color: u8) {
+        let rows = rows.start..rows.end.min(self.zones.len() / self.columns);
+        let columns = columns.start..columns.end.min(self.columns);
+        for row in rows {
+            for column in columns.clone() {
+                self.zones[row * self.columns + column] = color & 0b111;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn fill() {
+        let mut colors = ColorLayer::new(64, 32);
+        assert_eq!(1, colors.foreground(63, 31));
+
+        colors.fill(6..10, 30..40, 0xF4);
+
+        assert_eq!(4, colors.foreground(48, 30));
+        assert_eq!(4, colors.foreground(63, 31));
+        assert_eq!(1, colors.foreground(47, 31));
+        assert_eq!(1, colors.foreground(48, 29));
+    }
+
+    #[test]
+    fn cycle_background() {
+        let mut colors = ColorLayer::new(64, 32);
+
+        for expected in [1, 2, 3, 0] {
+            colors.cycle_background();
+            assert_eq!(expected, colors.background());
+        }
+    }
+}
@@ -1,5 +1,3 @@
-use super::{HEIGHT_PIXELS, WIDTH_PIXELS};
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DrawCoords {
     pub(super) pos_x: usize,
@@ -7,10 +5,11 @@ pub struct DrawCoords {
 }
 
 impl DrawCoords {
-    pub fn new(pos_x: u8, pos_y: u8) -> Self {
+    /// The coordinates wrapped around a `width`x`height` display.
+    pub fn new(pos_x: u8, pos_y: u8, width: usize, height: usize) -> Self {
         Self {
-            pos_x: (pos_x as usize) % WIDTH_PIXELS,
-            pos_y: (pos_y as usize) % HEIGHT_PIXELS,
+            pos_x: (pos_x as usize) % width,
+            pos_y: (pos_y as usize) % height,
         }
     }
 }
@@ -19,12 +18,15 @@ impl DrawCoords {
 mod test {
     use super::*;
 
+    const WIDTH_PIXELS: usize = 64;
+    const HEIGHT_PIXELS: usize = 32;
+
     #[test]
     fn from_opcode_within_bounds() {
         let pos_x = 1;
         let pos_y = 1;
 
-        let coords = DrawCoords::new(pos_x, pos_y);
+        let coords = DrawCoords::new(pos_x, pos_y, WIDTH_PIXELS, HEIGHT_PIXELS);
 
         assert_eq!(pos_x as usize, coords.pos_x);
         assert_eq!(pos_y as usize, coords.pos_y);
@@ -35,9 +37,22 @@ mod test {
         let pos_x = 65;
         let pos_y = 33;
 
-        let coords = DrawCoords::new(pos_x, pos_y);
+        let coords = DrawCoords::new(pos_x, pos_y, WIDTH_PIXELS, HEIGHT_PIXELS);
 
         assert_eq!(1, coords.pos_x); // 65 % WIDTH_PIXELS
         assert_eq!(1, coords.pos_y); // 33 % HEIGHT_PIXELS
     }
+
+    #[test]
+    fn wraps_on_display_size() {
+        let coords = DrawCoords::new(65, 33, 64, 48);
+
+        assert_eq!(1, coords.pos_x);
+        assert_eq!(33, coords.pos_y);
+
+        let coords = DrawCoords::new(65, 70, 64, 64);
+
+        assert_eq!(1, coords.pos_x);
+        assert_eq!(6, coords.pos_y);
+    }
 }
@@ -0,0 +1,124 @@
+use crate::palette::Rgb;
+
+/// How MEGA-CHIP sprites are mixed with what's under them, as set by `080n`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Blend {
+    #[default]
+    Normal,
+    /// A quarter of the sprite's colour and three quarters of the screen's.
+    Quarter,
+    Half,
+    Add,
+    Multiply,
+}
+
+impl Blend {
+    /// The blend mode `n` of `080n`, if there is one.
+    pub fn from_mode(n: u8) -> Option<Self> {
+        Some(match n {
+            0 => Self::Normal,
+            1 => Self::Quarter,
+            2 => Self::Half,
+            3 => Self::Add,
+            4 => Self::Multiply,
+            _ => return None,
+        })
+    }
+
+    fn mix(self, src: Rgb, dst: Rgb) -> Rgb {
+        let channel = |s: u8, d: u8| {
+            let (s, d) = (u16::from(s), u16::from(d));
+            let mixed = match self {
+                Self::Normal => s,
+                Self::Quarter => (s + 3 * d) / 4,
+                Self::Half => (s + d) / 2,
+                Self::Add => (s + d).min(0xFF),
+                Self::Multiply => s * d / 0xFF,
+            };
+            mixed as u8
+        };
+        [
+            channel(src[0], dst[0]),
+            channel(src[1], dst[1]),
+            channel(src[2], dst[2]),
+        ]
+    }
+}
+
+/// MEGA-CHIP's 256 colours, of which programs load 1 to 255 with `02nn`. Colour 0 is
+/// black, and transparent in sprites.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedPalette {
+    colors: Vec<Rgb>,
+    /// How opaque the whole screen is, as set by `05nn`.
+    pub alpha: u8,
+}
+
+impl Default for IndexedPalette {
+    fn default() -> Self {
+        let mut colors = vec![[0xFF; 3]; 256];
+        colors[0] = [0; 3];
+        Self {
+            colors,
+            alpha: 0xFF,
+        }
+    }
+}
+
+impl IndexedPalette {
+    pub fn colors(&self) -> &[Rgb] {
+        &self.colors
+    }
+
+    /// Loads colours 1 and up from ARGB bytes. Alpha isn't used.
+    pub fn load(&mut self, argb: &[u8]) {
+        for (color, bytes) in self.colors[1..].iter_mut().zip(argb.chunks_exact(4)) {
+            *color = [bytes[1], bytes[2], bytes[3]];
+        }
+    }
+
+    /// The colour closest to `rgb`, other than the transparent colour 0.
+    fn nearest(&self, rgb: Rgb) -> u8 {
+        let distance = |color: &Rgb| -> u32 {
+            (0..3)
+                .map(|c| (i32::from(color[c]) - i32::from(rgb[c])).pow(2) as u32)
+                .sum()
+        };
+        (1..=255)
+            .min_by_key(|&i| distance(&self.colors[i as usize]))
+            .unwrap()
+    }
+
+    /// The colour `sprite` blends into when drawn over `screen`.
+    pub(super) fn blend(&self, sprite: u8, screen: u8, blend: Blend) -> u8 {
+        if blend == Blend::Normal {
+            return sprite;
+        }
+        let mixed = blend.mix(self.colors[sprite as usize], self.colors[screen as usize]);
+        self.nearest(mixed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blend() {
+        let mut palette = IndexedPalette::default();
+        #[rustfmt::skip]
+        palette.load(&[
+            0xFF, 0xFF, 0x00, 0x00,
+            0xFF, 0x00, 0x00, 0xFF,
+            0xFF, 0x80, 0x00, 0x80,
+            0xFF, 0xFF, 0x00, 0xFF,
+        ]);
+
+        assert_eq!(2, palette.blend(2, 1, Blend::Normal));
+        assert_eq!(3, palette.blend(2, 1, Blend::Half));
+        assert_eq!(4, palette.blend(2, 1, Blend::Add));
+        // Black, but never the transparent colour.
+        assert_eq!(3, palette.blend(1, 0, Blend::Multiply));
+        assert_eq!(None, Blend::from_mode(5));
+    }
+}
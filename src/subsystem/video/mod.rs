@@ -1,34 +1,118 @@
+mod colors;
 mod coords;
+mod indexed;
+pub use colors::{ColorLayer, ZONE_WIDTH};
 pub use coords::DrawCoords;
-
-const WIDTH_PIXELS: usize = 64;
-const HEIGHT_PIXELS: usize = 32;
+pub use indexed::{Blend, IndexedPalette};
 
 #[derive(Debug, Clone)]
 pub struct Video {
-    buffer: [u8; WIDTH_PIXELS * HEIGHT_PIXELS],
+    buffer: Vec<u8>,
+    width: usize,
+    height: usize,
+    /// Only shown on platforms with colour.
+    colors: ColorLayer,
+    /// The colours of an indexed-colour buffer, whose bytes are indices into it rather
+    /// than on or off.
+    palette: Option<IndexedPalette>,
 }
 
-impl Default for Video {
-    fn default() -> Self {
+impl Video {
+    pub fn new(width: usize, height: usize) -> Self {
         Self {
-            buffer: [0; WIDTH_PIXELS * HEIGHT_PIXELS],
+            buffer: vec![0; width * height],
+            width,
+            height,
+            colors: ColorLayer::new(width, height),
+            palette: None,
+        }
+    }
+
+    /// A display with a colour index per pixel, as in MEGA-CHIP mode.
+    pub fn indexed(width: usize, height: usize) -> Self {
+        Self {
+            palette: Some(IndexedPalette::default()),
+            ..Self::new(width, height)
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Where a sprite drawn at (`pos_x`, `pos_y`) goes, wrapped around the display.
+    pub fn coords(&self, pos_x: u8, pos_y: u8) -> DrawCoords {
+        DrawCoords::new(pos_x, pos_y, self.width, self.height)
+    }
+
+    /// The screen, one byte per pixel, row by row.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn colors(&self) -> &ColorLayer {
+        &self.colors
+    }
+
+    pub fn colors_mut(&mut self) -> &mut ColorLayer {
+        &mut self.colors
+    }
+
+    pub fn palette(&self) -> Option<&IndexedPalette> {
+        self.palette.as_ref()
+    }
+
+    pub fn palette_mut(&mut self) -> Option<&mut IndexedPalette> {
+        self.palette.as_mut()
+    }
+
+    /// Shows `bits`, a bit per pixel with the leftmost in the high bit, as in the COSMAC
+    /// VIP's display memory.
+    pub fn load_bits(&mut self, bits: &[u8]) {
+        for (i, pixel) in self.buffer.iter_mut().enumerate() {
+            *pixel = if bits[i / 8] & 0x80 >> (i % 8) != 0 {
+                0xFF
+            } else {
+                0
+            };
         }
     }
-}
 
-impl Video {
     pub fn clear(&mut self) {
         self.buffer.fill(0);
     }
 
-    pub fn draw(&mut self, sprite: &[u8], &DrawCoords { pos_x, pos_y }: &DrawCoords) -> bool {
+    pub fn draw(&mut self, sprite: &[u8], coords: &DrawCoords) -> bool {
+        let rows = sprite.iter().map(|&byte| u16::from(byte) << 8);
+        self.draw_rows(rows, 8, coords)
+    }
+
+    /// Draws SUPER-CHIP's 16x16 sprites, two bytes per row.
+    pub fn draw_large(&mut self, sprite: &[u8], coords: &DrawCoords) -> bool {
+        let rows = sprite
+            .chunks_exact(2)
+            .map(|row| u16::from_be_bytes([row[0], row[1]]));
+        self.draw_rows(rows, 16, coords)
+    }
+
+    /// Draws rows of `width` pixels, with the leftmost in the high bit.
+    fn draw_rows(
+        &mut self,
+        rows: impl Iterator<Item = u16>,
+        width: usize,
+        &DrawCoords { pos_x, pos_y }: &DrawCoords,
+    ) -> bool {
         let mut has_overlap = false;
 
-        for (row, &byte) in sprite.iter().enumerate() {
-            for col in 0..8 {
-                let sprite_pixel = byte & (0b1000_0000 >> col);
-                let screen_pixel = &mut self.buffer[(pos_y + row) * WIDTH_PIXELS + (pos_x + col)];
+        // Sprites are clipped at the edges of the display.
+        for (row, bits) in rows.enumerate().take(self.height - pos_y) {
+            for col in 0..width.min(self.width - pos_x) {
+                let sprite_pixel = bits & (0x8000 >> col);
+                let screen_pixel = &mut self.buffer[(pos_y + row) * self.width + (pos_x + col)];
 
                 if sprite_pixel != 0 {
                     if *screen_pixel != 0 {
@@ -42,17 +126,77 @@ impl Video {
 
         has_overlap
     }
+
+    /// Moves the screen down by `rows`, leaving the top rows blank.
+    pub fn scroll_down(&mut self, rows: usize) {
+        let shift = (rows * self.width).min(self.buffer.len());
+        self.buffer.rotate_right(shift);
+        self.buffer[..shift].fill(0);
+    }
+
+    /// Moves the screen right by `cols`, leaving the leftmost columns blank.
+    pub fn scroll_right(&mut self, cols: usize) {
+        let cols = cols.min(self.width);
+        for row in self.buffer.chunks_exact_mut(self.width) {
+            row.rotate_right(cols);
+            row[..cols].fill(0);
+        }
+    }
+
+    /// Moves the screen left by `cols`, leaving the rightmost columns blank.
+    pub fn scroll_left(&mut self, cols: usize) {
+        let cols = cols.min(self.width);
+        for row in self.buffer.chunks_exact_mut(self.width) {
+            row.rotate_left(cols);
+            row[self.width - cols..].fill(0);
+        }
+    }
+
+    /// Draws a sprite of colour indices `width` pixels wide on an indexed display, clipped
+    /// at the edges. Colour 0 is transparent. Returns whether the sprite was drawn over a
+    /// pixel of the `collision` colour.
+    pub fn draw_indexed(
+        &mut self,
+        sprite: &[u8],
+        width: usize,
+        &DrawCoords { pos_x, pos_y }: &DrawCoords,
+        blend: Blend,
+        collision: u8,
+    ) -> bool {
+        let Some(palette) = &self.palette else {
+            return false;
+        };
+        let mut has_collision = false;
+
+        for (row, pixels) in sprite.chunks(width).enumerate().take(self.height - pos_y) {
+            for (col, &pixel) in pixels.iter().enumerate().take(self.width - pos_x) {
+                if pixel == 0 {
+                    continue;
+                }
+
+                let screen_pixel = &mut self.buffer[(pos_y + row) * self.width + (pos_x + col)];
+                if *screen_pixel != 0 && *screen_pixel == collision {
+                    has_collision = true;
+                }
+                *screen_pixel = palette.blend(pixel, *screen_pixel, blend);
+            }
+        }
+
+        has_collision
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    const WIDTH_PIXELS: usize = 64;
+    const HEIGHT_PIXELS: usize = 32;
+
     #[test]
     fn clear() {
-        let mut d = Video {
-            buffer: [99; WIDTH_PIXELS * HEIGHT_PIXELS],
-        };
+        let mut d = Video::new(WIDTH_PIXELS, HEIGHT_PIXELS);
+        d.buffer.fill(99);
 
         d.clear();
 
@@ -62,9 +206,9 @@ mod test {
     #[test]
     fn draw() {
         let sprite = [0xF0, 0x80, 0xF0, 0x80, 0x80];
-        let coords = DrawCoords::new(0, 0);
+        let coords = DrawCoords::new(0, 0, WIDTH_PIXELS, HEIGHT_PIXELS);
 
-        let mut video = Video::default();
+        let mut video = Video::new(WIDTH_PIXELS, HEIGHT_PIXELS);
 
         let has_overlap = video.draw(&sprite, &coords);
 
@@ -90,14 +234,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn draw_clipped() {
+        let sprite = [0xFF, 0xFF];
+        let mut video = Video::new(64, 48);
+
+        video.draw(&sprite, &video.coords(60, 47));
+
+        let lit: Vec<_> = (0..video.buffer.len())
+            .filter(|&i| video.buffer[i] != 0)
+            .collect();
+        assert_eq!((60..64).map(|x| 47 * 64 + x).collect::<Vec<_>>(), lit);
+    }
+
+    #[test]
+    fn draw_indexed() {
+        let mut video = Video::indexed(256, 192);
+        let sprite = [1, 0, 2, 3, 4, 5];
+
+        let coords = video.coords(254, 191);
+        assert!(!video.draw_indexed(&sprite, 3, &coords, Blend::Normal, 3));
+        assert_eq!([1, 0], video.buffer[191 * 256 + 254..]);
+
+        let coords = video.coords(10, 10);
+        video.draw_indexed(&sprite, 3, &coords, Blend::Normal, 3);
+        assert!(video.draw_indexed(&sprite, 3, &coords, Blend::Normal, 3));
+        assert!(!video.draw_indexed(&sprite, 3, &coords, Blend::Normal, 0));
+        assert_eq!([1, 0, 2], video.buffer[10 * 256 + 10..10 * 256 + 13]);
+    }
+
+    #[test]
+    fn draw_large() {
+        let mut sprite = [0; 32];
+        sprite[0] = 0x80;
+        sprite[31] = 0x01;
+        let mut video = Video::new(128, 64);
+
+        assert!(!video.draw_large(&sprite, &video.coords(120, 0)));
+        assert_eq!(0xFF, video.buffer[120]);
+        // The bottom right pixel is clipped.
+        assert!(video.buffer.iter().filter(|&&pixel| pixel != 0).count() == 1);
+
+        assert!(!video.draw_large(&sprite, &video.coords(0, 0)));
+        assert_eq!(0xFF, video.buffer[15 * 128 + 15]);
+        assert!(video.draw_large(&sprite, &video.coords(0, 0)));
+    }
+
+    #[test]
+    fn scroll() {
+        let mut video = Video::new(8, 4);
+        video.buffer[0] = 0xFF;
+
+        video.scroll_down(2);
+        assert_eq!(0xFF, video.buffer[16]);
+        video.scroll_right(4);
+        assert_eq!(0xFF, video.buffer[20]);
+        video.scroll_left(2);
+        assert_eq!(0xFF, video.buffer[18]);
+        assert_eq!(1, video.buffer.iter().filter(|&&pixel| pixel != 0).count());
+
+        video.scroll_left(8);
+        video.scroll_down(10);
+        assert!(video.buffer.iter().all(|&pixel| pixel == 0));
+    }
+
     #[test]
     fn draw_with_collision() {
         let f_sprite = [0xF0, 0x80, 0xF0, 0x80, 0x80];
         let e_sprite = [0xF0, 0x80, 0xF0, 0x80, 0xF0];
 
-        let coords = DrawCoords::new(0, 0);
+        let coords = DrawCoords::new(0, 0, WIDTH_PIXELS, HEIGHT_PIXELS);
 
-        let mut video = Video::default();
+        let mut video = Video::new(WIDTH_PIXELS, HEIGHT_PIXELS);
 
         let _ = video.draw(&f_sprite, &coords);
         let has_overlap = video.draw(&e_sprite, &coords);
@@ -0,0 +1,256 @@
+//! Symbol maps, which name addresses in a ROM so that the debugger can show `draw_player`
+//! instead of `0x2a4`.
+//!
+//! Two formats are understood:
+//!
+//! - Text, as in `.sym` files: one `ADDRESS LABEL` pair per line, with the address in hex
+//!   (`2a4`, `0x2a4` or `$2a4`). `LABEL ADDRESS` is accepted too when the address has a
+//!   `0x` or `$` prefix. Blank lines and lines starting with `#` or `;` are ignored.
+//! - JSON, as exported by Octo: an object mapping labels to addresses, either at the top
+//!   level or under a `"labels"` key. Addresses may be numbers or hex strings. Entries
+//!   whose value isn't an address, such as Octo's constants and aliases, are skipped.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+
+use crate::instruction::Instruction;
+
+// Extensions of symbol files that are picked up automatically next to a ROM.
+const SYMBOL_EXTENSIONS: [&str; 2] = ["sym", "json"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Symbols {
+    labels: BTreeMap<u16, String>,
+    addresses: HashMap<String, u16>,
+}
+
+impl Symbols {
+    /// Parses a symbol map, in JSON if it looks like a JSON object and as text otherwise.
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        if s.trim_start().starts_with('{') {
+            Self::parse_json(s)
+        } else {
+            Self::parse_text(s)
+        }
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read symbol file {}", path.display()))?;
+        Self::parse(&s).with_context(|| format!("invalid symbol file {}", path.display()))
+    }
+
+    /// Finds a symbol file sitting next to `rom_path`, e.g. `game.sym` for `game.ch8`.
+    pub fn find_for_rom(rom_path: &Path) -> Option<PathBuf> {
+        SYMBOL_EXTENSIONS
+            .iter()
+            .map(|ext| rom_path.with_extension(ext))
+            .find(|path| path.is_file())
+    }
+
+    fn parse_text(s: &str) -> anyhow::Result<Self> {
+        let mut symbols = Self::default();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let (Some(first), Some(second), None) = (tokens.next(), tokens.next(), tokens.next())
+            else {
+                bail!("line {}: expected `ADDRESS LABEL`, got {line:?}", i + 1);
+            };
+
+            let (addr, label) = if has_hex_prefix(second) && !has_hex_prefix(first) {
+                (second, first)
+            } else {
+                (first, second)
+            };
+            let addr = parse_address(addr)
+                .with_context(|| format!("line {}: invalid address {addr:?}", i + 1))?;
+
+            symbols.insert(addr, label);
+        }
+
+        Ok(symbols)
+    }
+
+    fn parse_json(s: &str) -> anyhow::Result<Self> {
+        let json: serde_json::Value = serde_json::from_str(s)?;
+        let Some(mut entries) = json.as_object() else {
+            bail!("expected a JSON object");
+        };
+        if let Some(labels) = entries.get("labels").and_then(|l| l.as_object()) {
+            entries = labels;
+        }
+
+        let mut symbols = Self::default();
+        for (label, value) in entries {
+            let addr = match value {
+                serde_json::Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+                serde_json::Value::String(s) => parse_address(s),
+                _ => None,
+            };
+
+            if let Some(addr) = addr {
+                symbols.insert(addr, label);
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    /// Names `addr`. An address with several labels is shown with the first one added.
+    pub fn insert(&mut self, addr: u16, label: &str) {
+        let label = label.trim_start_matches(':');
+        self.labels.entry(addr).or_insert_with(|| label.to_string());
+        self.addresses.insert(label.to_string(), addr);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    /// Iterates over the labelled addresses, in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &str)> {
+        self.labels
+            .iter()
+            .map(|(&addr, label)| (addr, label.as_str()))
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        self.labels.get(&addr).map(String::as_str)
+    }
+
+    pub fn address(&self, label: &str) -> Option<u16> {
+        self.addresses.get(label).copied()
+    }
+
+    /// Resolves a label, or failing that a hex address.
+    pub fn resolve(&self, s: &str) -> Option<u16> {
+        let s = s.trim();
+        self.address(s).or_else(|| parse_address(s))
+    }
+
+    /// Describes `addr` relative to the closest label at or before it, e.g. `main+4`.
+    pub fn locate(&self, addr: u16) -> Option<String> {
+        let (&base, label) = self.labels.range(..=addr).next_back()?;
+        Some(match addr - base {
+            0 => label.clone(),
+            offset => format!("{label}+{offset}"),
+        })
+    }
+
+    /// Formats `addr` as its label if it has one, as hex otherwise.
+    pub fn name(&self, addr: u16) -> String {
+        self.label(addr)
+            .map_or_else(|| format!("{addr:#05x}"), str::to_string)
+    }
+
+    /// Disassembles `instruction`, naming its address operand if it has a label.
+    pub fn disassemble(&self, instruction: &Instruction) -> String {
+        let text = instruction.to_string();
+        match instruction
+            .address()
+            .and_then(|a| Some((a, self.label(a)?)))
+        {
+            Some((addr, label)) => text.replacen(&format!("{addr:#05x}"), label, 1),
+            None => text,
+        }
+    }
+}
+
+fn has_hex_prefix(s: &str) -> bool {
+    s.starts_with("0x") || s.starts_with("0X") || s.starts_with('$')
+}
+
+fn parse_address(s: &str) -> Option<u16> {
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_text() {
+        let symbols = Symbols::parse(
+            "# generated\n\
+             200 main\n\
+             0x2a4 draw_player\n\
+             \n\
+             score 0x3F0\n\
+             ; trailing comment\n",
+        )
+        .unwrap();
+
+        assert_eq!(3, symbols.len());
+        assert_eq!(Some(0x200), symbols.address("main"));
+        assert_eq!(Some("draw_player"), symbols.label(0x2A4));
+        assert_eq!(Some(0x3F0), symbols.address("score"));
+    }
+
+    #[test]
+    fn parse_text_rejects_garbage() {
+        assert!(Symbols::parse("200").is_err());
+        assert!(Symbols::parse("zzz main").is_err());
+    }
+
+    #[test]
+    fn parse_json() {
+        let symbols = Symbols::parse(
+            r#"{ "labels": { "main": 512, "draw_player": "0x2a4", "SPEED": [1] } }"#,
+        )
+        .unwrap();
+
+        assert_eq!(2, symbols.len());
+        assert_eq!(Some(0x200), symbols.address("main"));
+        assert_eq!(Some(0x2A4), symbols.address("draw_player"));
+
+        let flat = Symbols::parse(r#"{ "main": 512 }"#).unwrap();
+        assert_eq!(Some("main"), flat.label(0x200));
+    }
+
+    #[test]
+    fn resolve_and_locate() {
+        let symbols = Symbols::parse("200 main\n2a4 draw_player").unwrap();
+
+        assert_eq!(Some(0x2A4), symbols.resolve("draw_player"));
+        assert_eq!(Some(0x2A6), symbols.resolve("2a6"));
+        assert_eq!(None, symbols.resolve("nope"));
+
+        assert_eq!(None, symbols.locate(0x1FE));
+        assert_eq!(Some("main".to_string()), symbols.locate(0x200));
+        assert_eq!(Some("draw_player+4".to_string()), symbols.locate(0x2A8));
+    }
+
+    #[test]
+    fn disassemble() {
+        let symbols = Symbols::parse("2a4 draw_player").unwrap();
+
+        assert_eq!(
+            "CALL draw_player",
+            symbols.disassemble(&Instruction::Call(0x2A4))
+        );
+        assert_eq!("JP 0x2a6", symbols.disassemble(&Instruction::Jp(0x2A6)));
+        assert_eq!(
+            "LD VA, 0x02",
+            symbols.disassemble(&Instruction::LdByte(0xA, 2))
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! How long instructions take to run.
+//!
+//! By default every instruction takes the same time, and a fixed number of them run per
+//! frame. The VIP model instead charges each instruction the machine cycles that the COSMAC
+//! VIP's interpreter spends on it. A frame is 3668 machine cycles of the VIP's 1.76 MHz
+//! CDP1802, of which the display's DMA and interrupt routine take 1070. The interrupt
+//! routine is also what counts the timers down, once per frame. `DRW` waits for it before
+//! drawing, so a program draws at most one sprite per frame.
+//!
+//! The costs are approximate: they follow the interpreter's routines, but don't count
+//! every branch in them.
+
+use std::{fmt, str::FromStr};
+
+use crate::{chip8::Chip8, instruction::Instruction};
+
+/// Machine cycles per 60 Hz frame.
+pub const VIP_FRAME_CYCLES: u64 = 3668;
+/// Machine cycles per frame that the display's DMA and the interrupt routine take.
+pub const VIP_DISPLAY_CYCLES: u64 = 1070;
+
+// Fetching, decoding and dispatching an instruction.
+const FETCH: u64 = 40;
+// The extra cost of a skip instruction that skips.
+const SKIP: u64 = 4;
+// Anything the model doesn't know the cost of.
+const DEFAULT: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    /// Every instruction takes the same time, `ipf` of them per frame.
+    #[default]
+    Fixed,
+    /// Instructions take as long as on the COSMAC VIP.
+    Vip,
+}
+
+impl Timing {
+    const NAMES: [(&'static str, Self); 2] = [("fixed", Self::Fixed), ("vip", Self::Vip)];
+}
+
+/// The machine cycles the VIP takes to run `opcode` on `chip8`, not counting `DRW`'s wait
+/// for the display interrupt.
+pub fn vip_cycles(chip8: &Chip8, opcode: u16) -> u64 {
+    let v = |x: u8| chip8.registers[x as usize];
+    let skip = |skips: bool| if skips { SKIP } else { 0 };
+    let key_down = |x: u8| chip8.keys() & 1 << (v(x) & 0xF) != 0;
+
+    let cycles = match Instruction::decode(opcode) {
+        // Clearing the 256 bytes of display memory.
+        Some(Instruction::Cls) => 3078,
+        Some(Instruction::Ret) => 10,
+        Some(Instruction::Jp(_)) => 12,
+        Some(Instruction::Call(_)) => 26,
+        Some(Instruction::SeByte(x, kk)) => 10 + skip(v(x) == kk),
+        Some(Instruction::SneByte(x, kk)) => 10 + skip(v(x) != kk),
+        Some(Instruction::SeReg(x, y)) => 14 + skip(v(x) == v(y)),
+        Some(Instruction::SneReg(x, y)) => 14 + skip(v(x) != v(y)),
+        Some(Instruction::LdByte(..)) => 6,
+        Some(Instruction::AddByte(..)) => 10,
+        // The interpreter runs these by building a routine in memory and calling it.
+        Some(
+            Instruction::LdReg(..)
+            | Instruction::Or(..)
+            | Instruction::And(..)
+            | Instruction::Xor(..)
+            | Instruction::AddReg(..)
+            | Instruction::Sub(..)
+            | Instruction::Shr(..)
+            | Instruction::Subn(..)
+            | Instruction::Shl(..),
+        ) => 44,
+        Some(Instruction::LdI(_)) => 12,
+        Some(Instruction::JpV0(_)) => 22,
+        Some(Instruction::Rnd(..)) => 36,
+        Some(Instruction::Drw(x, _, n)) => {
+            // Sprites that don't start on a byte are shifted into two bytes, a bit at a
+            // time.
+            let shift = u64::from(v(x) % 8);
+            let row = if shift == 0 { 34 } else { 50 + 6 * shift };
+            26 + u64::from(n) * row
+        }
+        Some(Instruction::Skp(x)) => 18 + skip(key_down(x)),
+        Some(Instruction::Sknp(x)) => 18 + skip(!key_down(x)),
+        Some(Instruction::LdVxDt(_) | Instruction::LdDtVx(_) | Instruction::LdStVx(_)) => 10,
+        // Each check for a key, which repeats until one is pressed.
+        Some(Instruction::LdVxK(_)) => 20,
+        Some(Instruction::AddIVx(_) | Instruction::LdFVx(_)) => 16,
+        Some(Instruction::LdBVx(x)) => {
+            // Each digit is found by repeated subtraction.
+            let value = v(x);
+            let digits = u64::from(value / 100 + value / 10 % 10 + value % 10);
+            80 + 16 * digits
+        }
+        Some(Instruction::LdMemVx(x) | Instruction::LdVxMem(x)) => 14 + 14 * (u64::from(x) + 1),
+        Some(Instruction::Sys(_)) | None => DEFAULT,
+    };
+
+    FETCH + cycles
+}
+
+/// Whether the VIP waits for the next display interrupt before running `opcode`.
+pub fn waits_for_display(opcode: u16) -> bool {
+    matches!(Instruction::decode(opcode), Some(Instruction::Drw(..)))
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, _) = Self::NAMES.iter().find(|(_, t)| t == self).unwrap();
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for Timing {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::NAMES
+            .iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, timing)| *timing)
+            .ok_or_else(|| anyhow::anyhow!("unknown timing {s:?}, expected fixed or vip"))
+    }
+}
+
+serde_string!(Timing);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn vip_cycles() {
+        let mut chip8 = Chip8::default();
+        chip8.registers[1] = 0x12;
+
+        assert_eq!(46, super::vip_cycles(&chip8, 0x6005));
+        assert_eq!(50, super::vip_cycles(&chip8, 0x3113));
+        assert_eq!(54, super::vip_cycles(&chip8, 0x3112));
+        // Aligned, then 2 pixels off.
+        assert_eq!(40 + 26 + 5 * 34, super::vip_cycles(&chip8, 0xD005));
+        assert_eq!(40 + 26 + 5 * 62, super::vip_cycles(&chip8, 0xD105));
+        // 1 + 8 digits.
+        assert_eq!(40 + 80 + 16 * 9, super::vip_cycles(&chip8, 0xF133));
+        assert!(waits_for_display(0xD105));
+        assert!(!waits_for_display(0x00E0));
+    }
+
+    #[test]
+    fn drw_waits_for_the_frame() {
+        // 200: LD VA, 30; 202: LD DT, VA; 204: DRW V0, V1, 5; 206: ADD V0, 1; 208: JP 204
+        let rom = [0x6A, 0x1E, 0xFA, 0x15, 0xD0, 0x15, 0x70, 0x01, 0x12, 0x04];
+        let mut chip8 = Chip8::default();
+        chip8.load_rom(&rom).unwrap();
+        chip8.timing = Timing::Vip;
+        let mut runner = crate::Chip8Runner::new(chip8, 700).unwrap();
+
+        runner.run_frames(10);
+        assert_eq!(10, runner.frames());
+        // The tenth sprite has just been drawn.
+        assert_eq!(9, runner.chip8.registers[0]);
+        assert_eq!(20, runner.chip8.delay_timer.cur_count());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Timing::Vip, "vip".parse().unwrap());
+        assert_eq!("fixed", Timing::Fixed.to_string());
+        assert!("fast".parse::<Timing>().is_err());
+    }
+}
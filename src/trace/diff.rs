@@ -0,0 +1,450 @@
+//! Aligns a patata trace with a trace from another emulator, and finds where they diverge.
+//!
+//! # Reference trace format
+//!
+//! Plain text with one executed instruction per line, describing the machine state right
+//! *before* that instruction executes. Each line holds whitespace separated `KEY=VALUE`
+//! fields with hex values (an optional `0x` prefix is allowed). Keys are case-insensitive:
+//!
+//! | Key                   | Value                                        |
+//! |-----------------------|----------------------------------------------|
+//! | `PC`                  | program counter                              |
+//! | `OP`                  | opcode at the program counter                |
+//! | `V`                   | all of V0-VF, as 32 consecutive hex digits   |
+//! | `V0` ... `VF`         | a single register                            |
+//! | `I`, `SP`, `DT`, `ST` | index register, stack pointer and the timers |
+//!
+//! For example:
+//!
+//! ```text
+//! PC=0200 OP=00E0 V0=00 V1=00 V2=00 I=0000 SP=00 DT=00 ST=00
+//! ```
+//!
+//! Fields missing from a line are not compared, so emulators that only log some of the
+//! state can still be diffed. Tokens without a `=` are ignored, as is everything after a
+//! `|`, and empty lines or lines starting with `#`. A leading decimal token is read as the
+//! step number. patata's own text traces follow this format, and binary traces written by
+//! a [`Tracer`](super::Tracer) can be read as well.
+
+use std::{fmt::Write as _, path::Path};
+
+use anyhow::Context;
+
+use super::{is_binary_trace, read_binary_trace, TraceRecord};
+use crate::instruction::Instruction;
+
+const FIELD_NAMES: [&str; 22] = [
+    "PC", "OP", "V0", "V1", "V2", "V3", "V4", "V5", "V6", "V7", "V8", "V9", "VA", "VB", "VC", "VD",
+    "VE", "VF", "I", "SP", "DT", "ST",
+];
+
+/// The state before a single step, as read from a trace. Fields are `None` if the trace
+/// did not record them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Line number in the trace file, or record number for binary traces.
+    pub line: usize,
+    pub step: Option<u64>,
+    pub pc: Option<u16>,
+    pub opcode: Option<u16>,
+    pub registers: [Option<u8>; 16],
+    pub index: Option<u16>,
+    pub stack_pointer: Option<u8>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+}
+
+impl TraceStep {
+    /// Parses a single line of a text trace. Returns `None` for lines without any fields.
+    pub fn parse_line(line: &str, line_no: usize) -> anyhow::Result<Option<Self>> {
+        let line = line.split('|').next().unwrap_or_default().trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let mut step = Self {
+            line: line_no,
+            ..Self::default()
+        };
+        let mut has_fields = false;
+
+        for (i, token) in line.split_whitespace().enumerate() {
+            let Some((key, value)) = token.split_once('=') else {
+                if i == 0 {
+                    step.step = token.parse().ok();
+                }
+                continue;
+            };
+
+            let key = key.to_ascii_uppercase();
+            let value = value.trim_start_matches("0x").trim_start_matches("0X");
+            let parse_u16 = || {
+                u16::from_str_radix(value, 16)
+                    .with_context(|| format!("line {line_no}: invalid value for {key}: {value:?}"))
+            };
+            let parse_u8 = || {
+                u8::from_str_radix(value, 16)
+                    .with_context(|| format!("line {line_no}: invalid value for {key}: {value:?}"))
+            };
+
+            match key.as_str() {
+                "PC" => step.pc = Some(parse_u16()?),
+                "OP" => step.opcode = Some(parse_u16()?),
+                "I" => step.index = Some(parse_u16()?),
+                "SP" => step.stack_pointer = Some(parse_u8()?),
+                "DT" => step.delay_timer = Some(parse_u8()?),
+                "ST" => step.sound_timer = Some(parse_u8()?),
+                "V" => {
+                    if value.len() != 32 {
+                        anyhow::bail!("line {line_no}: expected 32 hex digits for V");
+                    }
+                    for (reg, chunk) in step.registers.iter_mut().zip(value.as_bytes().chunks(2)) {
+                        let digits = std::str::from_utf8(chunk)?;
+                        *reg = Some(u8::from_str_radix(digits, 16).with_context(|| {
+                            format!("line {line_no}: invalid register value {digits:?}")
+                        })?);
+                    }
+                }
+                _ => match key.strip_prefix('V').map(|r| u8::from_str_radix(r, 16)) {
+                    Some(Ok(reg)) if reg < 16 => step.registers[reg as usize] = Some(parse_u8()?),
+                    // Unknown fields are allowed, so that richer logs can be diffed as-is.
+                    _ => continue,
+                },
+            }
+
+            has_fields = true;
+        }
+
+        Ok(has_fields.then_some(step))
+    }
+
+    pub fn from_record(record: &TraceRecord, line: usize) -> Self {
+        let state = &record.before;
+
+        Self {
+            line,
+            step: Some(record.step),
+            pc: Some(state.pc),
+            opcode: Some(record.opcode),
+            registers: state.registers.map(Some),
+            index: Some(state.index),
+            stack_pointer: Some(state.stack_pointer),
+            delay_timer: Some(state.delay_timer),
+            sound_timer: Some(state.sound_timer),
+        }
+    }
+
+    /// All fields in [`FIELD_NAMES`] order.
+    fn fields(&self) -> [Option<u16>; 22] {
+        let mut fields = [None; 22];
+        fields[0] = self.pc;
+        fields[1] = self.opcode;
+        for (field, reg) in fields[2..18].iter_mut().zip(self.registers) {
+            *field = reg.map(u16::from);
+        }
+        fields[18] = self.index;
+        fields[19] = self.stack_pointer.map(u16::from);
+        fields[20] = self.delay_timer.map(u16::from);
+        fields[21] = self.sound_timer.map(u16::from);
+        fields
+    }
+
+    /// Two steps are at the same location if their PC and opcode agree, where present.
+    fn same_location(&self, other: &Self) -> bool {
+        fn agree(a: Option<u16>, b: Option<u16>) -> bool {
+            a.zip(b).is_none_or(|(a, b)| a == b)
+        }
+
+        agree(self.pc, other.pc) && agree(self.opcode, other.opcode)
+    }
+}
+
+/// Reads a text or binary trace file.
+pub fn load_trace(path: &Path) -> anyhow::Result<Vec<TraceStep>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    if is_binary_trace(&bytes) {
+        let records = read_binary_trace(&bytes)?;
+        return Ok(records
+            .iter()
+            .enumerate()
+            .map(|(i, r)| TraceStep::from_record(r, i + 1))
+            .collect());
+    }
+
+    let text = String::from_utf8(bytes).context("trace is neither binary nor UTF-8 text")?;
+    let mut steps = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if let Some(step) = TraceStep::parse_line(line, i + 1)? {
+            steps.push(step);
+        }
+    }
+
+    Ok(steps)
+}
+
+/// Finds the first pair of indices at which the two traces can be lined up: the first
+/// step of either trace is looked up in the other one.
+pub fn align(left: &[TraceStep], right: &[TraceStep]) -> Option<(usize, usize)> {
+    let (first_left, first_right) = (left.first()?, right.first()?);
+
+    if let Some(j) = right.iter().position(|s| s.same_location(first_left)) {
+        return Some((0, j));
+    }
+
+    left.iter()
+        .position(|s| s.same_location(first_right))
+        .map(|i| (i, 0))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: &'static str,
+    pub left: u16,
+    pub right: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Index of the first divergent step in the left trace.
+    pub left: usize,
+    /// Index of the first divergent step in the right trace.
+    pub right: usize,
+    pub mismatches: Vec<Mismatch>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffOutcome {
+    /// Every aligned step matched; `compared` steps were compared in total.
+    Identical {
+        compared: usize,
+    },
+    Diverged(Divergence),
+}
+
+/// Compares the two traces step by step, starting at the aligned position.
+pub fn diff(left: &[TraceStep], right: &[TraceStep]) -> anyhow::Result<DiffOutcome> {
+    let (start_left, start_right) =
+        align(left, right).context("traces could not be aligned: no common starting step")?;
+
+    let pairs = left[start_left..].iter().zip(&right[start_right..]);
+    let mut compared = 0;
+
+    for (offset, (l, r)) in pairs.enumerate() {
+        let mismatches: Vec<_> = FIELD_NAMES
+            .iter()
+            .zip(l.fields().into_iter().zip(r.fields()))
+            .filter_map(|(&field, values)| match values {
+                (Some(left), Some(right)) if left != right => Some(Mismatch { field, left, right }),
+                _ => None,
+            })
+            .collect();
+
+        if !mismatches.is_empty() {
+            return Ok(DiffOutcome::Diverged(Divergence {
+                left: start_left + offset,
+                right: start_right + offset,
+                mismatches,
+            }));
+        }
+
+        compared += 1;
+    }
+
+    Ok(DiffOutcome::Identical { compared })
+}
+
+/// Renders a human readable report for a divergence, including the `context` steps that
+/// led up to it and the full state of both traces at the divergent step.
+pub fn report(
+    left: &[TraceStep],
+    right: &[TraceStep],
+    divergence: &Divergence,
+    context: usize,
+) -> String {
+    let mut out = String::new();
+    let l = &left[divergence.left];
+    let r = &right[divergence.right];
+
+    let _ = writeln!(
+        out,
+        "Traces diverge at step {} (left line {}, right line {})",
+        l.step
+            .map_or_else(|| divergence.left.to_string(), |s| s.to_string()),
+        l.line,
+        r.line
+    );
+
+    let first = divergence.left.saturating_sub(context);
+    if first < divergence.left {
+        let _ = writeln!(out, "\nPreceding steps:");
+        for step in &left[first..divergence.left] {
+            let _ = writeln!(out, "  {}", describe_location(step));
+        }
+    }
+
+    let _ = writeln!(out, "\nDivergent step:");
+    let _ = writeln!(out, "  left:  {}", describe_location(l));
+    let _ = writeln!(out, "  right: {}", describe_location(r));
+    let _ = writeln!(out, "\n  {:<5} {:>6} {:>6}", "", "left", "right");
+
+    for (name, (lv, rv)) in FIELD_NAMES
+        .iter()
+        .zip(l.fields().into_iter().zip(r.fields()))
+    {
+        let marker = if divergence.mismatches.iter().any(|m| m.field == *name) {
+            "  <--"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            out,
+            "  {:<5} {:>6} {:>6}{}",
+            name,
+            format_field(lv),
+            format_field(rv),
+            marker
+        );
+    }
+
+    out
+}
+
+/// Dumps the 16-byte memory rows around each of the given addresses.
+pub fn memory_context(memory: &[u8], addresses: &[(&str, usize)]) -> String {
+    let mut out = String::new();
+
+    for &(name, addr) in addresses {
+        let _ = writeln!(out, "Memory around {name} ({addr:03x}):");
+        let row_start = (addr & !0xF).saturating_sub(0x10);
+        let row_end = ((addr & !0xF) + 0x20).min(memory.len());
+        // Addresses past the end of memory have nothing around them to show.
+        let row_start = row_start.min(row_end);
+
+        for (row, chunk) in memory[row_start..row_end].chunks(16).enumerate() {
+            let bytes: Vec<_> = chunk.iter().map(|b| format!("{b:02x}")).collect();
+            let _ = writeln!(out, "  {:03x}  {}", row_start + 16 * row, bytes.join(" "));
+        }
+    }
+
+    out
+}
+
+fn describe_location(step: &TraceStep) -> String {
+    let instruction = step
+        .opcode
+        .and_then(Instruction::decode)
+        .map_or_else(|| "???".to_string(), |i| i.to_string());
+
+    format!(
+        "PC={} OP={} {}",
+        format_field(step.pc),
+        format_field(step.opcode),
+        instruction
+    )
+}
+
+fn format_field(value: Option<u16>) -> String {
+    value.map_or_else(|| "-".to_string(), |v| format!("{v:02X}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(lines: &[&str]) -> Vec<TraceStep> {
+        lines
+            .iter()
+            .enumerate()
+            .filter_map(|(i, l)| TraceStep::parse_line(l, i + 1).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_patata_text_line() {
+        let step = TraceStep::parse_line(
+            "00000012 PC=0208 OP=6A02 V=000102030405060708090A0B0C0D0E0F I=0200 SP=01 DT=00 ST=03 | LD VA, 0x02 | VA:0A->02",
+            1,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(Some(12), step.step);
+        assert_eq!(Some(0x208), step.pc);
+        assert_eq!(Some(0x6A02), step.opcode);
+        assert_eq!(Some(0x0F), step.registers[0xF]);
+        assert_eq!(Some(0x200), step.index);
+        assert_eq!(Some(3), step.sound_timer);
+    }
+
+    #[test]
+    fn parse_reference_line() {
+        let step = TraceStep::parse_line("pc=0x200 op=00e0 v3=ff cycles=12", 1)
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(None, step.step);
+        assert_eq!(Some(0x200), step.pc);
+        assert_eq!(Some(0xFF), step.registers[3]);
+        assert_eq!(None, step.registers[0]);
+
+        assert_eq!(None, TraceStep::parse_line("# comment", 2).unwrap());
+        assert!(TraceStep::parse_line("PC=xyz", 3).is_err());
+    }
+
+    #[test]
+    fn aligns_and_finds_first_divergence() {
+        let left = parse(&[
+            "PC=0202 OP=6001 V0=00",
+            "PC=0204 OP=7001 V0=01",
+            "PC=0206 OP=1206 V0=03",
+        ]);
+        let right = parse(&[
+            "PC=0200 OP=00E0 V0=00",
+            "PC=0202 OP=6001 V0=00",
+            "PC=0204 OP=7001 V0=01",
+            "PC=0206 OP=1206 V0=02",
+        ]);
+
+        assert_eq!(Some((0, 1)), align(&left, &right));
+
+        let DiffOutcome::Diverged(divergence) = diff(&left, &right).unwrap() else {
+            panic!("expected a divergence");
+        };
+        assert_eq!(2, divergence.left);
+        assert_eq!(3, divergence.right);
+        assert_eq!(
+            vec![Mismatch {
+                field: "V0",
+                left: 3,
+                right: 2
+            }],
+            divergence.mismatches
+        );
+
+        let report = report(&left, &right, &divergence, 5);
+        assert!(report.contains("V0        03     02  <--"));
+    }
+
+    #[test]
+    fn identical_traces() {
+        let left = parse(&["PC=0200 OP=00E0", "PC=0202 OP=1202"]);
+        let right = parse(&["PC=0200 OP=00E0 SP=00", "PC=0202"]);
+
+        assert_eq!(
+            DiffOutcome::Identical { compared: 2 },
+            diff(&left, &right).unwrap()
+        );
+    }
+
+    #[test]
+    fn memory_context_past_the_end() {
+        let memory = [0xAB; 0x200];
+
+        let context = memory_context(&memory, &[("I", 0x1F8), ("PC", 0xFFF)]);
+        assert!(context.contains("  1e0  ab ab"));
+        assert!(context.contains("  1f0  ab ab"));
+        assert!(context.ends_with("Memory around PC (fff):\n"));
+    }
+}
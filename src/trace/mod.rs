@@ -0,0 +1,462 @@
+//! Structured instruction execution traces.
+//!
+//! A [`Tracer`] records one [`TraceRecord`] per executed instruction, holding the
+//! machine state right before the instruction ran and right after it finished.
+//!
+//! # Text format
+//!
+//! One line per step, with whitespace separated `KEY=VALUE` fields (all values are hex,
+//! except for the step number), followed by the disassembled instruction and the state
+//! that changed as a result of executing it:
+//!
+//! ```text
+//! 00000012 PC=0208 OP=6A02 V=00000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00 | LD VA, 0x02 | VA:00->02
+//! ```
+//!
+//! With [symbols](Tracer::with_symbols), the instruction is prefixed with its location and
+//! address operands are named, e.g. `| main+8: CALL draw_player |`.
+//!
+//! # Binary format
+//!
+//! The file starts with the 4-byte magic `P8TR` and a version byte, followed by fixed-size
+//! little-endian records: the step number (`u64`), the opcode (`u16`), and then the state
+//! before and after the step, each encoded as PC (`u16`), V0-VF (16 bytes), I (`u16`),
+//! SP, DT and ST (1 byte each).
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    ops::RangeInclusive,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{chip8::Chip8, instruction::Instruction, symbols::Symbols};
+
+pub mod diff;
+
+const BINARY_MAGIC: &[u8; 4] = b"P8TR";
+const BINARY_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TraceFormat {
+    #[default]
+    Text,
+    Binary,
+}
+
+impl FromStr for TraceFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "binary" | "bin" => Ok(Self::Binary),
+            _ => anyhow::bail!("unknown trace format {s:?}, expected `text` or `binary`"),
+        }
+    }
+}
+
+/// Restricts which steps are written to a trace. Empty filters match everything.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    /// Only trace instructions located in this address range.
+    pub addresses: Option<RangeInclusive<u16>>,
+    /// Only trace instructions with one of these mnemonics (e.g. `DRW`, `CALL`).
+    pub kinds: Vec<String>,
+    /// Only trace steps within this window, counting from 0.
+    pub steps: Option<RangeInclusive<u64>>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, step: u64, pc: u16, opcode: u16) -> bool {
+        if self.addresses.as_ref().is_some_and(|r| !r.contains(&pc)) {
+            return false;
+        }
+
+        if self.steps.as_ref().is_some_and(|r| !r.contains(&step)) {
+            return false;
+        }
+
+        if !self.kinds.is_empty() {
+            let Some(instruction) = Instruction::decode(opcode) else {
+                return false;
+            };
+
+            return self
+                .kinds
+                .iter()
+                .any(|k| k.eq_ignore_ascii_case(instruction.mnemonic()));
+        }
+
+        true
+    }
+}
+
+/// The programmer-visible state of a [`Chip8`] at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CpuState {
+    pub pc: u16,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub stack_pointer: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl CpuState {
+    const ENCODED_LEN: usize = 2 + 16 + 2 + 3;
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn capture(chip8: &Chip8) -> Self {
+        Self {
+            pc: chip8.program_counter,
+            registers: chip8.registers,
+            index: chip8.index.get() as u16,
+            stack_pointer: chip8.stack_pointer,
+            delay_timer: chip8.delay_timer.cur_count(),
+            sound_timer: chip8.sound_timer.cur_count(),
+        }
+    }
+
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.pc.to_le_bytes());
+        buf.extend_from_slice(&self.registers);
+        buf.extend_from_slice(&self.index.to_le_bytes());
+        buf.extend_from_slice(&[self.stack_pointer, self.delay_timer, self.sound_timer]);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        let mut registers = [0; 16];
+        registers.copy_from_slice(&buf[2..18]);
+
+        Self {
+            pc: u16::from_le_bytes([buf[0], buf[1]]),
+            registers,
+            index: u16::from_le_bytes([buf[18], buf[19]]),
+            stack_pointer: buf[20],
+            delay_timer: buf[21],
+            sound_timer: buf[22],
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub step: u64,
+    pub opcode: u16,
+    pub before: CpuState,
+    pub after: CpuState,
+}
+
+impl TraceRecord {
+    pub const ENCODED_LEN: usize = 8 + 2 + 2 * CpuState::ENCODED_LEN;
+
+    pub fn instruction(&self) -> Option<Instruction> {
+        Instruction::decode(self.opcode)
+    }
+
+    /// Describes the state that changed during this step, e.g. `VA:00->02 I:0200->0205`.
+    /// The program counter is left out, since it changes on every step.
+    pub fn deltas(&self) -> String {
+        let (b, a) = (&self.before, &self.after);
+        let mut deltas = String::new();
+
+        for (i, (old, new)) in b.registers.iter().zip(a.registers.iter()).enumerate() {
+            if old != new {
+                let _ = write!(deltas, " V{i:X}:{old:02X}->{new:02X}");
+            }
+        }
+        if b.index != a.index {
+            let _ = write!(deltas, " I:{:04X}->{:04X}", b.index, a.index);
+        }
+        if b.stack_pointer != a.stack_pointer {
+            let _ = write!(
+                deltas,
+                " SP:{:02X}->{:02X}",
+                b.stack_pointer, a.stack_pointer
+            );
+        }
+        if b.delay_timer != a.delay_timer {
+            let _ = write!(deltas, " DT:{:02X}->{:02X}", b.delay_timer, a.delay_timer);
+        }
+        if b.sound_timer != a.sound_timer {
+            let _ = write!(deltas, " ST:{:02X}->{:02X}", b.sound_timer, a.sound_timer);
+        }
+
+        deltas.trim_start().to_string()
+    }
+
+    /// Formats the record as a line of the text format, naming addresses from `symbols`.
+    pub fn to_text(&self, symbols: &Symbols) -> String {
+        let s = &self.before;
+        let registers: String = s.registers.iter().map(|r| format!("{r:02X}")).collect();
+        let mut instruction = self
+            .instruction()
+            .map_or_else(|| "???".to_string(), |i| symbols.disassemble(&i));
+        if let Some(location) = symbols.locate(s.pc) {
+            instruction = format!("{location}: {instruction}");
+        }
+
+        format!(
+            "{:08} PC={:04X} OP={:04X} V={} I={:04X} SP={:02X} DT={:02X} ST={:02X} | {} | {}",
+            self.step,
+            s.pc,
+            self.opcode,
+            registers,
+            s.index,
+            s.stack_pointer,
+            s.delay_timer,
+            s.sound_timer,
+            instruction,
+            self.deltas()
+        )
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::ENCODED_LEN);
+        buf.extend_from_slice(&self.step.to_le_bytes());
+        buf.extend_from_slice(&self.opcode.to_le_bytes());
+        self.before.encode(&mut buf);
+        self.after.encode(&mut buf);
+        buf
+    }
+
+    fn from_binary(buf: &[u8]) -> Self {
+        let state_start = 10;
+        let state_end = state_start + CpuState::ENCODED_LEN;
+
+        Self {
+            step: u64::from_le_bytes(buf[0..8].try_into().unwrap()),
+            opcode: u16::from_le_bytes([buf[8], buf[9]]),
+            before: CpuState::decode(&buf[state_start..state_end]),
+            after: CpuState::decode(&buf[state_end..]),
+        }
+    }
+}
+
+/// Returns true if `bytes` start with the binary trace header.
+pub fn is_binary_trace(bytes: &[u8]) -> bool {
+    bytes.starts_with(BINARY_MAGIC)
+}
+
+/// Decodes a complete binary trace, as written by a [`Tracer`] in [`TraceFormat::Binary`].
+pub fn read_binary_trace(bytes: &[u8]) -> anyhow::Result<Vec<TraceRecord>> {
+    if !is_binary_trace(bytes) {
+        anyhow::bail!("not a binary trace: missing header");
+    }
+
+    let version = bytes.get(BINARY_MAGIC.len()).copied();
+    if version != Some(BINARY_VERSION) {
+        anyhow::bail!("unsupported binary trace version {version:?}");
+    }
+
+    let records = &bytes[BINARY_MAGIC.len() + 1..];
+    if !records.len().is_multiple_of(TraceRecord::ENCODED_LEN) {
+        anyhow::bail!("binary trace is truncated");
+    }
+
+    Ok(records
+        .chunks_exact(TraceRecord::ENCODED_LEN)
+        .map(TraceRecord::from_binary)
+        .collect())
+}
+
+/// Writes filtered [`TraceRecord`]s to an output stream.
+pub struct Tracer {
+    out: Box<dyn Write + Send>,
+    format: TraceFormat,
+    filter: TraceFilter,
+    symbols: Symbols,
+    step: u64,
+}
+
+impl std::fmt::Debug for Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Tracer")
+            .field("format", &self.format)
+            .field("filter", &self.filter)
+            .field("step", &self.step)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Tracer {
+    pub fn new(
+        mut out: Box<dyn Write + Send>,
+        format: TraceFormat,
+        filter: TraceFilter,
+    ) -> io::Result<Self> {
+        if format == TraceFormat::Binary {
+            out.write_all(BINARY_MAGIC)?;
+            out.write_all(&[BINARY_VERSION])?;
+        }
+
+        Ok(Self {
+            out,
+            format,
+            filter,
+            symbols: Symbols::default(),
+            step: 0,
+        })
+    }
+
+    /// Names locations and address operands in text traces.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    pub fn create(path: &Path, format: TraceFormat, filter: TraceFilter) -> io::Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        Self::new(Box::new(file), format, filter)
+    }
+
+    /// Records a single executed step, if it passes the filter.
+    pub fn record(&mut self, opcode: u16, before: CpuState, after: CpuState) -> io::Result<()> {
+        let step = self.step;
+        self.step += 1;
+
+        if !self.filter.matches(step, before.pc, opcode) {
+            return Ok(());
+        }
+
+        let record = TraceRecord {
+            step,
+            opcode,
+            before,
+            after,
+        };
+
+        match self.format {
+            TraceFormat::Text => writeln!(self.out, "{}", record.to_text(&self.symbols)),
+            TraceFormat::Binary => self.out.write_all(&record.to_binary()),
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn state(pc: u16) -> CpuState {
+        CpuState {
+            pc,
+            ..CpuState::default()
+        }
+    }
+
+    #[test]
+    fn text_record() {
+        let mut after = state(0x202);
+        after.registers[0xA] = 0x02;
+
+        let record = TraceRecord {
+            step: 12,
+            opcode: 0x6A02,
+            before: state(0x200),
+            after,
+        };
+
+        assert_eq!(
+            "00000012 PC=0200 OP=6A02 V=00000000000000000000000000000000 I=0000 SP=00 DT=00 ST=00 | LD VA, 0x02 | VA:00->02",
+            record.to_text(&Symbols::default())
+        );
+    }
+
+    #[test]
+    fn text_record_with_symbols() {
+        let record = TraceRecord {
+            step: 3,
+            opcode: 0x22A4,
+            before: state(0x208),
+            after: state(0x2A4),
+        };
+        let symbols = Symbols::parse("200 main\n2a4 draw_player").unwrap();
+
+        assert!(record
+            .to_text(&symbols)
+            .contains("| main+8: CALL draw_player |"));
+    }
+
+    #[test]
+    fn filter_by_address_kind_and_step() {
+        let filter = TraceFilter {
+            addresses: Some(0x200..=0x20F),
+            kinds: vec!["drw".to_string()],
+            steps: Some(10..=20),
+        };
+
+        assert!(filter.matches(10, 0x200, 0xD125));
+        assert!(!filter.matches(9, 0x200, 0xD125));
+        assert!(!filter.matches(10, 0x210, 0xD125));
+        assert!(!filter.matches(10, 0x200, 0x6A02));
+    }
+
+    #[test]
+    fn binary_output() {
+        let buf = SharedBuf::default();
+        let mut tracer = Tracer::new(
+            Box::new(buf.clone()),
+            TraceFormat::Binary,
+            TraceFilter::default(),
+        )
+        .unwrap();
+
+        tracer.record(0x00E0, state(0x200), state(0x202)).unwrap();
+        tracer.record(0x00E0, state(0x202), state(0x204)).unwrap();
+
+        let bytes = buf.0.lock().unwrap();
+        assert_eq!(BINARY_MAGIC, &bytes[0..4]);
+        assert_eq!(5 + 2 * TraceRecord::ENCODED_LEN, bytes.len());
+        // The second record's step number.
+        assert_eq!(1, bytes[5 + TraceRecord::ENCODED_LEN]);
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let buf = SharedBuf::default();
+        let mut tracer = Tracer::new(
+            Box::new(buf.clone()),
+            TraceFormat::Binary,
+            TraceFilter::default(),
+        )
+        .unwrap();
+
+        let mut after = state(0x202);
+        after.registers[3] = 0x42;
+        after.index = 0x2A4;
+        tracer.record(0x6342, state(0x200), after).unwrap();
+
+        let records = read_binary_trace(&buf.0.lock().unwrap()).unwrap();
+        assert_eq!(
+            vec![TraceRecord {
+                step: 0,
+                opcode: 0x6342,
+                before: state(0x200),
+                after,
+            }],
+            records
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! A frontend for terminals, e.g. over SSH: the display drawn with Unicode block or braille
+//! characters, and the registers in a pane next to it.
+//!
+//! Terminals only report key presses, not releases, so a key counts as held until no
+//! press or auto-repeat of it has arrived for the key-release timeout. Esc or Ctrl-C quits,
+//! and Space pauses and resumes.
+
+use std::{
+    io::Write,
+    time::{Duration, Instant},
+};
+
+use crate::{keymap::Keymap, palette::Palette, Chip8Runner, Reports, FRAME_HZ};
+pub use render::PixelMode;
+use terminal::RawTerminal;
+
+mod render;
+mod terminal;
+
+/// Long enough to bridge the pause before a terminal starts auto-repeating a held key.
+pub const DEFAULT_KEY_TIMEOUT: Duration = Duration::from_millis(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TuiOptions {
+    pub pixel_mode: PixelMode,
+    pub key_timeout: Duration,
+    pub palette: Palette,
+    pub keymap: Keymap,
+}
+
+impl Default for TuiOptions {
+    fn default() -> Self {
+        Self {
+            pixel_mode: PixelMode::default(),
+            key_timeout: DEFAULT_KEY_TIMEOUT,
+            palette: Palette::default(),
+            keymap: Keymap::default(),
+        }
+    }
+}
+
+pub struct TerminalInterface {
+    runner: Chip8Runner,
+    options: TuiOptions,
+    reports: Reports,
+}
+
+impl TerminalInterface {
+    pub fn new(runner: Chip8Runner) -> Self {
+        Self {
+            runner,
+            options: TuiOptions::default(),
+            reports: Reports::default(),
+        }
+    }
+
+    pub fn with_options(mut self, options: TuiOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Writes `reports` when the frontend exits.
+    pub fn with_reports(mut self, reports: Reports) -> Self {
+        reports.enable(&mut self.runner);
+        self.reports = reports;
+        self
+    }
+
+    /// Runs until Esc or Ctrl-C is pressed.
+    pub fn run(mut self) -> anyhow::Result<()> {
+        let frame_time = Duration::from_secs(1) / FRAME_HZ as u32;
+
+        let mut terminal = RawTerminal::enter()?;
+        let mut held = HeldKeys::default();
+        let mut last_frame = Instant::now();
+        self.runner.resume();
+
+        'frames: loop {
+            let now = Instant::now();
+
+            for input in parse_input(&terminal.read_input()) {
+                match input {
+                    Input::Quit => break 'frames,
+                    Input::Pause if self.runner.is_running() => self.runner.pause(),
+                    Input::Pause => self.runner.resume(),
+                    Input::Char(c) => {
+                        if let Some(key) = self.options.keymap.key_for_char(c) {
+                            held.press(key, now);
+                        }
+                    }
+                }
+            }
+
+            self.runner
+                .set_keys(held.keys(now, self.options.key_timeout));
+            self.runner.run_for(now - last_frame);
+            last_frame = now;
+
+            self.draw()?;
+
+            std::thread::sleep(frame_time.saturating_sub(now.elapsed()));
+        }
+
+        drop(terminal);
+        self.reports.write(&mut self.runner)
+    }
+
+    fn draw(&self) -> anyhow::Result<()> {
+        let screen = render::render_screen(&self.runner.chip8.screen(), self.options.pixel_mode);
+        let pane = render::register_pane(&self.runner);
+        let palette = self.options.palette;
+        let frame = render::compose(&screen, &pane, (palette.background, palette.foreground));
+
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Input {
+    Char(char),
+    Pause,
+    Quit,
+}
+
+/// Turns raw terminal input into key presses, skipping escape sequences like arrow keys.
+fn parse_input(bytes: &[u8]) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut bytes = bytes.iter().copied().peekable();
+
+    while let Some(byte) = bytes.next() {
+        match byte {
+            // Esc on its own quits. Followed by `[` or `O`, it starts a sequence that ends
+            // with a byte in `@`..=`~`.
+            0x1B => match bytes.peek() {
+                Some(b'[' | b'O') => {
+                    bytes.next();
+                    for b in bytes.by_ref() {
+                        if (0x40..=0x7E).contains(&b) {
+                            break;
+                        }
+                    }
+                }
+                _ => inputs.push(Input::Quit),
+            },
+            0x03 => inputs.push(Input::Quit),
+            b' ' => inputs.push(Input::Pause),
+            b if b.is_ascii_graphic() => inputs.push(Input::Char(b as char)),
+            _ => {}
+        }
+    }
+
+    inputs
+}
+
+/// When each key was last pressed or auto-repeated.
+#[derive(Debug, Default)]
+struct HeldKeys {
+    pressed: [Option<Instant>; 16],
+}
+
+impl HeldKeys {
+    fn press(&mut self, key: u8, now: Instant) {
+        self.pressed[key as usize] = Some(now);
+    }
+
+    /// The keys pressed within `timeout` of `now`, one bit per key.
+    fn keys(&self, now: Instant, timeout: Duration) -> u16 {
+        self.pressed
+            .iter()
+            .enumerate()
+            .filter(|(_, pressed)| pressed.is_some_and(|at| now - at < timeout))
+            .fold(0, |keys, (key, _)| keys | 1 << key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn input() {
+        assert_eq!(
+            vec![
+                Input::Char('q'),
+                Input::Char('4'),
+                Input::Pause,
+                Input::Quit
+            ],
+            parse_input(b"q\x1b[A4\x1bOP \x1b")
+        );
+        assert_eq!(vec![Input::Quit], parse_input(b"\x03"));
+    }
+
+    #[test]
+    fn keys_release_after_timeout() {
+        let start = Instant::now();
+        let timeout = Duration::from_millis(100);
+        let mut held = HeldKeys::default();
+
+        held.press(0x5, start);
+        held.press(0xA, start + Duration::from_millis(60));
+        assert_eq!(
+            1 << 0x5 | 1 << 0xA,
+            held.keys(start + Duration::from_millis(90), timeout)
+        );
+        assert_eq!(
+            1 << 0xA,
+            held.keys(start + Duration::from_millis(120), timeout)
+        );
+        assert_eq!(0, held.keys(start + Duration::from_millis(200), timeout));
+    }
+}
@@ -0,0 +1,190 @@
+use std::{fmt::Write as _, str::FromStr};
+
+use crate::{chip8::Screen, instruction::Instruction, palette::Rgb, Chip8Runner};
+
+/// How display pixels map onto character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PixelMode {
+    /// `▀`/`▄`, one column by two rows of pixels per cell: 64x16 cells for a 64x32 screen.
+    #[default]
+    HalfBlocks,
+    /// Braille dots, two columns by four rows per cell: 64x16 cells for a 128x64 screen.
+    Braille,
+}
+
+impl FromStr for PixelMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-blocks" => Ok(Self::HalfBlocks),
+            "braille" => Ok(Self::Braille),
+            _ => anyhow::bail!("unknown pixel mode {s:?}, expected `half-blocks` or `braille`"),
+        }
+    }
+}
+
+fn is_lit(screen: &Screen, x: usize, y: usize) -> bool {
+    x < screen.width && y < screen.height && screen.pixels[y * screen.width + x] != 0
+}
+
+pub(super) fn render_screen(screen: &Screen, mode: PixelMode) -> Vec<String> {
+    match mode {
+        PixelMode::HalfBlocks => half_blocks(screen),
+        PixelMode::Braille => braille(screen),
+    }
+}
+
+fn half_blocks(screen: &Screen) -> Vec<String> {
+    (0..screen.height.div_ceil(2))
+        .map(|row| {
+            (0..screen.width)
+                .map(
+                    |x| match (is_lit(screen, x, row * 2), is_lit(screen, x, row * 2 + 1)) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    },
+                )
+                .collect()
+        })
+        .collect()
+}
+
+fn braille(screen: &Screen) -> Vec<String> {
+    // The bit of each dot in a braille cell, by row and column.
+    const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+    (0..screen.height.div_ceil(4))
+        .map(|row| {
+            (0..screen.width.div_ceil(2))
+                .map(|col| {
+                    let mut bits = 0;
+                    for (dy, dots) in DOTS.iter().enumerate() {
+                        for (dx, dot) in dots.iter().enumerate() {
+                            if is_lit(screen, col * 2 + dx, row * 4 + dy) {
+                                bits |= dot;
+                            }
+                        }
+                    }
+                    char::from_u32(0x2800 + bits).unwrap()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Registers, timers and the next instruction, one line each.
+pub(super) fn register_pane(runner: &Chip8Runner) -> Vec<String> {
+    let chip8 = &runner.chip8;
+
+    let opcode = chip8.peek_opcode();
+    let instruction = Instruction::decode(opcode).map_or_else(
+        || format!("DW {opcode:#06x}"),
+        |i| runner.symbols().disassemble(&i),
+    );
+
+    let mut lines = vec![
+        if let Some(fault) = runner.fault() {
+            format!("FAULT {fault}")
+        } else if runner.is_running() {
+            "RUNNING".to_string()
+        } else {
+            "PAUSED".to_string()
+        },
+        format!("{:03x}  {instruction}", chip8.program_counter),
+        String::new(),
+        format!(
+            "PC {:03x}  I {:03x}  SP {:x}",
+            chip8.program_counter,
+            chip8.index.get(),
+            chip8.stack_pointer
+        ),
+        format!(
+            "DT {:02x}   ST {:02x}",
+            chip8.delay_timer.cur_count(),
+            chip8.sound_timer.cur_count()
+        ),
+    ];
+
+    for i in (0..16).step_by(4) {
+        let mut line = String::new();
+        for r in i..i + 4 {
+            let _ = write!(line, "V{r:X} {:02x}  ", chip8.registers[r]);
+        }
+        lines.push(line.trim_end().to_string());
+    }
+
+    lines
+}
+
+/// A whole frame: the screen in the palette's colours, with the pane to its right. Moves
+/// the cursor home first, so frames overwrite each other in place.
+pub(super) fn compose(screen: &[String], pane: &[String], colors: (Rgb, Rgb)) -> String {
+    let ([br, bg, bb], [fr, fg, fb]) = colors;
+    let width = screen.first().map_or(0, |line| line.chars().count());
+
+    let mut frame = String::from("\x1b[H");
+    for row in 0..screen.len().max(pane.len()) {
+        match screen.get(row) {
+            Some(line) => {
+                let _ = write!(
+                    frame,
+                    "\x1b[38;2;{fr};{fg};{fb}m\x1b[48;2;{br};{bg};{bb}m{line}\x1b[0m"
+                );
+            }
+            None => frame.push_str(&" ".repeat(width)),
+        }
+
+        frame.push_str("  ");
+        if let Some(line) = pane.get(row) {
+            frame.push_str(line);
+        }
+        // Clear whatever a longer line left behind, and go to the next one. Raw mode
+        // doesn't turn `\n` into `\r\n`.
+        frame.push_str("\x1b[K\r\n");
+    }
+
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::chip8::ScreenColors;
+
+    fn screen(pixels: &[u8], width: usize) -> Screen<'_> {
+        Screen {
+            pixels,
+            width,
+            height: pixels.len() / width,
+            colors: ScreenColors::Palette,
+        }
+    }
+
+    #[test]
+    fn half_block_cells() {
+        #[rustfmt::skip]
+        let pixels = [
+            1, 0, 1, 0,
+            1, 1, 0, 0,
+        ];
+
+        assert_eq!(vec!["█▄▀ "], half_blocks(&screen(&pixels, 4)));
+    }
+
+    #[test]
+    fn braille_cells() {
+        #[rustfmt::skip]
+        let pixels = [
+            1, 0, 0, 0,
+            0, 1, 0, 0,
+            0, 0, 0, 0,
+            1, 1, 0, 1,
+        ];
+
+        // Dots 1, 5, 7 and 8 in the first cell; dot 8 in the second.
+        assert_eq!(vec!["\u{28d1}\u{2880}"], braille(&screen(&pixels, 4)));
+    }
+}
@@ -0,0 +1,67 @@
+use std::io::Write;
+
+use anyhow::bail;
+
+/// Puts the terminal into raw mode on the alternate screen, and restores it when dropped,
+/// including when unwinding from a panic.
+pub(super) struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    pub(super) fn enter() -> anyhow::Result<Self> {
+        // SAFETY: `termios` is plain old data, and is only read after `tcgetattr` filled it.
+        let original = unsafe {
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut termios) != 0 {
+                bail!("standard input is not a terminal");
+            }
+            termios
+        };
+
+        let mut raw = original;
+        // SAFETY: `raw` is a valid `termios`.
+        unsafe { libc::cfmakeraw(&mut raw) };
+        // Reads return immediately, with whatever input is available.
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+
+        // SAFETY: `raw` is a valid `termios`.
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            bail!("failed to put the terminal into raw mode");
+        }
+
+        // Alternate screen, hidden cursor, cleared.
+        let mut stdout = std::io::stdout();
+        write!(stdout, "\x1b[?1049h\x1b[?25l\x1b[2J")?;
+        stdout.flush()?;
+
+        Ok(Self { original })
+    }
+
+    /// Everything typed since the last call, without blocking.
+    pub(super) fn read_input(&mut self) -> Vec<u8> {
+        let mut input = Vec::new();
+        let mut buf = [0u8; 64];
+
+        loop {
+            // SAFETY: `buf` is valid for writes of its length.
+            let n = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            if n <= 0 {
+                return input;
+            }
+            input.extend_from_slice(&buf[..n as usize]);
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let mut stdout = std::io::stdout();
+        let _ = write!(stdout, "\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = stdout.flush();
+
+        // SAFETY: `original` came from `tcgetattr`.
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
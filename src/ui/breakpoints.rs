@@ -0,0 +1,76 @@
+use eframe::egui::{self, Color32, RichText, Sense};
+
+use super::{disassembly::DisassemblyView, GREEN};
+use crate::Chip8Runner;
+
+/// Debugger panel for setting breakpoints by label or address, e.g. `break draw_player`.
+#[derive(Default)]
+pub(super) struct BreakpointList {
+    entry: String,
+    message: Option<String>,
+}
+
+impl BreakpointList {
+    pub(super) fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        runner: &mut Chip8Runner,
+        disassembly: &mut DisassemblyView,
+    ) {
+        ui.horizontal(|ui| {
+            let edit = egui::TextEdit::singleline(&mut self.entry)
+                .hint_text("break draw_player")
+                .desired_width(160.0)
+                .font(egui::TextStyle::Monospace);
+            let response = ui.add(edit);
+
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if submitted || ui.button(RichText::new("Add").monospace()).clicked() {
+                self.add(runner);
+            }
+        });
+
+        if let Some(message) = &self.message {
+            ui.label(RichText::new(message).color(Color32::LIGHT_RED).monospace());
+        }
+
+        let mut removed = None;
+        for &addr in runner.breakpoints() {
+            ui.horizontal(|ui| {
+                if ui.small_button("x").clicked() {
+                    removed = Some(addr);
+                }
+
+                let text = match runner.symbols().label(addr) {
+                    Some(label) => format!("{addr:03x} {label}"),
+                    None => format!("{addr:03x}"),
+                };
+                let label = egui::Label::new(RichText::new(text).color(GREEN).monospace());
+                if ui.add(label.sense(Sense::click())).clicked() {
+                    disassembly.go_to(addr as usize);
+                }
+            });
+        }
+
+        if let Some(addr) = removed {
+            runner.remove_breakpoint(addr);
+        }
+    }
+
+    fn add(&mut self, runner: &mut Chip8Runner) {
+        let entry = self.entry.trim();
+        let location = entry.strip_prefix("break ").unwrap_or(entry);
+        if location.is_empty() {
+            return;
+        }
+
+        match runner.symbols().resolve(location) {
+            Some(addr) => {
+                runner.add_breakpoint(addr);
+                self.entry.clear();
+                self.message = None;
+            }
+            None => self.message = Some(format!("unknown label or address {location:?}")),
+        }
+    }
+}
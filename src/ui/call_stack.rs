@@ -0,0 +1,172 @@
+use eframe::egui::{self, Color32, RichText, Sense};
+
+use super::{disassembly::DisassemblyView, GREEN};
+use crate::{chip8::Chip8, instruction::Instruction, Chip8Runner};
+
+// Warn once this many stack entries are in use.
+const STACK_WARNING_DEPTH: usize = 12;
+
+/// An active subroutine call, reconstructed from a return address on the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    /// Address of the `CALL` instruction.
+    call_site: usize,
+    /// Address that was called, if the instruction at `call_site` is still a `CALL`.
+    target: Option<usize>,
+    return_addr: usize,
+}
+
+/// Lists the active frames, innermost first. Return addresses that don't follow an
+/// instruction in memory are skipped, as they can't have come from a `CALL`.
+fn frames(chip8: &Chip8) -> Vec<Frame> {
+    let depth = (chip8.stack_pointer as usize).min(chip8.stack.len());
+
+    chip8.stack[..depth]
+        .iter()
+        .rev()
+        .filter_map(|&return_addr| {
+            let return_addr = return_addr as usize;
+            let call_site = return_addr.checked_sub(2)?;
+            let &[hi, lo] = chip8.memory.get(call_site..return_addr)? else {
+                return None;
+            };
+            let target = match Instruction::decode(u16::from_be_bytes([hi, lo]))? {
+                Instruction::Call(nnn) => Some(nnn as usize),
+                _ => None,
+            };
+
+            Some(Frame {
+                call_site,
+                target,
+                return_addr,
+            })
+        })
+        .collect()
+}
+
+pub(super) fn show(ui: &mut egui::Ui, runner: &Chip8Runner, disassembly: &mut DisassemblyView) {
+    let chip8 = &runner.chip8;
+    let symbols = runner.symbols();
+    let depth = chip8.stack_pointer as usize;
+    let capacity = chip8.stack.len();
+
+    let depth_color = if depth >= capacity {
+        Color32::LIGHT_RED
+    } else if depth >= STACK_WARNING_DEPTH {
+        Color32::YELLOW
+    } else {
+        Color32::WHITE
+    };
+
+    ui.horizontal(|ui| {
+        ui.label(RichText::new("SP").color(GREEN).monospace());
+        ui.label(
+            RichText::new(format!("{depth:02}/{capacity}"))
+                .color(depth_color)
+                .monospace(),
+        );
+        if depth >= capacity {
+            ui.label(RichText::new("stack full").color(depth_color).monospace());
+        } else if depth >= STACK_WARNING_DEPTH {
+            ui.label(RichText::new("nearly full").color(depth_color).monospace());
+        }
+    });
+
+    let pc = chip8.program_counter as usize;
+    let mut clicked = None;
+
+    let location = |addr: usize| match symbols.locate(addr as u16) {
+        Some(place) => format!("{addr:03x} {place}"),
+        None => format!("{addr:03x}"),
+    };
+
+    if frame_row(ui, 0, &format!("{}  <- PC", location(pc))).clicked() {
+        clicked = Some(pc);
+    }
+
+    for (i, frame) in frames(chip8).iter().enumerate() {
+        let target = frame
+            .target
+            .map_or_else(|| "???".to_string(), |t| symbols.name(t as u16));
+        let text = format!(
+            "{}  CALL {target}, returns to {:03x}",
+            location(frame.call_site),
+            frame.return_addr
+        );
+
+        if frame_row(ui, i + 1, &text).clicked() {
+            clicked = Some(frame.call_site);
+        }
+    }
+
+    if let Some(addr) = clicked {
+        disassembly.go_to(addr);
+    }
+}
+
+fn frame_row(ui: &mut egui::Ui, index: usize, text: &str) -> egui::Response {
+    ui.horizontal(|ui| {
+        ui.label(
+            RichText::new(format!("#{index:<2}"))
+                .color(GREEN)
+                .monospace(),
+        );
+        ui.add(egui::Label::new(RichText::new(text).monospace()).sense(Sense::click()))
+    })
+    .inner
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames_innermost_first() {
+        let mut chip8 = Chip8::default();
+        // 200: CALL 300, 300: CALL 400
+        chip8.load_rom(&[0x23, 0x00]).unwrap();
+        chip8.memory[0x300..0x302].copy_from_slice(&[0x24, 0x00]);
+        chip8.stack[0] = 0x202;
+        chip8.stack[1] = 0x302;
+        chip8.stack_pointer = 2;
+
+        assert_eq!(
+            vec![
+                Frame {
+                    call_site: 0x300,
+                    target: Some(0x400),
+                    return_addr: 0x302
+                },
+                Frame {
+                    call_site: 0x200,
+                    target: Some(0x300),
+                    return_addr: 0x202
+                },
+            ],
+            frames(&chip8)
+        );
+    }
+
+    #[test]
+    fn frame_with_overwritten_call_site() {
+        let mut chip8 = Chip8::default();
+        chip8.stack[0] = 0x202;
+        chip8.stack_pointer = 1;
+
+        assert_eq!(None, frames(&chip8)[0].target);
+    }
+
+    #[test]
+    fn frames_without_call_site_skipped() {
+        let mut chip8 = Chip8::default();
+        // 200: CALL 300, 300: an undecodable FFFF
+        chip8.load_rom(&[0x23, 0x00]).unwrap();
+        chip8.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+        chip8.stack[..4].copy_from_slice(&[0x202, 0x302, 0xFFFF, 0x000]);
+        chip8.stack_pointer = 4;
+
+        let frames = frames(&chip8);
+        assert_eq!(1, frames.len());
+        assert_eq!(0x200, frames[0].call_site);
+    }
+}
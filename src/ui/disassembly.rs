@@ -0,0 +1,100 @@
+use eframe::egui::{self, Color32, RichText, Sense};
+
+use super::GREEN;
+use crate::{instruction::Instruction, Chip8Runner};
+
+const LINES: usize = 24;
+// When following the PC, how many instructions to show above it.
+const LINES_BEFORE_PC: usize = 4;
+
+const PC_BG: Color32 = Color32::from_rgb(0x8A, 0x4B, 0x08);
+const TARGET_BG: Color32 = Color32::from_rgb(0x1F, 0x4E, 0x8C);
+const BREAKPOINT: Color32 = Color32::from_rgb(0xE0, 0x40, 0x40);
+
+/// Debugger panel that disassembles memory around the PC, or around an address the user
+/// navigated to.
+pub(super) struct DisassemblyView {
+    follow_pc: bool,
+    start: usize,
+    // Address the user navigated to, highlighted until the view follows the PC again.
+    target: Option<usize>,
+}
+
+impl Default for DisassemblyView {
+    fn default() -> Self {
+        Self {
+            follow_pc: true,
+            start: 0,
+            target: None,
+        }
+    }
+}
+
+impl DisassemblyView {
+    /// Stops following the PC and shows the code at `addr` instead.
+    pub(super) fn go_to(&mut self, addr: usize) {
+        self.follow_pc = false;
+        self.start = addr.saturating_sub(2 * LINES_BEFORE_PC);
+        self.target = Some(addr);
+    }
+
+    pub(super) fn show(&mut self, ui: &mut egui::Ui, runner: &Chip8Runner) {
+        let chip8 = &runner.chip8;
+        let symbols = runner.symbols();
+        let pc = chip8.program_counter as usize;
+
+        if ui
+            .checkbox(&mut self.follow_pc, RichText::new("Follow PC").monospace())
+            .changed()
+            && self.follow_pc
+        {
+            self.target = None;
+        }
+
+        if self.follow_pc {
+            // Keep PC's alignment, so that the lines before it decode the same way it does.
+            self.start = pc.saturating_sub(2 * LINES_BEFORE_PC);
+        }
+
+        for addr in (self.start..chip8.memory.len() - 1).step_by(2).take(LINES) {
+            let raw = u16::from_be_bytes([chip8.memory[addr], chip8.memory[addr + 1]]);
+            let instruction = Instruction::decode(raw)
+                .map_or_else(|| format!("DW {raw:#06x}"), |i| symbols.disassemble(&i));
+
+            if let Some(label) = symbols.label(addr as u16) {
+                ui.label(RichText::new(format!("{label}:")).color(GREEN).monospace());
+            }
+
+            let mut text = RichText::new(format!("{raw:04x}  {instruction}")).monospace();
+            if addr == pc {
+                text = text.background_color(PC_BG);
+            } else if Some(addr) == self.target {
+                text = text.background_color(TARGET_BG);
+            }
+
+            ui.horizontal(|ui| {
+                let marker = if runner.breakpoints().contains(&(addr as u16)) {
+                    RichText::new("●").color(BREAKPOINT)
+                } else {
+                    RichText::new(" ")
+                };
+                ui.label(marker.monospace());
+                ui.label(
+                    RichText::new(format!("{addr:03x}"))
+                        .color(GREEN)
+                        .monospace(),
+                );
+                let response = ui.add(egui::Label::new(text).sense(Sense::click()));
+
+                // Clicking a jump or call follows it.
+                if response.clicked() {
+                    if let Some(Instruction::Jp(nnn) | Instruction::Call(nnn)) =
+                        Instruction::decode(raw)
+                    {
+                        self.go_to(nnn as usize);
+                    }
+                }
+            });
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use eframe::egui::{self, Color32, Sense};
+
+use crate::chip8::Screen;
+use crate::palette::Palette;
+
+const SCALE: f32 = 4.0;
+
+/// Draws the screen in its colours, which for CHIP-8X are the VP-590's.
+pub(super) fn show(ui: &mut egui::Ui, screen: &Screen, palette: &Palette) {
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(screen.width as f32 * SCALE, screen.height as f32 * SCALE),
+        Sense::hover(),
+    );
+
+    let colors: Vec<_> = screen
+        .color_table(palette)
+        .into_iter()
+        .map(|[r, g, b]| Color32::from_rgb(r, g, b))
+        .collect();
+    let background = screen.background_index();
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, colors[background as usize]);
+
+    for i in 0..screen.pixels.len() {
+        let color = screen.color_index(i);
+        if color == background {
+            continue;
+        }
+
+        let (x, y) = (i % screen.width, i / screen.width);
+        let pixel = egui::Rect::from_min_size(
+            rect.min + egui::vec2(x as f32 * SCALE, y as f32 * SCALE),
+            egui::vec2(SCALE, SCALE),
+        );
+        painter.rect_filled(pixel, 0.0, colors[color as usize]);
+    }
+}
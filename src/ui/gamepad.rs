@@ -0,0 +1,144 @@
+use eframe::egui::{self, Color32, RichText};
+use log::warn;
+
+use super::GREEN;
+use crate::{
+    config::{Config, Settings},
+    gamepad::{GamepadSettings, CONTROLS},
+    platform::gamepad::StandaloneGamepads,
+    rom::Rom,
+};
+
+/// Lets the gamepad mapping of the running ROM be edited and saved.
+#[derive(Default)]
+pub(super) struct GamepadPanel {
+    gamepads: Option<StandaloneGamepads>,
+    settings: Option<GamepadSettings>,
+    message: Option<String>,
+}
+
+impl GamepadPanel {
+    pub(super) fn new(settings: GamepadSettings) -> Self {
+        let gamepads = StandaloneGamepads::init()
+            .map_err(|err| warn!("no gamepad support: {err:#}"))
+            .ok();
+
+        Self {
+            gamepads,
+            settings: Some(settings),
+            message: None,
+        }
+    }
+
+    /// Switches to the mapping `config` has for `rom`, keeping edits to the default.
+    pub(super) fn set_rom(&mut self, rom: &Rom, config: &Config) {
+        let Some(settings) = &mut self.settings else {
+            return;
+        };
+
+        settings.rom = GamepadSettings::new(config, &rom.sha1).rom;
+        if let Some(info) = &rom.info {
+            settings.apply_rom_keys(&info.keys);
+        }
+    }
+
+    /// The keys held down on the gamepads, one bit per key.
+    pub(super) fn poll(&mut self) -> u16 {
+        match (&mut self.gamepads, &self.settings) {
+            (Some(gamepads), Some(settings)) => settings.mapping().keys(gamepads.poll().pressed()),
+            _ => 0,
+        }
+    }
+
+    pub(super) fn show(&mut self, ui: &mut egui::Ui, config: &mut Settings) {
+        let Some(settings) = &mut self.settings else {
+            ui.label(
+                RichText::new("No gamepad config")
+                    .color(Color32::GRAY)
+                    .monospace(),
+            );
+            return;
+        };
+
+        let pressed: Vec<String> = match &mut self.gamepads {
+            Some(gamepads) => {
+                let gamepads = gamepads.poll();
+                let names: Vec<_> = gamepads.controllers().collect();
+                let status = if names.is_empty() {
+                    "No controllers".to_string()
+                } else {
+                    names.join(", ")
+                };
+                ui.label(RichText::new(status).color(Color32::GRAY).monospace());
+                gamepads.pressed().map(str::to_string).collect()
+            }
+            None => {
+                ui.label(
+                    RichText::new("SDL unavailable")
+                        .color(Color32::GRAY)
+                        .monospace(),
+                );
+                Vec::new()
+            }
+        };
+
+        let mut rom_profile = settings.has_rom_profile();
+        if ui
+            .checkbox(
+                &mut rom_profile,
+                RichText::new("Profile for this ROM").monospace(),
+            )
+            .changed()
+        {
+            settings.set_rom_profile(rom_profile);
+        }
+
+        egui::ScrollArea::vertical()
+            .id_source("gamepad")
+            .max_height(240.0)
+            .show(ui, |ui| {
+                egui::Grid::new("gamepad_mapping").show(ui, |ui| {
+                    for control in CONTROLS {
+                        let color = if pressed.iter().any(|p| p == control) {
+                            GREEN
+                        } else {
+                            Color32::WHITE
+                        };
+                        ui.label(RichText::new(control).color(color).monospace());
+
+                        let mapping = settings.mapping_mut();
+                        let mut key = mapping.key(control);
+                        egui::ComboBox::from_id_source(control)
+                            .width(40.0)
+                            .selected_text(key_text(key))
+                            .show_ui(ui, |ui| {
+                                ui.selectable_value(&mut key, None, key_text(None));
+                                for k in 0..16 {
+                                    ui.selectable_value(&mut key, Some(k), key_text(Some(k)));
+                                }
+                            });
+                        if key != mapping.key(control) {
+                            mapping.set(control, key);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+
+        ui.horizontal(|ui| {
+            if ui.button(RichText::new("Save").monospace()).clicked() {
+                self.message = Some(match settings.save(config) {
+                    Ok(()) => "Saved".to_string(),
+                    Err(err) => format!("{err:#}"),
+                });
+            }
+            if let Some(message) = &self.message {
+                ui.label(RichText::new(message).color(Color32::GRAY).monospace());
+            }
+        });
+    }
+}
+
+fn key_text(key: Option<u8>) -> String {
+    key.map_or_else(|| "-".to_string(), |key| format!("{key:X}"))
+}
@@ -0,0 +1,600 @@
+use std::{collections::BTreeSet, ops::Range};
+
+use eframe::egui::{self, Color32, RichText, Sense};
+use serde::{Deserialize, Serialize};
+
+use super::GREEN;
+use crate::{chip8::Chip8, coverage};
+
+const BYTES_PER_ROW: usize = 16;
+// Number of frames over which the highlight of a recently written byte fades out.
+const WRITE_FADE_FRAMES: u8 = 90;
+
+const PC_BG: Color32 = Color32::from_rgb(0x8A, 0x4B, 0x08);
+const INDEX_BG: Color32 = Color32::from_rgb(0x1F, 0x4E, 0x8C);
+const MATCH_BG: Color32 = Color32::from_rgb(0x5C, 0x5C, 0x1A);
+const WRITE_FG: Color32 = Color32::from_rgb(0xFF, 0x60, 0x60);
+const HEAT_COLD_BG: Color32 = Color32::from_rgb(0x2A, 0x18, 0x30);
+const HEAT_HOT_BG: Color32 = Color32::from_rgb(0xC8, 0x3C, 0x10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum MemoryColumn {
+    #[default]
+    Ascii,
+    Sprite,
+}
+
+/// Which access counts, if any, to show as a heatmap behind the bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub(super) enum Heatmap {
+    #[default]
+    Off,
+    Executions,
+    Reads,
+    Writes,
+}
+
+/// A row of the memory view: either a single line of 16 bytes, or a collapsed run of
+/// lines that are all zeroes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DisplayRow {
+    Line(usize),
+    Zeroes { first_line: usize, count: usize },
+}
+
+/// Hex editor state for the debugger's memory panel.
+#[derive(Default)]
+pub(super) struct MemoryEditor {
+    // Memory as of the last frame each byte was shown, used to detect writes.
+    previous: Vec<u8>,
+    // Frames each byte was shown for since it was last written, saturating at
+    // `WRITE_FADE_FRAMES`.
+    write_age: Vec<u8>,
+    // Memory as of when `rows` were worked out, and the settings they were worked out with.
+    rows_memory: Vec<u8>,
+    rows_settings: Option<(bool, Option<usize>)>,
+    rows: Vec<DisplayRow>,
+    // Instructions found by static analysis of `rows_memory`, once the coverage summary
+    // needs them.
+    code: Option<BTreeSet<usize>>,
+    editing: Option<(usize, String)>,
+    goto_text: String,
+    search_text: String,
+    search_len: usize,
+    matches: Vec<usize>,
+    current_match: Option<usize>,
+    scroll_to: Option<usize>,
+    message: Option<String>,
+    heatmap: Heatmap,
+    // Highest count of the current heatmap in view, which gets the hottest colour.
+    heat_max: u32,
+}
+
+impl MemoryEditor {
+    /// Scrolls the view to `addr` on the next frame.
+    pub(super) fn go_to(&mut self, addr: usize) {
+        self.scroll_to = Some(addr);
+    }
+
+    /// Ages the write highlights of the bytes in `range`, which are about to be shown.
+    fn track_writes(&mut self, memory: &[u8], range: Range<usize>) {
+        if self.previous.len() != memory.len() {
+            self.previous = memory.to_vec();
+            self.write_age = vec![WRITE_FADE_FRAMES; memory.len()];
+            return;
+        }
+
+        for ((prev, &cur), age) in self.previous[range.clone()]
+            .iter_mut()
+            .zip(&memory[range.clone()])
+            .zip(&mut self.write_age[range])
+        {
+            if *prev != cur {
+                *prev = cur;
+                *age = 0;
+            } else {
+                *age = age.saturating_add(1).min(WRITE_FADE_FRAMES);
+            }
+        }
+    }
+
+    /// Groups memory into rows again, if it or the settings changed since the last time.
+    fn update_rows(&mut self, memory: &[u8], show_zero_lines: bool, focus: Option<usize>) {
+        let settings = (show_zero_lines, focus.map(|addr| addr / BYTES_PER_ROW));
+        let changed = self.rows_memory != memory;
+        if !changed && self.rows_settings == Some(settings) {
+            return;
+        }
+
+        if changed {
+            self.rows_memory.clear();
+            self.rows_memory.extend_from_slice(memory);
+            self.code = None;
+        }
+        self.rows_settings = Some(settings);
+        self.rows = display_rows(memory, show_zero_lines, focus);
+    }
+
+    pub(super) fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        chip8: &mut Chip8,
+        show_zero_lines: &mut bool,
+        column: &mut MemoryColumn,
+        heatmap: &mut Heatmap,
+    ) {
+        ui.horizontal(|ui| {
+            ui.checkbox(
+                show_zero_lines,
+                RichText::new("Show zeroed lines").monospace(),
+            );
+            ui.selectable_value(
+                column,
+                MemoryColumn::Ascii,
+                RichText::new("ASCII").monospace(),
+            );
+            ui.selectable_value(
+                column,
+                MemoryColumn::Sprite,
+                RichText::new("Sprite").monospace(),
+            );
+        });
+
+        self.show_heatmap_controls(ui, chip8, heatmap);
+
+        ui.horizontal(|ui| {
+            let goto = egui::TextEdit::singleline(&mut self.goto_text)
+                .hint_text("addr")
+                .desired_width(40.0)
+                .font(egui::TextStyle::Monospace);
+            let response = ui.add(goto);
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button(RichText::new("Go").monospace()).clicked() || submitted {
+                match usize::from_str_radix(self.goto_text.trim().trim_start_matches("0x"), 16) {
+                    Ok(addr) if addr < chip8.memory.len() => {
+                        self.go_to(addr);
+                        self.message = None;
+                    }
+                    _ => self.message = Some(format!("invalid address {:?}", self.goto_text)),
+                }
+            }
+
+            let search = egui::TextEdit::singleline(&mut self.search_text)
+                .hint_text("f0 90 ?? 90")
+                .desired_width(100.0)
+                .font(egui::TextStyle::Monospace);
+            let response = ui.add(search);
+            let submitted = response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+            if ui.button(RichText::new("Find").monospace()).clicked() || submitted {
+                self.search(&chip8.memory);
+            }
+        });
+
+        if let Some(message) = &self.message {
+            ui.label(RichText::new(message).color(Color32::GRAY).monospace());
+        }
+
+        let focus = self.scroll_to.or(self.editing.as_ref().map(|(a, _)| *a));
+        self.update_rows(&chip8.memory, *show_zero_lines, focus);
+        let rows = std::mem::take(&mut self.rows);
+        let row_height =
+            ui.text_style_height(&egui::TextStyle::Monospace) + ui.spacing().item_spacing.y;
+
+        let mut scroll_area = egui::ScrollArea::vertical().auto_shrink([true, false]);
+        if let Some(addr) = self.scroll_to.take() {
+            let line = addr / BYTES_PER_ROW;
+            if let Some(pos) = rows
+                .iter()
+                .position(|r| matches!(*r, DisplayRow::Line(l) if l == line))
+            {
+                scroll_area = scroll_area.vertical_scroll_offset(pos as f32 * row_height);
+            }
+        }
+
+        scroll_area.show_rows(ui, row_height, rows.len(), |ui, range| {
+            let lines = rows[range.clone()].iter().filter_map(|row| match *row {
+                DisplayRow::Line(line) => Some(line),
+                DisplayRow::Zeroes { .. } => None,
+            });
+            self.heat_max = lines
+                .filter_map(|line| self.heat_counts(chip8).get(line_range(line, &chip8.memory)))
+                .flatten()
+                .copied()
+                .max()
+                .unwrap_or(0);
+
+            for row in &rows[range] {
+                match *row {
+                    DisplayRow::Line(line) => self.show_line(ui, chip8, line, *column),
+                    DisplayRow::Zeroes { first_line, count } => {
+                        ui.horizontal(|ui| {
+                            let addr = format!("{:03x}", first_line * BYTES_PER_ROW);
+                            ui.label(RichText::new(addr).color(GREEN).monospace());
+                            let s = if count == 1 { "" } else { "s" };
+                            ui.label(
+                                RichText::new(format!("… {count} zero line{s}"))
+                                    .color(Color32::DARK_GRAY)
+                                    .monospace(),
+                            );
+                        });
+                    }
+                }
+            }
+        });
+        self.rows = rows;
+    }
+
+    fn show_line(
+        &mut self,
+        ui: &mut egui::Ui,
+        chip8: &mut Chip8,
+        line: usize,
+        column: MemoryColumn,
+    ) {
+        let range = line_range(line, &chip8.memory);
+        let start = range.start;
+        let pc = chip8.program_counter as usize;
+        let index = chip8.index.get();
+        self.track_writes(&chip8.memory, range.clone());
+
+        ui.horizontal(|ui| {
+            ui.spacing_mut().item_spacing.x = 4.0;
+            ui.label(
+                RichText::new(format!("{start:03x}"))
+                    .color(GREEN)
+                    .monospace(),
+            );
+
+            for addr in range.clone() {
+                let byte = chip8.memory[addr];
+
+                if let Some((editing_addr, text)) = &mut self.editing {
+                    if *editing_addr == addr {
+                        let edit = egui::TextEdit::singleline(text)
+                            .desired_width(14.0)
+                            .char_limit(2)
+                            .font(egui::TextStyle::Monospace);
+                        let response = ui.add(edit);
+
+                        if response.lost_focus() {
+                            let cancelled = ui.input(|i| i.key_pressed(egui::Key::Escape));
+                            if let (false, Ok(value)) = (cancelled, u8::from_str_radix(text, 16)) {
+                                chip8.memory[addr] = value;
+                            }
+                            self.editing = None;
+                        } else if !response.has_focus() {
+                            response.request_focus();
+                        }
+                        continue;
+                    }
+                }
+
+                let mut text = RichText::new(format!("{byte:02x}")).monospace();
+                text = text.color(self.byte_color(addr, byte));
+
+                if addr == pc || addr == pc + 1 {
+                    text = text.background_color(PC_BG);
+                } else if addr == index {
+                    text = text.background_color(INDEX_BG);
+                } else if self.is_match(addr) {
+                    text = text.background_color(MATCH_BG);
+                } else if let Some(heat) = self.heat_color(chip8, addr) {
+                    text = text.background_color(heat);
+                }
+
+                let response = ui
+                    .add(egui::Label::new(text).sense(Sense::click()))
+                    .on_hover_text(format!("{addr:03x}: {byte:#04x} ({byte})"));
+                if response.clicked() {
+                    self.editing = Some((addr, format!("{byte:02x}")));
+                }
+            }
+
+            ui.add_space(8.0);
+            let bytes = &chip8.memory[range];
+            match column {
+                MemoryColumn::Ascii => {
+                    let ascii: String = bytes
+                        .iter()
+                        .map(|&b| {
+                            if b.is_ascii_graphic() || b == b' ' {
+                                b as char
+                            } else {
+                                '.'
+                            }
+                        })
+                        .collect();
+                    ui.label(RichText::new(ascii).color(Color32::GRAY).monospace());
+                }
+                MemoryColumn::Sprite => sprite_strip(ui, bytes),
+            }
+        });
+    }
+
+    fn byte_color(&self, addr: usize, byte: u8) -> Color32 {
+        let base = if byte == 0 {
+            Color32::DARK_GRAY
+        } else {
+            Color32::WHITE
+        };
+
+        match self.write_age.get(addr) {
+            Some(&age) if age < WRITE_FADE_FRAMES => {
+                let t = f32::from(age) / f32::from(WRITE_FADE_FRAMES);
+                WRITE_FG.lerp_to_gamma(base, t)
+            }
+            _ => base,
+        }
+    }
+
+    fn show_heatmap_controls(
+        &mut self,
+        ui: &mut egui::Ui,
+        chip8: &mut Chip8,
+        heatmap: &mut Heatmap,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("Heatmap").color(GREEN).monospace());
+            for (value, name) in [
+                (Heatmap::Off, "Off"),
+                (Heatmap::Executions, "Exec"),
+                (Heatmap::Reads, "Reads"),
+                (Heatmap::Writes, "Writes"),
+            ] {
+                ui.selectable_value(heatmap, value, RichText::new(name).monospace());
+            }
+
+            if *heatmap != Heatmap::Off {
+                chip8.enable_coverage();
+                if ui.button(RichText::new("Clear").monospace()).clicked() {
+                    if let Some(coverage) = chip8.coverage_mut() {
+                        coverage.clear();
+                    }
+                }
+            }
+        });
+
+        self.heatmap = *heatmap;
+
+        if let (Heatmap::Executions, Some(coverage)) = (*heatmap, chip8.coverage()) {
+            let start = chip8.layout().program_start as usize;
+            let code = self
+                .code
+                .get_or_insert_with(|| coverage::find_code(&chip8.memory, start));
+            let executions = coverage.executions();
+            let executed = code.iter().filter(|&&addr| executions[addr] > 0).count();
+            let percent = match code.len() {
+                0 => 0.,
+                total => 100. * executed as f64 / total as f64,
+            };
+            ui.label(
+                RichText::new(format!(
+                    "Executed {} of {} code bytes ({percent:.1}%)",
+                    2 * executed,
+                    2 * code.len(),
+                ))
+                .color(Color32::GRAY)
+                .monospace(),
+            );
+        }
+    }
+
+    fn heat_counts<'a>(&self, chip8: &'a Chip8) -> &'a [u32] {
+        match (self.heatmap, chip8.coverage()) {
+            (Heatmap::Executions, Some(coverage)) => coverage.executions(),
+            (Heatmap::Reads, Some(coverage)) => coverage.reads(),
+            (Heatmap::Writes, Some(coverage)) => coverage.writes(),
+            _ => &[],
+        }
+    }
+
+    /// Background colour for `addr` in the heatmap, on a log scale so that rarely touched
+    /// bytes still stand out.
+    fn heat_color(&self, chip8: &Chip8, addr: usize) -> Option<Color32> {
+        let count = *self.heat_counts(chip8).get(addr)?;
+        if count == 0 {
+            return None;
+        }
+
+        let t = (count as f32).ln_1p() / (self.heat_max as f32).ln_1p();
+        Some(HEAT_COLD_BG.lerp_to_gamma(HEAT_HOT_BG, t))
+    }
+
+    fn is_match(&self, addr: usize) -> bool {
+        // `matches` is sorted, so find the last match starting at or before `addr`.
+        let idx = self.matches.partition_point(|&m| m <= addr);
+        idx > 0 && addr < self.matches[idx - 1] + self.search_len
+    }
+
+    /// Runs the search if the pattern changed, otherwise moves to the next match.
+    fn search(&mut self, memory: &[u8]) {
+        let Some(pattern) = parse_pattern(&self.search_text) else {
+            self.message = Some(format!("invalid byte pattern {:?}", self.search_text));
+            self.matches.clear();
+            return;
+        };
+
+        let matches = find_pattern(memory, &pattern);
+        let next = if matches == self.matches && self.search_len == pattern.len() {
+            self.current_match
+                .map_or(0, |m| (m + 1) % matches.len().max(1))
+        } else {
+            0
+        };
+
+        self.search_len = pattern.len();
+        self.matches = matches;
+        self.current_match = (!self.matches.is_empty()).then_some(next);
+
+        match self.current_match {
+            Some(m) => {
+                self.go_to(self.matches[m]);
+                self.message = Some(format!("match {} of {}", m + 1, self.matches.len()));
+            }
+            None => self.message = Some("no matches".to_string()),
+        }
+    }
+}
+
+/// The addresses of `line`, which is short if it's the last one and memory doesn't fill
+/// it.
+fn line_range(line: usize, memory: &[u8]) -> Range<usize> {
+    let start = line * BYTES_PER_ROW;
+    start..(start + BYTES_PER_ROW).min(memory.len())
+}
+
+/// Paints each byte as a strip of 8 pixels, so that sprite data is easy to spot.
+fn sprite_strip(ui: &mut egui::Ui, bytes: &[u8]) {
+    let height = ui.text_style_height(&egui::TextStyle::Monospace);
+    let pixel = egui::vec2(1.0, height);
+    let (rect, _) = ui.allocate_exact_size(
+        egui::vec2(pixel.x * 8.0 * bytes.len() as f32, height),
+        Sense::hover(),
+    );
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(0x20));
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        for bit in 0..8 {
+            if byte & (0b1000_0000 >> bit) != 0 {
+                let x = rect.left() + (i * 8 + bit) as f32 * pixel.x;
+                let px = egui::Rect::from_min_size(egui::pos2(x, rect.top()), pixel);
+                painter.rect_filled(px, 0.0, Color32::WHITE);
+            }
+        }
+    }
+}
+
+/// Groups memory into display rows. Unless `show_zero_lines` is set, consecutive lines of
+/// zeroes are collapsed into a single row, except for the line containing `focus`.
+fn display_rows(memory: &[u8], show_zero_lines: bool, focus: Option<usize>) -> Vec<DisplayRow> {
+    let focus_line = focus.map(|addr| addr / BYTES_PER_ROW);
+    let mut rows = Vec::new();
+
+    for (line, chunk) in memory.chunks(BYTES_PER_ROW).enumerate() {
+        let collapsible =
+            !show_zero_lines && Some(line) != focus_line && chunk.iter().all(|&b| b == 0);
+
+        match (collapsible, rows.last_mut()) {
+            (true, Some(DisplayRow::Zeroes { count, .. })) => *count += 1,
+            (true, _) => rows.push(DisplayRow::Zeroes {
+                first_line: line,
+                count: 1,
+            }),
+            (false, _) => rows.push(DisplayRow::Line(line)),
+        }
+    }
+
+    rows
+}
+
+/// Parses a byte pattern such as `f0 90 ?? 90` or `f090`, where `??` matches any byte.
+fn parse_pattern(s: &str) -> Option<Vec<Option<u8>>> {
+    let mut pattern = Vec::new();
+
+    for token in s.split_whitespace() {
+        if token.len() % 2 != 0 {
+            return None;
+        }
+
+        for i in (0..token.len()).step_by(2) {
+            match token.get(i..i + 2)? {
+                "??" => pattern.push(None),
+                hex => pattern.push(Some(u8::from_str_radix(hex, 16).ok()?)),
+            }
+        }
+    }
+
+    (!pattern.is_empty()).then_some(pattern)
+}
+
+fn find_pattern(memory: &[u8], pattern: &[Option<u8>]) -> Vec<usize> {
+    memory
+        .windows(pattern.len())
+        .enumerate()
+        .filter(|(_, window)| {
+            window
+                .iter()
+                .zip(pattern)
+                .all(|(b, p)| p.is_none_or(|p| p == *b))
+        })
+        .map(|(addr, _)| addr)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn collapse_zero_lines() {
+        let mut memory = [0u8; 16 * 6];
+        memory[16] = 1;
+        memory[16 * 5] = 1;
+
+        assert_eq!(
+            vec![
+                DisplayRow::Zeroes {
+                    first_line: 0,
+                    count: 1
+                },
+                DisplayRow::Line(1),
+                DisplayRow::Zeroes {
+                    first_line: 2,
+                    count: 3
+                },
+                DisplayRow::Line(5),
+            ],
+            display_rows(&memory, false, None)
+        );
+
+        // The focused line is never collapsed.
+        assert_eq!(
+            DisplayRow::Line(3),
+            display_rows(&memory, false, Some(16 * 3 + 4))[3]
+        );
+
+        assert_eq!(6, display_rows(&memory, true, None).len());
+    }
+
+    #[test]
+    fn track_writes_in_view() {
+        let mut memory = vec![0u8; 16 * 3 + 4];
+        let mut editor = MemoryEditor::default();
+        editor.track_writes(&memory, line_range(0, &memory));
+
+        memory[1] = 1;
+        memory[16] = 1;
+        editor.track_writes(&memory, line_range(0, &memory));
+        assert_eq!(0, editor.write_age[1]);
+        assert_eq!(WRITE_FADE_FRAMES, editor.write_age[16]);
+
+        // Bytes written out of view light up once they're shown.
+        editor.track_writes(&memory, line_range(1, &memory));
+        assert_eq!(0, editor.write_age[16]);
+
+        assert_eq!(48..52, line_range(3, &memory));
+    }
+
+    #[test]
+    fn parse_byte_patterns() {
+        assert_eq!(
+            Some(vec![Some(0xF0), Some(0x90), None, Some(0x90)]),
+            parse_pattern("f0 90 ?? 90")
+        );
+        assert_eq!(Some(vec![Some(0xF0), Some(0x90)]), parse_pattern("F090"));
+        assert_eq!(None, parse_pattern("f0 9"));
+        assert_eq!(None, parse_pattern(""));
+    }
+
+    #[test]
+    fn find_byte_patterns() {
+        let memory = [0xF0, 0x90, 0x90, 0xF0, 0x90, 0x10];
+
+        assert_eq!(vec![0, 3], find_pattern(&memory, &[Some(0xF0), Some(0x90)]));
+        assert_eq!(vec![1, 2, 4], find_pattern(&memory, &[Some(0x90), None]));
+    }
+}
@@ -1,160 +1,727 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
 use eframe::egui::{self, Color32, RichText};
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::capture::{FrameCapture, RenderOptions};
+use crate::config::Settings;
+use crate::gamepad::GamepadSettings;
+use crate::keymap::Keymap;
+use crate::rom::{is_rom_file, Rom, RomLoader, ROM_EXTENSIONS};
+use crate::{movie::MovieSession, Chip8Runner, Reports};
+use breakpoints::BreakpointList;
+use disassembly::DisassemblyView;
+use gamepad::GamepadPanel;
+use memory::{Heatmap, MemoryColumn, MemoryEditor};
+use sprites::SpriteViewer;
 
-use crate::Chip8Runner;
+mod breakpoints;
+mod call_stack;
+mod disassembly;
+mod display;
+mod gamepad;
+mod memory;
+mod profile;
+mod sprites;
 
 const GREEN: Color32 = Color32::from_rgb(0xA0, 0xDB, 0x8E);
 
-struct DebugInterfaceSettings {
+const DEFAULT_WINDOW_SIZE: [f32; 2] = [1400.0, 600.0];
+// How often to check whether the ROM file changed.
+const RELOAD_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The debugger's layout, which is restored the next time it opens.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct DebugInterfaceSettings {
     mem_show_zero_lines: bool,
+    mem_column: MemoryColumn,
+    mem_heatmap: Heatmap,
+    panels: Panels,
+    window_size: Option<[f32; 2]>,
+}
+
+/// Which panels are shown.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+struct Panels {
+    display: bool,
+    disassembly: bool,
+    call_stack: bool,
+    breakpoints: bool,
+    profile: bool,
+    memory: bool,
+    registers: bool,
+    sprites: bool,
+    gamepad: bool,
 }
 
-impl Default for DebugInterfaceSettings {
+impl Default for Panels {
     fn default() -> Self {
         Self {
-            mem_show_zero_lines: false,
+            display: true,
+            disassembly: true,
+            call_stack: true,
+            breakpoints: true,
+            profile: true,
+            memory: true,
+            registers: true,
+            sprites: true,
+            gamepad: true,
         }
     }
 }
 
+impl Panels {
+    fn toggles(&mut self) -> [(&mut bool, &'static str); 9] {
+        [
+            (&mut self.display, "Display"),
+            (&mut self.disassembly, "Disassembly"),
+            (&mut self.call_stack, "Call stack"),
+            (&mut self.breakpoints, "Breakpoints"),
+            (&mut self.profile, "Profile"),
+            (&mut self.memory, "Memory"),
+            (&mut self.registers, "Registers"),
+            (&mut self.sprites, "Sprites"),
+            (&mut self.gamepad, "Gamepad"),
+        ]
+    }
+
+    fn any_code(&self) -> bool {
+        self.disassembly || self.call_stack || self.breakpoints || self.profile
+    }
+}
+
 pub struct DebugInterface {
-    rom_name: &'static str,
+    rom: Rom,
+    loader: RomLoader,
+    // When the ROM file was last modified, and when that was last checked.
+    rom_modified: Option<SystemTime>,
+    reload_checked: Instant,
     runner: Chip8Runner,
     settings: DebugInterfaceSettings,
+    memory_editor: MemoryEditor,
+    sprite_viewer: SpriteViewer,
+    disassembly: DisassemblyView,
+    breakpoints: BreakpointList,
+    reports: Reports,
+    // Why the reports couldn't be written on exit, for `run` to return.
+    reports_error: Rc<RefCell<Option<anyhow::Error>>>,
+    render: RenderOptions,
+    gamepad: GamepadPanel,
+    // The egui key for each keypad key.
+    keys: Vec<(egui::Key, u8)>,
+    config: Settings,
+    // The outcome of the last file operation or save from the settings menu.
+    message: Option<String>,
 }
 
 impl DebugInterface {
-    pub fn new(rom_name: &'static str, runner: Chip8Runner) -> Self {
+    /// Debugs `rom`, which `runner` has loaded.
+    pub fn new(rom: Rom, runner: Chip8Runner) -> Self {
         Self {
-            rom_name,
+            keys: egui_keys(&rom.keymap),
+            rom_modified: modified(&rom.path),
+            reload_checked: Instant::now(),
+            rom,
+            loader: RomLoader::default(),
             runner,
             settings: DebugInterfaceSettings::default(),
+            memory_editor: MemoryEditor::default(),
+            sprite_viewer: SpriteViewer::default(),
+            disassembly: DisassemblyView::default(),
+            breakpoints: BreakpointList::default(),
+            reports: Reports::default(),
+            reports_error: Rc::default(),
+            render: RenderOptions::default(),
+            gamepad: GamepadPanel::default(),
+            config: Settings::default(),
+            message: None,
         }
     }
 
-    pub fn run(self) -> eframe::Result<()> {
+    /// Loads ROMs opened from the debugger the way `loader` says. Their settings come from
+    /// the config file of [`DebugInterface::with_settings`] instead of the loader's.
+    pub fn with_loader(mut self, loader: RomLoader) -> Self {
+        self.loader = loader;
+        self
+    }
+
+    /// Restores the layout saved in the state file, and saves it there when the debugger is
+    /// closed. Settings saved from the settings menu go to the config file.
+    pub fn with_settings(mut self, settings: Settings) -> Self {
+        self.settings = settings.state.debugger.clone();
+        self.config = settings;
+        self
+    }
+
+    /// Reads game controllers, mapped the way `settings` say.
+    pub fn with_gamepad(mut self, settings: GamepadSettings) -> Self {
+        self.gamepad = GamepadPanel::new(settings);
+        self
+    }
+
+    /// How F12 screenshots and Shift+F12 GIF recordings are drawn.
+    pub fn with_render_options(mut self, render: RenderOptions) -> Self {
+        self.render = render;
+        self
+    }
+
+    /// Writes `reports` for the ROM that's open when the debugger is closed.
+    pub fn with_reports(mut self, reports: Reports) -> Self {
+        reports.enable(&mut self.runner);
+        self.reports = reports;
+        self
+    }
+
+    /// Opens the debugger, and returns once it's closed and the reports are written.
+    pub fn run(self) -> anyhow::Result<()> {
+        let reports_error = self.reports_error.clone();
         let options = eframe::NativeOptions {
-            viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 400.0]),
+            viewport: egui::ViewportBuilder::default()
+                .with_inner_size(self.settings.window_size.unwrap_or(DEFAULT_WINDOW_SIZE)),
             ..Default::default()
         };
         eframe::run_native(
-            &format!("Chip8 Debugger - {}", self.rom_name),
+            &window_title(&self.rom),
             options,
             Box::new(|_cc| Ok(Box::new(self))),
         )
+        .map_err(|err| anyhow::anyhow!("debugger failed: {err}"))?;
+
+        match reports_error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
     }
 }
 
 impl eframe::App for DebugInterface {
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(err) = self.reports.write(&mut self.runner) {
+            self.reports_error.replace(Some(err));
+        }
+
+        let layout = self.settings.clone();
+        if let Err(err) = self.config.update_state(|state| state.debugger = layout) {
+            error!("failed to save the debugger layout: {err:#}");
+        }
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        // Don't press keypad keys while typing into the debugger's text fields.
+        let keyboard_keys = if ctx.wants_keyboard_input() {
+            0
+        } else {
+            ctx.input(|i| {
+                self.keys
+                    .iter()
+                    .filter(|(key, _)| i.key_down(*key))
+                    .fold(0, |keys, (_, chip8_key)| keys | 1 << chip8_key)
+            })
+        };
+        self.runner.set_keys(keyboard_keys | self.gamepad.poll());
+
+        if ctx.input(|i| i.key_pressed(egui::Key::F12)) {
+            let result = if ctx.input(|i| i.modifiers.shift) {
+                self.toggle_gif_capture()
+            } else {
+                self.save_screenshot()
+            };
+            if let Err(err) = result {
+                error!("{err:#}");
+            }
+        }
+
+        self.handle_dropped_files(ctx);
+        if self.reload_checked.elapsed() >= RELOAD_INTERVAL {
+            self.reload_if_modified(ctx);
+        }
+        ctx.request_repaint_after(RELOAD_INTERVAL);
+
+        if let Some(rect) = ctx.input(|i| i.viewport().inner_rect) {
+            self.settings.window_size = Some([rect.width(), rect.height()]);
+        }
+
+        if self.runner.is_running() {
+            let dt = ctx.input(|i| i.stable_dt);
+            self.runner.run_for(Duration::from_secs_f32(dt));
+            ctx.request_repaint();
+        }
+
+        egui::TopBottomPanel::top("controls").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if self.runner.is_running() {
+                    if ui.button(RichText::new("Pause").monospace()).clicked() {
+                        self.runner.pause();
+                    }
+                } else if ui.button(RichText::new("Run").monospace()).clicked() {
+                    self.runner.resume();
+                }
+
+                let step_button = egui::Button::new(RichText::new("Step").monospace());
+                if ui
+                    .add_enabled(!self.runner.is_running(), step_button)
+                    .clicked()
+                {
+                    self.runner.step();
+                }
+
+                ui.menu_button(RichText::new("File").monospace(), |ui| {
+                    self.file_menu(ui);
+                });
+                ui.menu_button(RichText::new("View").monospace(), |ui| {
+                    for (shown, name) in self.settings.panels.toggles() {
+                        ui.checkbox(shown, RichText::new(name).monospace());
+                    }
+                });
+                ui.menu_button(RichText::new("Settings").monospace(), |ui| {
+                    self.settings_menu(ui);
+                });
+                if let Some(message) = &self.message {
+                    ui.label(RichText::new(message).color(Color32::GRAY).monospace());
+                }
+
+                if let Some(fault) = self.runner.fault() {
+                    ui.add_space(16.0);
+                    let text = format!("FAULT {fault}");
+                    ui.label(RichText::new(text).color(Color32::LIGHT_RED).monospace());
+                }
+
+                if let Some(capture) = self.runner.capture() {
+                    ui.add_space(16.0);
+                    let text = format!("● CAPTURE frame {}", capture.frame());
+                    ui.label(RichText::new(text).color(Color32::LIGHT_RED).monospace());
+                }
+
+                if let Some(session) = self.runner.movie() {
+                    ui.add_space(16.0);
+                    movie_status(ui, session);
+                }
+            });
+        });
+
+        let panels = self.settings.panels.clone();
+        if panels.any_code() {
+            egui::SidePanel::right("code").show(ctx, |ui| {
+                if panels.disassembly {
+                    ui.monospace("Disassembly".to_uppercase());
+                    self.disassembly.show(ui, &self.runner);
+                    ui.add_space(16.0);
+                }
+                if panels.call_stack {
+                    ui.monospace("Call stack".to_uppercase());
+                    call_stack::show(ui, &self.runner, &mut self.disassembly);
+                    ui.add_space(16.0);
+                }
+                if panels.breakpoints {
+                    ui.monospace("Breakpoints".to_uppercase());
+                    self.breakpoints
+                        .show(ui, &mut self.runner, &mut self.disassembly);
+                    ui.add_space(16.0);
+                }
+                if panels.profile {
+                    ui.monospace("Profile".to_uppercase());
+                    profile::show(ui, &mut self.runner, &mut self.disassembly);
+                }
+            });
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.with_layout(
                 egui::Layout::left_to_right(egui::Align::Center).with_cross_justify(true),
                 |ui| {
-                    ui.vertical(|ui| {
-                        ui.monospace("Memory".to_uppercase());
-                        ui.checkbox(
-                            &mut self.settings.mem_show_zero_lines,
-                            RichText::new("Show zeroed lines").monospace(),
-                        );
-                        egui::ScrollArea::vertical().show(ui, |ui| {
-                            for (idx, chunk) in self.runner.chip8.memory.chunks(16).enumerate() {
-                                let is_all_zeros = chunk.iter().all(|&x| x == 0);
-                                if !self.settings.mem_show_zero_lines && is_all_zeros {
-                                    continue;
-                                }
-
-                                let bytes_string = chunk
-                                    .iter()
-                                    .map(|byte| format!("{:02x}", byte))
-                                    .collect::<Vec<String>>()
-                                    .join(" ");
-                                ui.horizontal(|ui| {
-                                    let mem_idx_str = format!("{:03x}", 16 * idx);
-                                    ui.label(RichText::new(mem_idx_str).color(GREEN).monospace());
-                                    let bytes_color = if !is_all_zeros {
-                                        Color32::WHITE
-                                    } else {
-                                        Color32::DARK_GRAY
-                                    };
-                                    ui.label(
-                                        RichText::new(bytes_string).color(bytes_color).monospace(),
-                                    );
-                                });
-                            }
+                    if panels.display {
+                        ui.vertical(|ui| {
+                            ui.monospace("Display".to_uppercase());
+                            display::show(ui, &self.runner.chip8.screen(), &self.render.palette);
                         });
-                    });
-                    ui.add_space(16.0);
-                    ui.vertical(|ui| {
-                        ui.monospace("Registers".to_uppercase());
-                        ui.horizontal(|ui| {
-                            ui.label(RichText::new("PC").color(GREEN).monospace());
-                            ui.label(
-                                RichText::new(format!("{:03x}", self.runner.chip8.program_counter))
-                                    .color(Color32::WHITE)
-                                    .monospace(),
-                            );
-                            ui.add_space(32.0);
-                            ui.label(RichText::new("I").color(GREEN).monospace());
-                            ui.label(
-                                RichText::new(format!("{:03x}", self.runner.chip8.index.get()))
-                                    .color(Color32::WHITE)
-                                    .monospace(),
+                        ui.add_space(16.0);
+                    }
+                    if panels.memory {
+                        ui.vertical(|ui| {
+                            ui.monospace("Memory".to_uppercase());
+                            self.memory_editor.show(
+                                ui,
+                                &mut self.runner.chip8,
+                                &mut self.settings.mem_show_zero_lines,
+                                &mut self.settings.mem_column,
+                                &mut self.settings.mem_heatmap,
                             );
                         });
-                        ui.add_space(8.0);
-                        for i in (0..16).step_by(2) {
-                            let reg1_label =
-                                RichText::new(format!("V{:X}", i)).color(GREEN).monospace();
-                            let reg1_val =
-                                RichText::new(format!("{:02x}", self.runner.chip8.registers[i]))
-                                    .color(color_for_byte(self.runner.chip8.registers[i]))
-                                    .monospace();
-                            let reg2_label = RichText::new(format!("V{:X}", i + 1))
-                                .color(GREEN)
-                                .monospace();
-                            let reg2_val = RichText::new(format!(
-                                "{:02x}",
-                                self.runner.chip8.registers[i + 1]
-                            ))
-                            .color(color_for_byte(self.runner.chip8.registers[i + 1]))
-                            .monospace();
+                        ui.add_space(16.0);
+                    }
+                    if panels.registers {
+                        ui.vertical(|ui| {
+                            ui.monospace("Registers".to_uppercase());
                             ui.horizontal(|ui| {
-                                ui.label(reg1_label);
-                                ui.label(reg1_val);
+                                ui.label(RichText::new("PC").color(GREEN).monospace());
+                                ui.label(
+                                    RichText::new(format!(
+                                        "{:03x}",
+                                        self.runner.chip8.program_counter
+                                    ))
+                                    .color(Color32::WHITE)
+                                    .monospace(),
+                                );
                                 ui.add_space(32.0);
-                                ui.label(reg2_label);
-                                ui.label(reg2_val);
+                                ui.label(RichText::new("I").color(GREEN).monospace());
+                                ui.label(
+                                    RichText::new(format!("{:03x}", self.runner.chip8.index.get()))
+                                        .color(Color32::WHITE)
+                                        .monospace(),
+                                );
                             });
-                        }
-                        ui.add_space(8.0);
-                        ui.horizontal(|ui| {
-                            ui.label(RichText::new("DT").color(GREEN).monospace());
-                            ui.label(
-                                RichText::new(format!(
+                            ui.add_space(8.0);
+                            for i in (0..16).step_by(2) {
+                                let reg1_label =
+                                    RichText::new(format!("V{:X}", i)).color(GREEN).monospace();
+                                let reg1_val = RichText::new(format!(
                                     "{:02x}",
-                                    self.runner.chip8.delay_timer.cur_count()
+                                    self.runner.chip8.registers[i]
                                 ))
-                                .color(color_for_byte(self.runner.chip8.delay_timer.cur_count()))
-                                .monospace(),
-                            );
-                            ui.add_space(32.0);
-                            ui.label(RichText::new("ST").color(GREEN).monospace());
-                            ui.label(
-                                RichText::new(format!(
+                                .color(color_for_byte(self.runner.chip8.registers[i]))
+                                .monospace();
+                                let reg2_label = RichText::new(format!("V{:X}", i + 1))
+                                    .color(GREEN)
+                                    .monospace();
+                                let reg2_val = RichText::new(format!(
                                     "{:02x}",
-                                    self.runner.chip8.sound_timer.cur_count()
+                                    self.runner.chip8.registers[i + 1]
                                 ))
-                                .color(color_for_byte(self.runner.chip8.sound_timer.cur_count()))
-                                .monospace(),
+                                .color(color_for_byte(self.runner.chip8.registers[i + 1]))
+                                .monospace();
+                                ui.horizontal(|ui| {
+                                    ui.label(reg1_label);
+                                    ui.label(reg1_val);
+                                    ui.add_space(32.0);
+                                    ui.label(reg2_label);
+                                    ui.label(reg2_val);
+                                });
+                            }
+                            ui.add_space(8.0);
+                            ui.horizontal(|ui| {
+                                ui.label(RichText::new("DT").color(GREEN).monospace());
+                                ui.label(
+                                    RichText::new(format!(
+                                        "{:02x}",
+                                        self.runner.chip8.delay_timer.cur_count()
+                                    ))
+                                    .color(color_for_byte(
+                                        self.runner.chip8.delay_timer.cur_count(),
+                                    ))
+                                    .monospace(),
+                                );
+                                ui.add_space(32.0);
+                                ui.label(RichText::new("ST").color(GREEN).monospace());
+                                ui.label(
+                                    RichText::new(format!(
+                                        "{:02x}",
+                                        self.runner.chip8.sound_timer.cur_count()
+                                    ))
+                                    .color(color_for_byte(
+                                        self.runner.chip8.sound_timer.cur_count(),
+                                    ))
+                                    .monospace(),
+                                );
+                            });
+                        });
+                        ui.add_space(16.0);
+                    }
+                    if panels.sprites {
+                        ui.vertical(|ui| {
+                            ui.monospace("Sprites".to_uppercase());
+                            self.sprite_viewer.show(
+                                ui,
+                                &self.runner.chip8,
+                                &mut self.memory_editor,
                             );
                         });
-                    });
+                        ui.add_space(16.0);
+                    }
+                    if panels.gamepad {
+                        ui.vertical(|ui| {
+                            ui.monospace("Gamepad".to_uppercase());
+                            self.gamepad.show(ui, &mut self.config);
+                        });
+                    }
                 },
             );
         });
     }
 }
 
+impl DebugInterface {
+    fn file_menu(&mut self, ui: &mut egui::Ui) {
+        if ui.button(RichText::new("Open…").monospace()).clicked() {
+            ui.close_menu();
+            let dialog = rfd::FileDialog::new().add_filter("CHIP-8 ROMs", &ROM_EXTENSIONS);
+            let dialog = match self.rom.path.parent() {
+                Some(dir) => dialog.set_directory(dir),
+                None => dialog,
+            };
+            if let Some(path) = dialog.pick_file() {
+                self.open(ui.ctx(), &path);
+            }
+        }
+
+        ui.menu_button(RichText::new("Open recent").monospace(), |ui| {
+            let recent = self.config.state.recent_roms.clone();
+            if recent.is_empty() {
+                ui.label(
+                    RichText::new("No recent ROMs")
+                        .color(Color32::GRAY)
+                        .monospace(),
+                );
+            }
+            for path in recent {
+                let name = path.file_name().map_or_else(
+                    || path.display().to_string(),
+                    |n| n.to_string_lossy().into(),
+                );
+                let button = ui.button(RichText::new(name).monospace());
+                if button.on_hover_text(path.display().to_string()).clicked() {
+                    ui.close_menu();
+                    self.open(ui.ctx(), &path);
+                }
+            }
+        });
+        ui.separator();
+
+        if ui.button(RichText::new("Reload").monospace()).clicked() {
+            ui.close_menu();
+            self.reload(ui.ctx());
+        }
+        if ui.button(RichText::new("Soft reset").monospace()).clicked() {
+            ui.close_menu();
+            self.runner.soft_reset();
+        }
+        if ui.button(RichText::new("Hard reset").monospace()).clicked() {
+            ui.close_menu();
+            self.hard_reset();
+        }
+    }
+
+    /// Opens a ROM dropped onto the window.
+    fn handle_dropped_files(&mut self, ctx: &egui::Context) {
+        let dropped: Vec<_> = ctx.input(|i| {
+            i.raw
+                .dropped_files
+                .iter()
+                .filter_map(|file| file.path.clone())
+                .collect()
+        });
+        if dropped.is_empty() {
+            return;
+        }
+
+        match dropped.iter().find(|path| is_rom_file(path)) {
+            Some(path) => self.open(ctx, path),
+            None => {
+                self.message = Some(format!("Not a ROM file (.{})", ROM_EXTENSIONS.join(", .")))
+            }
+        }
+    }
+
+    /// Loads the ROM at `path` and starts debugging it instead, keeping the layout.
+    fn open(&mut self, ctx: &egui::Context, path: &Path) {
+        self.loader.config = self.config.config.clone();
+        let result = self
+            .loader
+            .load(path)
+            .and_then(|rom| Ok((rom.runner()?, rom)));
+        let (mut runner, rom) = match result {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                error!("{err:#}");
+                self.message = Some(format!("{err:#}"));
+                return;
+            }
+        };
+
+        if let Err(err) = self.runner.stop_capture() {
+            error!("{err:#}");
+        }
+        self.reports.enable(&mut runner);
+        self.runner = runner;
+        self.breakpoints = BreakpointList::default();
+        self.keys = egui_keys(&rom.keymap);
+        self.render.palette = rom.palette;
+        self.message = None;
+        self.set_rom(ctx, rom);
+
+        let path = self.rom.path.clone();
+        if let Err(err) = self.config.update_state(|state| state.remember_rom(&path)) {
+            error!("failed to update the recent ROMs: {err:#}");
+        }
+    }
+
+    /// Loads the ROM file again, and starts it over with the same speed and quirks.
+    fn reload(&mut self, ctx: &egui::Context) {
+        self.loader.config = self.config.config.clone();
+        let result = self.loader.load(&self.rom.path).and_then(|rom| {
+            let mut chip8 = rom.chip8()?;
+            chip8.quirks = self.runner.chip8.quirks;
+            Ok((chip8, rom))
+        });
+
+        match result {
+            Ok((chip8, rom)) => {
+                info!("reloaded {}", rom.path.display());
+                self.runner.reset(chip8);
+                self.runner.set_symbols(rom.symbols.clone());
+                self.message = None;
+                self.set_rom(ctx, rom);
+            }
+            Err(err) => {
+                error!("{err:#}");
+                self.message = Some(format!("{err:#}"));
+                // Don't retry until the file changes again.
+                self.rom_modified = modified(&self.rom.path);
+            }
+        }
+    }
+
+    fn reload_if_modified(&mut self, ctx: &egui::Context) {
+        self.reload_checked = Instant::now();
+        let modified = modified(&self.rom.path);
+        if modified.is_some() && modified != self.rom_modified {
+            self.reload(ctx);
+        }
+    }
+
+    /// Starts the ROM over from a freshly loaded machine, keeping the speed and quirks.
+    fn hard_reset(&mut self) {
+        match self.rom.chip8() {
+            Ok(mut chip8) => {
+                chip8.quirks = self.runner.chip8.quirks;
+                self.runner.reset(chip8);
+            }
+            Err(err) => error!("{err:#}"),
+        }
+    }
+
+    fn set_rom(&mut self, ctx: &egui::Context, rom: Rom) {
+        self.rom_modified = modified(&rom.path);
+        self.config.rom_sha1 = rom.sha1.clone();
+        self.config.rom_name = rom.name();
+        self.gamepad.set_rom(&rom, &self.config.config);
+        ctx.send_viewport_cmd(egui::ViewportCommand::Title(window_title(&rom)));
+        self.rom = rom;
+    }
+
+    /// Edits the speed and quirks, and saves them for the ROM or as the defaults.
+    fn settings_menu(&mut self, ui: &mut egui::Ui) {
+        // Movies replay with the speed and quirks they were recorded with.
+        ui.add_enabled_ui(self.runner.movie().is_none(), |ui| {
+            let mut ipf = self.runner.instructions_per_frame();
+            ui.horizontal(|ui| {
+                ui.label(RichText::new("Speed").color(GREEN).monospace());
+                let speed = egui::DragValue::new(&mut ipf)
+                    .range(1..=1000)
+                    .suffix(" ipf");
+                if ui.add(speed).changed() {
+                    self.runner.set_instructions_per_frame(ipf);
+                }
+            });
+
+            let quirks = &mut self.runner.chip8.quirks;
+            for (flag, name) in [
+                (&mut quirks.shift_vy, "shift-vy"),
+                (&mut quirks.load_store_i, "load-store-i"),
+                (&mut quirks.jump_vx, "jump-vx"),
+                (&mut quirks.vf_reset, "vf-reset"),
+            ] {
+                ui.checkbox(flag, RichText::new(name).monospace());
+            }
+        });
+        ui.separator();
+
+        let ipf = self.runner.instructions_per_frame() as u32;
+        let quirks = self.runner.chip8.quirks;
+        let result = if ui
+            .button(RichText::new("Save for this ROM").monospace())
+            .clicked()
+        {
+            Some(self.config.update_rom(|rom| {
+                rom.ipf = Some(ipf);
+                rom.quirks = Some(quirks);
+            }))
+        } else if ui
+            .button(RichText::new("Save as defaults").monospace())
+            .clicked()
+        {
+            Some(self.config.update(|config| {
+                config.ipf = Some(ipf);
+                config.quirks = Some(quirks);
+            }))
+        } else {
+            None
+        };
+
+        if let Some(result) = result {
+            self.message = Some(match result {
+                Ok(()) => "Saved".to_string(),
+                Err(err) => format!("{err:#}"),
+            });
+            ui.close_menu();
+        }
+    }
+
+    fn save_screenshot(&self) -> anyhow::Result<()> {
+        let path = self.capture_path("png");
+        self.render.save_png(&self.runner.chip8.screen(), &path)?;
+        info!("saved screenshot {}", path.display());
+        Ok(())
+    }
+
+    fn toggle_gif_capture(&mut self) -> anyhow::Result<()> {
+        if let Some(capture) = self.runner.capture() {
+            let path = capture.path().to_path_buf();
+            self.runner.stop_capture()?;
+            info!("saved {}", path.display());
+            return Ok(());
+        }
+
+        let capture = FrameCapture::new(&self.capture_path("gif"), self.render, 0..=u64::MAX)?;
+        self.runner.start_capture(capture)
+    }
+
+    /// A file name in the working directory like `pong-1718000000.png`.
+    fn capture_path(&self, extension: &str) -> PathBuf {
+        let stem = self
+            .rom
+            .path
+            .file_stem()
+            .map_or("screen".into(), |stem| stem.to_string_lossy());
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        PathBuf::from(format!("{stem}-{secs}.{extension}"))
+    }
+}
+
+fn movie_status(ui: &mut egui::Ui, session: &MovieSession) {
+    if session.is_recording() {
+        let text = format!("● REC frame {}", session.frame());
+        ui.label(RichText::new(text).color(Color32::LIGHT_RED).monospace());
+        return;
+    }
+
+    let frames = session.movie().frames.len();
+    let text = format!("▶ frame {}/{frames}", session.frame());
+    ui.label(RichText::new(text).color(GREEN).monospace());
+
+    if let Some(desync) = session.desync() {
+        let text = format!("desynced at frame {}", desync.frame);
+        ui.label(RichText::new(text).color(Color32::LIGHT_RED).monospace());
+    }
+}
+
 fn color_for_byte(byte: u8) -> Color32 {
     if byte == 0 {
         Color32::DARK_GRAY
@@ -162,3 +729,22 @@ fn color_for_byte(byte: u8) -> Color32 {
         Color32::WHITE
     }
 }
+
+fn window_title(rom: &Rom) -> String {
+    format!("Chip8 Debugger - {}", rom.title())
+}
+
+/// When the file at `path` was last modified, if that can be found out.
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// The egui keys typing `keymap`'s characters. Characters egui has no key for are skipped.
+fn egui_keys(keymap: &Keymap) -> Vec<(egui::Key, u8)> {
+    (0..16)
+        .filter_map(|key| {
+            let c = keymap.char_for_key(key).to_ascii_uppercase();
+            Some((egui::Key::from_name(&c.to_string())?, key))
+        })
+        .collect()
+}
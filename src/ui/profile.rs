@@ -0,0 +1,54 @@
+use eframe::egui::{self, RichText, Sense};
+
+use super::{disassembly::DisassemblyView, GREEN};
+use crate::Chip8Runner;
+
+// How many of the hottest subroutines to list.
+const TOP_FUNCTIONS: usize = 8;
+
+/// Lists the subroutines that the most cycles were spent in, profiling on demand.
+pub(super) fn show(ui: &mut egui::Ui, runner: &mut Chip8Runner, disassembly: &mut DisassemblyView) {
+    let mut profiling = runner.profiler().is_some();
+
+    ui.horizontal(|ui| {
+        if ui
+            .checkbox(&mut profiling, RichText::new("Profile").monospace())
+            .changed()
+            && profiling
+        {
+            runner.enable_profiler();
+        }
+
+        if let Some(profiler) = runner.profiler_mut() {
+            if ui.button(RichText::new("Reset").monospace()).clicked() {
+                profiler.clear();
+            }
+        }
+    });
+
+    let Some(profiler) = runner.profiler() else {
+        return;
+    };
+
+    let total = profiler.total_cycles().max(1) as f64;
+    let mut functions: Vec<_> = profiler.functions().into_iter().collect();
+    functions.sort_by_key(|&(addr, f)| (std::cmp::Reverse(f.self_cycles), addr));
+
+    for (addr, function) in functions.into_iter().take(TOP_FUNCTIONS) {
+        ui.horizontal(|ui| {
+            let self_percent = 100. * function.self_cycles as f64 / total;
+            let total_percent = 100. * function.total_cycles as f64 / total;
+            ui.label(
+                RichText::new(format!("{self_percent:>5.1}% {total_percent:>5.1}%"))
+                    .color(GREEN)
+                    .monospace(),
+            );
+
+            let name = runner.symbols().name(addr);
+            let label = egui::Label::new(RichText::new(name).monospace()).sense(Sense::click());
+            if ui.add(label).clicked() {
+                disassembly.go_to(addr as usize);
+            }
+        });
+    }
+}
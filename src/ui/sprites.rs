@@ -0,0 +1,173 @@
+use eframe::egui::{self, Color32, RichText, Sense};
+
+use super::{memory::MemoryEditor, GREEN};
+use crate::chip8::{Chip8, FONT_GLYPH_BYTES};
+
+const SPRITE_SCALE: f32 = 6.0;
+const FONT_SCALE: f32 = 3.0;
+const MAX_SPRITE_HEIGHT: usize = 15;
+const SCHIP_SPRITE_BYTES: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+enum SpriteKind {
+    /// 8 pixels wide, 1 byte per row, as drawn by `Dxyn`.
+    #[default]
+    Chip8,
+    /// 16x16 pixels, 2 bytes per row, as drawn by SCHIP's `Dxy0`.
+    Schip,
+}
+
+/// Debugger panel that renders memory as sprites.
+pub(super) struct SpriteViewer {
+    kind: SpriteKind,
+    // Start the range at I, rather than at `start`.
+    follow_index: bool,
+    start: usize,
+    start_text: String,
+    height: usize,
+    count: usize,
+}
+
+impl Default for SpriteViewer {
+    fn default() -> Self {
+        Self {
+            kind: SpriteKind::default(),
+            follow_index: true,
+            start: 0,
+            start_text: String::new(),
+            height: MAX_SPRITE_HEIGHT,
+            count: 1,
+        }
+    }
+}
+
+impl SpriteViewer {
+    pub(super) fn show(&mut self, ui: &mut egui::Ui, chip8: &Chip8, memory: &mut MemoryEditor) {
+        if self.follow_index {
+            self.start = chip8.index.get();
+        }
+
+        ui.horizontal(|ui| {
+            ui.selectable_value(
+                &mut self.kind,
+                SpriteKind::Chip8,
+                RichText::new("8xN").monospace(),
+            );
+            ui.selectable_value(
+                &mut self.kind,
+                SpriteKind::Schip,
+                RichText::new("16x16").monospace(),
+            );
+            let follow = ui.checkbox(
+                &mut self.follow_index,
+                RichText::new("Follow I").monospace(),
+            );
+            if follow.changed() && !self.follow_index {
+                self.start_text = format!("{:03x}", self.start);
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("From").color(GREEN).monospace());
+            if self.follow_index {
+                ui.label(RichText::new(format!("{:03x}", self.start)).monospace());
+            } else {
+                let edit = egui::TextEdit::singleline(&mut self.start_text)
+                    .desired_width(40.0)
+                    .font(egui::TextStyle::Monospace);
+                if ui.add(edit).changed() {
+                    if let Ok(start) = usize::from_str_radix(self.start_text.trim(), 16) {
+                        self.start = start.min(chip8.memory.len() - 1);
+                    }
+                }
+            }
+
+            if self.kind == SpriteKind::Chip8 {
+                ui.label(RichText::new("N").color(GREEN).monospace());
+                ui.add(egui::DragValue::new(&mut self.height).range(1..=MAX_SPRITE_HEIGHT));
+            }
+            ui.label(RichText::new("Count").color(GREEN).monospace());
+            ui.add(egui::DragValue::new(&mut self.count).range(1..=16));
+        });
+
+        let sprite_bytes = match self.kind {
+            SpriteKind::Chip8 => self.height,
+            SpriteKind::Schip => SCHIP_SPRITE_BYTES,
+        };
+
+        ui.horizontal_wrapped(|ui| {
+            for i in 0..self.count {
+                let addr = self.start + i * sprite_bytes;
+                let Some(bytes) = chip8.memory.get(addr..addr + sprite_bytes) else {
+                    break;
+                };
+
+                let response = match self.kind {
+                    SpriteKind::Chip8 => sprite(ui, bytes, 1, SPRITE_SCALE),
+                    SpriteKind::Schip => sprite(ui, bytes, 2, SPRITE_SCALE / 2.0),
+                };
+                if response.on_hover_text(format!("{addr:03x}")).clicked() {
+                    memory.go_to(addr);
+                }
+            }
+        });
+
+        ui.add_space(8.0);
+        let font_start = chip8.layout().font_start as usize;
+        ui.label(
+            RichText::new(format!("Font set @ {font_start:03x}"))
+                .color(GREEN)
+                .monospace(),
+        );
+        ui.horizontal_wrapped(|ui| {
+            for digit in 0..16 {
+                let addr = font_start + digit * FONT_GLYPH_BYTES;
+                let glyph = &chip8.memory[addr..addr + FONT_GLYPH_BYTES];
+                if sprite(ui, glyph, 1, FONT_SCALE)
+                    .on_hover_text(format!("{digit:X} @ {addr:03x}"))
+                    .clicked()
+                {
+                    memory.go_to(addr);
+                }
+            }
+        });
+    }
+}
+
+/// Paints `bytes` as a sprite `bytes_per_row * 8` pixels wide, returning a clickable
+/// response.
+fn sprite(ui: &mut egui::Ui, bytes: &[u8], bytes_per_row: usize, scale: f32) -> egui::Response {
+    let width = bytes_per_row * 8;
+    let height = bytes.len() / bytes_per_row;
+    let (rect, response) = ui.allocate_exact_size(
+        egui::vec2(width as f32 * scale, height as f32 * scale),
+        Sense::click(),
+    );
+
+    let painter = ui.painter_at(rect);
+    painter.rect_filled(rect, 0.0, Color32::from_gray(0x20));
+
+    for (row, row_bytes) in bytes.chunks(bytes_per_row).enumerate() {
+        for (col_byte, &byte) in row_bytes.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (0b1000_0000 >> bit) == 0 {
+                    continue;
+                }
+
+                let x = (col_byte * 8 + bit) as f32 * scale;
+                let y = row as f32 * scale;
+                let pixel = egui::Rect::from_min_size(
+                    rect.min + egui::vec2(x, y),
+                    egui::vec2(scale, scale),
+                );
+                painter.rect_filled(pixel, 0.0, Color32::WHITE);
+            }
+        }
+    }
+
+    if response.hovered() {
+        painter.rect_stroke(rect, 0.0, egui::Stroke::new(1.0, GREEN));
+    }
+
+    response
+}